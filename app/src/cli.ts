@@ -86,6 +86,18 @@ async function addCustody(
     oracleType: { [oracleType]: {} },
     oracleAccount: tokenOracle,
     oracleAuthority: PublicKey.default, // By default, permissionless oracle price update is not allowed.
+    emaHalfLifeSec: 0,
+    maxPriceDeviation: new BN(0),
+    extraOracleAuthorities: Array(4).fill(PublicKey.default),
+    minOracleSignatures: 0,
+    maxQuoteSpread: new BN(0),
+    messageDomain: new BN(0), // Must be set to a per-deployment value once an oracle authority is set.
+    allowLegacyMessage: false,
+    rejectStaleUpdates: false,
+    referenceOracleAccount: PublicKey.default,
+    referenceOracleType: { none: {} },
+    maxReferenceDeviation: new BN(0),
+    allowExitOutsideReference: false,
   };
 
   const pricingConfig: PricingParams = {
@@ -101,6 +113,19 @@ async function addCustody(
     maxUtilization: new BN(10_000),
     maxPositionLockedUsd: new BN(1_000_000_000),
     maxTotalLockedUsd: new BN(1_000_000_000),
+    maxOiLongUsd: new BN(0),
+    maxOiShortUsd: new BN(0),
+    maxOiImbalance: new BN(0),
+    useDynamicSpread: false,
+    confSpreadMult: new BN(0),
+    emaSpreadMult: new BN(0),
+    minDynamicSpread: new BN(0),
+    maxDynamicSpread: new BN(0),
+    priceImpactMult: new BN(0),
+    maxPriceImpact: new BN(0),
+    depegThreshold: new BN(0),
+    blockPositionsOnDepeg: false,
+    payOutAtSpotOnDepeg: false,
   };
   const permissions: Permissions = {
     allowSwap: true,
@@ -128,6 +153,8 @@ async function addCustody(
     protocolShare: new BN(10),
     feeMax: new BN(250),
     feeOptimal: new BN(10),
+    referralDiscount: new BN(0),
+    referralRebate: new BN(0),
   };
   const borrowRate: BorrowRateParams = {
     baseRate: new BN(0),
@@ -390,6 +417,23 @@ async function getPnl(
   );
 }
 
+async function getPositionInfo(
+  wallet: PublicKey,
+  poolName: string,
+  tokenMint: PublicKey,
+  side: PositionSide
+): Promise<void> {
+  client.prettyPrint(
+    await client.getPositionInfo(
+      wallet,
+      poolName,
+      tokenMint,
+      await client.getCollateralCustodyMint(wallet, poolName, tokenMint, side),
+      side
+    )
+  );
+}
+
 async function getSwapAmountAndFees(
   poolName: string,
   tokenMintIn: PublicKey,
@@ -406,10 +450,23 @@ async function getSwapAmountAndFees(
   );
 }
 
+async function getOpenInterestHeadroom(
+  poolName: string,
+  tokenMint: PublicKey
+): Promise<void> {
+  client.prettyPrint(
+    await client.getOpenInterestHeadroom(poolName, tokenMint)
+  );
+}
+
 async function getAum(poolName: string): Promise<void> {
   client.prettyPrint(await client.getAum(poolName));
 }
 
+async function getAumBreakdown(poolName: string): Promise<void> {
+  client.prettyPrint(await client.getAumBreakdown(poolName));
+}
+
 (async function main() {
   const program = new Command();
   program
@@ -801,6 +858,22 @@ async function getAum(poolName: string): Promise<void> {
       );
     });
 
+  program
+    .command("get-position-info")
+    .description("Compute full health snapshot of the position")
+    .argument("<pubkey>", "User wallet")
+    .argument("<string>", "Pool name")
+    .argument("<pubkey>", "Token mint")
+    .argument("<string>", "Position side (long / short)")
+    .action(async (wallet, poolName, tokenMint, side) => {
+      await getPositionInfo(
+        new PublicKey(wallet),
+        poolName,
+        new PublicKey(tokenMint),
+        side
+      );
+    });
+
   program
     .command("get-swap-amount-and-fees")
     .description("Compute amount out and fees for the swap")
@@ -817,6 +890,15 @@ async function getAum(poolName: string): Promise<void> {
       );
     });
 
+  program
+    .command("get-open-interest-headroom")
+    .description("Get open interest and remaining headroom per side")
+    .argument("<string>", "Pool name")
+    .argument("<pubkey>", "Token mint")
+    .action(async (poolName, tokenMint) => {
+      await getOpenInterestHeadroom(poolName, new PublicKey(tokenMint));
+    });
+
   program
     .command("get-aum")
     .description("Get assets under management")
@@ -825,6 +907,14 @@ async function getAum(poolName: string): Promise<void> {
       await getAum(poolName);
     });
 
+  program
+    .command("get-aum-breakdown")
+    .description("Get assets under management per custody")
+    .argument("<string>", "Pool name")
+    .action(async (poolName) => {
+      await getAumBreakdown(poolName);
+    });
+
   await program.parseAsync(process.argv);
 
   if (!process.argv.slice(2).length) {
@@ -32,6 +32,7 @@ import {
   NewPositionPricesAndFee,
   PriceAndFee,
   ProfitAndLoss,
+  PositionInfo,
   SwapAmountAndFees,
   Custody,
 } from "./types";
@@ -679,6 +680,7 @@ export class PerpetualsClient {
         collateral,
         size,
         side: side === "long" ? { long: {} } : { short: {} },
+        positionId: new BN(0),
       })
       .accounts({
         owner: this.provider.wallet.publicKey,
@@ -956,6 +958,37 @@ export class PerpetualsClient {
       });
   };
 
+  getPositionInfo = async (
+    wallet: PublicKey,
+    poolName: string,
+    tokenMint: PublicKey,
+    collateralMint: PublicKey,
+    side: PositionSide
+  ): Promise<PositionInfo> => {
+    return this.program.methods
+      .getPositionInfo({})
+      .accounts({
+        perpetuals: this.perpetuals.publicKey,
+        pool: this.getPoolKey(poolName),
+        position: this.getPositionKey(wallet, poolName, tokenMint, side),
+        custody: this.getCustodyKey(poolName, tokenMint),
+        custodyOracleAccount: await this.getCustodyOracleAccountKey(
+          poolName,
+          tokenMint
+        ),
+        collateralCustody: this.getCustodyKey(poolName, collateralMint),
+        collateralCustodyOracleAccount: await this.getCustodyOracleAccountKey(
+          poolName,
+          collateralMint
+        ),
+      })
+      .view()
+      .catch((err) => {
+        console.error(err);
+        throw err;
+      });
+  };
+
   getSwapAmountAndFees = async (
     poolName: string,
     tokenMintIn: PublicKey,
@@ -987,6 +1020,21 @@ export class PerpetualsClient {
       });
   };
 
+  getOpenInterestHeadroom = async (poolName: string, tokenMint: PublicKey) => {
+    return this.program.methods
+      .getOpenInterestHeadroom({})
+      .accounts({
+        perpetuals: this.perpetuals.publicKey,
+        pool: this.getPoolKey(poolName),
+        custody: this.getCustodyKey(poolName, tokenMint),
+      })
+      .view()
+      .catch((err) => {
+        console.error(err);
+        throw err;
+      });
+  };
+
   getAum = async (poolName: string): Promise<BN> => {
     return this.program.methods
       .getAssetsUnderManagement({})
@@ -1001,4 +1049,20 @@ export class PerpetualsClient {
         throw err;
       });
   };
+
+  getAumBreakdown = async (poolName: string) => {
+    return this.program.methods
+      .getAumBreakdown({})
+      .accounts({
+        perpetuals: this.perpetuals.publicKey,
+        pool: this.getPoolKey(poolName),
+        lpTokenMint: this.getPoolLpTokenKey(poolName),
+      })
+      .remainingAccounts(await this.getCustodyMetas(poolName))
+      .view()
+      .catch((err) => {
+        console.error(err);
+        throw err;
+      });
+  };
 }
@@ -20,6 +20,7 @@ export type AmountAndFee = Types["AmountAndFee"];
 export type NewPositionPricesAndFee = Types["NewPositionPricesAndFee"];
 export type PriceAndFee = Types["PriceAndFee"];
 export type ProfitAndLoss = Types["ProfitAndLoss"];
+export type PositionInfo = Types["PositionInfo"];
 export type SwapAmountAndFees = Types["SwapAmountAndFees"];
 
 export type Custody = Accounts["custody"];
@@ -68,4 +68,42 @@ pub enum PerpetualsError {
     MissingTwap,
     #[msg("Invalid instruction hash")]
     InvalidInstructionHash,
+    #[msg("Position is managed by a margin account")]
+    PositionInMarginAccount,
+    #[msg("Open interest limit exceeded")]
+    MaxOpenInterest,
+    #[msg("Pool is winding down")]
+    PoolWindingDown,
+    #[msg("Custody limit exceeded")]
+    MaxCustodies,
+    #[msg("Cached assets under management are stale")]
+    StaleAumCache,
+    #[msg("Swap order has expired")]
+    SwapOrderExpired,
+    #[msg("Invalid referral account")]
+    InvalidReferral,
+    #[msg("Custom oracle price deviates too much from the previous sample")]
+    CustomOraclePriceDeviation,
+    #[msg("Not enough oracle authority signatures")]
+    PermissionlessOracleQuorumNotReached,
+    #[msg("Signed message was produced for another program, deployment or pool")]
+    PermissionlessOracleDomainMismatch,
+    #[msg("Oracle update is not newer than the stored price")]
+    StaleOracleUpdate,
+    #[msg("Oracle price is outside the reference price band")]
+    PriceOutsideReferenceBand,
+    #[msg("Signer is not the position owner or an authorized delegate")]
+    DelegateNotAuthorized,
+    #[msg("User position index is full")]
+    MaxUserPositions,
+    #[msg("Stablecoin collateral is depegged")]
+    StablecoinDepegged,
+    #[msg("Referral rebates are tracked for too many custodies")]
+    MaxReferralRebates,
+    #[msg("Signed oracle quotes are too far apart")]
+    PermissionlessOracleQuoteSpread,
+    #[msg("Take profit or stop loss price is on the wrong side of the entry price")]
+    InvalidTriggerPrice,
+    #[msg("Margin account positions use too many custodies")]
+    MarginCustodyLimit,
 }
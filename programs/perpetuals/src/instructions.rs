@@ -8,8 +8,11 @@ pub mod remove_pool;
 pub mod set_admin_signers;
 pub mod set_custody_config;
 pub mod set_custom_oracle_price;
+pub mod set_fee_tiers;
 pub mod set_permissions;
+pub mod set_pool_config;
 pub mod upgrade_custody;
+pub mod wind_down_pool;
 pub mod withdraw_fees;
 pub mod withdraw_sol_fees;
 
@@ -19,36 +22,76 @@ pub mod set_test_time;
 // public instructions
 pub mod add_collateral;
 pub mod add_liquidity;
+pub mod add_margin;
+pub mod add_margin_position;
+pub mod cancel_swap_order;
+pub mod claim_referral_rebate;
+pub mod close_margin_position;
 pub mod close_position;
+pub mod execute_swap_order;
 pub mod get_add_liquidity_amount_and_fee;
 pub mod get_assets_under_management;
+pub mod get_aum_breakdown;
 pub mod get_entry_price_and_fee;
 pub mod get_exit_price_and_fee;
 pub mod get_liquidation_price;
 pub mod get_liquidation_state;
 pub mod get_lp_token_price;
+pub mod get_open_interest_headroom;
 pub mod get_oracle_price;
 pub mod get_pnl;
+pub mod get_position_info;
+pub mod get_remove_collateral_info;
 pub mod get_remove_liquidity_amount_and_fee;
 pub mod get_swap_amount_and_fees;
+pub mod init_margin_account;
+pub mod init_referral;
+pub mod init_trader_stats;
+pub mod init_user_positions;
 pub mod liquidate;
+pub mod liquidate_batch;
+pub mod liquidate_margin_account;
 pub mod open_position;
+pub mod place_swap_order;
+pub mod prune_user_positions;
 pub mod remove_collateral;
+pub mod remove_collateral_amount;
 pub mod remove_liquidity;
+pub mod remove_liquidity_in_kind;
+pub mod remove_margin;
+pub mod remove_margin_position;
+pub mod revoke_delegate;
 pub mod set_custom_oracle_price_permissionless;
+pub mod set_delegate;
+pub mod settle_position;
+pub mod settle_referral_rebate;
 pub mod swap;
+pub mod swap_position_collateral;
+pub mod transfer_position;
+pub mod update_custody_aum;
 pub mod update_pool_aum;
+pub mod update_position_triggers;
+pub mod withdraw_profit;
 
 // bring everything in scope
 // add_custody_init::*,
 pub use {
-    add_collateral::*, add_custody::*, add_liquidity::*, add_pool::*, close_position::*,
-    get_add_liquidity_amount_and_fee::*, get_assets_under_management::*,
-    get_entry_price_and_fee::*, get_exit_price_and_fee::*, get_liquidation_price::*,
-    get_liquidation_state::*, get_lp_token_price::*, get_oracle_price::*, get_pnl::*,
-    get_remove_liquidity_amount_and_fee::*, get_swap_amount_and_fees::*, init::*, liquidate::*,
-    open_position::*, remove_collateral::*, remove_custody::*, remove_liquidity::*, remove_pool::*,
-    set_admin_signers::*, set_custody_config::*, set_custom_oracle_price::*,
-    set_custom_oracle_price_permissionless::*, set_permissions::*, set_test_time::*, swap::*,
-    update_pool_aum::*, upgrade_custody::*, withdraw_fees::*, withdraw_sol_fees::*,
+    add_collateral::*, add_custody::*, add_liquidity::*, add_margin::*, add_margin_position::*,
+    add_pool::*, cancel_swap_order::*, claim_referral_rebate::*, close_margin_position::*,
+    close_position::*, execute_swap_order::*, get_add_liquidity_amount_and_fee::*,
+    get_assets_under_management::*, get_aum_breakdown::*, get_entry_price_and_fee::*,
+    get_exit_price_and_fee::*, get_liquidation_price::*, get_liquidation_state::*,
+    get_lp_token_price::*, get_open_interest_headroom::*, get_oracle_price::*, get_pnl::*,
+    get_position_info::*, get_remove_collateral_info::*, get_remove_liquidity_amount_and_fee::*,
+    get_swap_amount_and_fees::*, init::*, init_margin_account::*, init_referral::*,
+    init_trader_stats::*, init_user_positions::*, liquidate::*, liquidate_batch::*,
+    liquidate_margin_account::*, open_position::*, place_swap_order::*, prune_user_positions::*,
+    remove_collateral::*, remove_collateral_amount::*, remove_custody::*, remove_liquidity::*,
+    remove_liquidity_in_kind::*, remove_margin::*, remove_margin_position::*, remove_pool::*,
+    revoke_delegate::*, set_admin_signers::*, set_custody_config::*, set_custom_oracle_price::*,
+    set_custom_oracle_price_permissionless::*, set_delegate::*, set_fee_tiers::*,
+    set_permissions::*, set_pool_config::*, set_test_time::*, settle_position::*,
+    settle_referral_rebate::*, swap::*, swap_position_collateral::*, transfer_position::*,
+    update_custody_aum::*, update_pool_aum::*, update_position_triggers::*, upgrade_custody::*,
+    wind_down_pool::*, withdraw_fees::*, withdraw_profit::*, withdraw_sol_fees::*,
 };
@@ -6,6 +6,7 @@ use {
         math,
         state::{
             custody::Custody,
+            delegate::{Delegate, DelegateAction},
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
@@ -20,9 +21,11 @@ use {
 #[derive(Accounts)]
 #[instruction(params: AddCollateralParams)]
 pub struct AddCollateral<'info> {
+    /// CHECK: position owner, checked by the position constraints, signs unless a delegate does
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub owner: AccountInfo<'info>,
 
+    // owner's token account, a delegate must be approved as its SPL token delegate
     #[account(
         mut,
         constraint = funding_account.mint == custody.mint,
@@ -58,7 +61,8 @@ pub struct AddCollateral<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -76,6 +80,9 @@ pub struct AddCollateral<'info> {
 
     pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         constraint = position.collateral_custody == collateral_custody.key()
@@ -98,12 +105,18 @@ pub struct AddCollateral<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // optional delegate signing instead of the owner
+    pub signer: Option<Signer<'info>>,
+
+    // optional delegation, required when signer is not the position owner
+    pub delegate: Option<Account<'info, Delegate>>,
+
     token_program: Program<'info, Token>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct AddCollateralParams {
-    collateral: u64,
+    pub collateral: u64,
     // feed_id: [u8; 32],
 }
 
@@ -117,14 +130,36 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     let custody = ctx.accounts.custody.as_mut();
     let collateral_custody = ctx.accounts.collateral_custody.as_mut();
     let position = ctx.accounts.position.as_mut();
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
     let pool = ctx.accounts.pool.as_mut();
 
     // compute position price
     let curtime = perpetuals.get_time()?;
 
+    // the position owner or an authorized delegate can manage the position
+    let signer = match ctx.accounts.signer.as_ref() {
+        Some(signer) => signer.to_account_info(),
+        None if ctx.accounts.owner.is_signer => ctx.accounts.owner.to_account_info(),
+        None => return Err(ProgramError::MissingRequiredSignature.into()),
+    };
+    Delegate::check_signer(
+        &signer.key(),
+        position,
+        ctx.accounts.delegate.as_deref(),
+        DelegateAction::AddCollateral,
+        curtime,
+    )?;
+
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            true,
+        )?,
         &custody.oracle,
         curtime,
         false,
@@ -134,6 +169,7 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -143,18 +179,20 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
         false,
         collateral_custody.oracle.feed_id,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account,
+        &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
-        false,
+        collateral_custody.pricing.use_ema,
         collateral_custody.oracle.feed_id,
     )?;
 
@@ -197,7 +235,7 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
         ctx.accounts
             .collateral_custody_token_account
             .to_account_info(),
-        ctx.accounts.owner.to_account_info(),
+        signer,
         ctx.accounts.token_program.to_account_info(),
         params.collateral,
     )?;
@@ -8,7 +8,7 @@ use {
             multisig::{AdminInstruction, Multisig},
             oracle::OracleParams,
             perpetuals::{Permissions, Perpetuals},
-            pool::{Pool, TokenRatios},
+            pool::{AumCache, Pool, TokenRatios},
         },
     },
     anchor_lang::prelude::*,
@@ -36,7 +36,8 @@ pub struct AddCustody<'info> {
     #[account(
         mut,
         realloc = Pool::LEN + (pool.custodies.len() + 1) * std::mem::size_of::<Pubkey>() +
-                              (pool.ratios.len() + 1) * std::mem::size_of::<TokenRatios>(),
+                              (pool.ratios.len() + 1) * std::mem::size_of::<TokenRatios>() +
+                              (pool.custodies.len() + 1) * std::mem::size_of::<AumCache>(),
         realloc::payer = admin,
         realloc::zero = false,
         // seeds = [b"pool",
@@ -87,6 +88,10 @@ pub fn add_custody<'info>(
     if params.ratios.len() != ctx.accounts.pool.ratios.len() + 1 {
         return Err(ProgramError::InvalidArgument.into());
     }
+    require!(
+        ctx.accounts.pool.custodies.len() < Pool::MAX_CUSTODIES,
+        PerpetualsError::MaxCustodies
+    );
 
     // validate signatures
     let mut multisig = ctx.accounts.multisig.load_mut()?;
@@ -112,7 +117,12 @@ pub fn add_custody<'info>(
 
     // update pool data
     pool.custodies.push(ctx.accounts.custody.key());
+    pool.aum_cache.push(AumCache::default());
+    pool.align_aum_cache();
     pool.ratios = params.ratios.clone();
+    // the cached valuation must not outlive the custody price it was computed with
+    pool.aum_cache_max_age_sec =
+        std::cmp::min(pool.aum_cache_max_age_sec, params.oracle.max_price_age_sec);
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
@@ -73,6 +73,9 @@ pub struct AddLiquidity<'info> {
 
     pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         // seeds = [b"custody_token_account",
@@ -94,6 +97,7 @@ pub struct AddLiquidity<'info> {
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   both can be omitted while pool.aum_cache is fresh, a stale cache is rejected then
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -121,22 +125,31 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         return Err(ProgramError::InvalidArgument.into());
     }
     let pool = ctx.accounts.pool.as_mut();
+    require!(!pool.is_winding_down(), PerpetualsError::PoolWindingDown);
     let token_id = pool.get_token_id(&custody.key())?;
 
     // calculate fee
     let curtime = perpetuals.get_time()?;
 
-    // Refresh pool.aum_usm to adapt to token price change
-    pool.aum_usd = pool.get_assets_under_management_usd(
-        AumCalcMode::EMA,
-        ctx.remaining_accounts,
-        curtime,
-        // custody.oracle.feed_id,
-    )?;
+    // Refresh pool.aum_usm to adapt to token price change,
+    // cached custody valuations are used instead if all of them are fresh
+    let cached_aum_usd =
+        pool.get_cached_aum_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+    if let Some(aum_usd) = cached_aum_usd {
+        pool.aum_usd = aum_usd;
+    } else {
+        let custodies_aum =
+            pool.get_custodies_aum(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+        pool.set_aum_cache(custodies_aum)?;
+    }
 
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            false,
+        )?,
         &custody.oracle,
         curtime,
         false,
@@ -146,6 +159,7 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -183,12 +197,18 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
 
     // compute assets under management
     msg!("Compute assets under management");
-    let pool_amount_usd = pool.get_assets_under_management_usd(
-        AumCalcMode::Max,
-        ctx.remaining_accounts,
-        curtime,
-        // custody.oracle.feed_id,
-    )?;
+    let pool_amount_usd = if let Some(aum_usd) =
+        pool.get_cached_aum_usd(AumCalcMode::Max, ctx.remaining_accounts, curtime)?
+    {
+        aum_usd
+    } else {
+        pool.get_assets_under_management_usd(
+            AumCalcMode::Max,
+            ctx.remaining_accounts,
+            curtime,
+            // custody.oracle.feed_id,
+        )?
+    };
 
     // compute amount of lp tokens to mint
     let no_fee_amount = math::checked_sub(params.amount_in, fee_amount)?;
@@ -248,12 +268,13 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
     // update pool stats
     msg!("Update pool stats");
     custody.exit(&crate::ID)?;
-    pool.aum_usd = pool.get_assets_under_management_usd(
-        AumCalcMode::EMA,
-        ctx.remaining_accounts,
-        curtime,
-        // custody.oracle.feed_id,
-    )?;
+    if cached_aum_usd.is_some() {
+        pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    } else {
+        let custodies_aum =
+            pool.get_custodies_aum(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+        pool.set_aum_cache(custodies_aum)?;
+    }
 
     Ok(())
 }
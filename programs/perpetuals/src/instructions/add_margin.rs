@@ -0,0 +1,112 @@
+//! AddMargin instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool,
+        },
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: AddMarginParams)]
+pub struct AddMargin<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = collateral_custody,
+        seeds = [b"margin_account",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(mut)]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        constraint = collateral_custody_token_account.key() == collateral_custody.token_account
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddMarginParams {
+    pub amount: u64,
+}
+
+pub fn add_margin(ctx: Context<AddMargin>, params: &AddMarginParams) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_open_position
+            && collateral_custody.permissions.allow_open_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.amount == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require!(
+        !ctx.accounts.pool.is_winding_down(),
+        PerpetualsError::PoolWindingDown
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount,
+    )?;
+
+    // update margin account and custody stats
+    msg!("Update margin account");
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    margin_account.collateral_amount =
+        math::checked_add(margin_account.collateral_amount, params.amount)?;
+
+    collateral_custody.assets.collateral =
+        math::checked_add(collateral_custody.assets.collateral, params.amount)?;
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! AddMarginPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{margin_account::MarginAccount, pool::Pool, position::Position},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: AddMarginPositionParams)]
+pub struct AddMarginPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"margin_account",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = position.pool == pool.key()
+    )]
+    pub position: Box<Account<'info, Position>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddMarginPositionParams {}
+
+pub fn add_margin_position(
+    ctx: Context<AddMarginPosition>,
+    _params: &AddMarginPositionParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    let position = ctx.accounts.position.as_mut();
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
+
+    // move position under the margin account
+    msg!("Add position to margin account");
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    margin_account.add_position(position, position.key())?;
+    position.margin_account = Some(margin_account.key());
+
+    Ok(())
+}
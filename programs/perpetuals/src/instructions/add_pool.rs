@@ -118,6 +118,7 @@ pub fn add_pool<'info>(
     pool.name = params.name.clone();
     pool.bump = ctx.bumps.pool;
     pool.lp_token_bump = ctx.bumps.lp_token_mint;
+    pool.aum_cache_max_age_sec = Pool::DEFAULT_AUM_CACHE_MAX_AGE_SEC;
 
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
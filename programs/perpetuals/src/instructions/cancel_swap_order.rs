@@ -0,0 +1,87 @@
+//! CancelSwapOrder instruction handler
+
+use {
+    crate::state::{perpetuals::Perpetuals, swap_order::SwapOrder},
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: CancelSwapOrderParams)]
+pub struct CancelSwapOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == swap_order_token_account.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"swap_order",
+                 owner.key().as_ref(),
+                 swap_order.pool.as_ref(),
+                 &swap_order.order_id.to_le_bytes()],
+        bump = swap_order.bump,
+        close = owner
+    )]
+    pub swap_order: Box<Account<'info, SwapOrder>>,
+
+    #[account(
+        mut,
+        seeds = [b"swap_order_token_account",
+                 swap_order.key().as_ref()],
+        bump = swap_order.token_account_bump
+    )]
+    pub swap_order_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelSwapOrderParams {}
+
+pub fn cancel_swap_order(
+    ctx: Context<CancelSwapOrder>,
+    _params: &CancelSwapOrderParams,
+) -> Result<()> {
+    // no permission checks, escrowed tokens can always be refunded
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+
+    // refund escrowed tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.swap_order_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.swap_order_token_account.amount,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.swap_order_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    Ok(())
+}
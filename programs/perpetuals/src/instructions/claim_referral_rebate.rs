@@ -0,0 +1,108 @@
+//! ClaimReferralRebate instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{custody::Custody, perpetuals::Perpetuals, pool::Pool, referral::Referral},
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: ClaimReferralRebateParams)]
+pub struct ClaimReferralRebate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"referral",
+                 owner.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Box<Account<'info, Referral>>,
+
+    #[account(
+        mut,
+        constraint = custody.pool == pool.key()
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        constraint = custody_token_account.key() == custody.token_account
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ClaimReferralRebateParams {}
+
+pub fn claim_referral_rebate(
+    ctx: Context<ClaimReferralRebate>,
+    _params: &ClaimReferralRebateParams,
+) -> Result<u64> {
+    // validate inputs
+    msg!("Validate inputs");
+    let custody = ctx.accounts.custody.as_mut();
+    let referral = ctx.accounts.referral.as_mut();
+    let amount = referral.take_rebate(&custody.key());
+    require!(amount > 0, PerpetualsError::InvalidReferral);
+    msg!("Amount out: {}", amount);
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    ctx.accounts.perpetuals.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    custody.assets.referral_rebates = math::checked_sub(custody.assets.referral_rebates, amount)?;
+
+    Ok(amount)
+}
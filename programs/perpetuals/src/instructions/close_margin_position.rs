@@ -0,0 +1,202 @@
+//! CloseMarginPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            margin_account::MarginAccount,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            user_positions::UserPositions,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: CloseMarginPositionParams)]
+pub struct CloseMarginPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody_token_account.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"margin_account",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = position.margin_account == Some(margin_account.key()),
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          position collateral custody mint],
+        // bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    // optional index of the owner's positions
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   margin_account.custodies.len() custody accounts (write, unsigned)
+    //   margin_account.custodies.len() custody oracles (read-only, unsigned)
+    //   margin_account.positions.len() position accounts (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CloseMarginPositionParams {
+    pub price: u64,
+    pub min_amount_out: u64,
+}
+
+pub fn close_margin_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, CloseMarginPosition<'info>>,
+    params: &CloseMarginPositionParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    require!(
+        perpetuals.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    if params.price == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_mut();
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    let position_key = ctx.accounts.position.key();
+
+    let curtime = perpetuals.get_time()?;
+    let mut state = margin_account.load_state(pool, ctx.remaining_accounts, curtime)?;
+
+    let idx = margin_account
+        .positions
+        .iter()
+        .position(|&key| key == position_key)
+        .ok_or(PerpetualsError::InvalidPositionState)?;
+    let position = &state.positions[idx];
+    let custody_idx = state.get_custody_idx(&position.custody)?;
+    let collateral_idx = state.get_custody_idx(&position.collateral_custody)?;
+    require_keys_eq!(
+        ctx.accounts.collateral_custody_token_account.key(),
+        state.custodies[collateral_idx].token_account
+    );
+
+    // compute exit price
+    let exit_price = pool.get_exit_price(
+        &state.token_prices[custody_idx],
+        &state.token_ema_prices[custody_idx],
+        position.side,
+        position.size_usd,
+        &state.custodies[custody_idx],
+    )?;
+    msg!("Exit price: {}", exit_price);
+
+    if position.side == Side::Long {
+        require_gte!(exit_price, params.price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(params.price, exit_price, PerpetualsError::MaxPriceSlippage);
+    }
+
+    // settle the position, the deposit covers the loss its collateral doesn't
+    let (amount_out, shortfall_usd) = state.settle_position(pool, idx, curtime, false)?;
+    require_gte!(
+        amount_out,
+        params.min_amount_out,
+        PerpetualsError::InsufficientAmountReturned
+    );
+    state.debit_deposit(margin_account, shortfall_usd, false)?;
+
+    // move position out of the margin account
+    msg!("Remove position from margin account");
+    margin_account.remove_position(&position_key)?;
+    state.positions.remove(idx);
+    margin_account.refresh_custodies(&state.positions);
+
+    if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+        user_positions.remove_position(&position_key);
+    }
+
+    // check the remaining positions are still backed
+    if !state.positions.is_empty() {
+        msg!("Check margin account risks");
+        let (equity_usd, _, maintenance_margin_usd) =
+            state.get_margin_usd(pool, margin_account, curtime)?;
+        msg!(
+            "Equity: {}, maintenance margin: {}",
+            equity_usd,
+            maintenance_margin_usd
+        );
+        require_gte!(
+            equity_usd,
+            maintenance_margin_usd,
+            PerpetualsError::MaxLeverage
+        );
+    }
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount_out,
+    )?;
+
+    // update borrow rates, cached valuations and save custodies
+    state.save_custodies(pool, curtime)
+}
@@ -6,10 +6,14 @@ use {
         math,
         state::{
             custody::Custody,
+            delegate::{Delegate, DelegateAction},
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            user_positions::UserPositions,
         },
     },
     anchor_lang::prelude::*,
@@ -19,12 +23,15 @@ use {
 
 #[derive(Accounts)]
 pub struct ClosePosition<'info> {
+    /// CHECK: position owner, receives the position account rent, signs unless a delegate does
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.mint == receive_custody
+            .as_ref()
+            .map_or(collateral_custody.mint, |custody| custody.mint),
         has_one = owner
     )]
     pub receiving_account: Box<Account<'info, TokenAccount>>,
@@ -57,7 +64,8 @@ pub struct ClosePosition<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump,
         close = owner
     )]
@@ -76,6 +84,9 @@ pub struct ClosePosition<'info> {
 
     pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         constraint = position.collateral_custody == collateral_custody.key()
@@ -98,12 +109,67 @@ pub struct ClosePosition<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // optional custody to swap the payout into
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          receive_custody.mint.as_ref()],
+        // bump = receive_custody.bump
+    )]
+    pub receive_custody: Option<Box<Account<'info, Custody>>>,
+
+    // #[account(
+    //     constraint = receive_custody_oracle_account.key() == receive_custody.oracle.oracle_account
+    // )]
+    pub receive_custody_oracle_account: Option<Account<'info, PriceUpdateV2>>,
+
+    pub receive_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          receive_custody.mint.as_ref()],
+        // bump = receive_custody.token_account_bump
+    )]
+    pub receive_custody_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // optional referrer, receives a rebate and gives the trader a fee discount
+    #[account(
+        mut,
+        constraint = referral.owner != owner.key() @ PerpetualsError::InvalidReferral
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // optional trader volume stats, used for the fee tier discount
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
+    // optional index of the owner's positions
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
+    // optional delegate signing instead of the owner
+    pub signer: Option<Signer<'info>>,
+
+    // optional delegation, required when signer is not the position owner
+    pub delegate: Option<Account<'info, Delegate>>,
+
     token_program: Program<'info, Token>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
 pub struct ClosePositionParams {
     pub price: u64,
+    // minimum amount received, in receive_custody tokens if set, otherwise in collateral tokens
+    pub min_amount_out: u64,
     // pub feed_id: [u8; 32],
 }
 
@@ -123,15 +189,58 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
     if params.price == 0 {
         return Err(ProgramError::InvalidArgument.into());
     }
+    if let Some(receive_custody) = ctx.accounts.receive_custody.as_ref() {
+        require!(
+            perpetuals.permissions.allow_swap,
+            PerpetualsError::InstructionNotAllowed
+        );
+        require_keys_neq!(receive_custody.key(), collateral_custody.key());
+        require!(
+            ctx.accounts.receive_custody_oracle_account.is_some(),
+            PerpetualsError::InvalidOracleAccount
+        );
+        match ctx.accounts.receive_custody_token_account.as_ref() {
+            Some(token_account) => {
+                require_keys_eq!(token_account.key(), receive_custody.token_account)
+            }
+            None => return Err(ProgramError::NotEnoughAccountKeys.into()),
+        }
+    }
+    // keep the owner's position index up to date
+    if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+        user_positions.remove_position(&ctx.accounts.position.key());
+    }
     let position = ctx.accounts.position.as_mut();
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
     let pool = ctx.accounts.pool.as_mut();
 
     // compute exit price
     let curtime = perpetuals.get_time()?;
 
+    // the position owner or an authorized delegate can manage the position
+    let signer = match ctx.accounts.signer.as_ref() {
+        Some(signer) => signer.key(),
+        None if ctx.accounts.owner.is_signer => ctx.accounts.owner.key(),
+        None => return Err(ProgramError::MissingRequiredSignature.into()),
+    };
+    Delegate::check_signer(
+        &signer,
+        position,
+        ctx.accounts.delegate.as_deref(),
+        DelegateAction::ClosePosition,
+        curtime,
+    )?;
+
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            true,
+        )?,
         &custody.oracle,
         curtime,
         false,
@@ -141,6 +250,7 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -150,22 +260,30 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
         false,
         collateral_custody.oracle.feed_id,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account,
+        &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
-        false,
+        collateral_custody.pricing.use_ema,
         collateral_custody.oracle.feed_id,
     )?;
 
-    let exit_price = pool.get_exit_price(&token_price, &token_ema_price, position.side, custody)?;
+    let exit_price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        position.size_usd,
+        custody,
+    )?;
     msg!("Exit price: {}", exit_price);
 
     if position.side == Side::Long {
@@ -175,6 +293,11 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
     }
 
     msg!("Settle position");
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        perpetuals.get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
     let (transfer_amount, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
         position,
         &token_price,
@@ -185,6 +308,7 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         collateral_custody,
         curtime,
         false,
+        fee_discount,
     )?;
 
     let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
@@ -193,6 +317,20 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
             .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
     }
 
+    let (discount, rebate) = if let Some(referral) = ctx.accounts.referral.as_ref() {
+        let (discount, rebate) = custody.fees.get_referral_split(fee_amount)?;
+        if referral.can_accrue(&collateral_custody.key()) {
+            (discount, rebate)
+        } else {
+            msg!("Referral rebates are full, the rebate stays in the fee");
+            (discount, 0)
+        }
+    } else {
+        (0, 0)
+    };
+    fee_amount = math::checked_sub(fee_amount, discount)?;
+    let transfer_amount = math::checked_add(transfer_amount, discount)?;
+
     msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
     msg!("Collected fee: {}", fee_amount);
     msg!("Amount out: {}", transfer_amount);
@@ -207,18 +345,6 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         PerpetualsError::CustodyAmountLimit
     );
 
-    // transfer tokens
-    msg!("Transfer tokens");
-    perpetuals.transfer_tokens(
-        ctx.accounts
-            .collateral_custody_token_account
-            .to_account_info(),
-        ctx.accounts.receiving_account.to_account_info(),
-        ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
-        transfer_amount,
-    )?;
-
     // update custody stats
     msg!("Update custody stats");
     collateral_custody.collected_fees.close_position_usd = collateral_custody
@@ -240,7 +366,10 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         position.collateral_amount,
     )?;
 
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    let protocol_fee = Pool::get_fee_amount(
+        custody.fees.protocol_share,
+        math::checked_sub(fee_amount, rebate)?,
+    )?;
 
     // Pay protocol_fee from custody if possible, otherwise no protocol_fee
     if pool.check_available_amount(protocol_fee, collateral_custody)? {
@@ -251,6 +380,120 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
             math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
     }
 
+    // Same for the referral rebate, volume is attributed either way
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        let rebate = if pool.check_available_amount(rebate, collateral_custody)? {
+            collateral_custody.assets.referral_rebates =
+                math::checked_add(collateral_custody.assets.referral_rebates, rebate)?;
+
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, rebate)?;
+            rebate
+        } else {
+            0
+        };
+        msg!("Accrue referral rebate: {}", rebate);
+        referral.accrue(collateral_custody.key(), rebate, position.size_usd)?;
+    }
+
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(position.size_usd, curtime)?;
+    }
+
+    // swap the payout into receive_custody tokens, they stay in the pool as a deposit
+    let (dispensing_token_account, amount_out) =
+        if let Some(receive_custody) = ctx.accounts.receive_custody.as_mut() {
+            msg!("Swap payout");
+            let receive_custody_key = receive_custody.key();
+            let receive_custody_oracle_account = ctx
+                .accounts
+                .receive_custody_oracle_account
+                .as_ref()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+            let received_token_price = OraclePrice::new_from_oracle(
+                receive_custody_oracle_account,
+                ctx.accounts.receive_custody_twap_account.as_ref(),
+                None,
+                &receive_custody.oracle,
+                curtime,
+                false,
+                receive_custody.oracle.feed_id,
+            )?;
+
+            let received_token_ema_price = OraclePrice::new_from_oracle(
+                receive_custody_oracle_account,
+                ctx.accounts.receive_custody_twap_account.as_ref(),
+                None,
+                &receive_custody.oracle,
+                curtime,
+                receive_custody.pricing.use_ema,
+                receive_custody.oracle.feed_id,
+            )?;
+
+            // for a short position receiving the position token, custody is the dispensing custody
+            let dispensing_custody: &mut Custody = if receive_custody_key == custody.key() {
+                custody
+            } else {
+                receive_custody
+            };
+
+            let amount_out = pool.swap_internal(
+                pool.get_token_id(&collateral_custody.key())?,
+                pool.get_token_id(&receive_custody_key)?,
+                transfer_amount,
+                params.min_amount_out,
+                collateral_custody,
+                &collateral_token_price,
+                &collateral_token_ema_price,
+                dispensing_custody,
+                &received_token_price,
+                &received_token_ema_price,
+                curtime,
+            )?;
+
+            // the position custody is re-priced once the position is removed
+            if receive_custody_key != custody.key() {
+                pool.update_cached_custody_aum(
+                    receive_custody,
+                    &received_token_price,
+                    &received_token_ema_price,
+                    curtime,
+                )?;
+            }
+
+            (
+                ctx.accounts
+                    .receive_custody_token_account
+                    .as_ref()
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?
+                    .to_account_info(),
+                amount_out,
+            )
+        } else {
+            require_gte!(
+                transfer_amount,
+                params.min_amount_out,
+                PerpetualsError::InsufficientAmountReturned
+            );
+            (
+                ctx.accounts
+                    .collateral_custody_token_account
+                    .to_account_info(),
+                transfer_amount,
+            )
+        };
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        dispensing_token_account,
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount_out,
+    )?;
+
     // if custody and collateral_custody accounts are the same, ensure that data is in sync
     if position.side == Side::Long && !custody.is_virtual {
         collateral_custody.volume_stats.close_position_usd = collateral_custody
@@ -307,5 +550,21 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         collateral_custody.update_borrow_rate(curtime)?;
     }
 
+    // if custody and receive_custody accounts are the same, ensure that data is in sync
+    if let Some(receive_custody) = ctx.accounts.receive_custody.as_mut() {
+        if receive_custody.key() == custody.key() {
+            **receive_custody = custody.clone();
+        }
+    }
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+
     Ok(())
 }
@@ -0,0 +1,286 @@
+//! ExecuteSwapOrder instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            swap_order::SwapOrder,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+#[instruction(params: ExecuteSwapOrderParams)]
+pub struct ExecuteSwapOrder<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: order owner, receives the order accounts rent
+    #[account(
+        mut,
+        constraint = owner.key() == swap_order.owner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == dispensing_custody.mint,
+        constraint = receiving_account.owner == swap_order.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = receiving_custody,
+        has_one = dispensing_custody,
+        seeds = [b"swap_order",
+                 swap_order.owner.as_ref(),
+                 pool.key().as_ref(),
+                 &swap_order.order_id.to_le_bytes()],
+        bump = swap_order.bump,
+        close = owner
+    )]
+    pub swap_order: Box<Account<'info, SwapOrder>>,
+
+    #[account(
+        mut,
+        seeds = [b"swap_order_token_account",
+                 swap_order.key().as_ref()],
+        bump = swap_order.token_account_bump
+    )]
+    pub swap_order_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          receiving_custody.mint.as_ref()],
+        // bump = receiving_custody.bump
+    )]
+    pub receiving_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = receiving_custody_oracle_account.key() == receiving_custody.oracle.oracle_account
+    // )]
+    pub receiving_custody_oracle_account: Account<'info, PriceUpdateV2>,
+
+    pub receiving_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    /// CHECK: optional reference price, checked against receiving_custody.oracle.reference_oracle_account
+    pub receiving_custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          receiving_custody.mint.as_ref()],
+        // bump = receiving_custody.token_account_bump
+    )]
+    pub receiving_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          dispensing_custody.mint.as_ref()],
+        // bump = dispensing_custody.bump
+    )]
+    pub dispensing_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = dispensing_custody_oracle_account.key() == dispensing_custody.oracle.oracle_account
+    // )]
+    pub dispensing_custody_oracle_account: Account<'info, PriceUpdateV2>,
+
+    pub dispensing_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    /// CHECK: optional reference price, checked against dispensing_custody.oracle.reference_oracle_account
+    pub dispensing_custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          dispensing_custody.mint.as_ref()],
+        // bump = dispensing_custody.token_account_bump
+    )]
+    pub dispensing_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteSwapOrderParams {
+    // pub feed_id: [u8; 32],
+}
+
+pub fn execute_swap_order(
+    ctx: Context<ExecuteSwapOrder>,
+    _params: &ExecuteSwapOrderParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let receiving_custody = ctx.accounts.receiving_custody.as_mut();
+    let dispensing_custody = ctx.accounts.dispensing_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_swap,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let swap_order = ctx.accounts.swap_order.as_ref();
+    let curtime = perpetuals.get_time()?;
+    require!(
+        !swap_order.is_expired(curtime),
+        PerpetualsError::SwapOrderExpired
+    );
+
+    // compute token amount returned to the user
+    let pool = ctx.accounts.pool.as_mut();
+    let token_id_in = pool.get_token_id(&receiving_custody.key())?;
+    let token_id_out = pool.get_token_id(&dispensing_custody.key())?;
+
+    let received_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.receiving_custody_oracle_account,
+        ctx.accounts.receiving_custody_twap_account.as_ref(),
+        receiving_custody.oracle.get_reference_account(
+            ctx.accounts
+                .receiving_custody_reference_oracle_account
+                .as_deref(),
+            false,
+        )?,
+        &receiving_custody.oracle,
+        curtime,
+        false,
+        receiving_custody.oracle.feed_id,
+    )?;
+
+    let received_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.receiving_custody_oracle_account,
+        ctx.accounts.receiving_custody_twap_account.as_ref(),
+        None,
+        &receiving_custody.oracle,
+        curtime,
+        receiving_custody.pricing.use_ema,
+        receiving_custody.oracle.feed_id,
+    )?;
+
+    let dispensed_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.dispensing_custody_oracle_account,
+        ctx.accounts.dispensing_custody_twap_account.as_ref(),
+        dispensing_custody.oracle.get_reference_account(
+            ctx.accounts
+                .dispensing_custody_reference_oracle_account
+                .as_deref(),
+            false,
+        )?,
+        &dispensing_custody.oracle,
+        curtime,
+        false,
+        dispensing_custody.oracle.feed_id,
+    )?;
+
+    let dispensed_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.dispensing_custody_oracle_account,
+        ctx.accounts.dispensing_custody_twap_account.as_ref(),
+        None,
+        &dispensing_custody.oracle,
+        curtime,
+        dispensing_custody.pricing.use_ema,
+        dispensing_custody.oracle.feed_id,
+    )?;
+
+    // the order can only be filled once the limit price is reached
+    msg!("Compute swap amount");
+    let min_amount_out =
+        swap_order.get_min_amount_out(receiving_custody.decimals, dispensing_custody.decimals)?;
+    msg!("Min amount out: {}", min_amount_out);
+
+    let no_fee_amount = pool.swap_internal(
+        token_id_in,
+        token_id_out,
+        swap_order.amount_in,
+        min_amount_out,
+        receiving_custody,
+        &received_token_price,
+        &received_token_ema_price,
+        dispensing_custody,
+        &dispensed_token_price,
+        &dispensed_token_ema_price,
+        curtime,
+    )?;
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts.swap_order_token_account.to_account_info(),
+        ctx.accounts
+            .receiving_custody_token_account
+            .to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        swap_order.amount_in,
+    )?;
+
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .dispensing_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        no_fee_amount,
+    )?;
+
+    Perpetuals::close_token_account(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.swap_order_token_account.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        &[&[b"transfer_authority", &[perpetuals.transfer_authority_bump]]],
+    )?;
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(
+        receiving_custody,
+        &received_token_price,
+        &received_token_ema_price,
+        curtime,
+    )?;
+    pool.update_cached_custody_aum(
+        dispensing_custody,
+        &dispensed_token_price,
+        &dispensed_token_ema_price,
+        curtime,
+    )?;
+
+    Ok(())
+}
@@ -77,6 +77,7 @@ pub fn get_add_liquidity_amount_and_fee(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -86,6 +87,7 @@ pub fn get_add_liquidity_amount_and_fee(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -96,12 +98,18 @@ pub fn get_add_liquidity_amount_and_fee(
         pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_price)?;
     let no_fee_amount = math::checked_sub(params.amount_in, fee_amount)?;
 
-    let pool_amount_usd = pool.get_assets_under_management_usd(
-        AumCalcMode::Max,
-        ctx.remaining_accounts,
-        curtime,
-        // custody.oracle.feed_id,
-    )?;
+    let pool_amount_usd = if let Some(aum_usd) =
+        pool.get_cached_aum_usd(AumCalcMode::Max, ctx.remaining_accounts, curtime)?
+    {
+        aum_usd
+    } else {
+        pool.get_assets_under_management_usd(
+            AumCalcMode::Max,
+            ctx.remaining_accounts,
+            curtime,
+            // custody.oracle.feed_id,
+        )?
+    };
 
     let min_price = if token_price < token_ema_price {
         token_price
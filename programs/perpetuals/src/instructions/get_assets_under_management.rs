@@ -26,6 +26,7 @@ pub struct GetAssetsUnderManagement<'info> {
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   both can be omitted while pool.aum_cache is fresh
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -37,10 +38,19 @@ pub fn get_assets_under_management(
     ctx: Context<GetAssetsUnderManagement>,
     _params: &GetAssetsUnderManagementParams,
 ) -> Result<u128> {
-    ctx.accounts.pool.get_assets_under_management_usd(
-        AumCalcMode::EMA,
-        ctx.remaining_accounts,
-        ctx.accounts.perpetuals.get_time()?,
-        // _params.feed_id,
-    )
+    let pool = &ctx.accounts.pool;
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    if let Some(aum_usd) =
+        pool.get_cached_aum_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?
+    {
+        Ok(aum_usd)
+    } else {
+        pool.get_assets_under_management_usd(
+            AumCalcMode::EMA,
+            ctx.remaining_accounts,
+            curtime,
+            // _params.feed_id,
+        )
+    }
 }
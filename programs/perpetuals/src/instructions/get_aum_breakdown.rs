@@ -0,0 +1,84 @@
+//! GetAumBreakdown instruction handler
+
+use {
+    crate::{
+        math,
+        state::{
+            perpetuals::{AumBreakdown, Perpetuals},
+            pool::{AumCalcMode, Pool},
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::Mint,
+};
+
+#[derive(Accounts)]
+pub struct GetAumBreakdown<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"lp_token_mint",
+                 pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   both can be omitted while pool.aum_cache is fresh
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetAumBreakdownParams {}
+
+pub fn get_aum_breakdown(
+    ctx: Context<GetAumBreakdown>,
+    _params: &GetAumBreakdownParams,
+) -> Result<AumBreakdown> {
+    let pool = &ctx.accounts.pool;
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    let (aum_usd, custodies) = if let Some(aum_usd) =
+        pool.get_cached_aum_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?
+    {
+        (aum_usd, pool.aum_cache.clone())
+    } else {
+        let custodies =
+            pool.get_custodies_aum(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+        let mut aum_usd: u128 = 0;
+        for custody_aum in &custodies {
+            aum_usd = math::checked_add(aum_usd, custody_aum.get_aum_usd()?)?;
+        }
+        (aum_usd, custodies)
+    };
+
+    let lp_supply = ctx.accounts.lp_token_mint.supply;
+    let lp_token_price = if lp_supply == 0 {
+        0
+    } else {
+        math::checked_decimal_div(
+            math::checked_as_u64(aum_usd)?,
+            -(Perpetuals::USD_DECIMALS as i32),
+            lp_supply,
+            -(Perpetuals::LP_DECIMALS as i32),
+            -(Perpetuals::USD_DECIMALS as i32),
+        )?
+    };
+
+    Ok(AumBreakdown {
+        aum_usd,
+        lp_token_price,
+        custodies,
+    })
+}
@@ -7,6 +7,7 @@ use {
         perpetuals::{NewPositionPricesAndFee, Perpetuals},
         pool::Pool,
         position::{Position, Side},
+        trader_stats::TraderStats,
     },
     anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
     pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
@@ -53,13 +54,16 @@ pub struct GetEntryPriceAndFee<'info> {
     pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
 
     pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    // optional trader volume stats, the quote includes the fee tier discount
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct GetEntryPriceAndFeeParams {
-    collateral: u64,
-    size: u64,
-    side: Side,
+    pub collateral: u64,
+    pub size: u64,
+    pub side: Side,
     // feed_id: [u8; 32],
 }
 
@@ -81,6 +85,7 @@ pub fn get_entry_price_and_fee(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -90,6 +95,7 @@ pub fn get_entry_price_and_fee(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -99,6 +105,7 @@ pub fn get_entry_price_and_fee(
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &collateral_custody.oracle,
         curtime,
         false,
@@ -108,21 +115,32 @@ pub fn get_entry_price_and_fee(
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &collateral_custody.oracle,
         curtime,
-        false,
+        collateral_custody.pricing.use_ema,
         collateral_custody.oracle.feed_id,
     )?;
 
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    let entry_price = pool.get_entry_price(&token_price, &token_ema_price, params.side, custody)?;
+    let entry_price = pool.get_entry_price(
+        &token_price,
+        &token_ema_price,
+        params.side,
+        params.size,
+        custody,
+    )?;
+    let spread = pool.get_trade_spread(&token_price, &token_ema_price, params.side, custody)?;
+    let price_impact = pool.get_price_impact(
+        &token_ema_price,
+        token_ema_price.get_asset_amount_usd(params.size, custody.decimals)?,
+        custody.get_open_interest_usd(params.side),
+        custody,
+    )?;
 
-    let position_oracle_price = OraclePrice {
-        price: entry_price,
-        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
-    };
+    let position_oracle_price = OraclePrice::new(entry_price, -(Perpetuals::PRICE_DECIMALS as i32));
     let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
     let collateral_usd = min_collateral_price
         .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
@@ -153,8 +171,16 @@ pub fn get_entry_price_and_fee(
         curtime,
     )?;
 
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        ctx.accounts
+            .perpetuals
+            .get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
     let mut fee = pool.get_entry_fee(
         custody.fees.open_position,
+        fee_discount,
         params.size,
         locked_amount,
         collateral_custody,
@@ -170,5 +196,7 @@ pub fn get_entry_price_and_fee(
         entry_price,
         liquidation_price,
         fee,
+        spread,
+        price_impact,
     })
 }
@@ -7,6 +7,7 @@ use {
         perpetuals::{Perpetuals, PriceAndFee},
         pool::Pool,
         position::{Position, Side},
+        trader_stats::TraderStats,
     },
     anchor_lang::prelude::*,
     pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
@@ -32,7 +33,8 @@ pub struct GetExitPriceAndFee<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -64,6 +66,12 @@ pub struct GetExitPriceAndFee<'info> {
     // )]
     pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
     pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    // optional trader volume stats, the quote includes the fee tier discount
+    #[account(
+        constraint = trader_stats.owner == position.owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -85,6 +93,7 @@ pub fn get_exit_price_and_fee(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -94,6 +103,7 @@ pub fn get_exit_price_and_fee(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -103,17 +113,31 @@ pub fn get_exit_price_and_fee(
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
         collateral_custody.oracle.feed_id,
     )?;
 
-    let price = pool.get_exit_price(&token_price, &token_ema_price, position.side, custody)?;
+    let price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        position.size_usd,
+        custody,
+    )?;
 
     let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
 
-    let mut fee = pool.get_exit_fee(size, custody)?;
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        ctx.accounts
+            .perpetuals
+            .get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
+    let mut fee = pool.get_exit_fee(size, fee_discount, custody)?;
 
     if position.side == Side::Short || custody.is_virtual {
         let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee, custody.decimals)?;
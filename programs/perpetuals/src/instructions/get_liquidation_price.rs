@@ -32,7 +32,8 @@ pub struct GetLiquidationPrice<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -82,6 +83,7 @@ pub fn get_liquidation_price(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -91,6 +93,7 @@ pub fn get_liquidation_price(
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -100,6 +103,7 @@ pub fn get_liquidation_price(
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
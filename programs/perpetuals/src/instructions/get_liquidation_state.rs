@@ -29,7 +29,8 @@ pub struct GetLiquidationState<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -76,6 +77,7 @@ pub fn get_liquidation_state(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -85,6 +87,7 @@ pub fn get_liquidation_state(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -94,6 +97,7 @@ pub fn get_liquidation_state(
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -103,6 +107,7 @@ pub fn get_liquidation_state(
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -37,6 +37,7 @@ pub struct GetLpTokenPrice<'info> {
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   both can be omitted while pool.aum_cache is fresh
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -48,12 +49,23 @@ pub fn get_lp_token_price(
     ctx: Context<GetLpTokenPrice>,
     _params: &GetLpTokenPriceParams,
 ) -> Result<u64> {
-    let aum_usd = math::checked_as_u64(ctx.accounts.pool.get_assets_under_management_usd(
-        AumCalcMode::EMA,
-        ctx.remaining_accounts,
-        ctx.accounts.perpetuals.get_time()?,
-        // _params.feed_id,
-    )?)?;
+    let pool = &ctx.accounts.pool;
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    let aum_usd = math::checked_as_u64(
+        if let Some(aum_usd) =
+            pool.get_cached_aum_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?
+        {
+            aum_usd
+        } else {
+            pool.get_assets_under_management_usd(
+                AumCalcMode::EMA,
+                ctx.remaining_accounts,
+                curtime,
+                // _params.feed_id,
+            )?
+        },
+    )?;
 
     msg!("aum_usd: {}", aum_usd);
 
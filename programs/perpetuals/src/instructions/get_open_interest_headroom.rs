@@ -0,0 +1,53 @@
+//! GetOpenInterestHeadroom instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        perpetuals::{OpenInterestHeadroom, Perpetuals},
+        pool::Pool,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct GetOpenInterestHeadroom<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetOpenInterestHeadroomParams {}
+
+pub fn get_open_interest_headroom(
+    ctx: Context<GetOpenInterestHeadroom>,
+    _params: &GetOpenInterestHeadroomParams,
+) -> Result<OpenInterestHeadroom> {
+    let custody = &ctx.accounts.custody;
+
+    let (long_headroom_usd, short_headroom_usd) = custody.get_open_interest_headroom()?;
+
+    Ok(OpenInterestHeadroom {
+        oi_long_usd: custody.trade_stats.oi_long_usd,
+        oi_short_usd: custody.trade_stats.oi_short_usd,
+        long_headroom_usd,
+        short_headroom_usd,
+    })
+}
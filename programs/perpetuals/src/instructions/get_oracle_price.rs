@@ -52,6 +52,7 @@ pub fn get_oracle_price(
     let price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         params.ema,
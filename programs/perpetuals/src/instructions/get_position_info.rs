@@ -0,0 +1,250 @@
+//! GetPositionInfo instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::{Perpetuals, PositionInfo},
+            pool::Pool,
+            position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+        },
+    },
+    anchor_lang::prelude::*,
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+pub struct GetPositionInfo<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"position",
+                 position.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    // )]
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    // )]
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    // optional referrer and trader volume stats, the same fee discounts close_position applies
+    #[account(
+        constraint = referral.owner != position.owner @ PerpetualsError::InvalidReferral
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    #[account(
+        constraint = trader_stats.owner == position.owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetPositionInfoParams {
+    // feed_id: [u8; 32],
+}
+
+pub fn get_position_info(
+    ctx: Context<GetPositionInfo>,
+    _params: &GetPositionInfoParams,
+) -> Result<PositionInfo> {
+    let position = &ctx.accounts.position;
+    let pool = &ctx.accounts.pool;
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    let custody = &ctx.accounts.custody;
+    let collateral_custody = &ctx.accounts.collateral_custody;
+
+    // read oracle prices once, every metric below is derived from the same snapshot
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    // compute exit price and fee
+    let exit_price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        position.size_usd,
+        custody,
+    )?;
+
+    let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
+
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        ctx.accounts
+            .perpetuals
+            .get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
+    let mut exit_fee = pool.get_exit_fee(size, fee_discount, custody)?;
+    let fee_amount_usd = token_ema_price.get_asset_amount_usd(exit_fee, custody.decimals)?;
+
+    if position.side == Side::Short || custody.is_virtual {
+        exit_fee = collateral_token_ema_price
+            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+    }
+
+    // the referral discount is taken off the fee and paid back with the close amount
+    let (referral_discount, referral_discount_usd) = if ctx.accounts.referral.is_some() {
+        (
+            custody.fees.get_referral_split(exit_fee)?.0,
+            custody.fees.get_referral_split(fee_amount_usd)?.0,
+        )
+    } else {
+        (0, 0)
+    };
+    exit_fee = math::checked_sub(exit_fee, referral_discount)?;
+
+    // compute pnl and interest
+    let (mut profit_usd, mut loss_usd, _) = pool.get_exit_pnl_usd(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        false,
+        fee_discount,
+    )?;
+    if loss_usd >= referral_discount_usd {
+        loss_usd -= referral_discount_usd;
+    } else {
+        profit_usd = math::checked_add(profit_usd, referral_discount_usd - loss_usd)?;
+        loss_usd = 0;
+    }
+
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+
+    // compute leverage and liquidation state
+    let leverage = pool.get_leverage(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+    )?;
+
+    let liquidation_state = if pool.check_leverage(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        false,
+    )? {
+        0
+    } else {
+        1
+    };
+
+    let liquidation_price = pool.get_liquidation_price(
+        position,
+        &token_ema_price,
+        custody,
+        collateral_custody,
+        curtime,
+    )?;
+
+    Ok(PositionInfo {
+        entry_price: position.price,
+        exit_price,
+        size_usd: position.size_usd,
+        collateral_usd: position.collateral_usd,
+        profit_usd,
+        loss_usd,
+        exit_fee,
+        interest_usd,
+        leverage,
+        liquidation_price,
+        liquidation_state,
+        take_profit_price: position.take_profit_price,
+        stop_loss_price: position.stop_loss_price,
+    })
+}
@@ -0,0 +1,170 @@
+//! GetRemoveCollateralInfo instruction handler
+
+use {
+    crate::{
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::{Perpetuals, RemoveCollateralInfo},
+            pool::Pool,
+            position::Position,
+        },
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+pub struct GetRemoveCollateralInfo<'info> {
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"position",
+                 position.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    // )]
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    // )]
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account:
+        Option<Account<'info, pyth_solana_receiver_sdk::price_update::TwapUpdate>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GetRemoveCollateralInfoParams {
+    pub collateral: u64,
+    // feed_id: [u8; 32],
+}
+
+pub fn get_remove_collateral_info(
+    ctx: Context<GetRemoveCollateralInfo>,
+    params: &GetRemoveCollateralInfoParams,
+) -> Result<RemoveCollateralInfo> {
+    let custody = &ctx.accounts.custody;
+    let collateral_custody = &ctx.accounts.collateral_custody;
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
+        collateral_token_price
+    } else {
+        collateral_token_ema_price
+    };
+
+    // apply the withdrawal the same way remove_collateral_amount does
+    let mut position = ctx.accounts.position.clone();
+    position.update_time = curtime;
+
+    let collateral_usd = max_collateral_price
+        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+    if collateral_usd >= position.collateral_usd || params.collateral >= position.collateral_amount
+    {
+        return Err(ProgramError::InsufficientFunds.into());
+    }
+    position.collateral_usd = math::checked_sub(position.collateral_usd, collateral_usd)?;
+    position.collateral_amount = math::checked_sub(position.collateral_amount, params.collateral)?;
+
+    let pool = &ctx.accounts.pool;
+
+    let leverage = pool.get_leverage(
+        &position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+    )?;
+
+    let liquidation_price = pool.get_liquidation_price(
+        &position,
+        &token_ema_price,
+        custody,
+        collateral_custody,
+        curtime,
+    )?;
+
+    Ok(RemoveCollateralInfo {
+        collateral_usd,
+        leverage,
+        liquidation_price,
+    })
+}
@@ -76,6 +76,7 @@ pub fn get_remove_liquidity_amount_and_fee(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -85,18 +86,25 @@ pub fn get_remove_liquidity_amount_and_fee(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
         custody.oracle.feed_id,
     )?;
 
-    let pool_amount_usd = pool.get_assets_under_management_usd(
-        AumCalcMode::Min,
-        ctx.remaining_accounts,
-        curtime,
-        // params.feed_id,
-    )?;
+    let pool_amount_usd = if let Some(aum_usd) =
+        pool.get_cached_aum_usd(AumCalcMode::Min, ctx.remaining_accounts, curtime)?
+    {
+        aum_usd
+    } else {
+        pool.get_assets_under_management_usd(
+            AumCalcMode::Min,
+            ctx.remaining_accounts,
+            curtime,
+            // params.feed_id,
+        )?
+    };
 
     let remove_amount_usd = math::checked_as_u64(math::checked_div(
         math::checked_mul(pool_amount_usd, params.lp_amount_in as u128)?,
@@ -6,6 +6,7 @@ use {
         oracle::OraclePrice,
         perpetuals::{Perpetuals, SwapAmountAndFees},
         pool::Pool,
+        trader_stats::TraderStats,
     },
     anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
     pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
@@ -53,6 +54,9 @@ pub struct GetSwapAmountAndFees<'info> {
     // )]
     pub dispensing_custody_oracle_account: Account<'info, PriceUpdateV2>,
     pub dispensing_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    // optional trader volume stats, the quote includes the fee tier discount
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -86,6 +90,7 @@ pub fn get_swap_amount_and_fees(
     let received_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.receiving_custody_oracle_account,
         ctx.accounts.receiving_custody_twap_account.as_ref(),
+        None,
         &receiving_custody.oracle,
         curtime,
         false,
@@ -95,6 +100,7 @@ pub fn get_swap_amount_and_fees(
     let received_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.receiving_custody_oracle_account,
         ctx.accounts.receiving_custody_twap_account.as_ref(),
+        None,
         &receiving_custody.oracle,
         curtime,
         receiving_custody.pricing.use_ema,
@@ -104,6 +110,7 @@ pub fn get_swap_amount_and_fees(
     let dispensed_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.dispensing_custody_oracle_account,
         ctx.accounts.dispensing_custody_twap_account.as_ref(),
+        None,
         &dispensing_custody.oracle,
         curtime,
         false,
@@ -113,9 +120,10 @@ pub fn get_swap_amount_and_fees(
     let dispensed_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.dispensing_custody_oracle_account,
         ctx.accounts.dispensing_custody_twap_account.as_ref(),
+        None,
         &dispensing_custody.oracle,
         curtime,
-        false,
+        dispensing_custody.pricing.use_ema,
         dispensing_custody.oracle.feed_id,
     )?;
 
@@ -130,6 +138,13 @@ pub fn get_swap_amount_and_fees(
     )?;
 
     // calculate fee
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        ctx.accounts
+            .perpetuals
+            .get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
     let fees = pool.get_swap_fees(
         token_id_in,
         token_id_out,
@@ -139,11 +154,21 @@ pub fn get_swap_amount_and_fees(
         &received_token_price,
         dispensing_custody,
         &dispensed_token_price,
+        fee_discount,
+    )?;
+
+    let spread = pool.get_swap_spread(
+        &received_token_price,
+        &received_token_ema_price,
+        &dispensed_token_price,
+        &dispensed_token_ema_price,
+        receiving_custody,
     )?;
 
     Ok(SwapAmountAndFees {
         amount_out,
         fee_in: fees.0,
         fee_out: fees.1,
+        spread,
     })
 }
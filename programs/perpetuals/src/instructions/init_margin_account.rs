@@ -0,0 +1,79 @@
+//! InitMarginAccount instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody, margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: InitMarginAccountParams)]
+pub struct InitMarginAccount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = MarginAccount::LEN,
+        seeds = [b"margin_account",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    // custody holding the margin account deposit
+    #[account(
+        constraint = collateral_custody.pool == pool.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitMarginAccountParams {}
+
+pub fn init_margin_account(
+    ctx: Context<InitMarginAccount>,
+    _params: &InitMarginAccountParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    let collateral_custody = ctx.accounts.collateral_custody.as_ref();
+    require!(
+        !collateral_custody.is_virtual,
+        PerpetualsError::InvalidCollateralCustody
+    );
+
+    // init margin account
+    msg!("Initialize margin account");
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    margin_account.owner = ctx.accounts.owner.key();
+    margin_account.pool = ctx.accounts.pool.key();
+    margin_account.collateral_custody = collateral_custody.key();
+    margin_account.collateral_amount = 0;
+    margin_account.positions = Vec::new();
+    margin_account.bump = ctx.bumps.margin_account;
+    margin_account.custodies = vec![collateral_custody.key()];
+
+    Ok(())
+}
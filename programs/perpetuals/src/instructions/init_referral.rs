@@ -0,0 +1,46 @@
+//! InitReferral instruction handler
+
+use {
+    crate::state::{perpetuals::Perpetuals, referral::Referral},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: InitReferralParams)]
+pub struct InitReferral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Referral::LEN,
+        seeds = [b"referral",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub referral: Box<Account<'info, Referral>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitReferralParams {}
+
+pub fn init_referral(ctx: Context<InitReferral>, _params: &InitReferralParams) -> Result<()> {
+    // init referral
+    msg!("Initialize referral");
+    let referral = ctx.accounts.referral.as_mut();
+    referral.owner = ctx.accounts.owner.key();
+    referral.volume_usd = 0;
+    referral.rebates = Vec::new();
+    referral.bump = ctx.bumps.referral;
+
+    Ok(())
+}
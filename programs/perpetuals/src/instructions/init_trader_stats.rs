@@ -0,0 +1,49 @@
+//! InitTraderStats instruction handler
+
+use {
+    crate::state::{perpetuals::Perpetuals, trader_stats::TraderStats},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: InitTraderStatsParams)]
+pub struct InitTraderStats<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = TraderStats::LEN,
+        seeds = [b"trader_stats",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub trader_stats: Box<Account<'info, TraderStats>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitTraderStatsParams {}
+
+pub fn init_trader_stats(
+    ctx: Context<InitTraderStats>,
+    _params: &InitTraderStatsParams,
+) -> Result<()> {
+    // init trader stats
+    msg!("Initialize trader stats");
+    let trader_stats = ctx.accounts.trader_stats.as_mut();
+    trader_stats.owner = ctx.accounts.owner.key();
+    trader_stats.daily_volume_usd = [0; 30];
+    trader_stats.last_trade_day = 0;
+    trader_stats.bump = ctx.bumps.trader_stats;
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! InitUserPositions instruction handler
+
+use {
+    crate::state::{perpetuals::Perpetuals, user_positions::UserPositions},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: InitUserPositionsParams)]
+pub struct InitUserPositions<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = UserPositions::LEN,
+        seeds = [b"user_positions",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub user_positions: Box<Account<'info, UserPositions>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitUserPositionsParams {}
+
+pub fn init_user_positions(
+    ctx: Context<InitUserPositions>,
+    _params: &InitUserPositionsParams,
+) -> Result<()> {
+    // init user positions index
+    msg!("Initialize user positions index");
+    let user_positions = ctx.accounts.user_positions.as_mut();
+    user_positions.owner = ctx.accounts.owner.key();
+    user_positions.positions = Vec::new();
+    user_positions.bump = ctx.bumps.user_positions;
+
+    Ok(())
+}
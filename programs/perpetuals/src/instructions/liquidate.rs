@@ -10,6 +10,7 @@ use {
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            user_positions::UserPositions,
         },
     },
     anchor_lang::prelude::*,
@@ -64,7 +65,8 @@ pub struct Liquidate<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump,
         close = signer
     )]
@@ -79,6 +81,9 @@ pub struct Liquidate<'info> {
     pub custody_oracle_account: Account<'info, PriceUpdateV2>,
     pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         constraint = position.collateral_custody == collateral_custody.key()
@@ -97,6 +102,13 @@ pub struct Liquidate<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // optional index of the owner's positions
+    #[account(
+        mut,
+        constraint = user_positions.owner == position.owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
     token_program: Program<'info, Token>,
 }
 
@@ -116,7 +128,17 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         PerpetualsError::InstructionNotAllowed
     );
 
+    // validate inputs
+    msg!("Validate inputs");
+    // keep the owner's position index up to date
+    if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+        user_positions.remove_position(&ctx.accounts.position.key());
+    }
     let position = ctx.accounts.position.as_mut();
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
     let pool = ctx.accounts.pool.as_mut();
 
     // check if position can be liquidated
@@ -126,6 +148,10 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            true,
+        )?,
         &custody.oracle,
         curtime,
         false,
@@ -135,6 +161,7 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -144,18 +171,20 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
         false,
         collateral_custody.oracle.feed_id,
     )?;
 
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account,
+        &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
-        custody.pricing.use_ema,
+        collateral_custody.pricing.use_ema,
         collateral_custody.oracle.feed_id,
     )?;
 
@@ -185,6 +214,7 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         collateral_custody,
         curtime,
         true,
+        0,
     )?;
 
     let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
@@ -320,5 +350,14 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         collateral_custody.update_borrow_rate(curtime)?;
     }
 
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+
     Ok(())
 }
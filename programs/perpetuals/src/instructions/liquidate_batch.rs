@@ -0,0 +1,418 @@
+//! LiquidateBatch instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            user_positions::UserPositions,
+        },
+        try_from,
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::token::{Token, TokenAccount},
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+#[instruction(params: LiquidateBatchParams)]
+pub struct LiquidateBatch<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = rewards_receiving_account.mint == collateral_custody.mint,
+        constraint = rewards_receiving_account.owner == signer.key()
+    )]
+    pub rewards_receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          collateral_custody.mint.as_ref()],
+        // bump = collateral_custody.bump
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          collateral_custody.mint.as_ref()],
+        // bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   (position account, owner's receiving token account) pairs, all opened
+    //   against the custody / collateral_custody pair above (write)
+    //   optionally followed by the owners' UserPositions indexes (write)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LiquidateBatchParams {
+    // pub feed_id: [u8; 32],
+}
+
+pub fn liquidate_batch<'info>(
+    ctx: Context<'_, '_, '_, 'info, LiquidateBatch<'info>>,
+    _params: &LiquidateBatchParams,
+) -> Result<u64> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_close_position && custody.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let index_start = ctx
+        .remaining_accounts
+        .iter()
+        .position(UserPositions::is_user_positions)
+        .unwrap_or(ctx.remaining_accounts.len());
+    let (remaining_accounts, index_accounts) = ctx.remaining_accounts.split_at(index_start);
+    if remaining_accounts.is_empty() || remaining_accounts.len() % 2 != 0 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+    let mut user_positions = index_accounts
+        .iter()
+        .map(|info| try_from!(Account::<UserPositions>, info))
+        .collect::<Result<Vec<_>>>()?;
+    require_keys_eq!(
+        ctx.accounts.collateral_custody_token_account.key(),
+        collateral_custody.token_account
+    );
+
+    let pool = ctx.accounts.pool.as_mut();
+    let pool_key = pool.key();
+    let custody_key = custody.key();
+    let collateral_custody_key = collateral_custody.key();
+
+    // price once for the whole batch
+    msg!("Compute prices");
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            true,
+        )?,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let mut total_reward: u64 = 0;
+    let mut liquidated: u64 = 0;
+    let mut processed: Vec<Pubkey> = Vec::with_capacity(remaining_accounts.len() / 2);
+
+    for accounts in remaining_accounts.chunks(2) {
+        let position_info = &accounts[0];
+        let receiving_info = &accounts[1];
+
+        if processed.contains(position_info.key) {
+            msg!("Error: Duplicate position {}", position_info.key);
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        processed.push(*position_info.key);
+
+        let position = try_from!(Account::<Position>, position_info)?;
+        require_keys_eq!(position.pool, pool_key);
+        require_keys_eq!(position.custody, custody_key);
+        require_keys_eq!(
+            position.collateral_custody,
+            collateral_custody_key,
+            PerpetualsError::InvalidCollateralCustody
+        );
+
+        let receiving_account = try_from!(Account::<TokenAccount>, receiving_info)?;
+        require_keys_eq!(receiving_account.mint, collateral_custody.mint);
+        require_keys_eq!(receiving_account.owner, position.owner);
+
+        // skip positions backed by a margin account, these are liquidated as a whole
+        if position.margin_account.is_some() {
+            msg!("Skip margin account position {}", position_info.key);
+            continue;
+        }
+
+        // skip healthy positions
+        if pool.check_leverage(
+            &position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            false,
+        )? {
+            msg!("Skip healthy position {}", position_info.key);
+            continue;
+        }
+
+        msg!("Settle position {}", position_info.key);
+        let (total_amount_out, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+            &position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            true,
+            0,
+        )?;
+
+        let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+        if position.side == Side::Short || custody.is_virtual {
+            fee_amount = collateral_token_ema_price
+                .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+        }
+
+        msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+        msg!("Collected fee: {}", fee_amount);
+
+        let reward = Pool::get_fee_amount(custody.fees.liquidation, total_amount_out)?;
+        let user_amount = math::checked_sub(total_amount_out, reward)?;
+
+        msg!("Amount out: {}", user_amount);
+        msg!("Reward: {}", reward);
+
+        total_reward = math::checked_add(total_reward, reward)?;
+
+        // unlock pool funds
+        collateral_custody.unlock_funds(position.locked_amount)?;
+
+        // check pool constraints
+        require!(
+            pool.check_available_amount(total_amount_out, collateral_custody)?,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        // transfer tokens to the position owner
+        perpetuals.transfer_tokens(
+            ctx.accounts
+                .collateral_custody_token_account
+                .to_account_info(),
+            receiving_info.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            user_amount,
+        )?;
+
+        // update custody stats
+        collateral_custody.collected_fees.liquidation_usd = collateral_custody
+            .collected_fees
+            .liquidation_usd
+            .wrapping_add(fee_amount_usd);
+
+        if total_amount_out > position.collateral_amount {
+            let amount_lost = total_amount_out.saturating_sub(position.collateral_amount);
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
+        } else {
+            let amount_gained = position.collateral_amount.saturating_sub(total_amount_out);
+            collateral_custody.assets.owned =
+                math::checked_add(collateral_custody.assets.owned, amount_gained)?;
+        }
+        collateral_custody.assets.collateral = math::checked_sub(
+            collateral_custody.assets.collateral,
+            position.collateral_amount,
+        )?;
+
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+
+        // Pay protocol_fee from custody if possible, otherwise no protocol_fee
+        if pool.check_available_amount(protocol_fee, collateral_custody)? {
+            collateral_custody.assets.protocol_fees =
+                math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
+        }
+
+        // if custody and collateral_custody accounts are the same, ensure that data is in sync
+        if position.side == Side::Long && !custody.is_virtual {
+            collateral_custody.volume_stats.liquidation_usd = math::checked_add(
+                collateral_custody.volume_stats.liquidation_usd,
+                position.size_usd,
+            )?;
+
+            collateral_custody.trade_stats.oi_long_usd = collateral_custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(position.size_usd);
+
+            collateral_custody.trade_stats.profit_usd = collateral_custody
+                .trade_stats
+                .profit_usd
+                .wrapping_add(profit_usd);
+            collateral_custody.trade_stats.loss_usd = collateral_custody
+                .trade_stats
+                .loss_usd
+                .wrapping_add(loss_usd);
+
+            collateral_custody.remove_position(&position, curtime, None)?;
+            *custody = collateral_custody.clone();
+        } else {
+            custody.volume_stats.liquidation_usd =
+                math::checked_add(custody.volume_stats.liquidation_usd, position.size_usd)?;
+
+            if position.side == Side::Long {
+                custody.trade_stats.oi_long_usd = custody
+                    .trade_stats
+                    .oi_long_usd
+                    .saturating_sub(position.size_usd);
+            } else {
+                custody.trade_stats.oi_short_usd = custody
+                    .trade_stats
+                    .oi_short_usd
+                    .saturating_sub(position.size_usd);
+            }
+
+            custody.trade_stats.profit_usd =
+                custody.trade_stats.profit_usd.wrapping_add(profit_usd);
+            custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
+
+            custody.remove_position(&position, curtime, Some(collateral_custody))?;
+        }
+
+        // keep the owner's position index up to date
+        if let Some(index) = user_positions
+            .iter_mut()
+            .find(|index| index.owner == position.owner)
+        {
+            index.remove_position(position_info.key);
+        }
+
+        // close position account, rent goes to the liquidator
+        position.close(ctx.accounts.signer.to_account_info())?;
+
+        liquidated = math::checked_add(liquidated, 1)?;
+    }
+
+    msg!("Liquidated positions: {}", liquidated);
+    if liquidated == 0 {
+        return Ok(0);
+    }
+
+    for index in user_positions.iter() {
+        index.exit(&crate::ID)?;
+    }
+
+    // pay the summed reward once
+    msg!("Transfer reward: {}", total_reward);
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.rewards_receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        total_reward,
+    )?;
+
+    // update borrow rate once for the whole batch
+    collateral_custody.update_borrow_rate(curtime)?;
+    if custody_key == collateral_custody_key {
+        *custody = collateral_custody.clone();
+    }
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+
+    Ok(liquidated)
+}
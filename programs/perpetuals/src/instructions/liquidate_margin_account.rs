@@ -0,0 +1,213 @@
+//! LiquidateMarginAccount instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool,
+            user_positions::UserPositions,
+        },
+    },
+    anchor_lang::{prelude::*, AccountsClose},
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: LiquidateMarginAccountParams)]
+pub struct LiquidateMarginAccount<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = rewards_receiving_account.owner == signer.key()
+    )]
+    pub rewards_receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"margin_account",
+                 margin_account.owner.as_ref(),
+                 pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          margin_account.collateral_custody mint],
+        // bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    // optional index of the owner's positions
+    #[account(
+        mut,
+        constraint = user_positions.owner == margin_account.owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   margin_account.custodies.len() custody accounts (write, unsigned)
+    //   margin_account.custodies.len() custody oracles (read-only, unsigned)
+    //   margin_account.positions.len() position accounts (write, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LiquidateMarginAccountParams {
+    // pub feed_id: [u8; 32],
+}
+
+pub fn liquidate_margin_account<'info>(
+    ctx: Context<'_, '_, '_, 'info, LiquidateMarginAccount<'info>>,
+    _params: &LiquidateMarginAccountParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    require!(
+        perpetuals.permissions.allow_close_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    let pool = ctx.accounts.pool.as_mut();
+    let margin_account = ctx.accounts.margin_account.as_mut();
+
+    // check if margin account can be liquidated
+    msg!("Check margin account state");
+    let curtime = perpetuals.get_time()?;
+    let mut state = margin_account.load_state(pool, ctx.remaining_accounts, curtime)?;
+
+    let (equity_usd, _, maintenance_margin_usd) =
+        state.get_margin_usd(pool, margin_account, curtime)?;
+    msg!(
+        "Equity: {}, maintenance margin: {}",
+        equity_usd,
+        maintenance_margin_usd
+    );
+    require_gt!(
+        maintenance_margin_usd,
+        equity_usd,
+        PerpetualsError::InvalidPositionState
+    );
+
+    let deposit_idx = state.get_custody_idx(&margin_account.collateral_custody)?;
+    require_keys_eq!(
+        ctx.accounts.collateral_custody_token_account.key(),
+        state.custodies[deposit_idx].token_account
+    );
+    require_keys_eq!(
+        ctx.accounts.rewards_receiving_account.mint,
+        state.custodies[deposit_idx].mint
+    );
+
+    // settle every position into the margin account deposit, tokens stay in the pool
+    let mut credit_usd: u64 = 0;
+    let mut debit_usd: u64 = 0;
+
+    for idx in 0..state.positions.len() {
+        let (amount_out, shortfall_usd) = state.settle_position(pool, idx, curtime, true)?;
+
+        // amount owed to the owner, and the loss not covered by the position collateral
+        let collateral_idx = state.get_custody_idx(&state.positions[idx].collateral_custody)?;
+        let collateral_token_price = state.token_prices[collateral_idx];
+        let collateral_token_ema_price = state.token_ema_prices[collateral_idx];
+        let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
+            collateral_token_price
+        } else {
+            collateral_token_ema_price
+        };
+        credit_usd = math::checked_add(
+            credit_usd,
+            max_collateral_price
+                .get_asset_amount_usd(amount_out, state.custodies[collateral_idx].decimals)?,
+        )?;
+        debit_usd = math::checked_add(debit_usd, shortfall_usd)?;
+    }
+
+    // move the settled amount into the deposit, losses not covered by positions are paid first
+    msg!("Settle margin account deposit");
+    let deposit_custody = &mut state.custodies[deposit_idx];
+    let deposit_token_price = state.token_prices[deposit_idx];
+    let deposit_token_ema_price = state.token_ema_prices[deposit_idx];
+    let (min_deposit_price, max_deposit_price) = if deposit_token_price < deposit_token_ema_price {
+        (deposit_token_price, deposit_token_ema_price)
+    } else {
+        (deposit_token_ema_price, deposit_token_price)
+    };
+
+    let credit_amount = max_deposit_price.get_token_amount(credit_usd, deposit_custody.decimals)?;
+    let collateral_amount = math::checked_add(margin_account.collateral_amount, credit_amount)?;
+    let debit_amount = std::cmp::min(
+        min_deposit_price.get_token_amount(debit_usd, deposit_custody.decimals)?,
+        collateral_amount,
+    );
+    let collateral_amount = math::checked_sub(collateral_amount, debit_amount)?;
+    msg!("Credit: {}, debit: {}", credit_amount, debit_amount);
+
+    deposit_custody.assets.owned = math::checked_sub(
+        math::checked_add(deposit_custody.assets.owned, debit_amount)?,
+        credit_amount,
+    )?;
+
+    let reward = Pool::get_fee_amount(deposit_custody.fees.liquidation, collateral_amount)?;
+    msg!("Reward: {}", reward);
+
+    deposit_custody.assets.collateral = math::checked_sub(
+        math::checked_add(deposit_custody.assets.collateral, collateral_amount)?,
+        math::checked_add(margin_account.collateral_amount, reward)?,
+    )?;
+
+    margin_account.collateral_amount = math::checked_sub(collateral_amount, reward)?;
+    margin_account.positions.clear();
+    margin_account.refresh_custodies(&[]);
+
+    // pay the liquidator from the deposit
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.rewards_receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        reward,
+    )?;
+
+    // update borrow rates, cached valuations and save custodies
+    state.save_custodies(pool, curtime)?;
+
+    // close position accounts, rent goes to the liquidator
+    for position in state.positions.iter() {
+        if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+            user_positions.remove_position(&position.key());
+        }
+        position.close(ctx.accounts.signer.to_account_info())?;
+    }
+
+    Ok(())
+}
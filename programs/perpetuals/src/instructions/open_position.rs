@@ -10,6 +10,9 @@ use {
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            referral::Referral,
+            trader_stats::TraderStats,
+            user_positions::UserPositions,
         },
     },
     anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
@@ -25,7 +28,9 @@ pub struct OpenPosition<'info> {
 
     #[account(
         mut,
-        constraint = funding_account.mint == collateral_custody.mint,
+        constraint = funding_account.mint == funding_custody
+            .as_ref()
+            .map_or(collateral_custody.mint, |custody| custody.mint),
         has_one = owner
     )]
     pub funding_account: Box<Account<'info, TokenAccount>>,
@@ -59,7 +64,8 @@ pub struct OpenPosition<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[params.side as u8]],
+                 &[params.side as u8],
+                 Position::get_id_seed(&params.position_id)],
         bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -76,6 +82,9 @@ pub struct OpenPosition<'info> {
     pub custody_oracle_account: Account<'info, PriceUpdateV2>,
     pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         // seeds = [b"custody",
@@ -100,6 +109,52 @@ pub struct OpenPosition<'info> {
     )]
     pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // optional custody to swap the funding tokens from
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          funding_custody.mint.as_ref()],
+        // bump = funding_custody.bump
+    )]
+    pub funding_custody: Option<Box<Account<'info, Custody>>>,
+
+    // #[account(
+    //     constraint = funding_custody_oracle_account.key() == funding_custody.oracle.oracle_account
+    // )]
+    pub funding_custody_oracle_account: Option<Account<'info, PriceUpdateV2>>,
+    pub funding_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          funding_custody.mint.as_ref()],
+        // bump = funding_custody.token_account_bump
+    )]
+    pub funding_custody_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // optional referrer, receives a rebate and gives the trader a fee discount
+    #[account(
+        mut,
+        constraint = referral.owner != owner.key() @ PerpetualsError::InvalidReferral
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // optional trader volume stats, used for the fee tier discount
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
+    // optional index of the owner's positions
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
     system_program: Program<'info, System>,
     token_program: Program<'info, Token>,
 }
@@ -107,11 +162,16 @@ pub struct OpenPosition<'info> {
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
 pub struct OpenPositionParams {
     pub price: u64,
+    // collateral amount, in funding_custody tokens if set
     pub collateral: u64,
+    // minimum collateral tokens received from the funding swap
+    pub min_amount_out: u64,
     pub size: u64,
     pub side: Side,
     pub take_profit_price: Option<u64>,
     pub stop_loss_price: Option<u64>,
+    // 0 for the default position, other ids open additional isolated positions
+    pub position_id: u64,
 }
 
 pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) -> Result<()> {
@@ -143,8 +203,30 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     } else {
         require_keys_eq!(custody.key(), collateral_custody.key());
     };
+    if let Some(funding_custody) = ctx.accounts.funding_custody.as_ref() {
+        require!(
+            perpetuals.permissions.allow_swap,
+            PerpetualsError::InstructionNotAllowed
+        );
+        require_keys_neq!(funding_custody.key(), collateral_custody.key());
+        require!(
+            ctx.accounts.funding_custody_oracle_account.is_some(),
+            PerpetualsError::InvalidOracleAccount
+        );
+        match ctx.accounts.funding_custody_token_account.as_ref() {
+            Some(token_account) => {
+                require_keys_eq!(token_account.key(), funding_custody.token_account)
+            }
+            None => return Err(ProgramError::NotEnoughAccountKeys.into()),
+        }
+    }
+    // keep the owner's position index up to date
+    if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+        user_positions.add_position(ctx.accounts.position.key())?;
+    }
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
+    require!(!pool.is_winding_down(), PerpetualsError::PoolWindingDown);
 
     // compute position price
     let curtime = perpetuals.get_time()?;
@@ -152,6 +234,10 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            false,
+        )?,
         &custody.oracle,
         curtime,
         false,
@@ -161,6 +247,7 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -170,7 +257,8 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
         false,
         collateral_custody.oracle.feed_id,
@@ -179,17 +267,97 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
-        &custody.oracle,
+        None,
+        &collateral_custody.oracle,
         curtime,
-        false,
+        collateral_custody.pricing.use_ema,
         collateral_custody.oracle.feed_id,
     )?;
 
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    let position_price =
-        pool.get_entry_price(&token_price, &token_ema_price, params.side, custody)?;
+    // don't open new positions backed by a depegged stablecoin
+    if collateral_custody.pricing.block_positions_on_depeg {
+        require!(
+            !collateral_custody.is_depegged(&collateral_token_price)?,
+            PerpetualsError::StablecoinDepegged
+        );
+    }
+
+    // swap funding tokens into collateral tokens, they stay in the pool as a deposit
+    let swapped_amount = if let Some(funding_custody) = ctx.accounts.funding_custody.as_mut() {
+        msg!("Swap funding tokens");
+        let funding_custody_key = funding_custody.key();
+        let funding_custody_oracle_account = ctx
+            .accounts
+            .funding_custody_oracle_account
+            .as_ref()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        let funding_token_price = OraclePrice::new_from_oracle(
+            funding_custody_oracle_account,
+            ctx.accounts.funding_custody_twap_account.as_ref(),
+            None,
+            &funding_custody.oracle,
+            curtime,
+            false,
+            funding_custody.oracle.feed_id,
+        )?;
+
+        let funding_token_ema_price = OraclePrice::new_from_oracle(
+            funding_custody_oracle_account,
+            ctx.accounts.funding_custody_twap_account.as_ref(),
+            None,
+            &funding_custody.oracle,
+            curtime,
+            funding_custody.pricing.use_ema,
+            funding_custody.oracle.feed_id,
+        )?;
+
+        // for a short position funded with the position token, custody is the receiving custody
+        let receiving_custody: &mut Custody = if funding_custody_key == custody.key() {
+            custody
+        } else {
+            funding_custody
+        };
+
+        let swapped_amount = pool.swap_internal(
+            pool.get_token_id(&funding_custody_key)?,
+            pool.get_token_id(&collateral_custody.key())?,
+            params.collateral,
+            params.min_amount_out,
+            receiving_custody,
+            &funding_token_price,
+            &funding_token_ema_price,
+            collateral_custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            curtime,
+        )?;
+
+        // the position custody is re-priced once the position is added
+        if funding_custody_key != custody.key() {
+            pool.update_cached_custody_aum(
+                funding_custody,
+                &funding_token_price,
+                &funding_token_ema_price,
+                curtime,
+            )?;
+        }
+
+        Some(swapped_amount)
+    } else {
+        None
+    };
+
+    let position_price = pool.get_entry_price(
+        &token_price,
+        &token_ema_price,
+        params.side,
+        params.size,
+        custody,
+    )?;
     msg!("Entry price: {}", position_price);
 
     if params.side == Side::Long {
@@ -207,13 +375,12 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     }
 
     // compute position parameters
-    let position_oracle_price = OraclePrice {
-        price: position_price,
-        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
-    };
+    let position_oracle_price =
+        OraclePrice::new(position_price, -(Perpetuals::PRICE_DECIMALS as i32));
     let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
-    let collateral_usd = min_collateral_price
-        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+
+    // check open interest limits
+    custody.check_open_interest(params.side, size_usd)?;
 
     let locked_amount = if use_collateral_custody {
         custody.get_locked_amount(
@@ -240,8 +407,14 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     };
 
     // compute fee
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        perpetuals.get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
     let mut fee_amount = pool.get_entry_fee(
         custody.fees.open_position,
+        fee_discount,
         params.size,
         locked_amount,
         collateral_custody,
@@ -251,12 +424,42 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         fee_amount = collateral_token_ema_price
             .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
     }
+    let (discount, rebate) = if let Some(referral) = ctx.accounts.referral.as_ref() {
+        let (discount, rebate) = custody.fees.get_referral_split(fee_amount)?;
+        if referral.can_accrue(&collateral_custody.key()) {
+            (discount, rebate)
+        } else {
+            msg!("Referral rebates are full, the rebate stays in the fee");
+            (discount, 0)
+        }
+    } else {
+        (0, 0)
+    };
+    fee_amount = math::checked_sub(fee_amount, discount)?;
     msg!("Collected fee: {}", fee_amount);
 
-    // compute amount to transfer
-    let transfer_amount = math::checked_add(params.collateral, fee_amount)?;
+    // compute collateral and amount to transfer, swapped tokens pay the fee first
+    let (collateral, transfer_amount) = if let Some(swapped_amount) = swapped_amount {
+        require_gt!(
+            swapped_amount,
+            fee_amount,
+            PerpetualsError::InsufficientAmountReturned
+        );
+        (
+            math::checked_sub(swapped_amount, fee_amount)?,
+            params.collateral,
+        )
+    } else {
+        (
+            params.collateral,
+            math::checked_add(params.collateral, fee_amount)?,
+        )
+    };
     msg!("Amount in: {}", transfer_amount);
 
+    let collateral_usd =
+        min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
+
     // init new position
     msg!("Initialize new position");
     position.owner = ctx.accounts.owner.key();
@@ -274,10 +477,12 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     position.unrealized_loss_usd = 0;
     position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
     position.locked_amount = locked_amount;
-    position.collateral_amount = params.collateral;
+    position.collateral_amount = collateral;
     position.bump = ctx.bumps.position;
     position.take_profit_price = params.take_profit_price;
     position.stop_loss_price = params.stop_loss_price;
+    position.margin_account = None;
+    position.position_id = params.position_id;
 
     // check position risk
     msg!("Check position risks");
@@ -285,6 +490,10 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         position.locked_amount > 0,
         PerpetualsError::InsufficientAmountReturned
     );
+    require!(
+        position.validate_triggers(),
+        PerpetualsError::InvalidTriggerPrice
+    );
     require!(
         pool.check_leverage(
             position,
@@ -305,11 +514,16 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
 
     // transfer tokens
     msg!("Transfer tokens");
-    perpetuals.transfer_tokens_from_user(
-        ctx.accounts.funding_account.to_account_info(),
-        ctx.accounts
+    let destination_token_account = match ctx.accounts.funding_custody_token_account.as_ref() {
+        Some(token_account) => token_account.to_account_info(),
+        None => ctx
+            .accounts
             .collateral_custody_token_account
             .to_account_info(),
+    };
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        destination_token_account,
         ctx.accounts.owner.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         transfer_amount,
@@ -323,12 +537,26 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         .wrapping_add(fee_amount_usd);
 
     collateral_custody.assets.collateral =
-        math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
+        math::checked_add(collateral_custody.assets.collateral, collateral)?;
 
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+    let protocol_fee = Pool::get_fee_amount(
+        custody.fees.protocol_share,
+        math::checked_sub(fee_amount, rebate)?,
+    )?;
     collateral_custody.assets.protocol_fees =
         math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
 
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        msg!("Accrue referral rebate: {}", rebate);
+        collateral_custody.assets.referral_rebates =
+            math::checked_add(collateral_custody.assets.referral_rebates, rebate)?;
+        referral.accrue(collateral_custody.key(), rebate, size_usd)?;
+    }
+
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(size_usd, curtime)?;
+    }
+
     // if custody and collateral_custody accounts are the same, ensure that data is in sync
     if position.side == Side::Long && !custody.is_virtual {
         collateral_custody.volume_stats.open_position_usd = collateral_custody
@@ -370,5 +598,21 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         collateral_custody.update_borrow_rate(curtime)?;
     }
 
+    // if custody and funding_custody accounts are the same, ensure that data is in sync
+    if let Some(funding_custody) = ctx.accounts.funding_custody.as_mut() {
+        if funding_custody.key() == custody.key() {
+            **funding_custody = custody.clone();
+        }
+    }
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+
     Ok(())
 }
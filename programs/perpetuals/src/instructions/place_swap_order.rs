@@ -0,0 +1,156 @@
+//! PlaceSwapOrder instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{custody::Custody, perpetuals::Perpetuals, pool::Pool, swap_order::SwapOrder},
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: PlaceSwapOrderParams)]
+pub struct PlaceSwapOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == receiving_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = SwapOrder::LEN,
+        seeds = [b"swap_order",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 &params.order_id.to_le_bytes()],
+        bump
+    )]
+    pub swap_order: Box<Account<'info, SwapOrder>>,
+
+    #[account(
+        init,
+        payer = owner,
+        token::mint = receiving_custody_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"swap_order_token_account",
+                 swap_order.key().as_ref()],
+        bump
+    )]
+    pub swap_order_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = receiving_custody.pool == pool.key()
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          receiving_custody.mint.as_ref()],
+        // bump = receiving_custody.bump
+    )]
+    pub receiving_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        constraint = receiving_custody_token_mint.key() == receiving_custody.mint
+    )]
+    pub receiving_custody_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        constraint = dispensing_custody.pool == pool.key()
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          dispensing_custody.mint.as_ref()],
+        // bump = dispensing_custody.bump
+    )]
+    pub dispensing_custody: Box<Account<'info, Custody>>,
+
+    system_program: Program<'info, System>,
+    token_program: Program<'info, Token>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PlaceSwapOrderParams {
+    pub order_id: u64,
+    pub amount_in: u64,
+    // lowest accepted price of the token in, denominated in the token out
+    pub limit_price: u64,
+    pub expiry_time: i64,
+}
+
+pub fn place_swap_order(ctx: Context<PlaceSwapOrder>, params: &PlaceSwapOrderParams) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let receiving_custody = ctx.accounts.receiving_custody.as_ref();
+    let dispensing_custody = ctx.accounts.dispensing_custody.as_ref();
+    require!(
+        perpetuals.permissions.allow_swap
+            && receiving_custody.permissions.allow_swap
+            && dispensing_custody.permissions.allow_swap
+            && !receiving_custody.is_virtual
+            && !dispensing_custody.is_virtual,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let curtime = perpetuals.get_time()?;
+    if params.amount_in == 0 || params.limit_price == 0 || params.expiry_time <= curtime {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require_keys_neq!(receiving_custody.key(), dispensing_custody.key());
+    let pool = ctx.accounts.pool.as_ref();
+    require!(!pool.is_winding_down(), PerpetualsError::PoolWindingDown);
+
+    // escrow tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens_from_user(
+        ctx.accounts.funding_account.to_account_info(),
+        ctx.accounts.swap_order_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount_in,
+    )?;
+
+    // record order data
+    msg!("Initialize swap order");
+    let swap_order = ctx.accounts.swap_order.as_mut();
+    swap_order.owner = ctx.accounts.owner.key();
+    swap_order.pool = pool.key();
+    swap_order.receiving_custody = receiving_custody.key();
+    swap_order.dispensing_custody = dispensing_custody.key();
+    swap_order.order_id = params.order_id;
+    swap_order.amount_in = params.amount_in;
+    swap_order.limit_price = params.limit_price;
+    swap_order.expiry_time = params.expiry_time;
+    swap_order.open_time = curtime;
+    swap_order.bump = ctx.bumps.swap_order;
+    swap_order.token_account_bump = ctx.bumps.swap_order_token_account;
+
+    Ok(())
+}
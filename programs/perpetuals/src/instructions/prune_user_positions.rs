@@ -0,0 +1,53 @@
+//! PruneUserPositions instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{perpetuals::Perpetuals, user_positions::UserPositions},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: PruneUserPositionsParams)]
+pub struct PruneUserPositions<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"user_positions",
+                 owner.key().as_ref()],
+        bump = user_positions.bump
+    )]
+    pub user_positions: Box<Account<'info, UserPositions>>,
+    // remaining accounts:
+    //   closed position accounts to drop from the index (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct PruneUserPositionsParams {}
+
+pub fn prune_user_positions<'info>(
+    ctx: Context<'_, '_, '_, 'info, PruneUserPositions<'info>>,
+    _params: &PruneUserPositionsParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    if ctx.remaining_accounts.is_empty() {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    // drop keys of positions closed without updating the index
+    msg!("Prune user positions");
+    let user_positions = ctx.accounts.user_positions.as_mut();
+    for position_info in ctx.remaining_accounts {
+        require!(
+            Perpetuals::is_empty_account(position_info)?,
+            PerpetualsError::InvalidPositionState
+        );
+        user_positions.remove_position(position_info.key);
+    }
+
+    Ok(())
+}
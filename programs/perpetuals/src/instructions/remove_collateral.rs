@@ -58,7 +58,8 @@ pub struct RemoveCollateral<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -95,7 +96,7 @@ pub struct RemoveCollateral<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct RemoveCollateralParams {
-    collateral_usd: u64,
+    pub collateral_usd: u64,
     // feed_id: [u8; 32],
 }
 
@@ -120,6 +121,10 @@ pub fn remove_collateral(
     if params.collateral_usd == 0 || params.collateral_usd >= position.collateral_usd {
         return Err(ProgramError::InvalidArgument.into());
     }
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
     let pool = ctx.accounts.pool.as_mut();
 
     // compute position price
@@ -128,6 +133,7 @@ pub fn remove_collateral(
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -137,6 +143,7 @@ pub fn remove_collateral(
     let token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
@@ -146,6 +153,7 @@ pub fn remove_collateral(
     let collateral_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.collateral_custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         false,
@@ -155,6 +163,7 @@ pub fn remove_collateral(
     let collateral_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account,
         ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
         &custody.oracle,
         curtime,
         custody.pricing.use_ema,
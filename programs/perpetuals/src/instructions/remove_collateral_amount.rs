@@ -0,0 +1,240 @@
+//! RemoveCollateralAmount instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+        },
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    anchor_spl::token::{Token, TokenAccount},
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+#[instruction(params: RemoveCollateralAmountParams)]
+pub struct RemoveCollateralAmount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          collateral_custody.mint.as_ref()],
+        // bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveCollateralAmountParams {
+    pub collateral: u64,
+    pub max_collateral_usd_delta: u64,
+    // feed_id: [u8; 32],
+}
+
+pub fn remove_collateral_amount(
+    ctx: Context<RemoveCollateralAmount>,
+    params: &RemoveCollateralAmountParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_collateral_withdrawal
+            && custody.permissions.allow_collateral_withdrawal,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let position = ctx.accounts.position.as_mut();
+    if params.collateral == 0 || params.collateral >= position.collateral_amount {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
+    let pool = ctx.accounts.pool.as_mut();
+
+    // compute position price
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
+        collateral_token_price
+    } else {
+        collateral_token_ema_price
+    };
+
+    // compute collateral value to remove
+    let collateral_usd = max_collateral_price
+        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+    if collateral_usd >= position.collateral_usd {
+        return Err(ProgramError::InsufficientFunds.into());
+    }
+    msg!("Amount out: {}", params.collateral);
+    msg!("Collateral removed in USD: {}", collateral_usd);
+    require_gte!(
+        params.max_collateral_usd_delta,
+        collateral_usd,
+        PerpetualsError::MaxPriceSlippage
+    );
+
+    // update existing position
+    msg!("Update existing position");
+    position.update_time = perpetuals.get_time()?;
+    position.collateral_usd = math::checked_sub(position.collateral_usd, collateral_usd)?;
+    position.collateral_amount = math::checked_sub(position.collateral_amount, params.collateral)?;
+
+    // check position risk
+    msg!("Check position risks");
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.collateral,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    collateral_custody.assets.collateral =
+        math::checked_sub(collateral_custody.assets.collateral, params.collateral)?;
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if position.side == Side::Long && !custody.is_virtual {
+        *custody = collateral_custody.clone();
+    }
+
+    Ok(())
+}
@@ -7,7 +7,7 @@ use {
             custody::Custody,
             multisig::{AdminInstruction, Multisig},
             perpetuals::Perpetuals,
-            pool::{Pool, TokenRatios},
+            pool::{AumCache, Pool, TokenRatios},
         },
     },
     anchor_lang::prelude::*,
@@ -43,7 +43,8 @@ pub struct RemoveCustody<'info> {
     #[account(
         mut,
         realloc = Pool::LEN + (pool.custodies.len() - 1) * std::mem::size_of::<Pubkey>() +
-                              (pool.ratios.len() - 1) * std::mem::size_of::<TokenRatios>(),
+                              (pool.ratios.len() - 1) * std::mem::size_of::<TokenRatios>() +
+                              (pool.custodies.len() - 1) * std::mem::size_of::<AumCache>(),
         realloc::payer = admin,
         realloc::zero = false,
         // seeds = [b"pool",
@@ -75,7 +76,7 @@ pub struct RemoveCustody<'info> {
     token_program: Program<'info, Token>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct RemoveCustodyParams {
     pub ratios: Vec<TokenRatios>,
 }
@@ -116,6 +117,10 @@ pub fn remove_custody<'info>(
     let pool = ctx.accounts.pool.as_mut();
     let token_id = pool.get_token_id(&ctx.accounts.custody.key())?;
     pool.custodies.remove(token_id);
+    if token_id < pool.aum_cache.len() {
+        pool.aum_cache.remove(token_id);
+    }
+    pool.align_aum_cache();
     pool.ratios = params.ratios.clone();
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
@@ -0,0 +1,156 @@
+//! RemoveLiquidityInKind instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{custody::Custody, perpetuals::Perpetuals, pool::Pool},
+        try_from,
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    anchor_spl::token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: RemoveLiquidityInKindParams)]
+pub struct RemoveLiquidityInKind<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint",
+                 pool.key().as_ref()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   pool.custodies.len() custody accounts (write, unsigned)
+    //   pool.custodies.len() custody token accounts (write, unsigned)
+    //   pool.custodies.len() owner token accounts to receive tokens (write, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveLiquidityInKindParams {
+    pub lp_amount_in: u64,
+}
+
+pub fn remove_liquidity_in_kind<'info>(
+    ctx: Context<'_, '_, '_, 'info, RemoveLiquidityInKind<'info>>,
+    params: &RemoveLiquidityInKindParams,
+) -> Result<()> {
+    // no permission checks, LPs must be able to exit a pool that is winding down
+    msg!("Validate inputs");
+    let lp_supply = ctx.accounts.lp_token_mint.supply;
+    if params.lp_amount_in == 0 || params.lp_amount_in > lp_supply {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_mut();
+    require!(pool.is_winding_down(), PerpetualsError::InvalidPoolState);
+    let custodies_len = pool.custodies.len();
+    if ctx.remaining_accounts.len() < custodies_len * 3 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    // transfer the pro-rata share of every custody
+    msg!("Transfer tokens");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    for (idx, custody_key) in pool.custodies.iter().enumerate() {
+        let custody_info = &ctx.remaining_accounts[idx];
+        let custody_token_account_info = &ctx.remaining_accounts[custodies_len + idx];
+        let receiving_account_info = &ctx.remaining_accounts[custodies_len * 2 + idx];
+
+        require_keys_eq!(custody_info.key(), *custody_key);
+        let mut custody = try_from!(Account::<Custody>, custody_info)?;
+        require_keys_eq!(custody_token_account_info.key(), custody.token_account);
+        let receiving_account = try_from!(Account::<TokenAccount>, receiving_account_info)?;
+        require_keys_eq!(receiving_account.owner, ctx.accounts.owner.key());
+        require_keys_eq!(receiving_account.mint, custody.mint);
+
+        // open positions still have a claim on locked funds
+        require!(
+            custody.long_positions.open_positions == 0
+                && custody.short_positions.open_positions == 0,
+            PerpetualsError::InvalidPoolState
+        );
+
+        // the last LP takes everything left so that rounding doesn't strand dust in the custody
+        let amount = if params.lp_amount_in == lp_supply {
+            custody.assets.owned
+        } else {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(custody.assets.owned as u128, params.lp_amount_in as u128)?,
+                lp_supply as u128,
+            )?)?
+        };
+        msg!("Amount out: {}", amount);
+        if amount == 0 {
+            continue;
+        }
+
+        perpetuals.transfer_tokens(
+            custody_token_account_info.clone(),
+            receiving_account_info.clone(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            amount,
+        )?;
+
+        custody.assets.owned = math::checked_sub(custody.assets.owned, amount)?;
+        custody.exit(&crate::ID)?;
+    }
+
+    // burn lp tokens
+    msg!("Burn LP tokens");
+    perpetuals.burn_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.lp_amount_in,
+    )?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    pool.aum_usd = math::checked_div(
+        math::checked_mul(
+            pool.aum_usd,
+            math::checked_sub(lp_supply, params.lp_amount_in)? as u128,
+        )?,
+        lp_supply as u128,
+    )?;
+    // custodies are redeemed without oracle prices, their cached valuations are outdated
+    pool.invalidate_aum_cache();
+
+    Ok(())
+}
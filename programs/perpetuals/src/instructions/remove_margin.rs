@@ -0,0 +1,138 @@
+//! RemoveMargin instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool,
+        },
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    anchor_spl::token::{Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+#[instruction(params: RemoveMarginParams)]
+pub struct RemoveMargin<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = collateral_custody,
+        seeds = [b"margin_account",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(mut)]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        constraint = collateral_custody_token_account.key() == collateral_custody.token_account
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+    // remaining accounts:
+    //   margin_account.custodies.len() custody accounts (read-only, unsigned)
+    //   margin_account.custodies.len() custody oracles (read-only, unsigned)
+    //   margin_account.positions.len() position accounts (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveMarginParams {
+    pub amount: u64,
+}
+
+pub fn remove_margin<'info>(
+    ctx: Context<'_, '_, '_, 'info, RemoveMargin<'info>>,
+    params: &RemoveMarginParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_collateral_withdrawal
+            && collateral_custody.permissions.allow_collateral_withdrawal,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    if params.amount == 0 || params.amount > margin_account.collateral_amount {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let pool = ctx.accounts.pool.as_ref();
+
+    // update margin account
+    msg!("Update margin account");
+    margin_account.collateral_amount =
+        math::checked_sub(margin_account.collateral_amount, params.amount)?;
+
+    // check margin account risk
+    msg!("Check margin account risks");
+    let curtime = perpetuals.get_time()?;
+    let state = margin_account.load_state(pool, ctx.remaining_accounts, curtime)?;
+    let (equity_usd, initial_margin_usd, _) =
+        state.get_margin_usd(pool, margin_account, curtime)?;
+    msg!(
+        "Equity: {}, initial margin: {}",
+        equity_usd,
+        initial_margin_usd
+    );
+    require_gte!(equity_usd, initial_margin_usd, PerpetualsError::MaxLeverage);
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    collateral_custody.assets.collateral =
+        math::checked_sub(collateral_custody.assets.collateral, params.amount)?;
+
+    Ok(())
+}
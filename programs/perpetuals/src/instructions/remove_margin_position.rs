@@ -0,0 +1,107 @@
+//! RemoveMarginPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            margin_account::MarginAccount, perpetuals::Perpetuals, pool::Pool, position::Position,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: RemoveMarginPositionParams)]
+pub struct RemoveMarginPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"margin_account",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = margin_account.bump
+    )]
+    pub margin_account: Box<Account<'info, MarginAccount>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = position.margin_account == Some(margin_account.key())
+    )]
+    pub position: Box<Account<'info, Position>>,
+    // remaining accounts:
+    //   margin_account.custodies.len() custody accounts (read-only, unsigned)
+    //   margin_account.custodies.len() custody oracles (read-only, unsigned)
+    //   margin_account.positions.len() position accounts (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RemoveMarginPositionParams {}
+
+pub fn remove_margin_position<'info>(
+    ctx: Context<'_, '_, '_, 'info, RemoveMarginPosition<'info>>,
+    _params: &RemoveMarginPositionParams,
+) -> Result<()> {
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let pool = ctx.accounts.pool.as_ref();
+    let margin_account = ctx.accounts.margin_account.as_mut();
+    let position = ctx.accounts.position.as_mut();
+
+    let curtime = perpetuals.get_time()?;
+    let mut state = margin_account.load_state(pool, ctx.remaining_accounts, curtime)?;
+
+    // move position out of the margin account
+    msg!("Remove position from margin account");
+    let idx = margin_account.remove_position(&position.key())?;
+    state.positions.remove(idx);
+    margin_account.refresh_custodies(&state.positions);
+    position.margin_account = None;
+
+    // check the position can stand on its own margin, close_margin_position settles it otherwise
+    msg!("Check position risks");
+    let custody_idx = state.get_custody_idx(&position.custody)?;
+    let collateral_idx = state.get_custody_idx(&position.collateral_custody)?;
+    require!(
+        pool.check_leverage(
+            position,
+            &state.token_prices[custody_idx],
+            &state.token_ema_prices[custody_idx],
+            &state.custodies[custody_idx],
+            &state.token_prices[collateral_idx],
+            &state.token_ema_prices[collateral_idx],
+            &state.custodies[collateral_idx],
+            curtime,
+            false
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // check margin account risk without the position
+    msg!("Check margin account risks");
+    let (equity_usd, initial_margin_usd, _) =
+        state.get_margin_usd(pool, margin_account, curtime)?;
+    msg!(
+        "Equity: {}, initial margin: {}",
+        equity_usd,
+        initial_margin_usd
+    );
+    require_gte!(equity_usd, initial_margin_usd, PerpetualsError::MaxLeverage);
+
+    Ok(())
+}
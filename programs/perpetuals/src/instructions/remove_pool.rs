@@ -54,7 +54,7 @@ pub struct RemovePool<'info> {
     system_program: Program<'info, System>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct RemovePoolParams {}
 
 pub fn remove_pool<'info>(
@@ -0,0 +1,34 @@
+//! RevokeDelegate instruction handler
+
+use {crate::state::delegate::Delegate, anchor_lang::prelude::*};
+
+#[derive(Accounts)]
+#[instruction(params: RevokeDelegateParams)]
+pub struct RevokeDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"delegate",
+                 owner.key().as_ref(),
+                 delegate.delegate.as_ref()],
+        bump = delegate.bump,
+        close = owner
+    )]
+    pub delegate: Box<Account<'info, Delegate>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RevokeDelegateParams {}
+
+pub fn revoke_delegate(
+    _ctx: Context<RevokeDelegate>,
+    _params: &RevokeDelegateParams,
+) -> Result<()> {
+    // delegate account is closed by the accounts constraints
+    msg!("Revoke delegation");
+
+    Ok(())
+}
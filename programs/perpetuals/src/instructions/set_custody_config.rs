@@ -84,6 +84,9 @@ pub fn set_custody_config<'info>(
     // update pool data
     let pool = ctx.accounts.pool.as_mut();
     pool.ratios = params.ratios.clone();
+    // the cached valuation must not outlive the custody price it was computed with
+    pool.aum_cache_max_age_sec =
+        std::cmp::min(pool.aum_cache_max_age_sec, params.oracle.max_price_age_sec);
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
     }
@@ -88,13 +88,17 @@ pub fn set_custom_oracle_price<'info>(
         return Ok(signatures_left);
     }
 
-    // update oracle data
+    // update oracle data, the multisig is not held to the deviation limit so it can
+    // recover the feed after a large move
     ctx.accounts.oracle_account.set(
         params.price,
         params.expo,
         params.conf,
         params.ema,
         params.publish_time,
-    );
+        &ctx.accounts.custody.oracle,
+        false,
+    )?;
+
     Ok(0)
 }
@@ -3,7 +3,13 @@
 use {
     crate::{
         error::PerpetualsError,
-        state::{custody::Custody, oracle::CustomOracle, perpetuals::Perpetuals, pool::Pool},
+        math,
+        state::{
+            custody::Custody,
+            oracle::{CustomOracle, OracleParams, OraclePrice},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
     },
     anchor_lang::{
         prelude::*,
@@ -61,66 +67,211 @@ pub struct SetCustomOraclePricePermissionlessParams {
     pub publish_time: i64,
 }
 
+/// Signed message envelope, binds the quote to a program deployment, pool and custody.
+/// Legacy messages are the bare Borsh encoding of SetCustomOraclePricePermissionlessParams.
+#[derive(AnchorSerialize, AnchorDeserialize, Copy, Clone, PartialEq)]
+pub struct OraclePriceMessage {
+    pub prefix: [u8; 8],
+    pub version: u8,
+    pub program_id: Pubkey,
+    // deployment tag, must match custody.oracle.message_domain
+    pub domain: u64,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub params: SetCustomOraclePricePermissionlessParams,
+}
+
+impl OraclePriceMessage {
+    pub const PREFIX: [u8; 8] = *b"\xffperpsor";
+    pub const VERSION: u8 = 1;
+}
+
 pub fn set_custom_oracle_price_permissionless(
     ctx: Context<SetCustomOraclePricePermissionless>,
     params: &SetCustomOraclePricePermissionlessParams,
 ) -> Result<()> {
+    let oracle_params = &ctx.accounts.custody.oracle;
     if params.publish_time <= ctx.accounts.oracle_account.publish_time {
-        msg!("Custom oracle price did not update because the requested publish time is stale.");
-        return Ok(());
+        return skip_stale_update(oracle_params);
     }
-    // Get what should be the Ed25519Program signature verification instruction.
-    let signature_ix: Instruction =
-        sysvar::instructions::load_instruction_at_checked(0, &ctx.accounts.ix_sysvar)?;
-
-    validate_ed25519_signature_instruction(
-        &signature_ix,
-        &ctx.accounts.custody.oracle.oracle_authority,
-        params,
-    )?;
+
+    // Collect signed quotes from the Ed25519Program signature verification instructions
+    // that precede this one.
+    let current_index = sysvar::instructions::load_current_index_checked(&ctx.accounts.ix_sysvar)?;
+    let mut quotes: Vec<(Pubkey, SetCustomOraclePricePermissionlessParams)> = Vec::new();
+    for index in 0..current_index {
+        let signature_ix: Instruction = sysvar::instructions::load_instruction_at_checked(
+            index as usize,
+            &ctx.accounts.ix_sysvar,
+        )?;
+        if signature_ix.program_id == ed25519_program::ID {
+            get_signed_quotes(
+                &signature_ix,
+                &ctx.accounts.pool.key(),
+                oracle_params,
+                &mut quotes,
+            )?;
+        }
+    }
+    require!(
+        !quotes.is_empty(),
+        PerpetualsError::PermissionlessOracleMissingSignature
+    );
+
+    // every quote must come from a distinct oracle authority and price the same custody
+    let authorities = oracle_params.get_oracle_authorities();
+    let mut signers: Vec<Pubkey> = Vec::new();
+    for (signer, quote) in &quotes {
+        require!(
+            authorities.contains(signer) && !signers.contains(signer),
+            PerpetualsError::PermissionlessOracleSignerMismatch
+        );
+        require!(
+            quote.custody_account == params.custody_account && quote.expo == params.expo,
+            PerpetualsError::PermissionlessOracleMessageMismatch
+        );
+        signers.push(*signer);
+    }
+    require!(
+        quotes.iter().any(|(_, quote)| quote == params),
+        PerpetualsError::PermissionlessOracleMessageMismatch
+    );
+    require_gte!(
+        signers.len(),
+        oracle_params.get_min_oracle_signatures(),
+        PerpetualsError::PermissionlessOracleQuorumNotReached
+    );
+
+    // quotes must agree, otherwise a single leaked key paired with one honest quote
+    // would move the median by half the distance between them
+    if oracle_params.max_quote_spread > 0 {
+        let prices = quotes.iter().map(|(_, quote)| quote.price);
+        let min_price = OraclePrice::new(prices.clone().min().unwrap_or_default(), params.expo);
+        let max_price = OraclePrice::new(prices.max().unwrap_or_default(), params.expo);
+        require_gte!(
+            oracle_params.max_quote_spread,
+            min_price.get_divergence_bps(&max_price)?,
+            PerpetualsError::PermissionlessOracleQuoteSpread
+        );
+    }
+
+    // the aggregated price is as old as the oldest quote
+    let publish_time = quotes
+        .iter()
+        .map(|(_, quote)| quote.publish_time)
+        .min()
+        .unwrap_or(params.publish_time);
+    if publish_time <= ctx.accounts.oracle_account.publish_time {
+        return skip_stale_update(oracle_params);
+    }
+
+    let price = math::median(&mut quotes.iter().map(|(_, q)| q.price).collect::<Vec<u64>>())?;
+    let conf = math::median(&mut quotes.iter().map(|(_, q)| q.conf).collect::<Vec<u64>>())?;
+    let ema = math::median(&mut quotes.iter().map(|(_, q)| q.ema).collect::<Vec<u64>>())?;
 
     ctx.accounts.oracle_account.set(
-        params.price,
+        price,
         params.expo,
-        params.conf,
-        params.ema,
-        params.publish_time,
-    );
+        conf,
+        ema,
+        publish_time,
+        oracle_params,
+        true,
+    )?;
+    Ok(())
+}
+
+fn skip_stale_update(oracle_params: &OracleParams) -> Result<()> {
+    if oracle_params.reject_stale_updates {
+        return err!(PerpetualsError::StaleOracleUpdate);
+    }
+    msg!("Custom oracle price did not update because the requested publish time is stale.");
     Ok(())
 }
 
-fn validate_ed25519_signature_instruction(
+// Decodes a signed message, envelope first then legacy format if allowed by the custody
+fn decode_signed_message(
+    message: &[u8],
+    pool: &Pubkey,
+    oracle_params: &OracleParams,
+) -> Result<SetCustomOraclePricePermissionlessParams> {
+    let mut data = message;
+    if message.starts_with(&OraclePriceMessage::PREFIX) {
+        let envelope = OraclePriceMessage::deserialize(&mut data)?;
+        require!(
+            data.is_empty() && envelope.version == OraclePriceMessage::VERSION,
+            PerpetualsError::PermissionlessOracleMalformedEd25519Data
+        );
+        require!(
+            envelope.program_id == crate::ID
+                && envelope.domain == oracle_params.message_domain
+                && envelope.pool == *pool
+                && envelope.custody == envelope.params.custody_account,
+            PerpetualsError::PermissionlessOracleDomainMismatch
+        );
+        Ok(envelope.params)
+    } else {
+        require!(
+            oracle_params.allow_legacy_message,
+            PerpetualsError::PermissionlessOracleMalformedEd25519Data
+        );
+        let params = SetCustomOraclePricePermissionlessParams::deserialize(&mut data)?;
+        require!(
+            data.is_empty(),
+            PerpetualsError::PermissionlessOracleMalformedEd25519Data
+        );
+        Ok(params)
+    }
+}
+
+// Appends (signer, signed params) for every signature of the verification instruction
+fn get_signed_quotes(
     signature_ix: &Instruction,
-    expected_pubkey: &Pubkey,
-    expected_params: &SetCustomOraclePricePermissionlessParams,
+    pool: &Pubkey,
+    oracle_params: &OracleParams,
+    quotes: &mut Vec<(Pubkey, SetCustomOraclePricePermissionlessParams)>,
 ) -> Result<()> {
-    require_eq!(
-        signature_ix.program_id,
-        ed25519_program::ID,
-        PerpetualsError::PermissionlessOracleMissingSignature
-    );
+    // Signature offsets layout according to:
+    // https://docs.solana.com/developing/runtime-facilities/programs#ed25519-program
+    const OFFSETS_START: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const CURRENT_INSTRUCTION: usize = u16::MAX as usize;
+
+    let data = &signature_ix.data;
     require!(
         signature_ix.accounts.is_empty() /* no accounts touched */
-            && signature_ix.data[0] == 0x01 /* only one ed25519 signature */
-            && signature_ix.data.len() == 180, /* data len matches exactly the expected */
+            && !data.is_empty()
+            && data.len() >= OFFSETS_START + data[0] as usize * OFFSETS_LEN,
         PerpetualsError::PermissionlessOracleMalformedEd25519Data
     );
 
-    // Manually access offsets for signer pubkey and message data according to:
-    // https://docs.solana.com/developing/runtime-facilities/programs#ed25519-program
-    let signer_pubkey = &signature_ix.data[16..16 + 32];
-    let mut verified_message = &signature_ix.data[112..];
+    for i in 0..data[0] as usize {
+        let offsets = &data[OFFSETS_START + i * OFFSETS_LEN..][..OFFSETS_LEN];
+        let read_u16 = |pos: usize| u16::from_le_bytes([offsets[pos], offsets[pos + 1]]) as usize;
 
-    let deserialized_instruction_params =
-        SetCustomOraclePricePermissionlessParams::deserialize(&mut verified_message)?;
+        // signature, public key and message must be read from the verification instruction itself
+        require!(
+            read_u16(2) == CURRENT_INSTRUCTION
+                && read_u16(6) == CURRENT_INSTRUCTION
+                && read_u16(12) == CURRENT_INSTRUCTION,
+            PerpetualsError::PermissionlessOracleMalformedEd25519Data
+        );
+
+        let (pubkey_offset, message_offset, message_size) =
+            (read_u16(4), read_u16(8), read_u16(10));
+        let signer_pubkey = data
+            .get(pubkey_offset..pubkey_offset + 32)
+            .and_then(|bytes| Pubkey::try_from(bytes).ok())
+            .ok_or(PerpetualsError::PermissionlessOracleMalformedEd25519Data)?;
+        let verified_message = data
+            .get(message_offset..message_offset + message_size)
+            .ok_or(PerpetualsError::PermissionlessOracleMalformedEd25519Data)?;
+
+        quotes.push((
+            signer_pubkey,
+            decode_signed_message(verified_message, pool, oracle_params)?,
+        ));
+    }
 
-    require!(
-        signer_pubkey == expected_pubkey.to_bytes(),
-        PerpetualsError::PermissionlessOracleSignerMismatch
-    );
-    require!(
-        deserialized_instruction_params == *expected_params,
-        PerpetualsError::PermissionlessOracleMessageMismatch
-    );
     Ok(())
 }
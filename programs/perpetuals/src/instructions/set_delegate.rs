@@ -0,0 +1,67 @@
+//! SetDelegate instruction handler
+
+use {
+    crate::state::{
+        delegate::{Delegate, DelegatePermissions},
+        perpetuals::Perpetuals,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: SetDelegateParams)]
+pub struct SetDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: key allowed to manage the owner's positions
+    pub delegate_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    // can be called again to update an existing delegation, hence init_if_needed
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = Delegate::LEN,
+        seeds = [b"delegate",
+                 owner.key().as_ref(),
+                 delegate_authority.key().as_ref()],
+        bump
+    )]
+    pub delegate: Box<Account<'info, Delegate>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetDelegateParams {
+    pub permissions: DelegatePermissions,
+    pub expiry: Option<i64>,
+    pub max_size_usd: Option<u64>,
+}
+
+pub fn set_delegate(ctx: Context<SetDelegate>, params: &SetDelegateParams) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    require_keys_neq!(
+        ctx.accounts.owner.key(),
+        ctx.accounts.delegate_authority.key()
+    );
+
+    // record delegation
+    msg!("Record delegation");
+    let delegate = ctx.accounts.delegate.as_mut();
+    delegate.owner = ctx.accounts.owner.key();
+    delegate.delegate = ctx.accounts.delegate_authority.key();
+    delegate.permissions = params.permissions;
+    delegate.expiry = params.expiry;
+    delegate.max_size_usd = params.max_size_usd;
+    delegate.bump = ctx.bumps.delegate;
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! SetFeeTiers instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::{FeeTier, Perpetuals},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetFeeTiers<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetFeeTiersParams {
+    pub fee_tiers: [FeeTier; 4],
+}
+
+pub fn set_fee_tiers<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetFeeTiers<'info>>,
+    params: &SetFeeTiersParams,
+) -> Result<u8> {
+    // validate inputs
+    if !Perpetuals::validate_fee_tiers(&params.fee_tiers) {
+        return err!(PerpetualsError::InvalidPerpetualsConfig);
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetFeeTiers, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update fee tiers
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    perpetuals.fee_tiers = params.fee_tiers;
+
+    if !perpetuals.validate() {
+        err!(PerpetualsError::InvalidPerpetualsConfig)
+    } else {
+        Ok(0)
+    }
+}
@@ -0,0 +1,91 @@
+//! SetPoolConfig instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
+        try_from,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetPoolConfig<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    // remaining accounts:
+    //   pool.custodies.len() custody accounts (read-only, unsigned), in pool order
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetPoolConfigParams {
+    // cached custody valuations older than this are not used by LP instructions,
+    // can't be longer than the max_price_age_sec of any pool custody
+    pub aum_cache_max_age_sec: u32,
+}
+
+pub fn set_pool_config<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+    params: &SetPoolConfigParams,
+) -> Result<u8> {
+    // validate inputs
+    let pool = ctx.accounts.pool.as_ref();
+    if ctx.remaining_accounts.len() < pool.custodies.len() {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+    for (custody_key, account) in pool.custodies.iter().zip(ctx.remaining_accounts) {
+        require_keys_eq!(account.key(), *custody_key);
+        let custody = try_from!(Account::<Custody>, account)?;
+        require!(
+            params.aum_cache_max_age_sec <= custody.oracle.max_price_age_sec,
+            PerpetualsError::InvalidPoolConfig
+        );
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetPoolConfig, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update pool data
+    ctx.accounts.pool.aum_cache_max_age_sec = params.aum_cache_max_age_sec;
+
+    Ok(0)
+}
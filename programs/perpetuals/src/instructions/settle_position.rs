@@ -0,0 +1,436 @@
+//! SettlePosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            margin_account::MarginAccount,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+            user_positions::UserPositions,
+        },
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::token::{Token, TokenAccount},
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+#[instruction(params: SettlePositionParams)]
+pub struct SettlePosition<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: position owner, receives the position account rent
+    #[account(
+        mut,
+        constraint = owner.key() == position.owner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        constraint = receiving_account.owner == position.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"position",
+                 position.owner.as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          collateral_custody.mint.as_ref()],
+        // bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    // optional index of the owner's positions
+    #[account(
+        mut,
+        constraint = user_positions.owner == position.owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
+    // margin account backing the position, required for margin positions
+    #[account(
+        mut,
+        constraint = position.margin_account == Some(margin_account.key())
+    )]
+    pub margin_account: Option<Box<Account<'info, MarginAccount>>>,
+
+    // margin account deposit custody, only when it is neither custody nor collateral_custody
+    #[account(mut)]
+    pub deposit_custody: Option<Box<Account<'info, Custody>>>,
+
+    pub deposit_custody_oracle_account: Option<Account<'info, PriceUpdateV2>>,
+
+    // refund the rest of the deposit once the last margin position is settled
+    #[account(mut)]
+    pub deposit_custody_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = deposit_receiving_account.owner == position.owner
+    )]
+    pub deposit_receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SettlePositionParams {
+    // pub feed_id: [u8; 32],
+}
+
+pub fn settle_position(ctx: Context<SettlePosition>, _params: &SettlePositionParams) -> Result<()> {
+    // no permission checks, settlement must stay possible for the pool to wind down
+    msg!("Validate inputs");
+    let position_key = ctx.accounts.position.key();
+    // keep the owner's position index up to date
+    if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+        user_positions.remove_position(&position_key);
+    }
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    let position = ctx.accounts.position.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let curtime = perpetuals.get_time()?;
+    require!(
+        pool.is_winding_down() && curtime >= pool.wind_down_deadline,
+        PerpetualsError::InvalidPoolState
+    );
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    // settle at the oracle price, neither the spread, the price impact nor the exit fee is charged
+    msg!("Settle position");
+    let mut settlement_custody: Custody = (**custody).clone();
+    settlement_custody.pricing.trade_spread_long = 0;
+    settlement_custody.pricing.trade_spread_short = 0;
+    settlement_custody.pricing.use_dynamic_spread = false;
+    settlement_custody.pricing.price_impact_mult = 0;
+    settlement_custody.fees.close_position = 0;
+
+    let (transfer_amount, _, profit_usd, loss_usd, shortfall_usd) = pool
+        .get_close_amount_with_shortfall(
+            position,
+            &token_price,
+            &token_ema_price,
+            &settlement_custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            false,
+            0,
+        )?;
+
+    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+    msg!("Amount out: {}", transfer_amount);
+
+    // unlock pool funds
+    collateral_custody.unlock_funds(position.locked_amount)?;
+
+    // check pool constraints
+    msg!("Check pool constraints");
+    require!(
+        pool.check_available_amount(transfer_amount, collateral_custody)?,
+        PerpetualsError::CustodyAmountLimit
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    if transfer_amount > position.collateral_amount {
+        let amount_lost = transfer_amount.saturating_sub(position.collateral_amount);
+        collateral_custody.assets.owned =
+            math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
+    } else {
+        let amount_gained = position.collateral_amount.saturating_sub(transfer_amount);
+        collateral_custody.assets.owned =
+            math::checked_add(collateral_custody.assets.owned, amount_gained)?;
+    }
+    collateral_custody.assets.collateral = math::checked_sub(
+        collateral_custody.assets.collateral,
+        position.collateral_amount,
+    )?;
+
+    // margin positions draw the loss their collateral doesn't cover from the deposit,
+    // the rest of the deposit is refunded with the last position
+    if position.margin_account.is_some() {
+        msg!("Settle margin account");
+        let margin_account = ctx
+            .accounts
+            .margin_account
+            .as_mut()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let deposit_custody_key = margin_account.collateral_custody;
+
+        let mut deposit_prices = None;
+        let (deposit_custody, min_deposit_price) =
+            if deposit_custody_key == collateral_custody.key() {
+                let min_price = collateral_token_price
+                    .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
+                (&mut *collateral_custody, min_price)
+            } else if deposit_custody_key == custody.key() {
+                let min_price = token_price.get_min_price(&token_ema_price, custody.is_stable)?;
+                (&mut *custody, min_price)
+            } else {
+                let deposit_custody = ctx
+                    .accounts
+                    .deposit_custody
+                    .as_mut()
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                require_keys_eq!(deposit_custody.key(), deposit_custody_key);
+                let oracle_account = ctx
+                    .accounts
+                    .deposit_custody_oracle_account
+                    .as_ref()
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                require_keys_eq!(oracle_account.key(), deposit_custody.oracle.oracle_account);
+
+                let deposit_token_price = OraclePrice::new_from_oracle(
+                    oracle_account,
+                    None,
+                    None,
+                    &deposit_custody.oracle,
+                    curtime,
+                    false,
+                    deposit_custody.oracle.feed_id,
+                )?;
+                let deposit_token_ema_price = OraclePrice::new_from_oracle(
+                    oracle_account,
+                    None,
+                    None,
+                    &deposit_custody.oracle,
+                    curtime,
+                    deposit_custody.pricing.use_ema,
+                    deposit_custody.oracle.feed_id,
+                )?;
+                let min_price = deposit_token_price
+                    .get_min_price(&deposit_token_ema_price, deposit_custody.is_stable)?;
+                deposit_prices = Some((deposit_token_price, deposit_token_ema_price));
+                (&mut **deposit_custody, min_price)
+            };
+
+        // settlement is forced, a deposit short of the loss is drained rather than failing
+        margin_account.debit_deposit(deposit_custody, &min_deposit_price, shortfall_usd, true)?;
+
+        // custodies of the remaining positions are dropped once they are all settled
+        margin_account.remove_position(&position_key)?;
+        if margin_account.positions.is_empty() {
+            margin_account.refresh_custodies(&[]);
+
+            let refund_amount = margin_account.collateral_amount;
+            msg!("Refund deposit: {}", refund_amount);
+            margin_account.collateral_amount = 0;
+            deposit_custody.assets.collateral =
+                math::checked_sub(deposit_custody.assets.collateral, refund_amount)?;
+
+            if refund_amount > 0 {
+                let deposit_custody_token_account = ctx
+                    .accounts
+                    .deposit_custody_token_account
+                    .as_ref()
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                require_keys_eq!(
+                    deposit_custody_token_account.key(),
+                    deposit_custody.token_account
+                );
+                let deposit_receiving_account = ctx
+                    .accounts
+                    .deposit_receiving_account
+                    .as_ref()
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                require_keys_eq!(deposit_receiving_account.mint, deposit_custody.mint);
+
+                perpetuals.transfer_tokens(
+                    deposit_custody_token_account.to_account_info(),
+                    deposit_receiving_account.to_account_info(),
+                    ctx.accounts.transfer_authority.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                    refund_amount,
+                )?;
+            }
+        }
+
+        // custody and collateral_custody valuations are updated below
+        if let Some((deposit_token_price, deposit_token_ema_price)) = deposit_prices {
+            if !deposit_custody.is_virtual {
+                deposit_custody.update_borrow_rate(curtime)?;
+            }
+            pool.update_cached_custody_aum(
+                deposit_custody,
+                &deposit_token_price,
+                &deposit_token_ema_price,
+                curtime,
+            )?;
+        }
+    }
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if position.side == Side::Long && !custody.is_virtual {
+        collateral_custody.volume_stats.close_position_usd = collateral_custody
+            .volume_stats
+            .close_position_usd
+            .wrapping_add(position.size_usd);
+
+        collateral_custody.trade_stats.oi_long_usd = collateral_custody
+            .trade_stats
+            .oi_long_usd
+            .saturating_sub(position.size_usd);
+
+        collateral_custody.trade_stats.profit_usd = collateral_custody
+            .trade_stats
+            .profit_usd
+            .wrapping_add(profit_usd);
+        collateral_custody.trade_stats.loss_usd = collateral_custody
+            .trade_stats
+            .loss_usd
+            .wrapping_add(loss_usd);
+
+        collateral_custody.remove_position(position, curtime, None)?;
+        collateral_custody.update_borrow_rate(curtime)?;
+        *custody = collateral_custody.clone();
+    } else {
+        custody.volume_stats.close_position_usd = custody
+            .volume_stats
+            .close_position_usd
+            .wrapping_add(position.size_usd);
+
+        if position.side == Side::Long {
+            custody.trade_stats.oi_long_usd = custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(position.size_usd);
+        } else {
+            custody.trade_stats.oi_short_usd = custody
+                .trade_stats
+                .oi_short_usd
+                .saturating_sub(position.size_usd);
+        }
+
+        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
+        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
+
+        custody.remove_position(position, curtime, Some(collateral_custody))?;
+        collateral_custody.update_borrow_rate(curtime)?;
+    }
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+
+    Ok(())
+}
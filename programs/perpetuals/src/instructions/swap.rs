@@ -3,8 +3,10 @@
 use {
     crate::{
         error::PerpetualsError,
-        math,
-        state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
+        state::{
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            referral::Referral, trader_stats::TraderStats,
+        },
     },
     anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
     anchor_spl::token::{Token, TokenAccount},
@@ -68,6 +70,9 @@ pub struct Swap<'info> {
 
     pub receiving_custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against receiving_custody.oracle.reference_oracle_account
+    pub receiving_custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     // pub custody_oracle_account: Account<'info, PriceUpdateV2>,
 
     // pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
@@ -96,6 +101,9 @@ pub struct Swap<'info> {
 
     pub dispensing_custody_twap_account: Option<Account<'info, TwapUpdate>>,
 
+    /// CHECK: optional reference price, checked against dispensing_custody.oracle.reference_oracle_account
+    pub dispensing_custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         // seeds = [b"custody_token_account",
@@ -105,6 +113,20 @@ pub struct Swap<'info> {
     )]
     pub dispensing_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // optional referrer, receives a rebate and gives the trader a fee discount
+    #[account(
+        mut,
+        constraint = referral.owner != owner.key() @ PerpetualsError::InvalidReferral
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // optional trader volume stats, used for the fee tier discount
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub trader_stats: Option<Box<Account<'info, TraderStats>>>,
+
     token_program: Program<'info, Token>,
 }
 
@@ -146,6 +168,12 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     let received_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.receiving_custody_oracle_account,
         ctx.accounts.receiving_custody_twap_account.as_ref(),
+        receiving_custody.oracle.get_reference_account(
+            ctx.accounts
+                .receiving_custody_reference_oracle_account
+                .as_deref(),
+            false,
+        )?,
         &receiving_custody.oracle,
         curtime,
         false,
@@ -155,6 +183,7 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     let received_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.receiving_custody_oracle_account,
         ctx.accounts.receiving_custody_twap_account.as_ref(),
+        None,
         &receiving_custody.oracle,
         curtime,
         receiving_custody.pricing.use_ema,
@@ -164,6 +193,12 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     let dispensed_token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.dispensing_custody_oracle_account,
         ctx.accounts.dispensing_custody_twap_account.as_ref(),
+        dispensing_custody.oracle.get_reference_account(
+            ctx.accounts
+                .dispensing_custody_reference_oracle_account
+                .as_deref(),
+            false,
+        )?,
         &dispensing_custody.oracle,
         curtime,
         false,
@@ -173,75 +208,55 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     let dispensed_token_ema_price = OraclePrice::new_from_oracle(
         &ctx.accounts.dispensing_custody_oracle_account,
         ctx.accounts.dispensing_custody_twap_account.as_ref(),
+        None,
         &dispensing_custody.oracle,
         curtime,
-        false,
+        dispensing_custody.pricing.use_ema,
         dispensing_custody.oracle.feed_id,
     )?;
 
-    msg!("Compute swap amount");
-    let amount_out = pool.get_swap_amount(
-        &received_token_price,
-        &received_token_ema_price,
-        &dispensed_token_price,
-        &dispensed_token_ema_price,
-        receiving_custody,
-        dispensing_custody,
-        params.amount_in,
-    )?;
+    let fee_discount = if let Some(trader_stats) = ctx.accounts.trader_stats.as_ref() {
+        perpetuals.get_fee_discount(trader_stats.get_volume_usd(curtime)?)
+    } else {
+        0
+    };
+
+    // the rebate stays in the fee if the referral can't track another custody
+    let accrue_rebate = ctx
+        .accounts
+        .referral
+        .as_ref()
+        .is_some_and(|referral| referral.can_accrue(&dispensing_custody.key()));
 
-    // calculate fee
-    let fees = pool.get_swap_fees(
+    msg!("Compute swap amount");
+    let (no_fee_amount, rebate) = pool.swap_internal_with_referral(
         token_id_in,
         token_id_out,
         params.amount_in,
-        amount_out,
+        params.min_amount_out,
         receiving_custody,
         &received_token_price,
+        &received_token_ema_price,
         dispensing_custody,
         &dispensed_token_price,
+        &dispensed_token_ema_price,
+        curtime,
+        ctx.accounts.referral.is_some(),
+        accrue_rebate,
+        fee_discount,
     )?;
-    msg!("Collected fees: {} {}", fees.0, fees.1);
 
-    // check returned amount
-    let no_fee_amount = math::checked_sub(amount_out, fees.1)?;
-    msg!("Amount out: {}", no_fee_amount);
-    require_gte!(
-        no_fee_amount,
-        params.min_amount_out,
-        PerpetualsError::InsufficientAmountReturned
-    );
+    let volume_usd =
+        received_token_price.get_asset_amount_usd(params.amount_in, receiving_custody.decimals)?;
 
-    // check pool constraints
-    msg!("Check pool constraints");
-    let protocol_fee_in = Pool::get_fee_amount(receiving_custody.fees.protocol_share, fees.0)?;
-    let protocol_fee_out = Pool::get_fee_amount(dispensing_custody.fees.protocol_share, fees.1)?;
-    let deposit_amount = math::checked_sub(params.amount_in, protocol_fee_in)?;
-    let withdrawal_amount = math::checked_add(no_fee_amount, protocol_fee_out)?;
+    if let Some(referral) = ctx.accounts.referral.as_mut() {
+        msg!("Accrue referral rebate: {}", rebate);
+        referral.accrue(dispensing_custody.key(), rebate, volume_usd)?;
+    }
 
-    require!(
-        pool.check_token_ratio(
-            token_id_in,
-            deposit_amount,
-            0,
-            receiving_custody,
-            &received_token_price
-        )? && pool.check_token_ratio(
-            token_id_out,
-            0,
-            withdrawal_amount,
-            dispensing_custody,
-            &dispensed_token_price
-        )?,
-        PerpetualsError::TokenRatioOutOfRange
-    );
-    require!(
-        math::checked_sub(
-            dispensing_custody.assets.owned,
-            dispensing_custody.assets.locked
-        )? >= withdrawal_amount,
-        PerpetualsError::CustodyAmountLimit
-    );
+    if let Some(trader_stats) = ctx.accounts.trader_stats.as_mut() {
+        trader_stats.add_volume(volume_usd, curtime)?;
+    }
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -265,41 +280,19 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
         no_fee_amount,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    receiving_custody.volume_stats.swap_usd = receiving_custody.volume_stats.swap_usd.wrapping_add(
-        received_token_price.get_asset_amount_usd(params.amount_in, receiving_custody.decimals)?,
-    );
-
-    receiving_custody.collected_fees.swap_usd =
-        receiving_custody.collected_fees.swap_usd.wrapping_add(
-            received_token_price.get_asset_amount_usd(fees.0, receiving_custody.decimals)?,
-        );
-
-    receiving_custody.assets.owned =
-        math::checked_add(receiving_custody.assets.owned, deposit_amount)?;
-
-    receiving_custody.assets.protocol_fees =
-        math::checked_add(receiving_custody.assets.protocol_fees, protocol_fee_in)?;
-
-    dispensing_custody.collected_fees.swap_usd =
-        dispensing_custody.collected_fees.swap_usd.wrapping_add(
-            dispensed_token_price.get_asset_amount_usd(fees.1, dispensing_custody.decimals)?,
-        );
-
-    dispensing_custody.volume_stats.swap_usd =
-        dispensing_custody.volume_stats.swap_usd.wrapping_add(
-            dispensed_token_price.get_asset_amount_usd(amount_out, dispensing_custody.decimals)?,
-        );
-
-    dispensing_custody.assets.protocol_fees =
-        math::checked_add(dispensing_custody.assets.protocol_fees, protocol_fee_out)?;
-
-    dispensing_custody.assets.owned =
-        math::checked_sub(dispensing_custody.assets.owned, withdrawal_amount)?;
-
-    receiving_custody.update_borrow_rate(curtime)?;
-    dispensing_custody.update_borrow_rate(curtime)?;
+    // update cached custody valuations
+    pool.update_cached_custody_aum(
+        receiving_custody,
+        &received_token_price,
+        &received_token_ema_price,
+        curtime,
+    )?;
+    pool.update_cached_custody_aum(
+        dispensing_custody,
+        &dispensed_token_price,
+        &dispensed_token_ema_price,
+        curtime,
+    )?;
 
     Ok(())
 }
@@ -0,0 +1,312 @@
+//! SwapPositionCollateral instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool,
+            position::Position,
+        },
+    },
+    anchor_lang::prelude::*,
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+#[instruction(params: SwapPositionCollateralParams)]
+pub struct SwapPositionCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    // )]
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    // )]
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          new_collateral_custody.mint.as_ref()],
+        // bump = new_collateral_custody.bump
+        constraint = new_collateral_custody.pool == pool.key()
+    )]
+    pub new_collateral_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = new_collateral_custody_oracle_account.key() == new_collateral_custody.oracle.oracle_account
+    // )]
+    pub new_collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub new_collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SwapPositionCollateralParams {
+    // minimum collateral received, in new collateral tokens
+    pub min_amount_out: u64,
+}
+
+pub fn swap_position_collateral(
+    ctx: Context<SwapPositionCollateral>,
+    params: &SwapPositionCollateralParams,
+) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    let new_collateral_custody = ctx.accounts.new_collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_swap
+            && perpetuals.permissions.allow_collateral_withdrawal
+            && custody.permissions.allow_collateral_withdrawal
+            && new_collateral_custody.permissions.allow_open_position,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    require!(
+        collateral_custody.is_stable && new_collateral_custody.is_stable,
+        PerpetualsError::InvalidCollateralCustody
+    );
+    require_keys_neq!(collateral_custody.key(), new_collateral_custody.key());
+    require_keys_neq!(custody.key(), collateral_custody.key());
+    require_keys_neq!(custody.key(), new_collateral_custody.key());
+    let position = ctx.accounts.position.as_mut();
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
+    let pool = ctx.accounts.pool.as_mut();
+
+    // compute prices
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let new_collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.new_collateral_custody_oracle_account,
+        ctx.accounts.new_collateral_custody_twap_account.as_ref(),
+        None,
+        &new_collateral_custody.oracle,
+        curtime,
+        false,
+        new_collateral_custody.oracle.feed_id,
+    )?;
+
+    let new_collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.new_collateral_custody_oracle_account,
+        ctx.accounts.new_collateral_custody_twap_account.as_ref(),
+        None,
+        &new_collateral_custody.oracle,
+        curtime,
+        new_collateral_custody.pricing.use_ema,
+        new_collateral_custody.oracle.feed_id,
+    )?;
+
+    if new_collateral_custody.pricing.block_positions_on_depeg {
+        require!(
+            !new_collateral_custody.is_depegged(&new_collateral_token_price)?,
+            PerpetualsError::StablecoinDepegged
+        );
+    }
+
+    // remove the position from custody stats while its collateral changes
+    custody.remove_position(position, curtime, Some(collateral_custody))?;
+
+    // swap the collateral, tokens stay in the pool and become the new custody's collateral
+    msg!("Swap collateral");
+    collateral_custody.assets.collateral = math::checked_sub(
+        collateral_custody.assets.collateral,
+        position.collateral_amount,
+    )?;
+    let collateral_amount = pool.swap_internal(
+        pool.get_token_id(&collateral_custody.key())?,
+        pool.get_token_id(&new_collateral_custody.key())?,
+        position.collateral_amount,
+        params.min_amount_out,
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        new_collateral_custody,
+        &new_collateral_token_price,
+        &new_collateral_token_ema_price,
+        curtime,
+    )?;
+    msg!("Amount out: {}", collateral_amount);
+    new_collateral_custody.assets.collateral =
+        math::checked_add(new_collateral_custody.assets.collateral, collateral_amount)?;
+
+    // move locked funds at oracle prices
+    let locked_amount_usd = collateral_token_price
+        .get_asset_amount_usd(position.locked_amount, collateral_custody.decimals)?;
+    let locked_amount = new_collateral_token_price
+        .get_token_amount(locked_amount_usd, new_collateral_custody.decimals)?;
+    collateral_custody.unlock_funds(position.locked_amount)?;
+    new_collateral_custody.lock_funds(locked_amount)?;
+
+    // update position, entry price and the interest accrued so far are kept,
+    // the snapshot sits at the same distance below the new custody's cumulative interest
+    msg!("Update existing position");
+    let accrued_interest = collateral_custody
+        .get_cumulative_interest(curtime)?
+        .saturating_sub(position.cumulative_interest_snapshot);
+    let new_cumulative_interest = new_collateral_custody.get_cumulative_interest(curtime)?;
+    if accrued_interest > new_cumulative_interest {
+        // the part the new custody's history can't hold is carried in unrealized loss
+        let carried_interest_usd = math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                accrued_interest - new_cumulative_interest,
+                position.borrow_size_usd as u128,
+            )?,
+            Perpetuals::RATE_POWER,
+        )?)?;
+        position.unrealized_loss_usd =
+            math::checked_add(position.unrealized_loss_usd, carried_interest_usd)?;
+    }
+    position.cumulative_interest_snapshot =
+        new_cumulative_interest.saturating_sub(accrued_interest);
+    position.collateral_custody = new_collateral_custody.key();
+    position.collateral_amount = collateral_amount;
+    position.collateral_usd = new_collateral_token_price
+        .get_min_price(&new_collateral_token_ema_price, true)?
+        .get_asset_amount_usd(collateral_amount, new_collateral_custody.decimals)?;
+    position.locked_amount = locked_amount;
+    position.update_time = curtime;
+
+    // check position risk
+    msg!("Check position risks");
+    require!(
+        position.locked_amount > 0,
+        PerpetualsError::InsufficientAmountReturned
+    );
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &new_collateral_token_price,
+            &new_collateral_token_ema_price,
+            new_collateral_custody,
+            curtime,
+            true
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // update custody stats
+    msg!("Update custody stats");
+    custody.add_position(
+        position,
+        &token_ema_price,
+        curtime,
+        Some(new_collateral_custody),
+    )?;
+    collateral_custody.update_borrow_rate(curtime)?;
+    new_collateral_custody.update_borrow_rate(curtime)?;
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+    pool.update_cached_custody_aum(
+        new_collateral_custody,
+        &new_collateral_token_price,
+        &new_collateral_token_ema_price,
+        curtime,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! TransferPosition instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody, perpetuals::Perpetuals, pool::Pool, position::Position,
+            user_positions::UserPositions,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: TransferPositionParams)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: any wallet can receive a position
+    pub new_owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [b"position",
+                 new_owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump
+    )]
+    pub new_position: Box<Account<'info, Position>>,
+
+    #[account(
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    // optional position indexes of the previous and the new owner
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub user_positions: Option<Box<Account<'info, UserPositions>>>,
+
+    #[account(
+        mut,
+        constraint = new_owner_user_positions.owner == new_owner.key()
+    )]
+    pub new_owner_user_positions: Option<Box<Account<'info, UserPositions>>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct TransferPositionParams {}
+
+pub fn transfer_position(
+    ctx: Context<TransferPosition>,
+    _params: &TransferPositionParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    let position = ctx.accounts.position.as_ref();
+    require_keys_neq!(ctx.accounts.owner.key(), ctx.accounts.new_owner.key());
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
+
+    // move the position to the new owner's PDA, the old account is closed
+    msg!("Transfer position");
+    let new_position = ctx.accounts.new_position.as_mut();
+    new_position.owner = ctx.accounts.new_owner.key();
+    new_position.pool = position.pool;
+    new_position.custody = position.custody;
+    new_position.collateral_custody = position.collateral_custody;
+    new_position.open_time = position.open_time;
+    new_position.update_time = position.update_time;
+    new_position.side = position.side;
+    new_position.price = position.price;
+    new_position.size_usd = position.size_usd;
+    new_position.borrow_size_usd = position.borrow_size_usd;
+    new_position.collateral_usd = position.collateral_usd;
+    new_position.unrealized_profit_usd = position.unrealized_profit_usd;
+    new_position.unrealized_loss_usd = position.unrealized_loss_usd;
+    new_position.cumulative_interest_snapshot = position.cumulative_interest_snapshot;
+    new_position.locked_amount = position.locked_amount;
+    new_position.collateral_amount = position.collateral_amount;
+    new_position.take_profit_price = position.take_profit_price;
+    new_position.stop_loss_price = position.stop_loss_price;
+    new_position.margin_account = None;
+    new_position.position_id = position.position_id;
+    new_position.bump = ctx.bumps.new_position;
+
+    // keep the position indexes up to date
+    if let Some(user_positions) = ctx.accounts.user_positions.as_mut() {
+        user_positions.remove_position(&ctx.accounts.position.key());
+    }
+    if let Some(user_positions) = ctx.accounts.new_owner_user_positions.as_mut() {
+        user_positions.add_position(ctx.accounts.new_position.key())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! UpdateCustodyAum instruction handler
+
+use {
+    crate::state::{
+        custody::Custody,
+        oracle::OraclePrice,
+        perpetuals::Perpetuals,
+        pool::{AumCalcMode, Pool},
+    },
+    anchor_lang::prelude::*,
+    pyth_solana_receiver_sdk::price_update::PriceUpdateV2,
+};
+
+#[derive(Accounts)]
+pub struct UpdateCustodyAum<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        constraint = custody.pool == pool.key()
+        // seeds = [b"custody",
+        //          pool.key().as_ref(),
+        //          custody.mint.as_ref()],
+        // bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    )]
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+}
+
+pub fn update_custody_aum(ctx: Context<UpdateCustodyAum>) -> Result<u128> {
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let custody = ctx.accounts.custody.as_ref();
+    let pool = ctx.accounts.pool.as_mut();
+    let token_id = pool.get_token_id(&custody.key())?;
+
+    let curtime = perpetuals.get_time()?;
+
+    // same prices as the full pool re-pricing in get_assets_under_management_usd
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        None,
+        None,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        None,
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    // update cached custody valuation
+    msg!("Update custody asset under management");
+
+    msg!("Previous pool value: {}", pool.aum_usd);
+
+    let custody_aum = pool.get_custody_aum(
+        custody,
+        &token_price,
+        &token_ema_price,
+        AumCalcMode::EMA,
+        curtime,
+    )?;
+    pool.set_cached_custody_aum(token_id, custody_aum)?;
+
+    let aum_usd = custody_aum.get_aum_usd()?;
+    msg!("Custody value: {}", aum_usd);
+    msg!("Updated pool value: {}", pool.aum_usd);
+
+    Ok(aum_usd)
+}
@@ -3,7 +3,7 @@
 use {
     crate::state::{
         perpetuals::Perpetuals,
-        pool::{AumCalcMode, Pool},
+        pool::{AumCache, AumCalcMode, Pool, TokenRatios},
     },
     anchor_lang::prelude::*,
 };
@@ -25,13 +25,21 @@ pub struct UpdatePoolAum<'info> {
     )]
     pub perpetuals: Box<Account<'info, Perpetuals>>,
 
+    // the pool is resized to backfill the cache of pools created before it was introduced
     #[account(
         mut,
+        realloc = Pool::LEN + pool.custodies.len() * std::mem::size_of::<Pubkey>() +
+                              pool.ratios.len() * std::mem::size_of::<TokenRatios>() +
+                              pool.custodies.len() * std::mem::size_of::<AumCache>(),
+        realloc::payer = payer,
+        realloc::zero = false,
         // seeds = [b"pool",
         //          pool.name.as_bytes()],
         // bump = pool.bump
     )]
     pub pool: Box<Account<'info, Pool>>,
+
+    system_program: Program<'info, System>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
@@ -48,12 +56,11 @@ pub fn update_pool_aum(ctx: Context<UpdatePoolAum>) -> Result<u128> {
 
     msg!("Previous value: {}", pool.aum_usd);
 
-    pool.aum_usd = pool.get_assets_under_management_usd(
-        AumCalcMode::EMA,
-        ctx.remaining_accounts,
-        curtime,
-        // params.feed_id,
-    )?;
+    // refresh cached custody valuations along with the total
+    pool.align_aum_cache();
+    let custodies_aum =
+        pool.get_custodies_aum(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+    pool.set_aum_cache(custodies_aum)?;
 
     msg!("Updated value: {}", pool.aum_usd);
 
@@ -0,0 +1,87 @@
+//! UpdatePositionTriggers instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            delegate::{Delegate, DelegateAction},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::Position,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: UpdatePositionTriggersParams)]
+pub struct UpdatePositionTriggers<'info> {
+    pub signer: Signer<'info>,
+
+    /// CHECK: position owner, checked by the position constraints
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 position.custody.as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    // optional delegation, required when signer is not the position owner
+    pub delegate: Option<Account<'info, Delegate>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpdatePositionTriggersParams {
+    pub take_profit_price: Option<u64>,
+    pub stop_loss_price: Option<u64>,
+}
+
+pub fn update_position_triggers(
+    ctx: Context<UpdatePositionTriggers>,
+    params: &UpdatePositionTriggersParams,
+) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+    let position = ctx.accounts.position.as_mut();
+    Delegate::check_signer(
+        &ctx.accounts.signer.key(),
+        position,
+        ctx.accounts.delegate.as_deref(),
+        DelegateAction::UpdateTriggers,
+        curtime,
+    )?;
+
+    // update take profit and stop loss prices
+    msg!("Update position triggers");
+    position.take_profit_price = params.take_profit_price;
+    position.stop_loss_price = params.stop_loss_price;
+    require!(
+        position.validate_triggers(),
+        PerpetualsError::InvalidTriggerPrice
+    );
+
+    Ok(())
+}
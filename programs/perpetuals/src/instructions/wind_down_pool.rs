@@ -0,0 +1,74 @@
+//! WindDownPool instruction handler
+
+use {
+    crate::state::{
+        multisig::{AdminInstruction, Multisig},
+        perpetuals::Perpetuals,
+        pool::Pool,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct WindDownPool<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WindDownPoolParams {
+    // open positions can be force-settled once this time has passed
+    pub deadline: i64,
+}
+
+pub fn wind_down_pool<'info>(
+    ctx: Context<'_, '_, '_, 'info, WindDownPool<'info>>,
+    params: &WindDownPoolParams,
+) -> Result<u8> {
+    // validate inputs
+    if params.deadline <= 0 || params.deadline < ctx.accounts.perpetuals.get_time()? {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::WindDownPool, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // the deadline can be moved but wind-down can't be cancelled
+    ctx.accounts.pool.wind_down_deadline = params.deadline;
+
+    Ok(0)
+}
@@ -74,7 +74,7 @@ pub struct WithdrawFees<'info> {
     token_program: Program<'info, Token>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct WithdrawFeesParams {
     pub amount: u64,
 }
@@ -0,0 +1,359 @@
+//! WithdrawProfit instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+        },
+    },
+    anchor_lang::{prelude::*, solana_program::program_error::ProgramError},
+    anchor_spl::token::{Token, TokenAccount},
+    pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, TwapUpdate},
+};
+
+#[derive(Accounts)]
+#[instruction(params: WithdrawProfitParams)]
+pub struct WithdrawProfit<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == collateral_custody.mint,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = perpetuals.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        // seeds = [b"pool",
+        //          pool.name.as_bytes()],
+        // bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[position.side as u8],
+                 Position::get_id_seed(&position.position_id)],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        constraint = position.custody == custody.key()
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+    // )]
+    pub custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    /// CHECK: optional reference price, checked against custody.oracle.reference_oracle_account
+    pub custody_reference_oracle_account: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        constraint = position.collateral_custody == collateral_custody.key()
+    )]
+    pub collateral_custody: Box<Account<'info, Custody>>,
+
+    // #[account(
+    //     constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+    // )]
+    pub collateral_custody_oracle_account: Account<'info, PriceUpdateV2>,
+    pub collateral_custody_twap_account: Option<Account<'info, TwapUpdate>>,
+
+    #[account(
+        mut,
+        // seeds = [b"custody_token_account",
+        //          pool.key().as_ref(),
+        //          collateral_custody.mint.as_ref()],
+        // bump = collateral_custody.token_account_bump
+    )]
+    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct WithdrawProfitParams {
+    pub profit_usd: u64,
+    // feed_id: [u8; 32],
+}
+
+pub fn withdraw_profit(ctx: Context<WithdrawProfit>, params: &WithdrawProfitParams) -> Result<()> {
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let collateral_custody = ctx.accounts.collateral_custody.as_mut();
+    require!(
+        perpetuals.permissions.allow_pnl_withdrawal && custody.permissions.allow_pnl_withdrawal,
+        PerpetualsError::InstructionNotAllowed
+    );
+
+    // validate inputs
+    msg!("Validate inputs");
+    let position = ctx.accounts.position.as_mut();
+    if params.profit_usd == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require!(
+        position.margin_account.is_none(),
+        PerpetualsError::PositionInMarginAccount
+    );
+    let pool = ctx.accounts.pool.as_mut();
+
+    // compute position price
+    let curtime = perpetuals.get_time()?;
+
+    let token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        custody.oracle.get_reference_account(
+            ctx.accounts.custody_reference_oracle_account.as_deref(),
+            false,
+        )?,
+        &custody.oracle,
+        curtime,
+        false,
+        custody.oracle.feed_id,
+    )?;
+
+    let token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.custody_oracle_account,
+        ctx.accounts.custody_twap_account.as_ref(),
+        None,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+        custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        false,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    let collateral_token_ema_price = OraclePrice::new_from_oracle(
+        &ctx.accounts.collateral_custody_oracle_account,
+        ctx.accounts.collateral_custody_twap_account.as_ref(),
+        None,
+        &collateral_custody.oracle,
+        curtime,
+        collateral_custody.pricing.use_ema,
+        collateral_custody.oracle.feed_id,
+    )?;
+
+    // compute amount to transfer
+    let (profit_usd, _, _) = pool.get_exit_pnl_usd(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        false,
+        0,
+    )?;
+    msg!("Unrealized profit: {}", profit_usd);
+    if params.profit_usd > profit_usd {
+        return Err(ProgramError::InsufficientFunds.into());
+    }
+
+    let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
+        collateral_token_price
+    } else {
+        collateral_token_ema_price
+    };
+    let transfer_amount =
+        max_collateral_price.get_token_amount(params.profit_usd, collateral_custody.decimals)?;
+    if transfer_amount == 0 || transfer_amount > position.locked_amount {
+        return Err(ProgramError::InsufficientFunds.into());
+    }
+    msg!("Amount out: {}", transfer_amount);
+
+    // remove the position from custody stats while its entry basis changes
+    let same_custody = position.side == Side::Long && !custody.is_virtual;
+    if same_custody {
+        collateral_custody.remove_position(position, curtime, None)?;
+    } else {
+        custody.remove_position(position, curtime, Some(collateral_custody))?;
+    }
+
+    // reset entry basis to the mark price get_pnl_usd values the position at, price pnl and
+    // accrued interest are carried in unrealized profit and loss so they are not counted twice.
+    // Price impact stays out of the basis and is charged again on the next exit.
+    msg!("Update existing position");
+    let mark_price =
+        pool.get_exit_price(&token_price, &token_ema_price, position.side, 0, custody)?;
+    let position_price = math::scale_to_exponent(
+        position.price,
+        -(Perpetuals::PRICE_DECIMALS as i32),
+        -(Perpetuals::USD_DECIMALS as i32),
+    )?;
+    let (price_diff_profit, price_diff_loss) = if position.side == Side::Long {
+        if mark_price > position.price {
+            (math::checked_sub(mark_price, position.price)?, 0u64)
+        } else {
+            (0u64, math::checked_sub(position.price, mark_price)?)
+        }
+    } else if mark_price < position.price {
+        (math::checked_sub(position.price, mark_price)?, 0u64)
+    } else {
+        (0u64, math::checked_sub(mark_price, position.price)?)
+    };
+    if price_diff_profit > 0 {
+        let price_profit_usd = math::checked_as_u64(math::checked_div(
+            math::checked_mul(position.size_usd as u128, price_diff_profit as u128)?,
+            position_price as u128,
+        )?)?;
+        position.unrealized_profit_usd =
+            math::checked_add(position.unrealized_profit_usd, price_profit_usd)?;
+    } else {
+        let price_loss_usd = math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(position.size_usd as u128, price_diff_loss as u128)?,
+            position_price as u128,
+        )?)?;
+        position.unrealized_loss_usd =
+            math::checked_add(position.unrealized_loss_usd, price_loss_usd)?;
+    }
+
+    let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+    position.unrealized_loss_usd = math::checked_add(position.unrealized_loss_usd, interest_usd)?;
+    position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
+
+    position.unrealized_profit_usd =
+        math::checked_sub(position.unrealized_profit_usd, params.profit_usd)?;
+    position.price = mark_price;
+    position.update_time = curtime;
+
+    // unlock the share of pool funds backing the withdrawn profit
+    let locked_amount = position.locked_amount;
+    position.locked_amount = math::checked_sub(position.locked_amount, transfer_amount)?;
+    if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+        position.borrow_size_usd = math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                position.borrow_size_usd as u128,
+                position.locked_amount as u128,
+            )?,
+            locked_amount as u128,
+        )?)?;
+    }
+    collateral_custody.unlock_funds(transfer_amount)?;
+
+    // check pool constraints
+    msg!("Check pool constraints");
+    require!(
+        pool.check_available_amount(transfer_amount, collateral_custody)?,
+        PerpetualsError::CustodyAmountLimit
+    );
+
+    // check position risk
+    msg!("Check position risks");
+    if same_custody {
+        *custody = collateral_custody.clone();
+    }
+    require!(
+        pool.check_leverage(
+            position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            true
+        )?,
+        PerpetualsError::MaxLeverage
+    );
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    perpetuals.transfer_tokens(
+        ctx.accounts
+            .collateral_custody_token_account
+            .to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    // update custody stats
+    msg!("Update custody stats");
+    collateral_custody.assets.owned =
+        math::checked_sub(collateral_custody.assets.owned, transfer_amount)?;
+
+    // if custody and collateral_custody accounts are the same, ensure that data is in sync
+    if same_custody {
+        collateral_custody.trade_stats.profit_usd = collateral_custody
+            .trade_stats
+            .profit_usd
+            .wrapping_add(params.profit_usd);
+
+        collateral_custody.add_position(position, &token_ema_price, curtime, None)?;
+        collateral_custody.update_borrow_rate(curtime)?;
+        *custody = collateral_custody.clone();
+    } else {
+        custody.trade_stats.profit_usd = custody
+            .trade_stats
+            .profit_usd
+            .wrapping_add(params.profit_usd);
+
+        custody.add_position(
+            position,
+            &token_ema_price,
+            curtime,
+            Some(collateral_custody),
+        )?;
+        collateral_custody.update_borrow_rate(curtime)?;
+    }
+
+    // update cached custody valuations
+    pool.update_cached_custody_aum(custody, &token_price, &token_ema_price, curtime)?;
+    pool.update_cached_custody_aum(
+        collateral_custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        curtime,
+    )?;
+
+    Ok(())
+}
@@ -13,7 +13,8 @@ use {
     anchor_lang::prelude::*,
     instructions::*,
     state::perpetuals::{
-        AmountAndFee, NewPositionPricesAndFee, PriceAndFee, ProfitAndLoss, SwapAmountAndFees,
+        AmountAndFee, AumBreakdown, NewPositionPricesAndFee, OpenInterestHeadroom, PositionInfo,
+        PriceAndFee, ProfitAndLoss, RemoveCollateralInfo, SwapAmountAndFees,
     },
 };
 
@@ -123,6 +124,27 @@ pub mod perpetuals {
         instructions::upgrade_custody(ctx, &params)
     }
 
+    pub fn wind_down_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, WindDownPool<'info>>,
+        params: WindDownPoolParams,
+    ) -> Result<u8> {
+        instructions::wind_down_pool(ctx, &params)
+    }
+
+    pub fn set_fee_tiers<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetFeeTiers<'info>>,
+        params: SetFeeTiersParams,
+    ) -> Result<u8> {
+        instructions::set_fee_tiers(ctx, &params)
+    }
+
+    pub fn set_pool_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+        params: SetPoolConfigParams,
+    ) -> Result<u8> {
+        instructions::set_pool_config(ctx, &params)
+    }
+
     pub fn set_custom_oracle_price<'info>(
         ctx: Context<'_, '_, '_, 'info, SetCustomOraclePrice<'info>>,
         params: SetCustomOraclePriceParams,
@@ -145,6 +167,70 @@ pub mod perpetuals {
         instructions::swap(ctx, &params)
     }
 
+    pub fn place_swap_order(
+        ctx: Context<PlaceSwapOrder>,
+        params: PlaceSwapOrderParams,
+    ) -> Result<()> {
+        instructions::place_swap_order(ctx, &params)
+    }
+
+    pub fn execute_swap_order(
+        ctx: Context<ExecuteSwapOrder>,
+        params: ExecuteSwapOrderParams,
+    ) -> Result<()> {
+        instructions::execute_swap_order(ctx, &params)
+    }
+
+    pub fn cancel_swap_order(
+        ctx: Context<CancelSwapOrder>,
+        params: CancelSwapOrderParams,
+    ) -> Result<()> {
+        instructions::cancel_swap_order(ctx, &params)
+    }
+
+    pub fn init_referral(ctx: Context<InitReferral>, params: InitReferralParams) -> Result<()> {
+        instructions::init_referral(ctx, &params)
+    }
+
+    pub fn claim_referral_rebate(
+        ctx: Context<ClaimReferralRebate>,
+        params: ClaimReferralRebateParams,
+    ) -> Result<u64> {
+        instructions::claim_referral_rebate(ctx, &params)
+    }
+
+    pub fn init_trader_stats(
+        ctx: Context<InitTraderStats>,
+        params: InitTraderStatsParams,
+    ) -> Result<()> {
+        instructions::init_trader_stats(ctx, &params)
+    }
+
+    pub fn init_user_positions(
+        ctx: Context<InitUserPositions>,
+        params: InitUserPositionsParams,
+    ) -> Result<()> {
+        instructions::init_user_positions(ctx, &params)
+    }
+
+    pub fn prune_user_positions<'info>(
+        ctx: Context<'_, '_, '_, 'info, PruneUserPositions<'info>>,
+        params: PruneUserPositionsParams,
+    ) -> Result<()> {
+        instructions::prune_user_positions(ctx, &params)
+    }
+
+    pub fn set_delegate(ctx: Context<SetDelegate>, params: SetDelegateParams) -> Result<()> {
+        instructions::set_delegate(ctx, &params)
+    }
+
+    pub fn revoke_delegate(
+        ctx: Context<RevokeDelegate>,
+        params: RevokeDelegateParams,
+    ) -> Result<()> {
+        instructions::revoke_delegate(ctx, &params)
+    }
+
     pub fn add_liquidity(ctx: Context<AddLiquidity>, params: AddLiquidityParams) -> Result<()> {
         instructions::add_liquidity(ctx, &params)
     }
@@ -156,6 +242,13 @@ pub mod perpetuals {
         instructions::remove_liquidity(ctx, &params)
     }
 
+    pub fn remove_liquidity_in_kind<'info>(
+        ctx: Context<'_, '_, '_, 'info, RemoveLiquidityInKind<'info>>,
+        params: RemoveLiquidityInKindParams,
+    ) -> Result<()> {
+        instructions::remove_liquidity_in_kind(ctx, &params)
+    }
+
     pub fn open_position(ctx: Context<OpenPosition>, params: OpenPositionParams) -> Result<()> {
         instructions::open_position(ctx, &params)
     }
@@ -164,6 +257,13 @@ pub mod perpetuals {
         instructions::add_collateral(ctx, &params)
     }
 
+    pub fn update_position_triggers(
+        ctx: Context<UpdatePositionTriggers>,
+        params: UpdatePositionTriggersParams,
+    ) -> Result<()> {
+        instructions::update_position_triggers(ctx, &params)
+    }
+
     pub fn remove_collateral(
         ctx: Context<RemoveCollateral>,
         params: RemoveCollateralParams,
@@ -171,18 +271,117 @@ pub mod perpetuals {
         instructions::remove_collateral(ctx, &params)
     }
 
+    pub fn remove_collateral_amount(
+        ctx: Context<RemoveCollateralAmount>,
+        params: RemoveCollateralAmountParams,
+    ) -> Result<()> {
+        instructions::remove_collateral_amount(ctx, &params)
+    }
+
+    pub fn withdraw_profit(
+        ctx: Context<WithdrawProfit>,
+        params: WithdrawProfitParams,
+    ) -> Result<()> {
+        instructions::withdraw_profit(ctx, &params)
+    }
+
     pub fn close_position(ctx: Context<ClosePosition>, params: ClosePositionParams) -> Result<()> {
         instructions::close_position(ctx, &params)
     }
 
+    pub fn transfer_position(
+        ctx: Context<TransferPosition>,
+        params: TransferPositionParams,
+    ) -> Result<()> {
+        instructions::transfer_position(ctx, &params)
+    }
+
+    pub fn swap_position_collateral(
+        ctx: Context<SwapPositionCollateral>,
+        params: SwapPositionCollateralParams,
+    ) -> Result<()> {
+        instructions::swap_position_collateral(ctx, &params)
+    }
+
     pub fn liquidate(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
         instructions::liquidate(ctx, &params)
     }
 
+    pub fn settle_position(
+        ctx: Context<SettlePosition>,
+        params: SettlePositionParams,
+    ) -> Result<()> {
+        instructions::settle_position(ctx, &params)
+    }
+
+    pub fn settle_referral_rebate(
+        ctx: Context<SettleReferralRebate>,
+        params: SettleReferralRebateParams,
+    ) -> Result<u64> {
+        instructions::settle_referral_rebate(ctx, &params)
+    }
+
+    pub fn liquidate_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, LiquidateBatch<'info>>,
+        params: LiquidateBatchParams,
+    ) -> Result<u64> {
+        instructions::liquidate_batch(ctx, &params)
+    }
+
+    pub fn init_margin_account(
+        ctx: Context<InitMarginAccount>,
+        params: InitMarginAccountParams,
+    ) -> Result<()> {
+        instructions::init_margin_account(ctx, &params)
+    }
+
+    pub fn add_margin(ctx: Context<AddMargin>, params: AddMarginParams) -> Result<()> {
+        instructions::add_margin(ctx, &params)
+    }
+
+    pub fn remove_margin<'info>(
+        ctx: Context<'_, '_, '_, 'info, RemoveMargin<'info>>,
+        params: RemoveMarginParams,
+    ) -> Result<()> {
+        instructions::remove_margin(ctx, &params)
+    }
+
+    pub fn add_margin_position(
+        ctx: Context<AddMarginPosition>,
+        params: AddMarginPositionParams,
+    ) -> Result<()> {
+        instructions::add_margin_position(ctx, &params)
+    }
+
+    pub fn remove_margin_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, RemoveMarginPosition<'info>>,
+        params: RemoveMarginPositionParams,
+    ) -> Result<()> {
+        instructions::remove_margin_position(ctx, &params)
+    }
+
+    pub fn close_margin_position<'info>(
+        ctx: Context<'_, '_, '_, 'info, CloseMarginPosition<'info>>,
+        params: CloseMarginPositionParams,
+    ) -> Result<()> {
+        instructions::close_margin_position(ctx, &params)
+    }
+
+    pub fn liquidate_margin_account<'info>(
+        ctx: Context<'_, '_, '_, 'info, LiquidateMarginAccount<'info>>,
+        params: LiquidateMarginAccountParams,
+    ) -> Result<()> {
+        instructions::liquidate_margin_account(ctx, &params)
+    }
+
     pub fn update_pool_aum(ctx: Context<UpdatePoolAum>) -> Result<u128> {
         instructions::update_pool_aum(ctx)
     }
 
+    pub fn update_custody_aum(ctx: Context<UpdateCustodyAum>) -> Result<u128> {
+        instructions::update_custody_aum(ctx)
+    }
+
     pub fn get_add_liquidity_amount_and_fee(
         ctx: Context<GetAddLiquidityAmountAndFee>,
         params: GetAddLiquidityAmountAndFeeParams,
@@ -222,6 +421,13 @@ pub mod perpetuals {
         instructions::get_liquidation_price(ctx, &params)
     }
 
+    pub fn get_remove_collateral_info(
+        ctx: Context<GetRemoveCollateralInfo>,
+        params: GetRemoveCollateralInfoParams,
+    ) -> Result<RemoveCollateralInfo> {
+        instructions::get_remove_collateral_info(ctx, &params)
+    }
+
     pub fn get_liquidation_state(
         ctx: Context<GetLiquidationState>,
         params: GetLiquidationStateParams,
@@ -229,6 +435,13 @@ pub mod perpetuals {
         instructions::get_liquidation_state(ctx, &params)
     }
 
+    pub fn get_position_info(
+        ctx: Context<GetPositionInfo>,
+        params: GetPositionInfoParams,
+    ) -> Result<PositionInfo> {
+        instructions::get_position_info(ctx, &params)
+    }
+
     pub fn get_oracle_price(
         ctx: Context<GetOraclePrice>,
         params: GetOraclePriceParams,
@@ -257,6 +470,20 @@ pub mod perpetuals {
         instructions::get_lp_token_price(ctx, &params)
     }
 
+    pub fn get_aum_breakdown(
+        ctx: Context<GetAumBreakdown>,
+        params: GetAumBreakdownParams,
+    ) -> Result<AumBreakdown> {
+        instructions::get_aum_breakdown(ctx, &params)
+    }
+
+    pub fn get_open_interest_headroom(
+        ctx: Context<GetOpenInterestHeadroom>,
+        params: GetOpenInterestHeadroomParams,
+    ) -> Result<OpenInterestHeadroom> {
+        instructions::get_open_interest_headroom(ctx, &params)
+    }
+
     // This instruction must be part of a larger transaction where the **first** instruction
     // is an ed25519 verification of the serialized oracle price update params.
     pub fn set_custom_oracle_price_permissionless(
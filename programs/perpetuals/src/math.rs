@@ -392,6 +392,24 @@ pub fn to_token_amount(ui_amount: f64, decimals: u8) -> Result<u64> {
     )?)
 }
 
+// Sorts values in place, the median of an even count is the mean of the middle values
+pub fn median(values: &mut [u64]) -> Result<u64> {
+    if values.is_empty() {
+        msg!("Error: Median of an empty set");
+        return err!(PerpetualsError::MathOverflow);
+    }
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        Ok(values[mid])
+    } else {
+        checked_as_u64(checked_div(
+            checked_add(values[mid - 1] as u128, values[mid] as u128)?,
+            2,
+        )?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -456,4 +474,12 @@ mod tests {
         // Overflowing result
         assert!(checked_decimal_div(u64::MAX, -6, 1, -6, -6).is_err());
     }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(5, median(&mut [9, 5, 1]).unwrap());
+        assert_eq!(4, median(&mut [9, 5, 1, 3]).unwrap());
+        assert_eq!(u64::MAX, median(&mut [u64::MAX, u64::MAX]).unwrap());
+        assert!(median(&mut []).is_err());
+    }
 }
@@ -1,8 +1,14 @@
 // Program state handling.
 
 pub mod custody;
+pub mod delegate;
+pub mod margin_account;
 pub mod multisig;
 pub mod oracle;
 pub mod perpetuals;
 pub mod pool;
 pub mod position;
+pub mod referral;
+pub mod swap_order;
+pub mod trader_stats;
+pub mod user_positions;
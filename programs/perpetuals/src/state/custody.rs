@@ -37,6 +37,10 @@ pub struct Fees {
     // configs for optimal fee mode
     pub fee_max: u64,
     pub fee_optimal: u64,
+    // shares of trade fees returned to the trader and accrued to the referrer
+    // when a referral account is attached, together at most MAX_REFERRAL_SHARE
+    pub referral_discount: u64,
+    pub referral_rebate: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -78,6 +82,8 @@ pub struct Assets {
     pub owned: u64,
     // locked funds for pnl payoff
     pub locked: u64,
+    // fees reserved for referrers, not part of owned
+    pub referral_rebates: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -98,6 +104,30 @@ pub struct PricingParams {
     // USD denominated values always have implied USD_DECIMALS decimals
     pub max_position_locked_usd: u64,
     pub max_total_locked_usd: u64,
+    // open interest limits per side, zero means no limit
+    pub max_oi_long_usd: u64,
+    pub max_oi_short_usd: u64,
+    // max |oi_long_usd - oi_short_usd| as a share of the dominant side limit, zero means no limit
+    pub max_oi_imbalance: u64,
+    // dynamic spread = static spread + conf_spread_mult * conf / price
+    //                  + ema_spread_mult * |price - ema_price| / price,
+    // clamped to [min_dynamic_spread, max_dynamic_spread], ema_price is only read with use_ema
+    pub use_dynamic_spread: bool,
+    pub conf_spread_mult: u64,
+    pub ema_spread_mult: u64,
+    pub min_dynamic_spread: u64,
+    pub max_dynamic_spread: u64,
+    // price impact = price_impact_mult * trade_size_usd / (owned_usd - same side open interest),
+    // capped at max_price_impact, zero multiplier disables the impact
+    pub price_impact_mult: u64,
+    pub max_price_impact: u64,
+    // stable custody is depegged when its price is more than depeg_threshold away from 1 USD,
+    // zero disables depeg handling
+    pub depeg_threshold: u64,
+    // while depegged, reject new positions backed by this collateral
+    pub block_positions_on_depeg: bool,
+    // while depegged, convert payouts at the spot price instead of max(spot, ema)
+    pub pay_out_at_spot_on_depeg: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -213,6 +243,9 @@ impl Default for FeesMode {
 }
 
 impl Fees {
+    // a trader referring themselves through a second wallet still pays half the fee
+    pub const MAX_REFERRAL_SHARE: u64 = 5_000;
+
     pub fn validate(&self) -> bool {
         self.swap_in as u128 <= Perpetuals::BPS_POWER
             && self.swap_out as u128 <= Perpetuals::BPS_POWER
@@ -226,12 +259,65 @@ impl Fees {
             && self.protocol_share as u128 <= Perpetuals::BPS_POWER
             && self.fee_max as u128 <= Perpetuals::BPS_POWER
             && self.fee_optimal as u128 <= Perpetuals::BPS_POWER
+            && (self.referral_discount as u128 + self.referral_rebate as u128)
+                <= Fees::MAX_REFERRAL_SHARE as u128
+    }
+
+    // returns (trader discount, referrer rebate) carved out of fee_amount, rounded down
+    pub fn get_referral_split(&self, fee_amount: u64) -> Result<(u64, u64)> {
+        let share = |bps: u64| -> Result<u64> {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(fee_amount as u128, bps as u128)?,
+                Perpetuals::BPS_POWER,
+            )?)
+        };
+        Ok((share(self.referral_discount)?, share(self.referral_rebate)?))
     }
 }
 
 impl OracleParams {
     pub fn validate(&self) -> bool {
-        self.oracle_type == OracleType::None || self.oracle_account != Pubkey::default()
+        (self.oracle_type == OracleType::None || self.oracle_account != Pubkey::default())
+            && (self.min_oracle_signatures as usize) <= self.get_oracle_authorities().len()
+            && (self.get_min_oracle_signatures() < 2 || self.max_quote_spread > 0)
+            && (self.max_quote_spread as u128) < Perpetuals::BPS_POWER
+            && (self.get_oracle_authorities().is_empty() || self.message_domain != 0)
+            && (self.max_reference_deviation == 0
+                || (self.reference_oracle_type != OracleType::None
+                    && self.reference_oracle_account != Pubkey::default()
+                    && (self.max_reference_deviation as u128) < Perpetuals::BPS_POWER))
+    }
+
+    // Reference account to check the price against, None if no band is configured or the
+    // action is an exit allowed outside the band. Fails if a required account is missing.
+    pub fn get_reference_account<'a, 'info>(
+        &self,
+        reference_account: Option<&'a AccountInfo<'info>>,
+        is_exit: bool,
+    ) -> Result<Option<&'a AccountInfo<'info>>> {
+        if self.max_reference_deviation == 0 || (is_exit && self.allow_exit_outside_reference) {
+            return Ok(None);
+        }
+        reference_account
+            .map(Some)
+            .ok_or_else(|| error!(PerpetualsError::InvalidOracleAccount))
+    }
+
+    // distinct keys allowed to sign permissionless price updates
+    pub fn get_oracle_authorities(&self) -> Vec<Pubkey> {
+        let mut authorities: Vec<Pubkey> = Vec::new();
+        for authority in
+            std::iter::once(&self.oracle_authority).chain(&self.extra_oracle_authorities)
+        {
+            if *authority != Pubkey::default() && !authorities.contains(authority) {
+                authorities.push(*authority);
+            }
+        }
+        authorities
+    }
+
+    pub fn get_min_oracle_signatures(&self) -> usize {
+        std::cmp::max(1, self.min_oracle_signatures as usize)
     }
 }
 
@@ -245,6 +331,35 @@ impl PricingParams {
             && (self.swap_spread as u128) < Perpetuals::BPS_POWER
             && (self.max_utilization as u128) <= Perpetuals::BPS_POWER
             && self.max_position_locked_usd <= self.max_total_locked_usd
+            && (self.max_oi_imbalance as u128) <= Perpetuals::BPS_POWER
+            && (self.max_oi_imbalance == 0
+                || (self.max_oi_long_usd > 0 && self.max_oi_short_usd > 0))
+            && (!self.use_dynamic_spread
+                || (self.min_dynamic_spread <= self.max_dynamic_spread
+                    && (self.max_dynamic_spread as u128) < Perpetuals::BPS_POWER
+                    && (self.ema_spread_mult == 0 || self.use_ema)))
+            && (self.max_price_impact as u128) < Perpetuals::BPS_POWER
+            && (self.depeg_threshold as u128) < Perpetuals::BPS_POWER
+    }
+
+    // Returns static spread or, in dynamic mode, static spread widened by the oracle
+    // confidence and spot versus EMA divergence (all in BPS)
+    pub fn get_spread(&self, base_spread: u64, conf_bps: u64, divergence_bps: u64) -> Result<u64> {
+        if !self.use_dynamic_spread {
+            return Ok(base_spread);
+        }
+
+        let conf_spread = math::checked_as_u64(math::checked_div(
+            math::checked_mul(conf_bps as u128, self.conf_spread_mult as u128)?,
+            Perpetuals::BPS_POWER,
+        )?)?;
+        let ema_spread = math::checked_as_u64(math::checked_div(
+            math::checked_mul(divergence_bps as u128, self.ema_spread_mult as u128)?,
+            Perpetuals::BPS_POWER,
+        )?)?;
+        let spread = math::checked_add(base_spread, math::checked_add(conf_spread, ema_spread)?)?;
+
+        Ok(spread.clamp(self.min_dynamic_spread, self.max_dynamic_spread))
     }
 }
 
@@ -267,6 +382,17 @@ impl Custody {
             && self.borrow_rate.validate()
     }
 
+    pub fn is_depegged(&self, token_price: &OraclePrice) -> Result<bool> {
+        if !self.is_stable || self.pricing.depeg_threshold == 0 {
+            return Ok(false);
+        }
+        let one_usd = OraclePrice::new(
+            math::checked_pow(10u64, Perpetuals::PRICE_DECIMALS as usize)?,
+            -(Perpetuals::PRICE_DECIMALS as i32),
+        );
+        Ok(one_usd.get_divergence_bps(token_price)? > self.pricing.depeg_threshold)
+    }
+
     pub fn lock_funds(&mut self, amount: u64) -> Result<()> {
         require!(!self.is_virtual, PerpetualsError::InvalidCollateralCustody);
 
@@ -442,6 +568,74 @@ impl Custody {
         }
     }
 
+    pub fn get_open_interest_usd(&self, side: Side) -> u64 {
+        if side == Side::Long {
+            self.trade_stats.oi_long_usd
+        } else {
+            self.trade_stats.oi_short_usd
+        }
+    }
+
+    // returns open interest in USD that can still be added on (long, short) side
+    pub fn get_open_interest_headroom(&self) -> Result<(u64, u64)> {
+        let oi_long_usd = self.trade_stats.oi_long_usd;
+        let oi_short_usd = self.trade_stats.oi_short_usd;
+
+        let mut long_headroom_usd = if self.pricing.max_oi_long_usd > 0 {
+            self.pricing.max_oi_long_usd.saturating_sub(oi_long_usd)
+        } else {
+            u64::MAX
+        };
+        let mut short_headroom_usd = if self.pricing.max_oi_short_usd > 0 {
+            self.pricing.max_oi_short_usd.saturating_sub(oi_short_usd)
+        } else {
+            u64::MAX
+        };
+
+        if self.pricing.max_oi_imbalance > 0 {
+            let max_long_skew_usd = math::checked_as_u64(math::checked_div(
+                math::checked_mul(
+                    self.pricing.max_oi_long_usd as u128,
+                    self.pricing.max_oi_imbalance as u128,
+                )?,
+                Perpetuals::BPS_POWER,
+            )?)?;
+            let max_short_skew_usd = math::checked_as_u64(math::checked_div(
+                math::checked_mul(
+                    self.pricing.max_oi_short_usd as u128,
+                    self.pricing.max_oi_imbalance as u128,
+                )?,
+                Perpetuals::BPS_POWER,
+            )?)?;
+
+            long_headroom_usd = std::cmp::min(
+                long_headroom_usd,
+                oi_short_usd
+                    .saturating_add(max_long_skew_usd)
+                    .saturating_sub(oi_long_usd),
+            );
+            short_headroom_usd = std::cmp::min(
+                short_headroom_usd,
+                oi_long_usd
+                    .saturating_add(max_short_skew_usd)
+                    .saturating_sub(oi_short_usd),
+            );
+        }
+
+        Ok((long_headroom_usd, short_headroom_usd))
+    }
+
+    pub fn check_open_interest(&self, side: Side, size_usd: u64) -> Result<()> {
+        let (long_headroom_usd, short_headroom_usd) = self.get_open_interest_headroom()?;
+        let headroom_usd = if side == Side::Long {
+            long_headroom_usd
+        } else {
+            short_headroom_usd
+        };
+        require_gte!(headroom_usd, size_usd, PerpetualsError::MaxOpenInterest);
+        Ok(())
+    }
+
     pub fn add_position(
         &mut self,
         position: &Position,
@@ -648,6 +842,27 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_is_depegged() {
+        let mut custody = get_fixture();
+        let depegged_price = OraclePrice::new(950_000, -6);
+        let pegged_price = OraclePrice::new(99_500_000, -8);
+
+        // disabled by default and for non-stable custodies
+        custody.is_stable = true;
+        assert!(!custody.is_depegged(&depegged_price).unwrap());
+        custody.is_stable = false;
+        custody.pricing.depeg_threshold = 200;
+        assert!(!custody.is_depegged(&depegged_price).unwrap());
+
+        custody.is_stable = true;
+        assert!(custody.is_depegged(&depegged_price).unwrap());
+        assert!(!custody.is_depegged(&pegged_price).unwrap());
+        assert!(custody
+            .is_depegged(&OraclePrice::new(1_030_000, -6))
+            .unwrap());
+    }
+
     #[test]
     fn test_update_borrow_rate() {
         let mut custody = get_fixture();
@@ -742,4 +957,118 @@ mod test {
         custody.update_borrow_rate(3600).unwrap();
         assert_eq!(custody.borrow_rate_state.current_rate, 199400);
     }
+
+    #[test]
+    fn test_get_open_interest_headroom() {
+        let mut custody = get_fixture();
+        assert_eq!(
+            custody.get_open_interest_headroom().unwrap(),
+            (u64::MAX, u64::MAX)
+        );
+
+        // per side limits
+        custody.pricing.max_oi_long_usd = 1_000;
+        custody.pricing.max_oi_short_usd = 2_000;
+        custody.trade_stats.oi_long_usd = 400;
+        custody.trade_stats.oi_short_usd = 2_500;
+        assert_eq!(custody.get_open_interest_headroom().unwrap(), (600, 0));
+        assert!(custody.check_open_interest(Side::Long, 600).is_ok());
+        assert!(custody.check_open_interest(Side::Long, 601).is_err());
+        assert!(custody.check_open_interest(Side::Short, 1).is_err());
+
+        // imbalance limited to 50% of the side limit
+        custody.pricing.max_oi_imbalance = 5_000;
+        custody.trade_stats.oi_long_usd = 300;
+        custody.trade_stats.oi_short_usd = 0;
+        assert_eq!(custody.get_open_interest_headroom().unwrap(), (200, 1_300));
+
+        custody.trade_stats.oi_short_usd = 600;
+        assert_eq!(custody.get_open_interest_headroom().unwrap(), (700, 700));
+    }
+
+    #[test]
+    fn test_get_referral_split() {
+        let mut fees = Fees::default();
+        assert_eq!((0, 0), fees.get_referral_split(1_000).unwrap());
+
+        fees.referral_discount = 2_000;
+        fees.referral_rebate = 3_000;
+        assert_eq!((200, 300), fees.get_referral_split(1_000).unwrap());
+
+        // shares round down so they never exceed the fee
+        fees.referral_discount = 2_500;
+        fees.referral_rebate = 2_500;
+        assert_eq!((0, 0), fees.get_referral_split(3).unwrap());
+        assert_eq!((1, 1), fees.get_referral_split(5).unwrap());
+        assert!(fees.validate());
+
+        // the combined shares are capped below the fee
+        fees.referral_rebate = 2_501;
+        assert!(!fees.validate());
+    }
+
+    #[test]
+    fn test_oracle_params_message_domain() {
+        let mut oracle = OracleParams {
+            oracle_account: Pubkey::new_unique(),
+            oracle_type: OracleType::Custom,
+            ..Default::default()
+        };
+        assert!(oracle.validate());
+
+        // permissionless updates need a deployment domain
+        oracle.oracle_authority = Pubkey::new_unique();
+        assert!(!oracle.validate());
+
+        oracle.message_domain = 1;
+        assert!(oracle.validate());
+    }
+
+    #[test]
+    fn test_get_reference_account() {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+
+        // no band configured, the account is ignored
+        let mut oracle = OracleParams::default();
+        assert!(oracle.get_reference_account(None, false).unwrap().is_none());
+        assert!(oracle
+            .get_reference_account(Some(&account), false)
+            .unwrap()
+            .is_none());
+
+        // with a band every action needs the reference
+        oracle.max_reference_deviation = 500;
+        assert!(oracle.get_reference_account(None, false).is_err());
+        assert!(oracle.get_reference_account(None, true).is_err());
+        assert_eq!(
+            key,
+            oracle
+                .get_reference_account(Some(&account), true)
+                .unwrap()
+                .unwrap()
+                .key()
+        );
+
+        // unless exits are allowed outside of it
+        oracle.allow_exit_outside_reference = true;
+        assert!(oracle.get_reference_account(None, true).unwrap().is_none());
+        assert!(oracle.get_reference_account(None, false).is_err());
+        assert!(oracle
+            .get_reference_account(Some(&account), false)
+            .unwrap()
+            .is_some());
+    }
 }
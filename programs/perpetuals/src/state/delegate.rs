@@ -0,0 +1,138 @@
+use {
+    crate::{error::PerpetualsError, state::position::Position},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum DelegateAction {
+    ClosePosition,
+    AddCollateral,
+    UpdateTriggers,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DelegatePermissions {
+    pub allow_close_position: bool,
+    pub allow_add_collateral: bool,
+    pub allow_update_triggers: bool,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Delegate {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: DelegatePermissions,
+    // unix time after which the delegation is no longer valid
+    pub expiry: Option<i64>,
+    // largest position size the delegate is allowed to manage
+    pub max_size_usd: Option<u64>,
+    pub bump: u8,
+}
+
+impl DelegatePermissions {
+    pub fn is_allowed(&self, action: DelegateAction) -> bool {
+        match action {
+            DelegateAction::ClosePosition => self.allow_close_position,
+            DelegateAction::AddCollateral => self.allow_add_collateral,
+            DelegateAction::UpdateTriggers => self.allow_update_triggers,
+        }
+    }
+}
+
+impl Delegate {
+    pub const LEN: usize = 8 + std::mem::size_of::<Delegate>();
+
+    pub fn validate(&self, action: DelegateAction, size_usd: u64, curtime: i64) -> Result<()> {
+        require!(
+            self.permissions.is_allowed(action),
+            PerpetualsError::DelegateNotAuthorized
+        );
+        if let Some(expiry) = self.expiry {
+            require!(curtime < expiry, PerpetualsError::DelegateNotAuthorized);
+        }
+        if let Some(max_size_usd) = self.max_size_usd {
+            require!(
+                size_usd <= max_size_usd,
+                PerpetualsError::DelegateNotAuthorized
+            );
+        }
+        Ok(())
+    }
+
+    // checks that signer is either the position owner or one of its valid delegates
+    pub fn check_signer(
+        signer: &Pubkey,
+        position: &Position,
+        delegate: Option<&Delegate>,
+        action: DelegateAction,
+        curtime: i64,
+    ) -> Result<()> {
+        if *signer == position.owner {
+            return Ok(());
+        }
+        let Some(delegate) = delegate else {
+            return err!(PerpetualsError::DelegateNotAuthorized);
+        };
+        require!(
+            delegate.owner == position.owner && delegate.delegate == *signer,
+            PerpetualsError::DelegateNotAuthorized
+        );
+        delegate.validate(action, position.size_usd, curtime)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_signer() {
+        let owner = Pubkey::new_unique();
+        let bot = Pubkey::new_unique();
+        let position = Position {
+            owner,
+            size_usd: 1_000_000_000,
+            ..Default::default()
+        };
+        let mut delegate = Delegate {
+            owner,
+            delegate: bot,
+            permissions: DelegatePermissions {
+                allow_close_position: true,
+                allow_add_collateral: false,
+                allow_update_triggers: true,
+            },
+            expiry: Some(100),
+            max_size_usd: Some(1_000_000_000),
+            bump: 255,
+        };
+        let close = DelegateAction::ClosePosition;
+
+        // the owner never needs a delegation
+        assert!(Delegate::check_signer(&owner, &position, None, close, 0).is_ok());
+        assert!(Delegate::check_signer(&bot, &position, None, close, 0).is_err());
+
+        assert!(Delegate::check_signer(&bot, &position, Some(&delegate), close, 99).is_ok());
+        assert!(Delegate::check_signer(
+            &bot,
+            &position,
+            Some(&delegate),
+            DelegateAction::AddCollateral,
+            99
+        )
+        .is_err());
+
+        // expired
+        assert!(Delegate::check_signer(&bot, &position, Some(&delegate), close, 100).is_err());
+
+        // position is larger than the delegation limit
+        delegate.max_size_usd = Some(999_999_999);
+        assert!(Delegate::check_signer(&bot, &position, Some(&delegate), close, 0).is_err());
+
+        // delegation granted by someone else
+        delegate.max_size_usd = None;
+        delegate.owner = Pubkey::new_unique();
+        assert!(Delegate::check_signer(&bot, &position, Some(&delegate), close, 0).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+use {
+    crate::{
+        error::PerpetualsError,
+        math,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+        },
+        try_from,
+    },
+    anchor_lang::prelude::*,
+    pyth_solana_receiver_sdk::price_update::PriceUpdateV2,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct MarginAccount {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    // custody holding the shared collateral deposit
+    pub collateral_custody: Pubkey,
+    pub collateral_amount: u64,
+    // positions sharing the account margin
+    pub positions: Vec<Pubkey>,
+    pub bump: u8,
+    // custodies the deposit and positions are priced with, deposit custody first
+    pub custodies: Vec<Pubkey>,
+}
+
+// Margin account custodies with their prices and positions, loaded from remaining accounts
+pub struct MarginAccountState<'info> {
+    pub custodies: Vec<Account<'info, Custody>>,
+    pub token_prices: Vec<OraclePrice>,
+    pub token_ema_prices: Vec<OraclePrice>,
+    pub positions: Vec<Account<'info, Position>>,
+}
+
+impl MarginAccount {
+    // keep custodies, oracles and positions within a single transaction account list
+    pub const MAX_POSITIONS: usize = 4;
+    pub const MAX_CUSTODIES: usize = 4;
+    pub const LEN: usize = 8
+        + std::mem::size_of::<MarginAccount>()
+        + MarginAccount::MAX_POSITIONS * 32
+        + MarginAccount::MAX_CUSTODIES * 32;
+
+    pub fn add_position(&mut self, position: &Position, position_key: Pubkey) -> Result<()> {
+        require!(
+            self.positions.len() < MarginAccount::MAX_POSITIONS
+                && !self.positions.contains(&position_key),
+            PerpetualsError::PositionAmountLimit
+        );
+        self.positions.push(position_key);
+
+        for custody in [position.custody, position.collateral_custody] {
+            if !self.custodies.contains(&custody) {
+                self.custodies.push(custody);
+            }
+        }
+        require!(
+            self.custodies.len() <= MarginAccount::MAX_CUSTODIES,
+            PerpetualsError::MarginCustodyLimit
+        );
+
+        Ok(())
+    }
+
+    // drops custodies no remaining position is priced with, the deposit custody always stays
+    pub fn refresh_custodies(&mut self, positions: &[Account<Position>]) {
+        let collateral_custody = self.collateral_custody;
+        self.custodies.retain(|custody| {
+            *custody == collateral_custody
+                || positions.iter().any(|position| {
+                    position.custody == *custody || position.collateral_custody == *custody
+                })
+        });
+    }
+
+    // covers a loss the position collateral couldn't with the deposit, returns the amount debited
+    pub fn debit_deposit(
+        &mut self,
+        deposit_custody: &mut Custody,
+        min_deposit_price: &OraclePrice,
+        shortfall_usd: u64,
+        allow_partial: bool,
+    ) -> Result<u64> {
+        let shortfall_amount =
+            min_deposit_price.get_token_amount(shortfall_usd, deposit_custody.decimals)?;
+        require!(
+            allow_partial || shortfall_amount <= self.collateral_amount,
+            PerpetualsError::MaxLeverage
+        );
+        let debit_amount = std::cmp::min(shortfall_amount, self.collateral_amount);
+        msg!("Debit deposit: {}", debit_amount);
+
+        self.collateral_amount = math::checked_sub(self.collateral_amount, debit_amount)?;
+        deposit_custody.assets.collateral =
+            math::checked_sub(deposit_custody.assets.collateral, debit_amount)?;
+        deposit_custody.assets.owned =
+            math::checked_add(deposit_custody.assets.owned, debit_amount)?;
+
+        Ok(debit_amount)
+    }
+
+    pub fn remove_position(&mut self, position: &Pubkey) -> Result<usize> {
+        let idx = self
+            .positions
+            .iter()
+            .position(|&key| key == *position)
+            .ok_or(PerpetualsError::InvalidPositionState)?;
+        self.positions.remove(idx);
+        Ok(idx)
+    }
+
+    // remaining accounts layout:
+    //   self.custodies.len() custody accounts, in margin account order
+    //   self.custodies.len() custody oracles
+    //   self.positions.len() position accounts, in margin account order
+    pub fn load_state<'info>(
+        &self,
+        pool: &Pool,
+        accounts: &[AccountInfo<'info>],
+        curtime: i64,
+    ) -> Result<MarginAccountState<'info>> {
+        let custodies_len = self.custodies.len();
+        let positions_idx = custodies_len * 2;
+        if accounts.len() < positions_idx + self.positions.len() {
+            return Err(ProgramError::NotEnoughAccountKeys.into());
+        }
+
+        let mut state = MarginAccountState {
+            custodies: Vec::with_capacity(custodies_len),
+            token_prices: Vec::with_capacity(custodies_len),
+            token_ema_prices: Vec::with_capacity(custodies_len),
+            positions: Vec::with_capacity(self.positions.len()),
+        };
+
+        for (idx, &custody) in self.custodies.iter().enumerate() {
+            require_keys_eq!(accounts[idx].key(), custody);
+            pool.get_token_id(&custody)?;
+            let custody = try_from!(Account::<Custody>, &accounts[idx])?;
+
+            let oracle_idx = idx + custodies_len;
+            require_keys_eq!(accounts[oracle_idx].key(), custody.oracle.oracle_account);
+            let oracle_account = try_from!(Account::<PriceUpdateV2>, &accounts[oracle_idx])?;
+
+            state.token_prices.push(OraclePrice::new_from_oracle(
+                &oracle_account,
+                None,
+                None,
+                &custody.oracle,
+                curtime,
+                false,
+                custody.oracle.feed_id,
+            )?);
+
+            state.token_ema_prices.push(OraclePrice::new_from_oracle(
+                &oracle_account,
+                None,
+                None,
+                &custody.oracle,
+                curtime,
+                custody.pricing.use_ema,
+                custody.oracle.feed_id,
+            )?);
+
+            state.custodies.push(custody);
+        }
+
+        for (idx, &position) in self.positions.iter().enumerate() {
+            let position_info = &accounts[positions_idx + idx];
+            require_keys_eq!(position_info.key(), position);
+            state
+                .positions
+                .push(try_from!(Account::<Position>, position_info)?);
+        }
+
+        Ok(state)
+    }
+}
+
+impl MarginAccountState<'_> {
+    // index of the custody in the loaded state
+    pub fn get_custody_idx(&self, custody: &Pubkey) -> Result<usize> {
+        self.custodies
+            .iter()
+            .position(|loaded| loaded.key() == *custody)
+            .ok_or_else(|| PerpetualsError::UnsupportedToken.into())
+    }
+
+    // returns (equity_usd, initial_margin_usd, maintenance_margin_usd)
+    pub fn get_margin_usd(
+        &self,
+        pool: &Pool,
+        margin_account: &MarginAccount,
+        curtime: i64,
+    ) -> Result<(u64, u64, u64)> {
+        let idx = self.get_custody_idx(&margin_account.collateral_custody)?;
+        let min_collateral_price = self.token_prices[idx]
+            .get_min_price(&self.token_ema_prices[idx], self.custodies[idx].is_stable)?;
+        let mut margin_usd = min_collateral_price.get_asset_amount_usd(
+            margin_account.collateral_amount,
+            self.custodies[idx].decimals,
+        )?;
+
+        let mut loss_usd: u64 = 0;
+        let mut initial_margin_usd: u64 = 0;
+        let mut maintenance_margin_usd: u64 = 0;
+
+        for position in self.positions.iter() {
+            let custody_idx = self.get_custody_idx(&position.custody)?;
+            let collateral_idx = self.get_custody_idx(&position.collateral_custody)?;
+            let custody = &self.custodies[custody_idx];
+            let collateral_custody = &self.custodies[collateral_idx];
+
+            // position collateral counts at its current value, not at the deposit time value
+            let position_collateral_usd = self.token_prices[collateral_idx]
+                .get_min_price(
+                    &self.token_ema_prices[collateral_idx],
+                    collateral_custody.is_stable,
+                )?
+                .get_asset_amount_usd(position.collateral_amount, collateral_custody.decimals)?;
+
+            let (position_profit_usd, position_loss_usd, _) = pool.get_pnl_usd(
+                position,
+                &self.token_prices[custody_idx],
+                &self.token_ema_prices[custody_idx],
+                custody,
+                &self.token_prices[collateral_idx],
+                &self.token_ema_prices[collateral_idx],
+                collateral_custody,
+                curtime,
+                false,
+                0,
+            )?;
+
+            margin_usd = math::checked_add(
+                margin_usd,
+                math::checked_add(position_collateral_usd, position_profit_usd)?,
+            )?;
+            loss_usd = math::checked_add(loss_usd, position_loss_usd)?;
+
+            initial_margin_usd = math::checked_add(
+                initial_margin_usd,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
+                    custody.pricing.max_initial_leverage as u128,
+                )?)?,
+            )?;
+            maintenance_margin_usd = math::checked_add(
+                maintenance_margin_usd,
+                math::checked_as_u64(math::checked_div(
+                    math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
+                    custody.pricing.max_leverage as u128,
+                )?)?,
+            )?;
+        }
+
+        Ok((
+            margin_usd.saturating_sub(loss_usd),
+            initial_margin_usd,
+            maintenance_margin_usd,
+        ))
+    }
+
+    // Settles the position against its custodies, the position account is left to the caller.
+    // Liquidated collateral stays in the pool, otherwise the caller pays amount_out out.
+    // returns (amount_out, shortfall_usd), the loss the position collateral doesn't cover
+    pub fn settle_position(
+        &mut self,
+        pool: &Pool,
+        idx: usize,
+        curtime: i64,
+        liquidation: bool,
+    ) -> Result<(u64, u64)> {
+        let position = &self.positions[idx];
+        let custody_idx = self.get_custody_idx(&position.custody)?;
+        let collateral_idx = self.get_custody_idx(&position.collateral_custody)?;
+        require!(
+            self.custodies[custody_idx].permissions.allow_close_position,
+            PerpetualsError::InstructionNotAllowed
+        );
+
+        msg!("Settle position {}", position.key());
+        let token_ema_price = self.token_ema_prices[custody_idx];
+        let collateral_token_ema_price = self.token_ema_prices[collateral_idx];
+
+        let (amount_out, mut fee_amount, profit_usd, loss_usd, shortfall_usd) = pool
+            .get_close_amount_with_shortfall(
+                position,
+                &self.token_prices[custody_idx],
+                &token_ema_price,
+                &self.custodies[custody_idx],
+                &self.token_prices[collateral_idx],
+                &collateral_token_ema_price,
+                &self.custodies[collateral_idx],
+                curtime,
+                liquidation,
+                0,
+            )?;
+
+        let fee_amount_usd = token_ema_price
+            .get_asset_amount_usd(fee_amount, self.custodies[custody_idx].decimals)?;
+        if position.side == Side::Short || self.custodies[custody_idx].is_virtual {
+            fee_amount = collateral_token_ema_price
+                .get_token_amount(fee_amount_usd, self.custodies[collateral_idx].decimals)?;
+        }
+
+        msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
+        msg!("Collected fee: {}", fee_amount);
+        msg!("Amount out: {}, shortfall: {}", amount_out, shortfall_usd);
+
+        let (custody, collateral_custody) = if custody_idx == collateral_idx {
+            (None, &mut *self.custodies[collateral_idx])
+        } else {
+            let (custody, collateral_custody) =
+                get_pair_mut(&mut self.custodies, custody_idx, collateral_idx);
+            (Some(custody), collateral_custody)
+        };
+
+        // unlock pool funds
+        collateral_custody.unlock_funds(position.locked_amount)?;
+
+        if liquidation {
+            collateral_custody.collected_fees.liquidation_usd = collateral_custody
+                .collected_fees
+                .liquidation_usd
+                .wrapping_add(fee_amount_usd);
+        } else {
+            collateral_custody.collected_fees.close_position_usd = collateral_custody
+                .collected_fees
+                .close_position_usd
+                .wrapping_add(fee_amount_usd);
+        }
+
+        // position collateral is handed over to the pool, minus what is paid out
+        collateral_custody.assets.collateral = math::checked_sub(
+            collateral_custody.assets.collateral,
+            position.collateral_amount,
+        )?;
+        collateral_custody.assets.owned =
+            math::checked_add(collateral_custody.assets.owned, position.collateral_amount)?;
+        if !liquidation {
+            require!(
+                pool.check_available_amount(amount_out, collateral_custody)?,
+                PerpetualsError::CustodyAmountLimit
+            );
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount_out)?;
+        }
+
+        let protocol_fee = Pool::get_fee_amount(
+            custody
+                .as_ref()
+                .map_or(collateral_custody.fees.protocol_share, |custody| {
+                    custody.fees.protocol_share
+                }),
+            fee_amount,
+        )?;
+
+        // Pay protocol_fee from custody if possible, otherwise no protocol_fee
+        if pool.check_available_amount(protocol_fee, collateral_custody)? {
+            collateral_custody.assets.protocol_fees =
+                math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
+        }
+
+        // if custody and collateral_custody accounts are the same, ensure that data is in sync
+        let custody = match custody {
+            None => {
+                collateral_custody.remove_position(position, curtime, None)?;
+                collateral_custody
+            }
+            Some(custody) => {
+                custody.remove_position(position, curtime, Some(collateral_custody))?;
+                custody
+            }
+        };
+
+        if liquidation {
+            custody.volume_stats.liquidation_usd =
+                math::checked_add(custody.volume_stats.liquidation_usd, position.size_usd)?;
+        } else {
+            custody.volume_stats.close_position_usd = custody
+                .volume_stats
+                .close_position_usd
+                .wrapping_add(position.size_usd);
+        }
+
+        if position.side == Side::Long {
+            custody.trade_stats.oi_long_usd = custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(position.size_usd);
+        } else {
+            custody.trade_stats.oi_short_usd = custody
+                .trade_stats
+                .oi_short_usd
+                .saturating_sub(position.size_usd);
+        }
+
+        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
+        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
+
+        Ok((amount_out, shortfall_usd))
+    }
+
+    // Takes the shortfall of settled positions from the deposit, tokens stay in the pool.
+    // returns the debited amount, capped at the deposit if partial debits are allowed
+    pub fn debit_deposit(
+        &mut self,
+        margin_account: &mut MarginAccount,
+        shortfall_usd: u64,
+        allow_partial: bool,
+    ) -> Result<u64> {
+        let idx = self.get_custody_idx(&margin_account.collateral_custody)?;
+        let min_deposit_price = self.token_prices[idx]
+            .get_min_price(&self.token_ema_prices[idx], self.custodies[idx].is_stable)?;
+
+        margin_account.debit_deposit(
+            &mut self.custodies[idx],
+            &min_deposit_price,
+            shortfall_usd,
+            allow_partial,
+        )
+    }
+
+    // update borrow rates and cached valuations, then save custodies
+    pub fn save_custodies(&mut self, pool: &mut Pool, curtime: i64) -> Result<()> {
+        for (idx, custody) in self.custodies.iter_mut().enumerate() {
+            if !custody.is_virtual {
+                custody.update_borrow_rate(curtime)?;
+            }
+            pool.update_cached_custody_aum(
+                custody,
+                &self.token_prices[idx],
+                &self.token_ema_prices[idx],
+                curtime,
+            )?;
+            custody.exit(&crate::ID)?;
+        }
+        Ok(())
+    }
+}
+
+fn get_pair_mut<'a, 'info>(
+    custodies: &'a mut [Account<'info, Custody>],
+    first: usize,
+    second: usize,
+) -> (&'a mut Custody, &'a mut Custody) {
+    if first < second {
+        let (head, tail) = custodies.split_at_mut(second);
+        (&mut *head[first], &mut *tail[0])
+    } else {
+        let (head, tail) = custodies.split_at_mut(first);
+        (&mut *tail[0], &mut *head[second])
+    }
+}
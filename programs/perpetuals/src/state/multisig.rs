@@ -36,6 +36,9 @@ pub enum AdminInstruction {
     SetCustomOraclePrice,
     SetTestTime,
     UpgradeCustody,
+    WindDownPool,
+    SetFeeTiers,
+    SetPoolConfig,
 }
 
 impl Multisig {
@@ -28,6 +28,8 @@ impl Default for OracleType {
 pub struct OraclePrice {
     pub price: u64,
     pub exponent: i32,
+    // oracle confidence interval, same exponent as the price
+    pub conf: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -39,6 +41,41 @@ pub struct OracleParams {
     pub max_price_error: u64,
     pub max_price_age_sec: u32,
     pub feed_id: [u8; 32],
+    // half-life of the custom oracle EMA computed on-chain, 0 keeps the submitted EMA,
+    // the EMA is what pricing.use_ema reads
+    pub ema_half_life_sec: u32,
+    // max permissionless custom oracle price move from the previous sample (BPS) per
+    // max_price_age_sec elapsed, 0 for no limit
+    pub max_price_deviation: u64,
+    // additional permissionless update signers, unused slots are left as default
+    pub extra_oracle_authorities: [Pubkey; 4],
+    // number of distinct authorities that must sign a permissionless update (at least 1)
+    pub min_oracle_signatures: u8,
+    // max distance between the lowest and the highest signed price of a permissionless
+    // update (BPS), required with a quorum so one key can't drag the median, 0 for no limit
+    pub max_quote_spread: u64,
+    // deployment tag that signed permissionless messages must carry, required (non-zero)
+    // once an oracle authority is set so quotes signed for one cluster don't replay on another
+    pub message_domain: u64,
+    // also accept the unversioned message format (bare params, no domain separation)
+    pub allow_legacy_message: bool,
+    // fail permissionless updates that are not newer than the stored price instead of skipping them
+    pub reject_stale_updates: bool,
+    // optional secondary oracle the price is checked against, a custom oracle for
+    // Pyth custodies or a Pyth feed (same feed_id) for custom oracle custodies
+    pub reference_oracle_account: Pubkey,
+    pub reference_oracle_type: OracleType,
+    // max distance between the price and the reference price (BPS), 0 disables the check
+    pub max_reference_deviation: u64,
+    // let closes and liquidations proceed when the price is outside the reference band
+    pub allow_exit_outside_reference: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct OracleSample {
+    pub price: u64,
+    pub conf: u64,
+    pub publish_time: i64,
 }
 
 #[account]
@@ -49,17 +86,107 @@ pub struct CustomOracle {
     pub conf: u64,
     pub ema: u64,
     pub publish_time: i64,
+    // ring buffer of recent samples, the oldest one is overwritten first
+    pub samples: [OracleSample; 32],
+    // slot of the next sample
+    pub next_sample: u32,
 }
 
 impl CustomOracle {
     pub const LEN: usize = 8 + std::mem::size_of::<CustomOracle>();
+    pub const MAX_SAMPLES: usize = 32;
+
+    /// Records a new price, the EMA is computed on-chain if oracle_params.ema_half_life_sec is set.
+    /// With check_deviation, fails if the price moved more than oracle_params.max_price_deviation
+    /// from the previous sample for every max_price_age_sec elapsed since it (at least one).
+    #[allow(clippy::too_many_arguments)]
+    pub fn set(
+        &mut self,
+        price: u64,
+        expo: i32,
+        conf: u64,
+        ema: u64,
+        publish_time: i64,
+        oracle_params: &OracleParams,
+        check_deviation: bool,
+    ) -> Result<()> {
+        // history is discarded if there is none yet or the exponent changed
+        let has_history = self.publish_time > 0 && self.price > 0 && self.expo == expo;
+
+        if check_deviation && has_history && oracle_params.max_price_deviation > 0 {
+            let deviation = OraclePrice::new(self.price, self.expo)
+                .get_divergence_bps(&OraclePrice::new(price, expo))?;
+            let periods = std::cmp::max(
+                1,
+                math::checked_sub(publish_time, self.publish_time)?
+                    / std::cmp::max(1, oracle_params.max_price_age_sec as i64),
+            );
+            let max_deviation =
+                math::checked_mul(oracle_params.max_price_deviation as u128, periods as u128)?;
+            if deviation as u128 > max_deviation {
+                msg!(
+                    "Error: Custom oracle price deviation {} exceeds the limit",
+                    deviation
+                );
+                return err!(PerpetualsError::CustomOraclePriceDeviation);
+            }
+        }
+
+        self.ema = if oracle_params.ema_half_life_sec == 0 {
+            ema
+        } else if has_history {
+            self.get_next_ema(
+                price,
+                math::checked_sub(publish_time, self.publish_time)?,
+                oracle_params.ema_half_life_sec,
+            )?
+        } else {
+            price
+        };
+
+        if !has_history {
+            self.samples = [OracleSample::default(); 32];
+            self.next_sample = 0;
+        }
+        self.samples[self.next_sample as usize] = OracleSample {
+            price,
+            conf,
+            publish_time,
+        };
+        self.next_sample = (self.next_sample + 1) % CustomOracle::MAX_SAMPLES as u32;
 
-    pub fn set(&mut self, price: u64, expo: i32, conf: u64, ema: u64, publish_time: i64) {
         self.price = price;
         self.expo = expo;
         self.conf = conf;
-        self.ema = ema;
         self.publish_time = publish_time;
+
+        Ok(())
+    }
+
+    // recorded samples, oldest first
+    pub fn get_samples(&self) -> Vec<OracleSample> {
+        let next = self.next_sample as usize;
+        self.samples[next..]
+            .iter()
+            .chain(self.samples[..next].iter())
+            .filter(|sample| sample.publish_time > 0)
+            .copied()
+            .collect()
+    }
+
+    // ema = price + (ema - price) * 0.5 ^ (elapsed / half_life)
+    fn get_next_ema(&self, price: u64, elapsed_sec: i64, half_life_sec: u32) -> Result<u64> {
+        if elapsed_sec <= 0 {
+            return Ok(self.ema);
+        }
+        let decay = math::checked_powf(
+            0.5,
+            math::checked_float_div(elapsed_sec as f64, half_life_sec as f64)?,
+        )?;
+        let ema = math::checked_as_f64(self.ema)?;
+        let price = math::checked_as_f64(price)?;
+
+        math::checked_as_u64(price + (ema - price) * decay)
     }
 }
 
@@ -85,31 +212,44 @@ impl PartialOrd for OraclePrice {
 #[allow(dead_code)]
 impl OraclePrice {
     pub fn new(price: u64, exponent: i32) -> Self {
-        Self { price, exponent }
+        Self {
+            price,
+            exponent,
+            conf: 0,
+        }
     }
 
-    pub fn new_from_token(amount_and_decimals: (u64, u8)) -> Self {
+    pub fn new_with_conf(price: u64, exponent: i32, conf: u64) -> Self {
         Self {
-            price: amount_and_decimals.0,
-            exponent: -(amount_and_decimals.1 as i32),
+            price,
+            exponent,
+            conf,
         }
     }
 
+    pub fn new_from_token(amount_and_decimals: (u64, u8)) -> Self {
+        Self::new(amount_and_decimals.0, -(amount_and_decimals.1 as i32))
+    }
+
+    /// Reads the spot price, or the EMA (the TWAP for Pyth feeds if a TWAP account is given)
+    /// if use_ema is set, and checks it against the reference band if a reference account is given
+    /// (see OracleParams::get_reference_account).
     pub fn new_from_oracle(
         price_update: &Account<PriceUpdateV2>,
         twap_update: Option<&Account<TwapUpdate>>,
+        reference_account: Option<&AccountInfo>,
         oracle_params: &OracleParams,
         current_time: i64,
-        _use_ema: bool,
+        use_ema: bool,
         feed_id: [u8; 32],
     ) -> Result<Self> {
-        match oracle_params.oracle_type {
+        let price = match oracle_params.oracle_type {
             OracleType::Custom => Self::get_custom_price(
                 &price_update.to_account_info(),
                 oracle_params.max_price_error,
                 oracle_params.max_price_age_sec,
                 current_time,
-                false,
+                use_ema,
             ),
             OracleType::Pyth => Self::get_pyth_price(
                 price_update,
@@ -117,11 +257,17 @@ impl OraclePrice {
                 oracle_params.max_price_error,
                 oracle_params.max_price_age_sec,
                 current_time,
-                false,
+                use_ema,
                 feed_id,
             ),
             _ => err!(PerpetualsError::UnsupportedOracle),
+        }?;
+
+        if let Some(reference_account) = reference_account {
+            price.check_reference_band(reference_account, oracle_params, current_time)?;
         }
+
+        Ok(price)
     }
 
     // Converts token amount to USD with implied USD_DECIMALS decimals using oracle price
@@ -156,15 +302,18 @@ impl OraclePrice {
     pub fn normalize(&self) -> Result<OraclePrice> {
         let mut p = self.price;
         let mut e = self.exponent;
+        let mut c = self.conf;
 
         while p > ORACLE_MAX_PRICE {
             p = math::checked_div(p, 10)?;
             e = math::checked_add(e, 1)?;
+            c = math::checked_div(c, 10)?;
         }
 
         Ok(OraclePrice {
             price: p,
             exponent: e,
+            conf: c,
         })
     }
 
@@ -172,23 +321,23 @@ impl OraclePrice {
         let base = self.normalize()?;
         let other = other.normalize()?;
 
-        Ok(OraclePrice {
-            price: math::checked_div(
+        Ok(OraclePrice::new(
+            math::checked_div(
                 math::checked_mul(base.price, ORACLE_PRICE_SCALE)?,
                 other.price,
             )?,
-            exponent: math::checked_sub(
+            math::checked_sub(
                 math::checked_add(base.exponent, ORACLE_EXPONENT_SCALE)?,
                 other.exponent,
             )?,
-        })
+        ))
     }
 
     pub fn checked_mul(&self, other: &OraclePrice) -> Result<OraclePrice> {
-        Ok(OraclePrice {
-            price: math::checked_mul(self.price, other.price)?,
-            exponent: math::checked_add(self.exponent, other.exponent)?,
-        })
+        Ok(OraclePrice::new(
+            math::checked_mul(self.price, other.price)?,
+            math::checked_add(self.exponent, other.exponent)?,
+        ))
     }
 
     pub fn scale_to_exponent(&self, target_exponent: i32) -> Result<OraclePrice> {
@@ -197,14 +346,18 @@ impl OraclePrice {
         }
         let delta = math::checked_sub(target_exponent, self.exponent)?;
         if delta > 0 {
+            let scale = math::checked_pow(10, delta as usize)?;
             Ok(OraclePrice {
-                price: math::checked_div(self.price, math::checked_pow(10, delta as usize)?)?,
+                price: math::checked_div(self.price, scale)?,
                 exponent: target_exponent,
+                conf: math::checked_div(self.conf, scale)?,
             })
         } else {
+            let scale = math::checked_pow(10, (-delta) as usize)?;
             Ok(OraclePrice {
-                price: math::checked_mul(self.price, math::checked_pow(10, (-delta) as usize)?)?,
+                price: math::checked_mul(self.price, scale)?,
                 exponent: target_exponent,
+                conf: math::checked_mul(self.conf, scale)?,
             })
         }
     }
@@ -216,6 +369,34 @@ impl OraclePrice {
         )
     }
 
+    // Returns confidence interval relative to the price, in BPS
+    pub fn get_conf_bps(&self) -> Result<u64> {
+        if self.price == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.conf as u128, Perpetuals::BPS_POWER)?,
+            self.price as u128,
+        )?)
+    }
+
+    // Returns |self - other| relative to self, in BPS
+    pub fn get_divergence_bps(&self, other: &OraclePrice) -> Result<u64> {
+        if self.price == 0 {
+            return Ok(0);
+        }
+        let other = other.scale_to_exponent(self.exponent)?;
+        let diff = if self.price > other.price {
+            self.price - other.price
+        } else {
+            other.price - self.price
+        };
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(diff as u128, Perpetuals::BPS_POWER)?,
+            self.price as u128,
+        )?)
+    }
+
     pub fn get_min_price(&self, other: &OraclePrice, is_stable: bool) -> Result<OraclePrice> {
         let min_price = if self < other { self } else { other };
         if is_stable {
@@ -223,10 +404,7 @@ impl OraclePrice {
                 if min_price.price == 0 {
                     return Ok(*min_price);
                 } else {
-                    return Ok(OraclePrice {
-                        price: 1000000u64,
-                        exponent: -6,
-                    });
+                    return Ok(OraclePrice::new(1000000u64, -6));
                 }
             }
             let one_usd = math::checked_pow(10u64, (-min_price.exponent) as usize)?;
@@ -234,6 +412,7 @@ impl OraclePrice {
                 Ok(OraclePrice {
                     price: one_usd,
                     exponent: min_price.exponent,
+                    conf: min_price.conf,
                 })
             } else {
                 Ok(*min_price)
@@ -244,6 +423,50 @@ impl OraclePrice {
     }
 
     // private helpers
+    fn check_reference_band(
+        &self,
+        reference_account: &AccountInfo,
+        oracle_params: &OracleParams,
+        current_time: i64,
+    ) -> Result<()> {
+        require_keys_eq!(
+            reference_account.key(),
+            oracle_params.reference_oracle_account,
+            PerpetualsError::InvalidOracleAccount
+        );
+
+        let reference_price = match oracle_params.reference_oracle_type {
+            OracleType::Custom => Self::get_custom_price(
+                reference_account,
+                oracle_params.max_price_error,
+                oracle_params.max_price_age_sec,
+                current_time,
+                false,
+            )?,
+            OracleType::Pyth => Self::get_pyth_price(
+                &try_from!(Account<PriceUpdateV2>, reference_account)?,
+                None,
+                oracle_params.max_price_error,
+                oracle_params.max_price_age_sec,
+                current_time,
+                false,
+                oracle_params.feed_id,
+            )?,
+            _ => return err!(PerpetualsError::UnsupportedOracle),
+        };
+
+        let deviation = reference_price.get_divergence_bps(self)?;
+        if deviation > oracle_params.max_reference_deviation {
+            msg!(
+                "Error: Oracle price deviates {} BPS from the reference price",
+                deviation
+            );
+            return err!(PerpetualsError::PriceOutsideReferenceBand);
+        }
+
+        Ok(())
+    }
+
     fn get_custom_price(
         custom_price_info: &AccountInfo,
         max_price_error: u64,
@@ -283,9 +506,13 @@ impl OraclePrice {
             // price is i64 and > 0 per check above
             price,
             exponent: oracle_acc.expo,
+            conf: oracle_acc.conf,
         })
     }
 
+    /// Reads a Pyth pull oracle price update. The account can be a persistent feed account or an
+    /// ephemeral one posted earlier in the same transaction, either way it is rejected if the
+    /// price is older than the custody's max_price_age_sec.
     fn get_pyth_price(
         price_update: &Account<PriceUpdateV2>,
         twap_update: Option<&Account<TwapUpdate>>,
@@ -300,7 +527,7 @@ impl OraclePrice {
             PerpetualsError::InvalidOracleAccount
         );
 
-        let maximum_age: u64 = 300;
+        let maximum_age = max_price_age_sec as u64;
 
         let twap_price = match twap_update {
             Some(twap) => Some(twap.get_twap_no_older_than(
@@ -314,31 +541,24 @@ impl OraclePrice {
 
         let price = price_update.get_price_no_older_than(&Clock::get()?, maximum_age, &feed_id)?;
 
-        let final_price = if use_ema {
-            twap_price.ok_or(PerpetualsError::MissingTwap)?.price
-        } else {
-            let publish_time: i64 = price.publish_time;
-
-            let last_update_age_sec = math::checked_sub(current_time, publish_time)?;
-
-            if last_update_age_sec > max_price_age_sec as i64 {
-                msg!("Error: Pyth oracle price is stale");
-                return err!(PerpetualsError::StaleOraclePrice);
-            }
-
-            price.price
-        };
-
-        let final_exponent: i32 = if use_ema {
-            twap_price.ok_or(PerpetualsError::MissingTwap)?.exponent
-        } else {
-            price.exponent
-        };
+        // the receiver sdk checks the age against the cluster clock, this check uses the program
+        // time so both clocks reject a stale update (they differ with a test time set)
+        let last_update_age_sec = math::checked_sub(current_time, price.publish_time)?;
+        if last_update_age_sec > max_price_age_sec as i64 {
+            msg!("Error: Pyth oracle price is stale");
+            return err!(PerpetualsError::StaleOraclePrice);
+        }
 
-        let conf_value = if use_ema {
-            twap_price.ok_or(PerpetualsError::MissingTwap)?.conf
-        } else {
-            price.conf
+        // use_ema reads the TWAP when the caller passes one, the EMA published with the
+        // price update otherwise, so paths that only carry the price update can still use it
+        let (final_price, final_exponent, conf_value) = match (use_ema, twap_price) {
+            (true, Some(twap_price)) => (twap_price.price, twap_price.exponent, twap_price.conf),
+            (true, None) => (
+                price_update.price_message.ema_price,
+                price.exponent,
+                price_update.price_message.ema_conf,
+            ),
+            (false, _) => (price.price, price.exponent, price.conf),
         };
 
         if final_price <= 0
@@ -361,6 +581,7 @@ impl OraclePrice {
         Ok(OraclePrice {
             price: final_price as u64,
             exponent: final_exponent,
+            conf: conf_value,
         })
     }
 }
@@ -393,4 +614,147 @@ mod test {
         assert_eq!(1, scaled.price);
         assert_eq!(1, scaled.exponent);
     }
+
+    #[test]
+    fn test_custom_oracle_set() {
+        let oracle_params = OracleParams {
+            max_price_age_sec: 30,
+            ema_half_life_sec: 60,
+            max_price_deviation: 1_000,
+            ..Default::default()
+        };
+        let mut oracle = CustomOracle::default();
+
+        // first sample initializes the EMA
+        oracle
+            .set(1_000, -3, 10, 0, 100, &oracle_params, true)
+            .unwrap();
+        assert_eq!(1_000, oracle.ema);
+
+        // one half-life later the EMA is halfway to the new price
+        oracle
+            .set(1_080, -3, 10, 0, 160, &oracle_params, true)
+            .unwrap();
+        assert_eq!(1_040, oracle.ema);
+
+        // more than 10% away from the previous sample
+        assert!(oracle
+            .set(1_200, -3, 10, 0, 170, &oracle_params, true)
+            .is_err());
+        assert_eq!(1_080, oracle.price);
+
+        let samples = oracle.get_samples();
+        assert_eq!(2, samples.len());
+        assert_eq!(1_000, samples[0].price);
+        assert_eq!(160, samples[1].publish_time);
+
+        // ring buffer keeps the most recent samples
+        for i in 0..CustomOracle::MAX_SAMPLES as i64 {
+            oracle
+                .set(1_080, -3, 10, 0, 200 + i, &oracle_params, true)
+                .unwrap();
+        }
+        let samples = oracle.get_samples();
+        assert_eq!(CustomOracle::MAX_SAMPLES, samples.len());
+        assert_eq!(200, samples[0].publish_time);
+
+        // without a half-life the submitted EMA is kept
+        oracle
+            .set(1_080, -3, 10, 1_234, 300, &OracleParams::default(), true)
+            .unwrap();
+        assert_eq!(1_234, oracle.ema);
+    }
+
+    #[test]
+    fn test_custom_oracle_set_deviation() {
+        let oracle_params = OracleParams {
+            max_price_age_sec: 30,
+            max_price_deviation: 1_000,
+            ..Default::default()
+        };
+        let mut oracle = CustomOracle::default();
+        oracle
+            .set(1_000, -3, 10, 1_000, 100, &oracle_params, true)
+            .unwrap();
+
+        // the allowed move grows with the time since the previous sample
+        assert!(oracle
+            .set(1_150, -3, 10, 1_150, 129, &oracle_params, true)
+            .is_err());
+        oracle
+            .set(1_150, -3, 10, 1_150, 160, &oracle_params, true)
+            .unwrap();
+
+        // the admin path is not limited
+        oracle
+            .set(2_000, -3, 10, 2_000, 161, &oracle_params, false)
+            .unwrap();
+        assert_eq!(2_000, oracle.price);
+    }
+
+    #[test]
+    fn test_check_reference_band() {
+        let reference_key = Pubkey::new_unique();
+        let oracle_params = OracleParams {
+            max_price_error: 100,
+            max_price_age_sec: 30,
+            reference_oracle_account: reference_key,
+            reference_oracle_type: OracleType::Custom,
+            max_reference_deviation: 500,
+            ..Default::default()
+        };
+        let reference_oracle = CustomOracle {
+            price: 1_000,
+            expo: -3,
+            conf: 1,
+            ema: 1_000,
+            publish_time: 100,
+            ..Default::default()
+        };
+
+        let program_id = crate::ID;
+        let mut lamports = 1_000_000;
+        let mut data = Vec::new();
+        reference_oracle.try_serialize(&mut data).unwrap();
+        let reference_account = AccountInfo::new(
+            &reference_key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        // within 5% of the reference in either direction
+        assert!(OraclePrice::new(1_050, -3)
+            .check_reference_band(&reference_account, &oracle_params, 110)
+            .is_ok());
+        assert!(OraclePrice::new(950_000, -6)
+            .check_reference_band(&reference_account, &oracle_params, 110)
+            .is_ok());
+
+        // outside the band
+        assert!(OraclePrice::new(1_051, -3)
+            .check_reference_band(&reference_account, &oracle_params, 110)
+            .is_err());
+        assert!(OraclePrice::new(949, -3)
+            .check_reference_band(&reference_account, &oracle_params, 110)
+            .is_err());
+
+        // a stale reference is not trusted
+        assert!(OraclePrice::new(1_000, -3)
+            .check_reference_band(&reference_account, &oracle_params, 131)
+            .is_err());
+
+        // nor is an account other than the configured one
+        let other_params = OracleParams {
+            reference_oracle_account: Pubkey::new_unique(),
+            ..oracle_params
+        };
+        assert!(OraclePrice::new(1_000, -3)
+            .check_reference_band(&reference_account, &other_params, 110)
+            .is_err());
+    }
 }
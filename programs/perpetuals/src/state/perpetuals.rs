@@ -1,5 +1,5 @@
 use {
-    crate::try_from,
+    crate::{state::pool::AumCache, try_from},
     anchor_lang::{prelude::*, solana_program},
     anchor_spl::token::{Burn, MintTo, Transfer},
 };
@@ -21,6 +21,10 @@ pub struct NewPositionPricesAndFee {
     pub entry_price: u64,
     pub liquidation_price: u64,
     pub fee: u64,
+    // spread applied to the entry price (BPS)
+    pub spread: u64,
+    // size-based price impact added to the spread (BPS)
+    pub price_impact: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -28,6 +32,8 @@ pub struct SwapAmountAndFees {
     pub amount_out: u64,
     pub fee_in: u64,
     pub fee_out: u64,
+    // spread applied to the swap price (BPS)
+    pub spread: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -36,6 +42,47 @@ pub struct ProfitAndLoss {
     pub loss: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct PositionInfo {
+    pub entry_price: u64,
+    pub exit_price: u64,
+    pub size_usd: u64,
+    pub collateral_usd: u64,
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    pub exit_fee: u64,
+    pub interest_usd: u64,
+    pub leverage: u64,
+    pub liquidation_price: u64,
+    pub liquidation_state: u8,
+    pub take_profit_price: Option<u64>,
+    pub stop_loss_price: Option<u64>,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct RemoveCollateralInfo {
+    pub collateral_usd: u64,
+    pub leverage: u64,
+    pub liquidation_price: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct OpenInterestHeadroom {
+    pub oi_long_usd: u64,
+    pub oi_short_usd: u64,
+    // u64::MAX if the side is not limited
+    pub long_headroom_usd: u64,
+    pub short_headroom_usd: u64,
+}
+
+#[derive(Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct AumBreakdown {
+    pub aum_usd: u128,
+    pub lp_token_price: u64,
+    // same order as pool.custodies
+    pub custodies: Vec<AumCache>,
+}
+
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct Permissions {
     pub allow_swap: bool,
@@ -48,6 +95,14 @@ pub struct Permissions {
     pub allow_size_change: bool,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct FeeTier {
+    // 30-day trading volume required to reach the tier
+    pub min_volume_usd: u64,
+    // discount applied to open, close and swap fees (BPS)
+    pub fee_discount: u64,
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Perpetuals {
@@ -58,6 +113,8 @@ pub struct Perpetuals {
     pub perpetuals_bump: u8,
     // time of inception, also used as current wall clock time for testing
     pub inception_time: i64,
+    // volume based fee discounts, in ascending order of min_volume_usd
+    pub fee_tiers: [FeeTier; 4],
 }
 
 impl anchor_lang::Id for Perpetuals {
@@ -77,7 +134,27 @@ impl Perpetuals {
     pub const RATE_POWER: u128 = 10u64.pow(Self::RATE_DECIMALS as u32) as u128;
 
     pub fn validate(&self) -> bool {
-        true
+        Self::validate_fee_tiers(&self.fee_tiers)
+    }
+
+    pub fn validate_fee_tiers(fee_tiers: &[FeeTier]) -> bool {
+        fee_tiers
+            .iter()
+            .all(|tier| tier.fee_discount as u128 <= Perpetuals::BPS_POWER)
+            && fee_tiers.windows(2).all(|w| {
+                w[1].fee_discount == 0
+                    || (w[0].fee_discount > 0 && w[0].min_volume_usd < w[1].min_volume_usd)
+            })
+    }
+
+    // discount of the highest tier reached, unused (zero discount) tiers are skipped
+    pub fn get_fee_discount(&self, volume_usd: u64) -> u64 {
+        self.fee_tiers
+            .iter()
+            .filter(|tier| tier.fee_discount > 0 && volume_usd >= tier.min_volume_usd)
+            .map(|tier| tier.fee_discount)
+            .next_back()
+            .unwrap_or(0)
     }
 
     #[cfg(feature = "test")]
@@ -30,6 +30,22 @@ pub struct TokenRatios {
     pub max: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct AumCache {
+    // value of custody owned assets
+    pub owned_usd: u64,
+    // owned assets valued at the lower and the higher of spot and ema prices,
+    // LP tokens are burned against the lower value and minted against the higher one
+    pub min_owned_usd: u64,
+    pub max_owned_usd: u64,
+    // collective unrealized pnl of traders, accrued interest is counted as loss
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    // price used for the valuation, scaled to PRICE_DECIMALS
+    pub price: u64,
+    pub update_time: i64,
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Pool {
@@ -41,6 +57,35 @@ pub struct Pool {
     pub bump: u8,
     pub lp_token_bump: u8,
     pub inception_time: i64,
+    // time after which open positions can be force-settled, zero if the pool is not winding down
+    pub wind_down_deadline: i64,
+    // cached custody valuations, same order as custodies
+    pub aum_cache: Vec<AumCache>,
+    // cached custody valuations older than this are not used by LP instructions,
+    // never longer than the max_price_age_sec of any pool custody
+    pub aum_cache_max_age_sec: u32,
+}
+
+impl AumCache {
+    pub fn get_aum_usd(&self) -> Result<u128> {
+        self.get_adjusted_aum_usd(self.owned_usd)
+    }
+
+    // Min and Max use the matching owned value, other modes the one the cache was priced with
+    pub fn get_aum_usd_with_mode(&self, aum_calc_mode: AumCalcMode) -> Result<u128> {
+        match aum_calc_mode {
+            AumCalcMode::Min => self.get_adjusted_aum_usd(self.min_owned_usd),
+            AumCalcMode::Max => self.get_adjusted_aum_usd(self.max_owned_usd),
+            AumCalcMode::Last | AumCalcMode::EMA => self.get_aum_usd(),
+        }
+    }
+
+    fn get_adjusted_aum_usd(&self, owned_usd: u64) -> Result<u128> {
+        Ok(
+            math::checked_add(owned_usd as u128, self.unrealized_loss_usd as u128)?
+                .saturating_sub(self.unrealized_profit_usd as u128),
+        )
+    }
 }
 
 impl TokenRatios {
@@ -59,6 +104,15 @@ impl TokenRatios {
 ///
 impl Pool {
     pub const LEN: usize = 8 + 64 + std::mem::size_of::<Pool>();
+    // re-pricing the pool takes every custody and its oracle as remaining accounts,
+    // the limit keeps LP instructions within the transaction account limit
+    pub const MAX_CUSTODIES: usize = 16;
+    // aum_cache_max_age_sec of new pools, custodies with fresher oracles lower it
+    pub const DEFAULT_AUM_CACHE_MAX_AGE_SEC: u32 = 60;
+
+    pub fn is_winding_down(&self) -> bool {
+        self.wind_down_deadline > 0
+    }
 
     pub fn validate(&self) -> bool {
         for ratio in &self.ratios {
@@ -86,7 +140,10 @@ impl Pool {
             }
         }
 
-        !self.name.is_empty() && self.name.len() <= 64 && self.custodies.len() == self.ratios.len()
+        !self.name.is_empty()
+            && self.name.len() <= 64
+            && self.custodies.len() <= Self::MAX_CUSTODIES
+            && self.custodies.len() == self.ratios.len()
     }
 
     pub fn get_token_id(&self, custody: &Pubkey) -> Result<usize> {
@@ -96,22 +153,29 @@ impl Pool {
             .ok_or_else(|| PerpetualsError::UnsupportedToken.into())
     }
 
+    // size is the token amount of the new position
     pub fn get_entry_price(
         &self,
         token_price: &OraclePrice,
         token_ema_price: &OraclePrice,
         side: Side,
+        size: u64,
         custody: &Custody,
     ) -> Result<u64> {
+        let price_impact = self.get_price_impact(
+            token_ema_price,
+            token_ema_price.get_asset_amount_usd(size, custody.decimals)?,
+            custody.get_open_interest_usd(side),
+            custody,
+        )?;
         let price = self.get_price(
             token_price,
             token_ema_price,
             side,
-            if side == Side::Long {
-                custody.pricing.trade_spread_long
-            } else {
-                custody.pricing.trade_spread_short
-            },
+            math::checked_add(
+                self.get_trade_spread(token_price, token_ema_price, side, custody)?,
+                price_impact,
+            )?,
         )?;
         require_gt!(price.price, 0, PerpetualsError::MaxPriceSlippage);
 
@@ -123,6 +187,7 @@ impl Pool {
     pub fn get_entry_fee(
         &self,
         base_fee: u64,
+        fee_discount: u64,
         size: u64,
         locked_amount: u64,
         collateral_custody: &Custody,
@@ -172,29 +237,37 @@ impl Pool {
             )?)?;
         }
 
-        Ok(size_fee)
+        Self::apply_fee_discount(size_fee, fee_discount)
     }
 
+    // size_usd is the closed size, already part of the custody open interest
     pub fn get_exit_price(
         &self,
         token_price: &OraclePrice,
         token_ema_price: &OraclePrice,
         side: Side,
+        size_usd: u64,
         custody: &Custody,
     ) -> Result<u64> {
+        let exit_side = if side == Side::Long {
+            Side::Short
+        } else {
+            Side::Long
+        };
+        let price_impact = self.get_price_impact(
+            token_ema_price,
+            size_usd,
+            custody.get_open_interest_usd(side).saturating_sub(size_usd),
+            custody,
+        )?;
         let price = self.get_price(
             token_price,
             token_ema_price,
-            if side == Side::Long {
-                Side::Short
-            } else {
-                Side::Long
-            },
-            if side == Side::Long {
-                custody.pricing.trade_spread_short
-            } else {
-                custody.pricing.trade_spread_long
-            },
+            exit_side,
+            math::checked_add(
+                self.get_trade_spread(token_price, token_ema_price, exit_side, custody)?,
+                price_impact,
+            )?,
         )?;
 
         Ok(price
@@ -202,8 +275,11 @@ impl Pool {
             .price)
     }
 
-    pub fn get_exit_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
-        Self::get_fee_amount(custody.fees.close_position, size)
+    pub fn get_exit_fee(&self, size: u64, fee_discount: u64, custody: &Custody) -> Result<u64> {
+        Self::apply_fee_discount(
+            Self::get_fee_amount(custody.fees.close_position, size)?,
+            fee_discount,
+        )
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -218,8 +294,42 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
+        fee_discount: u64,
     ) -> Result<(u64, u64, u64, u64)> {
-        let (profit_usd, loss_usd, fee_amount) = self.get_pnl_usd(
+        let (close_amount, fee_amount, profit_usd, loss_usd, _) = self
+            .get_close_amount_with_shortfall(
+                position,
+                token_price,
+                token_ema_price,
+                custody,
+                collateral_token_price,
+                collateral_token_ema_price,
+                collateral_custody,
+                curtime,
+                liquidation,
+                fee_discount,
+            )?;
+
+        Ok((close_amount, fee_amount, profit_usd, loss_usd))
+    }
+
+    // returns get_close_amount totals and the loss the position collateral doesn't cover,
+    // exit fee and accrued interest included
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_close_amount_with_shortfall(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        liquidation: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64, u64, u64, u64)> {
+        let (profit_usd, loss_usd, fee_amount) = self.get_exit_pnl_usd(
             position,
             token_price,
             token_ema_price,
@@ -229,17 +339,22 @@ impl Pool {
             collateral_custody,
             curtime,
             liquidation,
+            fee_discount,
         )?;
 
-        let available_amount_usd = if profit_usd > 0 {
-            math::checked_add(position.collateral_usd, profit_usd)?
+        let (available_amount_usd, shortfall_usd) = if profit_usd > 0 {
+            (math::checked_add(position.collateral_usd, profit_usd)?, 0)
         } else if loss_usd < position.collateral_usd {
-            math::checked_sub(position.collateral_usd, loss_usd)?
+            (math::checked_sub(position.collateral_usd, loss_usd)?, 0)
         } else {
-            0
+            (0, math::checked_sub(loss_usd, position.collateral_usd)?)
         };
 
-        let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
+        // max(spot, ema), or spot if the depeg policy of the stable collateral says so
+        let max_collateral_price = if collateral_token_price > collateral_token_ema_price
+            || (collateral_custody.pricing.pay_out_at_spot_on_depeg
+                && collateral_custody.is_depegged(collateral_token_price)?)
+        {
             collateral_token_price
         } else {
             collateral_token_ema_price
@@ -256,6 +371,7 @@ impl Pool {
             fee_amount,
             profit_usd,
             loss_usd,
+            shortfall_usd,
         ))
     }
 
@@ -285,7 +401,83 @@ impl Pool {
             &pair_price,
             &pair_price,
             Side::Short,
+            self.get_swap_spread(
+                token_in_price,
+                token_in_ema_price,
+                token_out_price,
+                token_out_ema_price,
+                custody_in,
+            )?,
+        )
+    }
+
+    // Returns size-based price impact (BPS), open_interest_usd is the trade side interest
+    // excluding the trade itself
+    pub fn get_price_impact(
+        &self,
+        token_price: &OraclePrice,
+        size_usd: u64,
+        open_interest_usd: u64,
+        custody: &Custody,
+    ) -> Result<u64> {
+        if custody.pricing.price_impact_mult == 0 || size_usd == 0 {
+            return Ok(0);
+        }
+
+        let owned_usd = token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
+        if owned_usd <= open_interest_usd {
+            return Ok(custody.pricing.max_price_impact);
+        }
+        let depth_usd = math::checked_sub(owned_usd, open_interest_usd)?;
+
+        let price_impact = math::checked_div(
+            math::checked_mul(size_usd as u128, custody.pricing.price_impact_mult as u128)?,
+            depth_usd as u128,
+        )?;
+
+        Ok(std::cmp::min(price_impact, custody.pricing.max_price_impact as u128) as u64)
+    }
+
+    // Returns spread applied to trades of the given price side (BPS)
+    pub fn get_trade_spread(
+        &self,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        side: Side,
+        custody: &Custody,
+    ) -> Result<u64> {
+        let base_spread = if side == Side::Long {
+            custody.pricing.trade_spread_long
+        } else {
+            custody.pricing.trade_spread_short
+        };
+
+        custody.pricing.get_spread(
+            base_spread,
+            token_price.get_conf_bps()?,
+            token_price.get_divergence_bps(token_ema_price)?,
+        )
+    }
+
+    // Returns spread applied to swaps (BPS), both legs of the pair add to the dynamic part
+    pub fn get_swap_spread(
+        &self,
+        token_in_price: &OraclePrice,
+        token_in_ema_price: &OraclePrice,
+        token_out_price: &OraclePrice,
+        token_out_ema_price: &OraclePrice,
+        custody_in: &Custody,
+    ) -> Result<u64> {
+        custody_in.pricing.get_spread(
             custody_in.pricing.swap_spread,
+            math::checked_add(
+                token_in_price.get_conf_bps()?,
+                token_out_price.get_conf_bps()?,
+            )?,
+            math::checked_add(
+                token_in_price.get_divergence_bps(token_in_ema_price)?,
+                token_out_price.get_divergence_bps(token_out_ema_price)?,
+            )?,
         )
     }
 
@@ -328,6 +520,7 @@ impl Pool {
         token_price_in: &OraclePrice,
         custody_out: &Custody,
         token_price_out: &OraclePrice,
+        fee_discount: u64,
     ) -> Result<(u64, u64)> {
         let stable_swap = custody_in.is_stable && custody_out.is_stable;
 
@@ -357,7 +550,193 @@ impl Pool {
             token_price_out,
         )?;
 
-        Ok((swap_in_fee, swap_out_fee))
+        Ok((
+            Self::apply_fee_discount(swap_in_fee, fee_discount)?,
+            Self::apply_fee_discount(swap_out_fee, fee_discount)?,
+        ))
+    }
+
+    /// Prices and books a swap chained into another instruction (open / close position).
+    /// Token transfers are left to the caller, returns the amount out net of fees.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_internal(
+        &self,
+        token_id_in: usize,
+        token_id_out: usize,
+        amount_in: u64,
+        min_amount_out: u64,
+        receiving_custody: &mut Custody,
+        received_token_price: &OraclePrice,
+        received_token_ema_price: &OraclePrice,
+        dispensing_custody: &mut Custody,
+        dispensed_token_price: &OraclePrice,
+        dispensed_token_ema_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<u64> {
+        Ok(self
+            .swap_internal_with_referral(
+                token_id_in,
+                token_id_out,
+                amount_in,
+                min_amount_out,
+                receiving_custody,
+                received_token_price,
+                received_token_ema_price,
+                dispensing_custody,
+                dispensed_token_price,
+                dispensed_token_ema_price,
+                curtime,
+                false,
+                false,
+                0,
+            )?
+            .0)
+    }
+
+    /// Same as swap_internal, with the referral split applied to the output fee
+    /// (the rebate part only if accrue_rebate is set, it stays in the fee otherwise)
+    /// and the trader fee tier discount (BPS) applied to both fees.
+    /// Returns (amount out net of fees, referrer rebate in dispensed tokens).
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_internal_with_referral(
+        &self,
+        token_id_in: usize,
+        token_id_out: usize,
+        amount_in: u64,
+        min_amount_out: u64,
+        receiving_custody: &mut Custody,
+        received_token_price: &OraclePrice,
+        received_token_ema_price: &OraclePrice,
+        dispensing_custody: &mut Custody,
+        dispensed_token_price: &OraclePrice,
+        dispensed_token_ema_price: &OraclePrice,
+        curtime: i64,
+        use_referral: bool,
+        accrue_rebate: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64)> {
+        require!(!self.is_winding_down(), PerpetualsError::PoolWindingDown);
+        require!(
+            receiving_custody.permissions.allow_swap
+                && dispensing_custody.permissions.allow_swap
+                && !receiving_custody.is_virtual
+                && !dispensing_custody.is_virtual,
+            PerpetualsError::InstructionNotAllowed
+        );
+
+        let amount_out = self.get_swap_amount(
+            received_token_price,
+            received_token_ema_price,
+            dispensed_token_price,
+            dispensed_token_ema_price,
+            receiving_custody,
+            dispensing_custody,
+            amount_in,
+        )?;
+
+        let fees = self.get_swap_fees(
+            token_id_in,
+            token_id_out,
+            amount_in,
+            amount_out,
+            receiving_custody,
+            received_token_price,
+            dispensing_custody,
+            dispensed_token_price,
+            fee_discount,
+        )?;
+        msg!("Collected swap fees: {} {}", fees.0, fees.1);
+
+        let (discount, rebate) = if use_referral {
+            let (discount, rebate) = dispensing_custody.fees.get_referral_split(fees.1)?;
+            (discount, if accrue_rebate { rebate } else { 0 })
+        } else {
+            (0, 0)
+        };
+        let fee_out = math::checked_sub(fees.1, math::checked_add(discount, rebate)?)?;
+
+        // check returned amount
+        let no_fee_amount = math::checked_add(math::checked_sub(amount_out, fees.1)?, discount)?;
+        msg!("Swap amount out: {}", no_fee_amount);
+        require_gte!(
+            no_fee_amount,
+            min_amount_out,
+            PerpetualsError::InsufficientAmountReturned
+        );
+
+        // check pool constraints
+        let protocol_fee_in = Self::get_fee_amount(receiving_custody.fees.protocol_share, fees.0)?;
+        let protocol_fee_out =
+            Self::get_fee_amount(dispensing_custody.fees.protocol_share, fee_out)?;
+        let deposit_amount = math::checked_sub(amount_in, protocol_fee_in)?;
+        let withdrawal_amount =
+            math::checked_add(no_fee_amount, math::checked_add(protocol_fee_out, rebate)?)?;
+
+        require!(
+            self.check_token_ratio(
+                token_id_in,
+                deposit_amount,
+                0,
+                receiving_custody,
+                received_token_price
+            )? && self.check_token_ratio(
+                token_id_out,
+                0,
+                withdrawal_amount,
+                dispensing_custody,
+                dispensed_token_price
+            )?,
+            PerpetualsError::TokenRatioOutOfRange
+        );
+        require!(
+            math::checked_sub(
+                dispensing_custody.assets.owned,
+                dispensing_custody.assets.locked
+            )? >= withdrawal_amount,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        // update custody stats
+        receiving_custody.volume_stats.swap_usd =
+            receiving_custody.volume_stats.swap_usd.wrapping_add(
+                received_token_price.get_asset_amount_usd(amount_in, receiving_custody.decimals)?,
+            );
+
+        receiving_custody.collected_fees.swap_usd =
+            receiving_custody.collected_fees.swap_usd.wrapping_add(
+                received_token_price.get_asset_amount_usd(fees.0, receiving_custody.decimals)?,
+            );
+
+        receiving_custody.assets.owned =
+            math::checked_add(receiving_custody.assets.owned, deposit_amount)?;
+
+        receiving_custody.assets.protocol_fees =
+            math::checked_add(receiving_custody.assets.protocol_fees, protocol_fee_in)?;
+
+        dispensing_custody.collected_fees.swap_usd =
+            dispensing_custody.collected_fees.swap_usd.wrapping_add(
+                dispensed_token_price.get_asset_amount_usd(fees.1, dispensing_custody.decimals)?,
+            );
+
+        dispensing_custody.volume_stats.swap_usd =
+            dispensing_custody.volume_stats.swap_usd.wrapping_add(
+                dispensed_token_price
+                    .get_asset_amount_usd(amount_out, dispensing_custody.decimals)?,
+            );
+
+        dispensing_custody.assets.protocol_fees =
+            math::checked_add(dispensing_custody.assets.protocol_fees, protocol_fee_out)?;
+
+        dispensing_custody.assets.referral_rebates =
+            math::checked_add(dispensing_custody.assets.referral_rebates, rebate)?;
+
+        dispensing_custody.assets.owned =
+            math::checked_sub(dispensing_custody.assets.owned, withdrawal_amount)?;
+
+        receiving_custody.update_borrow_rate(curtime)?;
+        dispensing_custody.update_borrow_rate(curtime)?;
+
+        Ok((no_fee_amount, rebate))
     }
 
     pub fn get_add_liquidity_fee(
@@ -447,6 +826,7 @@ impl Pool {
             collateral_custody,
             curtime,
             false,
+            0,
         )?;
 
         let current_margin_usd = if profit_usd > 0 {
@@ -512,7 +892,7 @@ impl Pool {
         }
 
         let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
-        let exit_fee_tokens = self.get_exit_fee(size, custody)?;
+        let exit_fee_tokens = self.get_exit_fee(size, 0, custody)?;
         let exit_fee_usd =
             token_ema_price.get_asset_amount_usd(exit_fee_tokens, custody.decimals)?;
         let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
@@ -572,7 +952,8 @@ impl Pool {
         }
     }
 
-    // returns (profit_usd, loss_usd, fee_amount)
+    // returns (profit_usd, loss_usd, fee_amount) at the mark price, the price impact of
+    // unwinding the position is left out, so valuations and risk checks don't depend on depth
     #[allow(clippy::too_many_arguments)]
     pub fn get_pnl_usd(
         &self,
@@ -585,20 +966,91 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
+        fee_discount: u64,
     ) -> Result<(u64, u64, u64)> {
         if position.size_usd == 0 || position.price == 0 {
             return Ok((0, 0, 0));
         }
 
         let exit_price =
-            self.get_exit_price(token_price, token_ema_price, position.side, custody)?;
+            self.get_exit_price(token_price, token_ema_price, position.side, 0, custody)?;
+
+        self.get_pnl_usd_at_price(
+            position,
+            exit_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            liquidation,
+            fee_discount,
+        )
+    }
+
+    // returns (profit_usd, loss_usd, fee_amount) realized by closing the whole position,
+    // price impact included
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_exit_pnl_usd(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        liquidation: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64, u64)> {
+        if position.size_usd == 0 || position.price == 0 {
+            return Ok((0, 0, 0));
+        }
+
+        let exit_price = self.get_exit_price(
+            token_price,
+            token_ema_price,
+            position.side,
+            position.size_usd,
+            custody,
+        )?;
+
+        self.get_pnl_usd_at_price(
+            position,
+            exit_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            liquidation,
+            fee_discount,
+        )
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn get_pnl_usd_at_price(
+        &self,
+        position: &Position,
+        exit_price: u64,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        liquidation: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64, u64)> {
         let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
 
         let exit_fee = if liquidation {
             self.get_liquidation_fee(size, custody)?
         } else {
-            self.get_exit_fee(size, custody)?
+            self.get_exit_fee(size, fee_discount, custody)?
         };
 
         let exit_fee_usd = token_ema_price.get_asset_amount_usd(exit_fee, custody.decimals)?;
@@ -641,10 +1093,10 @@ impl Pool {
                     // if collateral_custody is virtual it means this function is called from get_assets_under_management_usd()
                     // (to calculate unrealized pnl of all open positions) and actual collateral custody is a stablecoin.
                     // we need to use 1USD reference price for such positions
-                    OraclePrice {
-                        price: 10u64.pow(Perpetuals::USD_DECIMALS as u32),
-                        exponent: -(Perpetuals::USD_DECIMALS as i32),
-                    }
+                    OraclePrice::new(
+                        10u64.pow(Perpetuals::USD_DECIMALS as u32),
+                        -(Perpetuals::USD_DECIMALS as i32),
+                    )
                 } else {
                     collateral_token_price
                         .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?
@@ -685,10 +1137,10 @@ impl Pool {
                 let cur_profit_usd =
                     math::checked_sub(position.unrealized_profit_usd, potential_loss_usd)?;
                 let min_collateral_price = if collateral_custody.is_virtual {
-                    OraclePrice {
-                        price: 10u64.pow(Perpetuals::USD_DECIMALS as u32),
-                        exponent: -(Perpetuals::USD_DECIMALS as i32),
-                    }
+                    OraclePrice::new(
+                        10u64.pow(Perpetuals::USD_DECIMALS as u32),
+                        -(Perpetuals::USD_DECIMALS as i32),
+                    )
                 } else {
                     collateral_token_price
                         .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?
@@ -716,6 +1168,20 @@ impl Pool {
         // feed_id: [u8; 32],
     ) -> Result<u128> {
         let mut pool_amount_usd: u128 = 0;
+        for custody_aum in self.get_custodies_aum(aum_calc_mode, accounts, curtime)? {
+            pool_amount_usd = math::checked_add(pool_amount_usd, custody_aum.get_aum_usd()?)?;
+        }
+
+        Ok(pool_amount_usd)
+    }
+
+    pub fn get_custodies_aum(
+        &self,
+        aum_calc_mode: AumCalcMode,
+        accounts: &[AccountInfo],
+        curtime: i64,
+    ) -> Result<Vec<AumCache>> {
+        let mut custodies_aum = Vec::with_capacity(self.custodies.len());
         for (idx, &custody) in self.custodies.iter().enumerate() {
             let oracle_idx = idx + self.custodies.len();
             if oracle_idx >= accounts.len() {
@@ -741,6 +1207,7 @@ impl Pool {
             let token_price = OraclePrice::new_from_oracle(
                 &oracle_account,
                 None,
+                None,
                 &custody.oracle,
                 curtime,
                 false,
@@ -750,83 +1217,206 @@ impl Pool {
             let token_ema_price = OraclePrice::new_from_oracle(
                 &oracle_account,
                 None,
+                None,
                 &custody.oracle,
                 curtime,
-                false,
+                custody.pricing.use_ema,
                 custody.oracle.feed_id,
             )?;
 
-            let aum_token_price = match aum_calc_mode {
-                AumCalcMode::Last => token_price,
-                AumCalcMode::EMA => token_ema_price,
-                AumCalcMode::Min => {
-                    if token_price < token_ema_price {
-                        token_price
-                    } else {
-                        token_ema_price
-                    }
-                }
-                AumCalcMode::Max => {
-                    if token_price > token_ema_price {
-                        token_price
-                    } else {
-                        token_ema_price
-                    }
-                }
-            };
+            custodies_aum.push(self.get_custody_aum(
+                &custody,
+                &token_price,
+                &token_ema_price,
+                aum_calc_mode,
+                curtime,
+            )?);
+        }
+
+        Ok(custodies_aum)
+    }
 
-            let token_amount_usd =
-                aum_token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
+    pub fn get_custody_aum(
+        &self,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        aum_calc_mode: AumCalcMode,
+        curtime: i64,
+    ) -> Result<AumCache> {
+        let (min_token_price, max_token_price) = if token_price < token_ema_price {
+            (*token_price, *token_ema_price)
+        } else {
+            (*token_ema_price, *token_price)
+        };
+        let aum_token_price = match aum_calc_mode {
+            AumCalcMode::Last => *token_price,
+            AumCalcMode::EMA => *token_ema_price,
+            AumCalcMode::Min => min_token_price,
+            AumCalcMode::Max => max_token_price,
+        };
 
-            pool_amount_usd = math::checked_add(pool_amount_usd, token_amount_usd as u128)?;
+        let mut custody_aum = AumCache {
+            owned_usd: aum_token_price
+                .get_asset_amount_usd(custody.assets.owned, custody.decimals)?,
+            min_owned_usd: min_token_price
+                .get_asset_amount_usd(custody.assets.owned, custody.decimals)?,
+            max_owned_usd: max_token_price
+                .get_asset_amount_usd(custody.assets.owned, custody.decimals)?,
+            unrealized_profit_usd: 0,
+            unrealized_loss_usd: 0,
+            price: aum_token_price
+                .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
+                .price,
+            update_time: curtime,
+        };
 
-            if custody.pricing.use_unrealized_pnl_in_aum {
-                if custody.is_stable {
-                    // compute accumulated interest
-                    let collective_position = custody.get_collective_position(Side::Long)?;
-                    let interest_usd =
-                        custody.get_interest_amount_usd(&collective_position, curtime)?;
-                    pool_amount_usd = math::checked_add(pool_amount_usd, interest_usd as u128)?;
+        if custody.pricing.use_unrealized_pnl_in_aum {
+            if custody.is_stable {
+                // compute accumulated interest
+                let collective_position = custody.get_collective_position(Side::Long)?;
+                let long_interest_usd =
+                    custody.get_interest_amount_usd(&collective_position, curtime)?;
 
-                    let collective_position = custody.get_collective_position(Side::Short)?;
-                    let interest_usd =
-                        custody.get_interest_amount_usd(&collective_position, curtime)?;
-                    pool_amount_usd = math::checked_add(pool_amount_usd, interest_usd as u128)?;
-                } else {
-                    // compute aggregate unrealized pnl
-                    let (long_profit, long_loss, _) = self.get_pnl_usd(
-                        &custody.get_collective_position(Side::Long)?,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        curtime,
-                        false,
-                    )?;
-                    let (short_profit, short_loss, _) = self.get_pnl_usd(
-                        &custody.get_collective_position(Side::Short)?,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        &token_price,
-                        &token_ema_price,
-                        &custody,
-                        curtime,
-                        false,
-                    )?;
-
-                    // adjust pool amount by collective profit/loss
-                    pool_amount_usd = math::checked_add(pool_amount_usd, long_loss as u128)?;
-                    pool_amount_usd = math::checked_add(pool_amount_usd, short_loss as u128)?;
-                    pool_amount_usd = pool_amount_usd.saturating_sub(long_profit as u128);
-                    pool_amount_usd = pool_amount_usd.saturating_sub(short_profit as u128);
-                }
+                let collective_position = custody.get_collective_position(Side::Short)?;
+                let short_interest_usd =
+                    custody.get_interest_amount_usd(&collective_position, curtime)?;
+
+                custody_aum.unrealized_loss_usd =
+                    math::checked_add(long_interest_usd, short_interest_usd)?;
+            } else {
+                // compute aggregate unrealized pnl
+                let (long_profit, long_loss, _) = self.get_pnl_usd(
+                    &custody.get_collective_position(Side::Long)?,
+                    token_price,
+                    token_ema_price,
+                    custody,
+                    token_price,
+                    token_ema_price,
+                    custody,
+                    curtime,
+                    false,
+                    0,
+                )?;
+                let (short_profit, short_loss, _) = self.get_pnl_usd(
+                    &custody.get_collective_position(Side::Short)?,
+                    token_price,
+                    token_ema_price,
+                    custody,
+                    token_price,
+                    token_ema_price,
+                    custody,
+                    curtime,
+                    false,
+                    0,
+                )?;
+
+                // custody amount is adjusted by collective profit/loss
+                custody_aum.unrealized_profit_usd = math::checked_add(long_profit, short_profit)?;
+                custody_aum.unrealized_loss_usd = math::checked_add(long_loss, short_loss)?;
             }
         }
 
-        Ok(pool_amount_usd)
+        Ok(custody_aum)
+    }
+
+    /// Returns the sum of cached custody valuations if all of them are fresh.
+    /// Otherwise the caller must provide custody and oracle accounts to re-price the pool,
+    /// None is returned in that case and the cache is rejected if no accounts were provided.
+    pub fn get_cached_aum_usd(
+        &self,
+        aum_calc_mode: AumCalcMode,
+        accounts: &[AccountInfo],
+        curtime: i64,
+    ) -> Result<Option<u128>> {
+        let mut pool_amount_usd: u128 = 0;
+        let mut is_fresh = self.aum_cache.len() == self.custodies.len();
+        for cache in &self.aum_cache {
+            if cache.update_time == 0
+                || math::checked_sub(curtime, cache.update_time)?
+                    > self.aum_cache_max_age_sec as i64
+            {
+                is_fresh = false;
+                break;
+            }
+            pool_amount_usd =
+                math::checked_add(pool_amount_usd, cache.get_aum_usd_with_mode(aum_calc_mode)?)?;
+        }
+
+        if is_fresh {
+            Ok(Some(pool_amount_usd))
+        } else if accounts.is_empty() {
+            err!(PerpetualsError::StaleAumCache)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Stores valuations of all custodies and refreshes pool.aum_usd.
+    pub fn set_aum_cache(&mut self, custodies_aum: Vec<AumCache>) -> Result<()> {
+        let mut pool_amount_usd: u128 = 0;
+        for custody_aum in &custodies_aum {
+            pool_amount_usd = math::checked_add(pool_amount_usd, custody_aum.get_aum_usd()?)?;
+        }
+        self.aum_usd = pool_amount_usd;
+
+        // the cache is only stored once it has been aligned with custodies
+        if self.aum_cache.len() == custodies_aum.len() {
+            self.aum_cache = custodies_aum;
+        }
+
+        Ok(())
+    }
+
+    /// Resets cached valuations that don't line up with custodies, e.g. in pools created
+    /// before the cache was introduced. Reset entries are stale until the crank prices them.
+    pub fn align_aum_cache(&mut self) {
+        if self.aum_cache.len() != self.custodies.len() {
+            self.aum_cache = vec![AumCache::default(); self.custodies.len()];
+        }
+    }
+
+    /// Stores a single custody valuation and refreshes pool.aum_usd from the cache.
+    pub fn set_cached_custody_aum(&mut self, token_id: usize, custody_aum: AumCache) -> Result<()> {
+        require_eq!(
+            self.aum_cache.len(),
+            self.custodies.len(),
+            PerpetualsError::InvalidPoolState
+        );
+        self.aum_cache[token_id] = custody_aum;
+        self.refresh_aum_usd_from_cache()
+    }
+
+    /// Marks every cached valuation stale, LP instructions need re-pricing accounts until
+    /// the crank runs again.
+    pub fn invalidate_aum_cache(&mut self) {
+        for cache in self.aum_cache.iter_mut() {
+            cache.update_time = 0;
+        }
+    }
+
+    /// Re-prices the cached custody valuation after its balances have changed, so that
+    /// LP tokens are never priced against owned or locked amounts the cache predates.
+    /// Pools whose cache hasn't been aligned yet are left to update_pool_aum.
+    pub fn update_cached_custody_aum(
+        &mut self,
+        custody: &Account<Custody>,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        curtime: i64,
+    ) -> Result<()> {
+        if self.aum_cache.len() != self.custodies.len() {
+            return Ok(());
+        }
+        let token_id = self.get_token_id(&custody.key())?;
+        let custody_aum = self.get_custody_aum(
+            custody,
+            token_price,
+            token_ema_price,
+            AumCalcMode::EMA,
+            curtime,
+        )?;
+        self.set_cached_custody_aum(token_id, custody_aum)
     }
 
     pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
@@ -839,7 +1429,30 @@ impl Pool {
         )?)
     }
 
+    // fee net of the trader tier discount, the discount is rounded down
+    pub fn apply_fee_discount(fee: u64, fee_discount: u64) -> Result<u64> {
+        if fee_discount == 0 {
+            return Ok(fee);
+        }
+        math::checked_sub(
+            fee,
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(fee as u128, fee_discount as u128)?,
+                Perpetuals::BPS_POWER,
+            )?)?,
+        )
+    }
+
     // private helpers
+    fn refresh_aum_usd_from_cache(&mut self) -> Result<()> {
+        let mut pool_amount_usd: u128 = 0;
+        for cache in &self.aum_cache {
+            pool_amount_usd = math::checked_add(pool_amount_usd, cache.get_aum_usd()?)?;
+        }
+        self.aum_usd = pool_amount_usd;
+        Ok(())
+    }
+
     fn get_current_ratio(&self, custody: &Custody, token_price: &OraclePrice) -> Result<u64> {
         if self.aum_usd == 0 || custody.is_virtual {
             return Ok(0);
@@ -935,6 +1548,7 @@ impl Pool {
                     )?,
                 )?,
                 exponent: max_price.exponent,
+                conf: max_price.conf,
             })
         } else {
             let min_price = if token_price < token_ema_price {
@@ -960,6 +1574,7 @@ impl Pool {
             Ok(OraclePrice {
                 price,
                 exponent: min_price.exponent,
+                conf: min_price.conf,
             })
         }
     }
@@ -1148,7 +1763,7 @@ mod test {
         crate::state::{
             custody::{BorrowRateParams, Fees, PricingParams},
             oracle::{OracleParams, OracleType},
-            perpetuals::Permissions,
+            perpetuals::{FeeTier, Permissions},
         },
     };
 
@@ -1166,6 +1781,18 @@ mod test {
             max_price_error: 100,
             max_price_age_sec: 1,
             feed_id: [0; 32],
+            ema_half_life_sec: 0,
+            max_price_deviation: 0,
+            extra_oracle_authorities: [Pubkey::default(); 4],
+            min_oracle_signatures: 0,
+            max_quote_spread: 0,
+            message_domain: 0,
+            allow_legacy_message: false,
+            reject_stale_updates: false,
+            reference_oracle_account: Pubkey::default(),
+            reference_oracle_type: OracleType::None,
+            max_reference_deviation: 0,
+            allow_exit_outside_reference: false,
         };
 
         let pricing = PricingParams {
@@ -1181,6 +1808,19 @@ mod test {
             max_utilization: 0,
             max_position_locked_usd: 0,
             max_total_locked_usd: 0,
+            max_oi_long_usd: 0,
+            max_oi_short_usd: 0,
+            max_oi_imbalance: 0,
+            use_dynamic_spread: false,
+            conf_spread_mult: 0,
+            ema_spread_mult: 0,
+            min_dynamic_spread: 0,
+            max_dynamic_spread: 0,
+            price_impact_mult: 0,
+            max_price_impact: 0,
+            depeg_threshold: 0,
+            block_positions_on_depeg: false,
+            pay_out_at_spot_on_depeg: false,
         };
 
         let permissions = Permissions {
@@ -1210,6 +1850,8 @@ mod test {
             protocol_share: 25,
             fee_max: 0,
             fee_optimal: 0,
+            referral_discount: 0,
+            referral_rebate: 0,
         };
 
         let custody = Custody {
@@ -1235,14 +1877,8 @@ mod test {
             ..Position::default()
         };
 
-        let token_price = OraclePrice {
-            price: 25_000_000,
-            exponent: -3,
-        };
-        let token_ema_price = OraclePrice {
-            price: 25_300_000,
-            exponent: -3,
-        };
+        let token_price = OraclePrice::new(25_000_000, -3);
+        let token_ema_price = OraclePrice::new(25_300_000, -3);
 
         (
             Pool {
@@ -1331,10 +1967,7 @@ mod test {
         let (pool, custody, _position, token_price, token_ema_price) = get_fixture();
 
         assert_eq!(
-            OraclePrice {
-                price: 25_553_000,
-                exponent: -3
-            },
+            OraclePrice::new(25_553_000, -3),
             pool.get_price(
                 &token_price,
                 &token_ema_price,
@@ -1345,10 +1978,7 @@ mod test {
         );
 
         assert_eq!(
-            OraclePrice {
-                price: 24_750_000,
-                exponent: -3
-            },
+            OraclePrice::new(24_750_000, -3),
             pool.get_price(
                 &token_price,
                 &token_ema_price,
@@ -1359,6 +1989,145 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_get_trade_spread() {
+        let (pool, mut custody, _position, mut token_price, token_ema_price) = get_fixture();
+        token_price.conf = 50_000;
+
+        // static spread
+        assert_eq!(
+            100,
+            pool.get_trade_spread(&token_price, &token_ema_price, Side::Long, &custody)
+                .unwrap()
+        );
+
+        // 100 + 20 (conf) * 2 + 120 (ema divergence) * 0.5
+        custody.pricing.use_dynamic_spread = true;
+        custody.pricing.conf_spread_mult = 20_000;
+        custody.pricing.ema_spread_mult = 5_000;
+        custody.pricing.min_dynamic_spread = 150;
+        custody.pricing.max_dynamic_spread = 500;
+        assert_eq!(
+            200,
+            pool.get_trade_spread(&token_price, &token_ema_price, Side::Long, &custody)
+                .unwrap()
+        );
+
+        custody.pricing.max_dynamic_spread = 180;
+        assert_eq!(
+            180,
+            pool.get_trade_spread(&token_price, &token_ema_price, Side::Short, &custody)
+                .unwrap()
+        );
+
+        custody.pricing.conf_spread_mult = 0;
+        custody.pricing.ema_spread_mult = 0;
+        assert_eq!(
+            150,
+            pool.get_trade_spread(&token_price, &token_ema_price, Side::Short, &custody)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_price_impact() {
+        let (pool, mut custody, _position, token_price, _token_ema_price) = get_fixture();
+
+        // 2.5M USD owned
+        custody.assets.owned = scale(100, custody.decimals);
+        custody.pricing.price_impact_mult = 1_000;
+        custody.pricing.max_price_impact = 500;
+
+        let usd = |amount| scale(amount, Perpetuals::USD_DECIMALS);
+
+        // (size_usd, open_interest_usd, expected impact)
+        for (size_usd, open_interest_usd, expected) in [
+            (0, 0, 0),
+            (usd(250_000), 0, 100),
+            (usd(750_000), 0, 300),
+            (usd(500_000), usd(500_000), 250),
+            (usd(750_000), usd(1_000_000), 500),
+            (usd(2_500_000), 0, 500),
+            (usd(1), usd(2_500_000), 500),
+            (usd(1), usd(3_000_000), 500),
+        ] {
+            assert_eq!(
+                expected,
+                pool.get_price_impact(&token_price, size_usd, open_interest_usd, &custody)
+                    .unwrap()
+            );
+        }
+
+        custody.pricing.price_impact_mult = 0;
+        assert_eq!(
+            0,
+            pool.get_price_impact(&token_price, usd(750_000), 0, &custody)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_entry_and_exit_price_with_impact() {
+        let (pool, mut custody, _position, token_price, token_ema_price) = get_fixture();
+
+        custody.assets.owned = scale(100, custody.decimals);
+        let size = scale(10, custody.decimals);
+        let size_usd = token_ema_price
+            .get_asset_amount_usd(size, custody.decimals)
+            .unwrap();
+
+        // no impact
+        assert_eq!(
+            scale(25_553, Perpetuals::PRICE_DECIMALS),
+            pool.get_entry_price(&token_price, &token_ema_price, Side::Long, size, &custody)
+                .unwrap()
+        );
+
+        // 10% of depth adds 100 BPS to the spread
+        custody.pricing.price_impact_mult = 1_000;
+        custody.pricing.max_price_impact = 500;
+        assert_eq!(
+            scale(25_806, Perpetuals::PRICE_DECIMALS),
+            pool.get_entry_price(&token_price, &token_ema_price, Side::Long, size, &custody)
+                .unwrap()
+        );
+
+        // closing position is excluded from the open interest
+        custody.trade_stats.oi_long_usd = size_usd;
+        assert_eq!(
+            scale(24_500, Perpetuals::PRICE_DECIMALS),
+            pool.get_exit_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                size_usd,
+                &custody
+            )
+            .unwrap()
+        );
+
+        // same side open interest reduces depth
+        custody.trade_stats.oi_long_usd = math::checked_mul(size_usd, 5).unwrap();
+        assert_eq!(
+            scale(26_059, Perpetuals::PRICE_DECIMALS),
+            pool.get_entry_price(&token_price, &token_ema_price, Side::Long, size, &custody)
+                .unwrap()
+        );
+
+        custody.pricing.price_impact_mult = 0;
+        assert_eq!(
+            scale(24_750, Perpetuals::PRICE_DECIMALS),
+            pool.get_exit_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                size_usd,
+                &custody
+            )
+            .unwrap()
+        );
+    }
+
     #[test]
     fn test_get_entry_fee() {
         let (pool, mut custody, _position, _token_price, _token_ema_price) = get_fixture();
@@ -1372,6 +2141,7 @@ mod test {
             pool.get_entry_fee(
                 custody.fees.open_position,
                 0,
+                0,
                 custody.get_locked_amount(0, Side::Long).unwrap(),
                 &custody
             )
@@ -1382,6 +2152,7 @@ mod test {
             1_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
                 &custody
@@ -1393,6 +2164,7 @@ mod test {
             3_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
                 &custody
@@ -1404,6 +2176,7 @@ mod test {
             6_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
                 &custody
@@ -1415,6 +2188,7 @@ mod test {
             9_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
                 &custody
@@ -1430,6 +2204,7 @@ mod test {
             1_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
                 &custody
@@ -1441,6 +2216,7 @@ mod test {
             2_250,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
                 &custody
@@ -1452,6 +2228,7 @@ mod test {
             4_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
                 &custody
@@ -1463,6 +2240,7 @@ mod test {
             6_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
                 &custody
@@ -1476,6 +2254,7 @@ mod test {
             1_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
                 &custody
@@ -1487,6 +2266,7 @@ mod test {
             1_875,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
                 &custody
@@ -1498,6 +2278,7 @@ mod test {
             3_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
                 &custody
@@ -1509,6 +2290,7 @@ mod test {
             4_500,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
                 &custody
@@ -1523,6 +2305,7 @@ mod test {
             1_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
                 &custody
@@ -1534,6 +2317,7 @@ mod test {
             1_500,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
                 &custody
@@ -1545,6 +2329,7 @@ mod test {
             2_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
                 &custody
@@ -1556,6 +2341,7 @@ mod test {
             3_000,
             pool.get_entry_fee(
                 custody.fees.open_position,
+                0,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
                 &custody
@@ -1564,6 +2350,39 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_fee_discount() {
+        let (pool, mut custody, _position, _token_price, _token_ema_price) = get_fixture();
+
+        let mut perpetuals = Perpetuals::default();
+        perpetuals.fee_tiers[0] = FeeTier {
+            min_volume_usd: 1_000_000,
+            fee_discount: 1_000,
+        };
+        perpetuals.fee_tiers[1] = FeeTier {
+            min_volume_usd: 10_000_000,
+            fee_discount: 2_500,
+        };
+        assert!(perpetuals.validate());
+
+        assert_eq!(0, perpetuals.get_fee_discount(999_999));
+        assert_eq!(1_000, perpetuals.get_fee_discount(1_000_000));
+        assert_eq!(2_500, perpetuals.get_fee_discount(50_000_000));
+
+        assert_eq!(999, Pool::apply_fee_discount(999, 0).unwrap());
+        assert_eq!(900, Pool::apply_fee_discount(999, 1_000).unwrap());
+        custody.fees.close_position = 100;
+        assert_eq!(1_000, pool.get_exit_fee(100_000, 0, &custody).unwrap());
+        assert_eq!(750, pool.get_exit_fee(100_000, 2_500, &custody).unwrap());
+
+        // descending thresholds are rejected
+        perpetuals.fee_tiers[2] = FeeTier {
+            min_volume_usd: 5_000_000,
+            fee_discount: 3_000,
+        };
+        assert!(!perpetuals.validate());
+    }
+
     #[test]
     fn test_get_fee() {
         let (mut pool, mut custody, _position, token_price, _token_ema_price) = get_fixture();
@@ -1773,7 +2592,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0,
             )
             .unwrap()
         );
@@ -1791,7 +2611,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0,
             )
             .unwrap()
         );
@@ -1809,12 +2630,65 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0,
             )
             .unwrap()
         );
     }
 
+    #[test]
+    fn test_get_pnl_usd_price_impact() {
+        let (pool, mut custody, position, token_price, token_ema_price) = get_fixture();
+
+        let get_pnl = |custody: &Custody, exit: bool| {
+            let get_pnl_usd = if exit {
+                Pool::get_exit_pnl_usd
+            } else {
+                Pool::get_pnl_usd
+            };
+            get_pnl_usd(
+                &pool,
+                &position,
+                &token_price,
+                &token_ema_price,
+                custody,
+                &token_price,
+                &token_ema_price,
+                custody,
+                1,
+                false,
+                0,
+            )
+            .unwrap()
+        };
+        let get_leverage = |custody: &Custody| {
+            pool.get_leverage(
+                &position,
+                &token_price,
+                &token_ema_price,
+                custody,
+                &token_price,
+                &token_ema_price,
+                custody,
+                1,
+            )
+            .unwrap()
+        };
+
+        let mark_pnl = get_pnl(&custody, false);
+        let leverage = get_leverage(&custody);
+        assert_eq!(mark_pnl, get_pnl(&custody, true));
+
+        // unwinding the position moves the exit price, the mark valuation is unchanged
+        custody.pricing.price_impact_mult = 1_000;
+        custody.pricing.max_price_impact = 500;
+        custody.trade_stats.oi_long_usd = position.size_usd;
+        assert_eq!(mark_pnl, get_pnl(&custody, false));
+        assert_eq!(leverage, get_leverage(&custody));
+        assert!(get_pnl(&custody, true).1 > mark_pnl.1);
+    }
+
     #[test]
     fn test_get_leverage() {
         let (pool, custody, mut position, token_price, token_ema_price) = get_fixture();
@@ -2005,9 +2879,67 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0,
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_close_amount_with_shortfall() {
+        let (pool, mut custody, mut position, _token_price, _token_ema_price) = get_fixture();
+
+        // 28% drop, the x4 position loses more than its collateral
+        let token_price = OraclePrice::new(18_000_000, -3);
+        let get_close_amount = |position: &Position, custody: &Custody, liquidation: bool| {
+            pool.get_close_amount_with_shortfall(
+                position,
+                &token_price,
+                &token_price,
+                custody,
+                &token_price,
+                &token_price,
+                custody,
+                1,
+                liquidation,
+                0,
             )
             .unwrap()
+        };
+
+        let (close_amount, _, profit_usd, loss_usd, shortfall_usd) =
+            get_close_amount(&position, &custody, false);
+        assert_eq!(close_amount, 0);
+        assert_eq!(profit_usd, 0);
+        assert_eq!(shortfall_usd, loss_usd - position.collateral_usd);
+
+        // the liquidation fee adds to the shortfall
+        let (_, fee_amount, _, _, liquidation_shortfall_usd) =
+            get_close_amount(&position, &custody, true);
+        assert_eq!(
+            liquidation_shortfall_usd,
+            shortfall_usd
+                + token_price
+                    .get_asset_amount_usd(fee_amount, custody.decimals)
+                    .unwrap()
+        );
+
+        // so does accrued interest
+        custody.borrow_rate_state.cumulative_interest = 70_000;
+        let interest_usd = custody.get_interest_amount_usd(&position, 1).unwrap();
+        assert!(interest_usd > 0);
+        assert_eq!(
+            get_close_amount(&position, &custody, false).4,
+            shortfall_usd + interest_usd
+        );
+
+        // and interest carried over from earlier updates
+        custody.borrow_rate_state.cumulative_interest = 0;
+        position.unrealized_loss_usd = scale(1_000, Perpetuals::USD_DECIMALS);
+        assert_eq!(
+            get_close_amount(&position, &custody, false).4,
+            shortfall_usd + position.unrealized_loss_usd
         );
     }
 
@@ -2039,4 +2971,132 @@ mod test {
         let interest = custody.get_interest_amount_usd(&position, 7_200).unwrap();
         assert_eq!(interest, scale(7, Perpetuals::USD_DECIMALS));
     }
+
+    #[test]
+    fn test_get_cached_aum_usd() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+        pool.custodies = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let custody_aum = |owned_usd, profit_usd, loss_usd, update_time| AumCache {
+            owned_usd,
+            min_owned_usd: owned_usd,
+            max_owned_usd: owned_usd,
+            unrealized_profit_usd: profit_usd,
+            unrealized_loss_usd: loss_usd,
+            price: 1_000_000,
+            update_time,
+        };
+
+        // cache not initialized
+        assert!(pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 100).is_err());
+        assert!(pool
+            .set_cached_custody_aum(0, custody_aum(1_000, 0, 0, 100))
+            .is_err());
+
+        pool.aum_cache = vec![AumCache::default(); 2];
+        pool.aum_cache_max_age_sec = 60;
+        pool.set_cached_custody_aum(0, custody_aum(1_000, 0, 0, 100))
+            .unwrap();
+        assert_eq!(pool.aum_usd, 1_000);
+
+        // one custody has never been priced
+        assert!(pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 100).is_err());
+
+        // traders' profit is owed by the pool, their loss is owed to it
+        pool.set_cached_custody_aum(1, custody_aum(2_000, 300, 100, 110))
+            .unwrap();
+        assert_eq!(pool.aum_usd, 2_800);
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 110).unwrap(),
+            Some(2_800)
+        );
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 160).unwrap(),
+            Some(2_800)
+        );
+        assert!(pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 161).is_err());
+
+        // the max age is a pool setting
+        pool.aum_cache_max_age_sec = 10;
+        assert!(pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 110).is_ok());
+        assert!(pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 111).is_err());
+        pool.aum_cache_max_age_sec = 60;
+
+        // LP tokens are minted against the higher valuation and burned against the lower one
+        pool.set_cached_custody_aum(
+            1,
+            AumCache {
+                min_owned_usd: 1_900,
+                max_owned_usd: 2_100,
+                ..custody_aum(2_000, 300, 100, 110)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::Min, &[], 110).unwrap(),
+            Some(2_700)
+        );
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::Max, &[], 110).unwrap(),
+            Some(2_900)
+        );
+
+        // full refresh replaces every entry
+        pool.set_aum_cache(vec![
+            custody_aum(500, 0, 0, 200),
+            custody_aum(100, 200, 0, 200),
+        ])
+        .unwrap();
+        assert_eq!(pool.aum_usd, 500);
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 200).unwrap(),
+            Some(500)
+        );
+    }
+
+    #[test]
+    fn test_align_aum_cache() {
+        let (mut pool, _custody, _position, _token_price, _token_ema_price) = get_fixture();
+        let custody_aum = AumCache {
+            owned_usd: 1_000,
+            min_owned_usd: 1_000,
+            max_owned_usd: 1_000,
+            unrealized_profit_usd: 0,
+            unrealized_loss_usd: 0,
+            price: 1_000_000,
+            update_time: 100,
+        };
+
+        // pool created before the cache, add_custody appends to an empty cache
+        pool.custodies = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        pool.custodies.push(Pubkey::new_unique());
+        pool.aum_cache.push(AumCache::default());
+        assert!(pool.set_cached_custody_aum(2, custody_aum).is_err());
+
+        pool.align_aum_cache();
+        assert_eq!(pool.aum_cache, vec![AumCache::default(); 3]);
+        pool.set_cached_custody_aum(2, custody_aum).unwrap();
+        assert_eq!(pool.aum_usd, 1_000);
+        assert!(pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 100).is_err());
+
+        // the crank backfills every entry
+        pool.set_aum_cache(vec![custody_aum; 3]).unwrap();
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 100).unwrap(),
+            Some(3_000)
+        );
+
+        // aligned entries are kept
+        pool.align_aum_cache();
+        assert_eq!(pool.aum_cache, vec![custody_aum; 3]);
+
+        // remove_custody drops the entry of the removed custody
+        pool.custodies.remove(1);
+        pool.aum_cache.remove(1);
+        pool.align_aum_cache();
+        assert_eq!(
+            pool.get_cached_aum_usd(AumCalcMode::EMA, &[], 100).unwrap(),
+            Some(2_000)
+        );
+    }
 }
@@ -53,12 +53,38 @@ pub struct Position {
     pub take_profit_price: Option<u64>, // new
     pub stop_loss_price: Option<u64>,   // new
 
+    // cross-margin account sharing this position's margin, if any
+    pub margin_account: Option<Pubkey>,
+
+    // distinguishes multiple positions of the same owner, custody and side
+    pub position_id: u64,
+
     pub bump: u8,
 }
 
 impl Position {
     pub const LEN: usize = 8 + std::mem::size_of::<Position>();
 
+    // extra PDA seed, empty for the default position id to keep legacy position addresses
+    pub fn get_id_seed(position_id: &u64) -> &[u8] {
+        if *position_id == 0 {
+            &[]
+        } else {
+            bytemuck::bytes_of(position_id)
+        }
+    }
+
+    // take profit above and stop loss below the entry price for longs, the reverse for shorts
+    pub fn validate_triggers(&self) -> bool {
+        let (above, below) = match self.side {
+            Side::Long => (self.take_profit_price, self.stop_loss_price),
+            Side::Short => (self.stop_loss_price, self.take_profit_price),
+            Side::None => return false,
+        };
+        !matches!(above, Some(price) if price <= self.price)
+            && !matches!(below, Some(price) if price == 0 || price >= self.price)
+    }
+
     pub fn get_initial_leverage(&self) -> Result<u64> {
         math::checked_as_u64(math::checked_div(
             math::checked_mul(self.size_usd as u128, Perpetuals::BPS_POWER)?,
@@ -66,3 +92,40 @@ impl Position {
         )?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_triggers() {
+        let mut position = Position {
+            side: Side::Long,
+            price: 1_500,
+            ..Default::default()
+        };
+        assert!(position.validate_triggers());
+
+        position.take_profit_price = Some(2_000);
+        position.stop_loss_price = Some(1_200);
+        assert!(position.validate_triggers());
+
+        position.stop_loss_price = Some(1_600);
+        assert!(!position.validate_triggers());
+
+        position.stop_loss_price = Some(0);
+        assert!(!position.validate_triggers());
+
+        // the same prices are reversed for a short
+        position.side = Side::Short;
+        position.stop_loss_price = Some(1_200);
+        assert!(!position.validate_triggers());
+
+        position.take_profit_price = Some(1_200);
+        position.stop_loss_price = Some(2_000);
+        assert!(position.validate_triggers());
+
+        position.take_profit_price = Some(1_500);
+        assert!(!position.validate_triggers());
+    }
+}
@@ -0,0 +1,100 @@
+use {
+    crate::{error::PerpetualsError, math},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct ReferralRebate {
+    pub custody: Pubkey,
+    // claimable custody tokens
+    pub amount: u64,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Referral {
+    pub owner: Pubkey,
+    // trading volume attributed to the referrer
+    pub volume_usd: u64,
+    // rebates accrued per custody
+    pub rebates: Vec<ReferralRebate>,
+    pub bump: u8,
+}
+
+impl Referral {
+    pub const MAX_REBATES: usize = 16;
+    pub const LEN: usize = 8
+        + std::mem::size_of::<Referral>()
+        + Referral::MAX_REBATES * std::mem::size_of::<ReferralRebate>();
+
+    // a new custody can only be tracked while there is room, trades check this first
+    // and keep the rebate in the fee instead of failing
+    pub fn can_accrue(&self, custody: &Pubkey) -> bool {
+        self.rebates.len() < Referral::MAX_REBATES
+            || self.rebates.iter().any(|e| e.custody == *custody)
+    }
+
+    pub fn accrue(&mut self, custody: Pubkey, rebate: u64, volume_usd: u64) -> Result<()> {
+        self.volume_usd = self.volume_usd.wrapping_add(volume_usd);
+
+        if rebate == 0 {
+            return Ok(());
+        }
+        if let Some(entry) = self.rebates.iter_mut().find(|e| e.custody == custody) {
+            entry.amount = math::checked_add(entry.amount, rebate)?;
+        } else {
+            require!(
+                self.rebates.len() < Referral::MAX_REBATES,
+                PerpetualsError::MaxReferralRebates
+            );
+            self.rebates.push(ReferralRebate {
+                custody,
+                amount: rebate,
+            });
+        }
+        Ok(())
+    }
+
+    // removes and returns the rebate accrued for the custody
+    pub fn take_rebate(&mut self, custody: &Pubkey) -> u64 {
+        if let Some(idx) = self.rebates.iter().position(|e| e.custody == *custody) {
+            self.rebates.remove(idx).amount
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accrue() {
+        let mut referral = Referral::default();
+        let custodies: Vec<Pubkey> = (0..=Referral::MAX_REBATES)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+
+        for custody in &custodies[..Referral::MAX_REBATES] {
+            assert!(referral.can_accrue(custody));
+            referral.accrue(*custody, 10, 100).unwrap();
+        }
+        assert_eq!(1_600, referral.volume_usd);
+
+        // known custodies keep accruing, a new one has no slot left
+        assert!(referral.can_accrue(&custodies[0]));
+        referral.accrue(custodies[0], 5, 100).unwrap();
+        assert_eq!(15, referral.rebates[0].amount);
+
+        let new_custody = &custodies[Referral::MAX_REBATES];
+        assert!(!referral.can_accrue(new_custody));
+        assert!(referral.accrue(*new_custody, 10, 100).is_err());
+        // volume is still attributed without a rebate
+        referral.accrue(*new_custody, 0, 100).unwrap();
+
+        // claiming frees the slot
+        assert_eq!(15, referral.take_rebate(&custodies[0]));
+        assert!(referral.can_accrue(new_custody));
+    }
+}
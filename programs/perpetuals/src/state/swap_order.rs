@@ -0,0 +1,42 @@
+use {
+    crate::{math, state::perpetuals::Perpetuals},
+    anchor_lang::prelude::*,
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct SwapOrder {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    // custody receiving the escrowed tokens when the order is filled
+    pub receiving_custody: Pubkey,
+    // custody paying out the swap
+    pub dispensing_custody: Pubkey,
+    pub order_id: u64,
+    pub amount_in: u64,
+    // lowest accepted price of the token in, denominated in the token out
+    // and scaled to PRICE_DECIMALS
+    pub limit_price: u64,
+    pub expiry_time: i64,
+    pub open_time: i64,
+    pub bump: u8,
+    pub token_account_bump: u8,
+}
+
+impl SwapOrder {
+    pub const LEN: usize = 8 + std::mem::size_of::<SwapOrder>();
+
+    pub fn is_expired(&self, curtime: i64) -> bool {
+        curtime > self.expiry_time
+    }
+
+    pub fn get_min_amount_out(&self, decimals_in: u8, decimals_out: u8) -> Result<u64> {
+        math::checked_decimal_ceil_mul(
+            self.amount_in,
+            -(decimals_in as i32),
+            self.limit_price,
+            -(Perpetuals::PRICE_DECIMALS as i32),
+            -(decimals_out as i32),
+        )
+    }
+}
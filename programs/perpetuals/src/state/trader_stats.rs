@@ -0,0 +1,95 @@
+use {crate::math, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct TraderStats {
+    pub owner: Pubkey,
+    // trading volume per day, indexed by day % VOLUME_WINDOW_DAYS
+    pub daily_volume_usd: [u64; 30],
+    // day (unix time / SECONDS_PER_DAY) of the last recorded trade
+    pub last_trade_day: i64,
+    pub bump: u8,
+}
+
+impl TraderStats {
+    pub const LEN: usize = 8 + std::mem::size_of::<TraderStats>();
+    pub const VOLUME_WINDOW_DAYS: i64 = 30;
+    pub const SECONDS_PER_DAY: i64 = 86400;
+
+    fn get_day(curtime: i64) -> i64 {
+        curtime / TraderStats::SECONDS_PER_DAY
+    }
+
+    fn get_bucket(day: i64) -> usize {
+        day.rem_euclid(TraderStats::VOLUME_WINDOW_DAYS) as usize
+    }
+
+    // rolling volume over the last VOLUME_WINDOW_DAYS days, including today
+    pub fn get_volume_usd(&self, curtime: i64) -> Result<u64> {
+        let day = TraderStats::get_day(curtime);
+        let elapsed = std::cmp::max(0, day - self.last_trade_day);
+        if elapsed >= TraderStats::VOLUME_WINDOW_DAYS {
+            return Ok(0);
+        }
+
+        let mut volume_usd = 0u64;
+        for (bucket, amount) in self.daily_volume_usd.iter().enumerate() {
+            // skip buckets recycled since the last trade
+            let age = (TraderStats::get_bucket(self.last_trade_day) as i64 - bucket as i64)
+                .rem_euclid(TraderStats::VOLUME_WINDOW_DAYS);
+            if age + elapsed < TraderStats::VOLUME_WINDOW_DAYS {
+                volume_usd = math::checked_add(volume_usd, *amount)?;
+            }
+        }
+
+        Ok(volume_usd)
+    }
+
+    pub fn add_volume(&mut self, volume_usd: u64, curtime: i64) -> Result<()> {
+        let day = TraderStats::get_day(curtime);
+
+        // clear buckets for the days without trades
+        let elapsed = (day - self.last_trade_day).clamp(0, TraderStats::VOLUME_WINDOW_DAYS);
+        for offset in 0..elapsed {
+            self.daily_volume_usd[TraderStats::get_bucket(day - offset)] = 0;
+        }
+        if day > self.last_trade_day {
+            self.last_trade_day = day;
+        }
+
+        let bucket = TraderStats::get_bucket(self.last_trade_day);
+        self.daily_volume_usd[bucket] =
+            math::checked_add(self.daily_volume_usd[bucket], volume_usd)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rolling_volume() {
+        let mut stats = TraderStats::default();
+        let day = TraderStats::SECONDS_PER_DAY;
+        let start = 1000 * day;
+
+        stats.add_volume(100, start).unwrap();
+        stats.add_volume(50, start + 10).unwrap();
+        stats.add_volume(20, start + day).unwrap();
+        assert_eq!(stats.get_volume_usd(start + day).unwrap(), 170);
+
+        // first day drops out of the window
+        assert_eq!(stats.get_volume_usd(start + 29 * day).unwrap(), 170);
+        assert_eq!(stats.get_volume_usd(start + 30 * day).unwrap(), 20);
+        assert_eq!(stats.get_volume_usd(start + 31 * day).unwrap(), 0);
+
+        // recycled bucket is reset
+        stats.add_volume(5, start + 30 * day).unwrap();
+        assert_eq!(stats.get_volume_usd(start + 30 * day).unwrap(), 25);
+
+        stats.add_volume(7, start + 100 * day).unwrap();
+        assert_eq!(stats.get_volume_usd(start + 100 * day).unwrap(), 7);
+    }
+}
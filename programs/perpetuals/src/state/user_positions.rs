@@ -0,0 +1,69 @@
+use {
+    crate::error::PerpetualsError,
+    anchor_lang::{prelude::*, Discriminator},
+};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct UserPositions {
+    pub owner: Pubkey,
+    // open position accounts of the owner
+    pub positions: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl UserPositions {
+    pub const MAX_POSITIONS: usize = 64;
+    pub const LEN: usize = 8 + 32 + 4 + 32 * UserPositions::MAX_POSITIONS + 1;
+
+    pub fn add_position(&mut self, position: Pubkey) -> Result<()> {
+        if self.positions.contains(&position) {
+            return Ok(());
+        }
+        require!(
+            self.positions.len() < UserPositions::MAX_POSITIONS,
+            PerpetualsError::MaxUserPositions
+        );
+        self.positions.push(position);
+        Ok(())
+    }
+
+    pub fn remove_position(&mut self, position: &Pubkey) {
+        self.positions.retain(|key| key != position);
+    }
+
+    // tells an index account apart from the other accounts passed as remaining accounts
+    pub fn is_user_positions(account_info: &AccountInfo) -> bool {
+        account_info.owner == &crate::ID
+            && account_info
+                .try_borrow_data()
+                .is_ok_and(|data| data.starts_with(&UserPositions::DISCRIMINATOR))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_remove_position() {
+        let mut user_positions = UserPositions::default();
+        let first = Pubkey::new_unique();
+
+        user_positions.add_position(first).unwrap();
+        user_positions.add_position(first).unwrap();
+        assert_eq!(user_positions.positions, vec![first]);
+
+        for _ in 1..UserPositions::MAX_POSITIONS {
+            user_positions.add_position(Pubkey::new_unique()).unwrap();
+        }
+        assert!(user_positions.add_position(Pubkey::new_unique()).is_err());
+
+        user_positions.remove_position(&first);
+        assert_eq!(
+            user_positions.positions.len(),
+            UserPositions::MAX_POSITIONS - 1
+        );
+        assert!(!user_positions.positions.contains(&first));
+    }
+}
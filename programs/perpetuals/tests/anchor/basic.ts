@@ -48,6 +48,10 @@ describe("perpetuals", () => {
       transferAuthorityBump: tc.authority.bump,
       perpetualsBump: tc.perpetuals.bump,
       inceptionTime: new BN(0),
+      feeTiers: Array(4).fill({
+        minVolumeUsd: new BN(0),
+        feeDiscount: new BN(0),
+      }),
     };
 
     multisigExpected = {
@@ -141,6 +145,18 @@ describe("perpetuals", () => {
       oracleType: { custom: {} },
       oracleAccount: tc.custodies[0].oracleAccount,
       oracleAuthority: tc.oracleAuthority.publicKey,
+      emaHalfLifeSec: 0,
+      maxPriceDeviation: new BN(0),
+      extraOracleAuthorities: Array(4).fill(anchor.web3.PublicKey.default),
+      minOracleSignatures: 0,
+      maxQuoteSpread: new BN(0),
+      messageDomain: new BN(tc.messageDomain),
+      allowLegacyMessage: false,
+      rejectStaleUpdates: false,
+      referenceOracleAccount: anchor.web3.PublicKey.default,
+      referenceOracleType: { none: {} },
+      maxReferenceDeviation: new BN(0),
+      allowExitOutsideReference: false,
     };
     pricing = {
       useEma: true,
@@ -155,6 +171,19 @@ describe("perpetuals", () => {
       maxUtilization: new BN(10000),
       maxPositionLockedUsd: new BN(1000000000),
       maxTotalLockedUsd: new BN(1000000000),
+      maxOiLongUsd: new BN(0),
+      maxOiShortUsd: new BN(0),
+      maxOiImbalance: new BN(0),
+      useDynamicSpread: false,
+      confSpreadMult: new BN(0),
+      emaSpreadMult: new BN(0),
+      minDynamicSpread: new BN(0),
+      maxDynamicSpread: new BN(0),
+      priceImpactMult: new BN(0),
+      maxPriceImpact: new BN(0),
+      depegThreshold: new BN(0),
+      blockPositionsOnDepeg: false,
+      payOutAtSpotOnDepeg: false,
     };
     permissions = {
       allowSwap: true,
@@ -182,6 +211,8 @@ describe("perpetuals", () => {
       protocolShare: new BN(10),
       feeMax: new BN(250),
       feeOptimal: new BN(10),
+      referralDiscount: new BN(0),
+      referralRebate: new BN(0),
     };
     borrowRate = {
       baseRate: new BN(0),
@@ -236,6 +267,18 @@ describe("perpetuals", () => {
         oracleAuthority: tc.oracleAuthority.publicKey,
         maxPriceError: "10000",
         maxPriceAgeSec: 60,
+        emaHalfLifeSec: 0,
+        maxPriceDeviation: "0",
+        extraOracleAuthorities: Array(4).fill(anchor.web3.PublicKey.default),
+        minOracleSignatures: 0,
+        maxQuoteSpread: "0",
+        messageDomain: tc.messageDomain.toString(),
+        allowLegacyMessage: false,
+        rejectStaleUpdates: false,
+        referenceOracleAccount: anchor.web3.PublicKey.default,
+        referenceOracleType: { none: {} },
+        maxReferenceDeviation: "0",
+        allowExitOutsideReference: false,
       },
       pricing: {
         useEma: true,
@@ -250,6 +293,19 @@ describe("perpetuals", () => {
         maxUtilization: "10000",
         maxPositionLockedUsd: "1000000000",
         maxTotalLockedUsd: "1000000000",
+        maxOiLongUsd: "0",
+        maxOiShortUsd: "0",
+        maxOiImbalance: "0",
+        useDynamicSpread: false,
+        confSpreadMult: "0",
+        emaSpreadMult: "0",
+        minDynamicSpread: "0",
+        maxDynamicSpread: "0",
+        priceImpactMult: "0",
+        maxPriceImpact: "0",
+        depegThreshold: "0",
+        blockPositionsOnDepeg: false,
+        payOutAtSpotOnDepeg: false,
       },
       permissions: {
         allowSwap: true,
@@ -277,6 +333,8 @@ describe("perpetuals", () => {
         protocolShare: "10",
         feeMax: "250",
         feeOptimal: "10",
+        referralDiscount: "0",
+        referralRebate: "0",
       },
       borrowRate: {
         baseRate: "0",
@@ -289,6 +347,7 @@ describe("perpetuals", () => {
         protocolFees: "0",
         owned: "0",
         locked: "0",
+        referralRebates: "0",
       },
       collectedFees: {
         swapUsd: "0",
@@ -413,6 +472,8 @@ describe("perpetuals", () => {
       conf: new BN(0),
       ema: new BN(123000),
       publishTime: oracle.publishTime,
+      samples: oracle.samples,
+      nextSample: oracle.nextSample,
     };
     expect(JSON.stringify(oracle)).to.equal(JSON.stringify(oracleExpected));
   });
@@ -433,6 +494,8 @@ describe("perpetuals", () => {
       conf: new BN(10),
       ema: new BN(500000),
       publishTime: oracle.publishTime,
+      samples: oracle.samples,
+      nextSample: oracle.nextSample,
     };
     expect(JSON.stringify(oracle)).to.equal(JSON.stringify(oracleExpected));
 
@@ -468,6 +531,8 @@ describe("perpetuals", () => {
         price: new BN(1000000),
         ema: new BN(1000000),
         publishTime: oracle.publishTime,
+        samples: oracle.samples,
+        nextSample: oracle.nextSample,
       })
     );
 
@@ -511,6 +576,53 @@ describe("perpetuals", () => {
         randomMessage
       )
     );
+
+    // Legacy messages without the domain envelope are rejected by default.
+    let legacyMessage = tc.program.coder.types.encode(
+      "SetCustomOraclePricePermissionlessParams",
+      {
+        custodyAccount: tc.custodies[1].custody,
+        price: new BN(100000),
+        expo: -3,
+        conf: new BN(10),
+        ema: new BN(100000),
+        publishTime: new BN(tc.getTime()),
+      }
+    );
+    await tc.ensureFails(
+      tc.setCustomOraclePricePermissionless(
+        tc.oracleAuthority,
+        100,
+        tc.custodies[1],
+        null,
+        null,
+        legacyMessage
+      )
+    );
+
+    // Messages signed for another deployment domain should fail.
+    let otherDomainMessage = tc.encodeOraclePriceMessage(
+      tc.custodies[1],
+      {
+        custodyAccount: tc.custodies[1].custody,
+        price: new BN(100000),
+        expo: -3,
+        conf: new BN(10),
+        ema: new BN(100000),
+        publishTime: new BN(tc.getTime()),
+      },
+      tc.messageDomain + 1
+    );
+    await tc.ensureFails(
+      tc.setCustomOraclePricePermissionless(
+        tc.oracleAuthority,
+        100,
+        tc.custodies[1],
+        null,
+        null,
+        otherDomainMessage
+      )
+    );
   });
 
   it("setTestTime", async () => {
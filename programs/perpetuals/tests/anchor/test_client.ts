@@ -27,6 +27,7 @@ export class TestClient {
   feesAccount: PublicKey;
   adminMetas: AccountMeta[];
   oracleAuthority: Keypair;
+  messageDomain: number;
 
   // pdas
   multisig: { publicKey: PublicKey; bump: number };
@@ -79,6 +80,7 @@ export class TestClient {
     }
 
     this.oracleAuthority = Keypair.generate();
+    this.messageDomain = 1;
 
     // pdas
     this.multisig = this.findProgramAddress("multisig");
@@ -710,6 +712,28 @@ export class TestClient {
     }
   };
 
+  // versioned envelope: prefix, version, program id, domain, pool, custody, params
+  encodeOraclePriceMessage = (
+    custody,
+    params,
+    domain = this.messageDomain
+  ) => {
+    let domainBuffer = Buffer.alloc(8);
+    domainBuffer.writeBigUInt64LE(BigInt(domain));
+    return Buffer.concat([
+      Buffer.from([0xff, ...Buffer.from("perpsor")]),
+      Buffer.from([1]),
+      this.program.programId.toBuffer(),
+      domainBuffer,
+      this.pool.publicKey.toBuffer(),
+      custody.custody.toBuffer(),
+      this.program._coder.types.encode(
+        "SetCustomOraclePricePermissionlessParams",
+        params
+      ),
+    ]);
+  };
+
   setCustomOraclePricePermissionless = async (
     oracleAuthority: Keypair,
     price: number,
@@ -732,8 +756,8 @@ export class TestClient {
     let message =
       messageOverwrite != null
         ? messageOverwrite
-        : this.program._coder.types.encode(
-            "SetCustomOraclePricePermissionlessParams",
+        : this.encodeOraclePriceMessage(
+            custody,
             setCustomOraclePricePermissionlessParams
           );
 
@@ -939,6 +963,7 @@ export class TestClient {
           collateral,
           size,
           side: side === "long" ? { long: {} } : { short: {} },
+          positionId: new BN(0),
         })
         .accounts({
           owner: user.wallet.publicKey,
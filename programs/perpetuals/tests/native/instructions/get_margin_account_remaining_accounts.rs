@@ -0,0 +1,50 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::Pubkey, solana_program::instruction::AccountMeta},
+    perpetuals::state::{custody::Custody, margin_account::MarginAccount},
+    solana_program_test::ProgramTestContext,
+    tokio::sync::RwLock,
+};
+
+// Margin account custodies, their oracles and positions, in the order expected on-chain
+pub async fn get_margin_account_remaining_accounts(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    margin_account_pda: &Pubkey,
+    is_writable: bool,
+) -> Vec<AccountMeta> {
+    let mut accounts_meta = Vec::new();
+
+    let margin_account =
+        utils::get_account::<MarginAccount>(program_test_ctx, *margin_account_pda).await;
+
+    // For each token, add custody account as remaining_account
+    for custody in &margin_account.custodies {
+        accounts_meta.push(AccountMeta {
+            pubkey: *custody,
+            is_signer: false,
+            is_writable,
+        });
+    }
+
+    // For each token, add custody oracle account as remaining_account
+    for custody in &margin_account.custodies {
+        let custody_account = utils::get_account::<Custody>(program_test_ctx, *custody).await;
+
+        accounts_meta.push(AccountMeta {
+            pubkey: custody_account.oracle.oracle_account,
+            is_signer: false,
+            is_writable: false,
+        });
+    }
+
+    // For each margin account position, add position account as remaining_account
+    for position in &margin_account.positions {
+        accounts_meta.push(AccountMeta {
+            pubkey: *position,
+            is_signer: false,
+            is_writable,
+        });
+    }
+
+    accounts_meta
+}
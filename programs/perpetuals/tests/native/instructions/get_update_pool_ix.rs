@@ -25,6 +25,7 @@ pub async fn get_update_pool_ix(
             payer: payer.pubkey(),
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
+            system_program: anchor_lang::system_program::ID,
         };
 
         let mut accounts_meta = accounts.to_account_metas(None);
@@ -1,21 +1,74 @@
+pub mod get_margin_account_remaining_accounts;
 pub mod get_update_pool_ix;
+pub mod test_add_collateral;
 pub mod test_add_custody;
 pub mod test_add_liquidity;
+pub mod test_add_margin;
+pub mod test_add_margin_position;
 pub mod test_add_pool;
+pub mod test_cancel_swap_order;
+pub mod test_claim_referral_rebate;
+pub mod test_close_margin_position;
 pub mod test_close_position;
+pub mod test_execute_swap_order;
+pub mod test_get_aum_breakdown;
+pub mod test_get_entry_price_and_fee;
 pub mod test_get_lp_token_price;
+pub mod test_get_open_interest_headroom;
+pub mod test_get_position_info;
+pub mod test_get_remove_collateral_info;
 pub mod test_init;
+pub mod test_init_margin_account;
+pub mod test_init_referral;
+pub mod test_init_trader_stats;
+pub mod test_init_user_positions;
 pub mod test_liquidate;
+pub mod test_liquidate_batch;
+pub mod test_liquidate_margin_account;
 pub mod test_open_position;
+pub mod test_place_swap_order;
+pub mod test_prune_user_positions;
+pub mod test_remove_collateral_amount;
+pub mod test_remove_custody;
 pub mod test_remove_liquidity;
+pub mod test_remove_liquidity_in_kind;
+pub mod test_remove_margin;
+pub mod test_remove_pool;
 pub mod test_set_custody_config;
 pub mod test_set_custom_oracle_price;
+pub mod test_set_custom_oracle_price_permissionless;
+pub mod test_set_delegate;
+pub mod test_set_fee_tiers;
+pub mod test_set_pool_config;
+pub mod test_settle_position;
+pub mod test_settle_referral_rebate;
 pub mod test_swap;
+pub mod test_swap_position_collateral;
+pub mod test_transfer_position;
+pub mod test_update_custody_aum;
 pub mod test_update_pool_aum;
+pub mod test_update_position_triggers;
+pub mod test_wind_down_pool;
+pub mod test_withdraw_fees;
+pub mod test_withdraw_profit;
 
 pub use {
-    get_update_pool_ix::*, test_add_custody::*, test_add_liquidity::*, test_add_pool::*,
-    test_close_position::*, test_get_lp_token_price::*, test_init::*, test_liquidate::*,
-    test_open_position::*, test_remove_liquidity::*, test_set_custody_config::*,
-    test_set_custom_oracle_price::*, test_swap::*, test_update_pool_aum::*,
+    get_margin_account_remaining_accounts::*, get_update_pool_ix::*, test_add_collateral::*,
+    test_add_custody::*, test_add_liquidity::*, test_add_margin::*, test_add_margin_position::*,
+    test_add_pool::*, test_cancel_swap_order::*, test_claim_referral_rebate::*,
+    test_close_margin_position::*, test_close_position::*, test_execute_swap_order::*,
+    test_get_aum_breakdown::*, test_get_entry_price_and_fee::*, test_get_lp_token_price::*,
+    test_get_open_interest_headroom::*, test_get_position_info::*,
+    test_get_remove_collateral_info::*, test_init::*, test_init_margin_account::*,
+    test_init_referral::*, test_init_trader_stats::*, test_init_user_positions::*,
+    test_liquidate::*, test_liquidate_batch::*, test_liquidate_margin_account::*,
+    test_open_position::*, test_place_swap_order::*, test_prune_user_positions::*,
+    test_remove_collateral_amount::*, test_remove_custody::*, test_remove_liquidity::*,
+    test_remove_liquidity_in_kind::*, test_remove_margin::*, test_remove_pool::*,
+    test_set_custody_config::*, test_set_custom_oracle_price::*,
+    test_set_custom_oracle_price_permissionless::*, test_set_delegate::*, test_set_fee_tiers::*,
+    test_set_pool_config::*, test_settle_position::*, test_settle_referral_rebate::*, test_swap::*,
+    test_swap_position_collateral::*, test_transfer_position::*, test_update_custody_aum::*,
+    test_update_pool_aum::*, test_update_position_triggers::*, test_wind_down_pool::*,
+    test_withdraw_fees::*, test_withdraw_profit::*,
 };
@@ -60,6 +60,9 @@ pub async fn test_add_liquidity(
             lp_token_mint: lp_token_mint_pda,
             token_program: anchor_spl::token::ID,
             custody_twap_account: None, // TODO: add twap account
+            custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &custody_account.oracle,
+            ),
         };
 
         let mut accounts_meta = accounts.to_account_metas(None);
@@ -0,0 +1,78 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::AddMarginParams, state::margin_account::MarginAccount},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_add_margin(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: AddMarginParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let margin_account_pda = pda::get_margin_account_pda(&owner.pubkey(), pool_pda).0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+    let collateral_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let funding_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), collateral_custody_token_mint).0;
+
+    let amount = params.amount;
+
+    // Save account state before tx execution
+    let owner_funding_account_before =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+    let margin_account_before =
+        utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::AddMargin {
+            owner: owner.pubkey(),
+            funding_account: funding_account_address,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            margin_account: margin_account_pda,
+            collateral_custody: collateral_custody_pda,
+            collateral_custody_token_account: collateral_custody_token_account_pda,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::AddMargin { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the balance change
+    {
+        let owner_funding_account_after =
+            utils::get_token_account(program_test_ctx, funding_account_address).await;
+        let margin_account_after =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+        assert_eq!(
+            owner_funding_account_after.amount,
+            owner_funding_account_before.amount - amount
+        );
+        assert_eq!(
+            margin_account_after.collateral_amount,
+            margin_account_before.collateral_amount + amount
+        );
+    }
+
+    Ok(())
+}
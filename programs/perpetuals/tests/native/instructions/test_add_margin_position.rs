@@ -0,0 +1,55 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::AddMarginPositionParams,
+        state::{margin_account::MarginAccount, position::Position},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_add_margin_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+    params: AddMarginPositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let margin_account_pda = pda::get_margin_account_pda(&owner.pubkey(), pool_pda).0;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::AddMarginPosition {
+            owner: owner.pubkey(),
+            pool: *pool_pda,
+            margin_account: margin_account_pda,
+            position: *position_pda,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::AddMarginPosition { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    {
+        let margin_account =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+        let position_account =
+            utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+
+        assert!(margin_account.positions.contains(position_pda));
+        assert_eq!(position_account.margin_account, Some(margin_account_pda));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::CancelSwapOrderParams, state::swap_order::SwapOrder},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_cancel_swap_order(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    // Mint sent by the User when placing the order
+    receiving_custody_token_mint: &Pubkey,
+    swap_order_pda: &Pubkey,
+    params: CancelSwapOrderParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let swap_order_token_account_pda = pda::get_swap_order_token_account_pda(swap_order_pda).0;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), receiving_custody_token_mint).0;
+
+    let swap_order = utils::get_account::<SwapOrder>(program_test_ctx, *swap_order_pda).await;
+
+    // Save account state before tx execution
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::CancelSwapOrder {
+            owner: owner.pubkey(),
+            receiving_account: receiving_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            swap_order: *swap_order_pda,
+            swap_order_token_account: swap_order_token_account_pda,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::CancelSwapOrder { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the escrowed tokens are refunded
+    let owner_receiving_account_after =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    assert_eq!(
+        owner_receiving_account_after.amount - owner_receiving_account_before.amount,
+        swap_order.amount_in
+    );
+
+    // Check the order accounts are closed
+    for account in [*swap_order_pda, swap_order_token_account_pda] {
+        let account = program_test_ctx
+            .write()
+            .await
+            .banks_client
+            .get_account(account)
+            .await
+            .unwrap();
+
+        assert!(account.is_none());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use {
+    super::{get_margin_account_remaining_accounts, get_update_pool_ix},
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::CloseMarginPositionParams,
+        state::{custody::Custody, margin_account::MarginAccount, position::Position},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_close_margin_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+    params: CloseMarginPositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let margin_account_pda = pda::get_margin_account_pda(&owner.pubkey(), pool_pda).0;
+
+    let position_account = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, position_account.collateral_custody).await;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), &collateral_custody_account.mint).0;
+
+    // Save account state before tx execution
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+    let min_amount_out = params.min_amount_out;
+
+    let mut accounts_meta = perpetuals::accounts::CloseMarginPosition {
+        owner: owner.pubkey(),
+        receiving_account: receiving_account_address,
+        transfer_authority: transfer_authority_pda,
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        margin_account: margin_account_pda,
+        position: *position_pda,
+        collateral_custody_token_account: collateral_custody_account.token_account,
+        user_positions: utils::get_user_positions_account(program_test_ctx, &owner.pubkey()).await,
+        token_program: anchor_spl::token::ID,
+    }
+    .to_account_metas(None);
+
+    accounts_meta.extend(
+        get_margin_account_remaining_accounts(program_test_ctx, &margin_account_pda, true).await,
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::CloseMarginPosition { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    {
+        let owner_receiving_account_after =
+            utils::get_token_account(program_test_ctx, receiving_account_address).await;
+        let margin_account_after =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+        assert!(
+            owner_receiving_account_after.amount
+                >= owner_receiving_account_before.amount + min_amount_out
+        );
+        assert!(!margin_account_after.positions.contains(position_pda));
+
+        // Closed position account is gone
+        let mut ctx = program_test_ctx.write().await;
+        assert!(ctx
+            .banks_client
+            .get_account(*position_pda)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    Ok(())
+}
@@ -2,7 +2,10 @@ use {
     super::get_update_pool_ix,
     crate::utils::{self, pda},
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
-    perpetuals::{instructions::ClosePositionParams, state::custody::Custody},
+    perpetuals::{
+        instructions::ClosePositionParams,
+        state::{custody::Custody, position::Position},
+    },
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -16,6 +19,198 @@ pub async fn test_close_position(
     custody_token_mint: &Pubkey,
     position_pda: &Pubkey,
     params: ClosePositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    close_position(
+        program_test_ctx,
+        &owner.pubkey(),
+        owner,
+        None,
+        payer,
+        pool_pda,
+        custody_token_mint,
+        position_pda,
+        None,
+        None,
+        params,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn test_close_position_with_referral(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    referral_pda: Option<&Pubkey>,
+    trader_stats_pda: Option<&Pubkey>,
+    params: ClosePositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    close_position(
+        program_test_ctx,
+        &owner.pubkey(),
+        owner,
+        None,
+        payer,
+        pool_pda,
+        custody_token_mint,
+        position_pda,
+        referral_pda,
+        trader_stats_pda,
+        params,
+    )
+    .await
+}
+
+// Closes the position on behalf of its owner, signed by the delegate authority only
+#[allow(clippy::too_many_arguments)]
+pub async fn test_delegate_close_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    delegate_authority: &Keypair,
+    delegate_pda: &Pubkey,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    params: ClosePositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    let owner = utils::get_account::<Position>(program_test_ctx, *position_pda)
+        .await
+        .owner;
+
+    close_position(
+        program_test_ctx,
+        &owner,
+        delegate_authority,
+        Some(delegate_pda),
+        payer,
+        pool_pda,
+        custody_token_mint,
+        position_pda,
+        None,
+        None,
+        params,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn close_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Pubkey,
+    signer: &Keypair,
+    delegate_pda: Option<&Pubkey>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    referral_pda: Option<&Pubkey>,
+    trader_stats_pda: Option<&Pubkey>,
+    params: ClosePositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let custody_oracle_account_address = custody_account.oracle.oracle_account;
+
+    // Shorts and virtual longs pay out from their stable collateral custody
+    let collateral_custody_pda = utils::get_account::<Position>(program_test_ctx, *position_pda)
+        .await
+        .collateral_custody;
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+    let collateral_custody_token_account_pda = collateral_custody_account.token_account;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(owner, &collateral_custody_account.mint).0;
+
+    // Save account state before tx execution
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    let collateral_custody_token_account_before =
+        utils::get_token_account(program_test_ctx, collateral_custody_token_account_pda).await;
+
+    let mut accounts_meta = perpetuals::accounts::ClosePosition {
+        owner: *owner,
+        receiving_account: receiving_account_address,
+        transfer_authority: transfer_authority_pda,
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        position: *position_pda,
+        custody: custody_pda,
+        custody_oracle_account: custody_oracle_account_address,
+        collateral_custody: collateral_custody_pda,
+        collateral_custody_oracle_account: collateral_custody_account.oracle.oracle_account,
+        collateral_custody_token_account: collateral_custody_token_account_pda,
+        token_program: anchor_spl::token::ID,
+        custody_twap_account: None, // TODO: add twap account
+        custody_reference_oracle_account: utils::get_reference_oracle_account(
+            &custody_account.oracle,
+        ),
+        collateral_custody_twap_account: None, // TODO: add twap account
+        receive_custody: None,
+        receive_custody_oracle_account: None,
+        receive_custody_twap_account: None,
+        receive_custody_token_account: None,
+        referral: referral_pda.copied(),
+        trader_stats: trader_stats_pda.copied(),
+        user_positions: utils::get_user_positions_account(program_test_ctx, owner).await,
+        signer: delegate_pda.map(|_| signer.pubkey()),
+        delegate: delegate_pda.copied(),
+    }
+    .to_account_metas(None);
+    // without a delegate the owner signs directly
+    if delegate_pda.is_none() {
+        accounts_meta[0].is_signer = true;
+    }
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::ClosePosition { params },
+        Some(&payer.pubkey()),
+        &[signer, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the balance change
+    {
+        let owner_receiving_account_after =
+            utils::get_token_account(program_test_ctx, receiving_account_address).await;
+        let collateral_custody_token_account_after =
+            utils::get_token_account(program_test_ctx, collateral_custody_token_account_pda).await;
+
+        assert!(owner_receiving_account_after.amount > owner_receiving_account_before.amount);
+        assert!(
+            collateral_custody_token_account_after.amount
+                < collateral_custody_token_account_before.amount
+        );
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn test_close_position_with_swap(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    receive_custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    params: ClosePositionParams,
 ) -> std::result::Result<(), BanksClientError> {
     // ==== WHEN ==============================================================
 
@@ -25,39 +220,63 @@ pub async fn test_close_position(
     let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
     let custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+    let receive_custody_pda = pda::get_custody_pda(pool_pda, receive_custody_token_mint).0;
+    let receive_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, receive_custody_token_mint).0;
 
     let receiving_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
+        utils::find_associated_token_account(&owner.pubkey(), receive_custody_token_mint).0;
 
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
 
+    let receive_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, receive_custody_pda).await;
+    let receive_custody_oracle_account_address = receive_custody_account.oracle.oracle_account;
+
     // Save account state before tx execution
     let owner_receiving_account_before =
         utils::get_token_account(program_test_ctx, receiving_account_address).await;
-
     let custody_token_account_before =
         utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+    let receive_custody_token_account_before =
+        utils::get_token_account(program_test_ctx, receive_custody_token_account_pda).await;
+
+    let mut accounts_meta = perpetuals::accounts::ClosePosition {
+        owner: owner.pubkey(),
+        receiving_account: receiving_account_address,
+        transfer_authority: transfer_authority_pda,
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        position: *position_pda,
+        custody: custody_pda,
+        custody_oracle_account: custody_oracle_account_address,
+        collateral_custody: custody_pda,
+        collateral_custody_oracle_account: custody_oracle_account_address,
+        collateral_custody_token_account: custody_token_account_pda,
+        token_program: anchor_spl::token::ID,
+        custody_twap_account: None, // TODO: add twap account
+        custody_reference_oracle_account: utils::get_reference_oracle_account(
+            &custody_account.oracle,
+        ),
+        collateral_custody_twap_account: None, // TODO: add twap account
+        receive_custody: Some(receive_custody_pda),
+        receive_custody_oracle_account: Some(receive_custody_oracle_account_address),
+        receive_custody_twap_account: None, // TODO: add twap account
+        receive_custody_token_account: Some(receive_custody_token_account_pda),
+        referral: None,
+        trader_stats: None,
+        user_positions: utils::get_user_positions_account(program_test_ctx, &owner.pubkey()).await,
+        signer: None,
+        delegate: None,
+    }
+    .to_account_metas(None);
+    // the owner signs directly
+    accounts_meta[0].is_signer = true;
 
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
-        perpetuals::accounts::ClosePosition {
-            owner: owner.pubkey(),
-            receiving_account: receiving_account_address,
-            transfer_authority: transfer_authority_pda,
-            perpetuals: perpetuals_pda,
-            pool: *pool_pda,
-            position: *position_pda,
-            custody: custody_pda,
-            custody_oracle_account: custody_oracle_account_address,
-            collateral_custody: custody_pda,
-            collateral_custody_oracle_account: custody_oracle_account_address,
-            collateral_custody_token_account: custody_token_account_pda,
-            token_program: anchor_spl::token::ID,
-            custody_twap_account: None, // TODO: add twap account
-            collateral_custody_twap_account: None, // TODO: add twap account
-        }
-        .to_account_metas(None),
+        accounts_meta,
         perpetuals::instruction::ClosePosition { params },
         Some(&payer.pubkey()),
         &[owner, payer],
@@ -67,15 +286,24 @@ pub async fn test_close_position(
     .await?;
 
     // ==== THEN ==============================================================
-    // Check the balance change
+    // Check the balance change, the payout stays in the collateral custody
     {
         let owner_receiving_account_after =
             utils::get_token_account(program_test_ctx, receiving_account_address).await;
         let custody_token_account_after =
             utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+        let receive_custody_token_account_after =
+            utils::get_token_account(program_test_ctx, receive_custody_token_account_pda).await;
 
         assert!(owner_receiving_account_after.amount > owner_receiving_account_before.amount);
-        assert!(custody_token_account_after.amount < custody_token_account_before.amount);
+        assert_eq!(
+            custody_token_account_after.amount,
+            custody_token_account_before.amount
+        );
+        assert!(
+            receive_custody_token_account_after.amount
+                < receive_custody_token_account_before.amount
+        );
     }
 
     Ok(())
@@ -0,0 +1,114 @@
+use {
+    super::get_update_pool_ix,
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::ExecuteSwapOrderParams,
+        state::{custody::Custody, swap_order::SwapOrder},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_execute_swap_order(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    keeper: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    swap_order_pda: &Pubkey,
+    params: ExecuteSwapOrderParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let swap_order_token_account_pda = pda::get_swap_order_token_account_pda(swap_order_pda).0;
+
+    let swap_order = utils::get_account::<SwapOrder>(program_test_ctx, *swap_order_pda).await;
+
+    let receiving_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, swap_order.receiving_custody).await;
+    let dispensing_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, swap_order.dispensing_custody).await;
+
+    let receiving_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, &receiving_custody_account.mint).0;
+    let dispensing_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, &dispensing_custody_account.mint).0;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(&swap_order.owner, &dispensing_custody_account.mint).0;
+
+    // Save account state before tx execution
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::ExecuteSwapOrder {
+            signer: keeper.pubkey(),
+            owner: swap_order.owner,
+            receiving_account: receiving_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            swap_order: *swap_order_pda,
+            swap_order_token_account: swap_order_token_account_pda,
+            receiving_custody: swap_order.receiving_custody,
+            receiving_custody_oracle_account: receiving_custody_account.oracle.oracle_account,
+            receiving_custody_twap_account: None, // TODO: add twap account
+            receiving_custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &receiving_custody_account.oracle,
+            ),
+            receiving_custody_token_account: receiving_custody_token_account_pda,
+            dispensing_custody: swap_order.dispensing_custody,
+            dispensing_custody_oracle_account: dispensing_custody_account.oracle.oracle_account,
+            dispensing_custody_twap_account: None, // TODO: add twap account
+            dispensing_custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &dispensing_custody_account.oracle,
+            ),
+            dispensing_custody_token_account: dispensing_custody_token_account_pda,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::ExecuteSwapOrder { params },
+        Some(&payer.pubkey()),
+        &[keeper, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the owner received at least the limit price worth of tokens
+    let owner_receiving_account_after =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    let min_amount_out = swap_order
+        .get_min_amount_out(
+            receiving_custody_account.decimals,
+            dispensing_custody_account.decimals,
+        )
+        .unwrap();
+
+    assert!(
+        owner_receiving_account_after.amount - owner_receiving_account_before.amount
+            >= min_amount_out
+    );
+
+    // Check the order accounts are closed
+    for account in [*swap_order_pda, swap_order_token_account_pda] {
+        let account = program_test_ctx
+            .write()
+            .await
+            .banks_client
+            .get_account(account)
+            .await
+            .unwrap();
+
+        assert!(account.is_none());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, solana_program::instruction::AccountMeta, ToAccountMetas},
+    perpetuals::{
+        instructions::GetAumBreakdownParams,
+        state::{custody::Custody, perpetuals::AumBreakdown, pool::Pool},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+pub async fn test_get_aum_breakdown(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    // Read the cached valuations only, without custody and oracle accounts
+    use_cache: bool,
+) -> std::result::Result<AumBreakdown, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let lp_token_mint_pda = pda::get_lp_token_mint_pda(pool_pda).0;
+
+    let accounts_meta = {
+        let accounts = perpetuals::accounts::GetAumBreakdown {
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            lp_token_mint: lp_token_mint_pda,
+        };
+
+        let mut accounts_meta = accounts.to_account_metas(None);
+
+        if !use_cache {
+            let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+
+            // For each token, add custody account as remaining_account
+            for custody in &pool_account.custodies {
+                accounts_meta.push(AccountMeta {
+                    pubkey: *custody,
+                    is_signer: false,
+                    is_writable: false,
+                });
+            }
+
+            // For each token, add custody oracle account as remaining_account
+            for custody in &pool_account.custodies {
+                let custody_account =
+                    utils::get_account::<Custody>(program_test_ctx, *custody).await;
+
+                accounts_meta.push(AccountMeta {
+                    pubkey: custody_account.oracle.oracle_account,
+                    is_signer: false,
+                    is_writable: false,
+                });
+            }
+        }
+
+        accounts_meta
+    };
+
+    let result: AumBreakdown = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::GetAumBreakdown {
+            params: GetAumBreakdownParams {},
+        },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
@@ -0,0 +1,53 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetEntryPriceAndFeeParams,
+        state::{custody::Custody, perpetuals::NewPositionPricesAndFee},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+pub async fn test_get_entry_price_and_fee(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: GetEntryPriceAndFeeParams,
+) -> std::result::Result<NewPositionPricesAndFee, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+
+    let accounts_meta = perpetuals::accounts::GetEntryPriceAndFee {
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        custody: custody_pda,
+        custody_oracle_account: custody_account.oracle.oracle_account,
+        custody_twap_account: None, // TODO: add twap account
+        collateral_custody: collateral_custody_pda,
+        collateral_custody_oracle_account: collateral_custody_account.oracle.oracle_account,
+        collateral_custody_twap_account: None, // TODO: add twap account
+        trader_stats: None,
+    }
+    .to_account_metas(None);
+
+    let result: NewPositionPricesAndFee = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::GetEntryPriceAndFee { params },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
@@ -0,0 +1,40 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetOpenInterestHeadroomParams, state::perpetuals::OpenInterestHeadroom,
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+pub async fn test_get_open_interest_headroom(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+) -> std::result::Result<OpenInterestHeadroom, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    let accounts_meta = perpetuals::accounts::GetOpenInterestHeadroom {
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        custody: *custody_pda,
+    }
+    .to_account_metas(None);
+
+    let result: OpenInterestHeadroom = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::GetOpenInterestHeadroom {
+            params: GetOpenInterestHeadroomParams {},
+        },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
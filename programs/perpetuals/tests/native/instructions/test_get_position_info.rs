@@ -0,0 +1,60 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetPositionInfoParams,
+        state::{custody::Custody, perpetuals::PositionInfo, position::Position},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+pub async fn test_get_position_info(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+    referral_pda: Option<&Pubkey>,
+    trader_stats_pda: Option<&Pubkey>,
+) -> std::result::Result<PositionInfo, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    let position_account = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let custody_account =
+        utils::get_account::<Custody>(program_test_ctx, position_account.custody).await;
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, position_account.collateral_custody).await;
+
+    let accounts_meta = perpetuals::accounts::GetPositionInfo {
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        position: *position_pda,
+        custody: position_account.custody,
+        custody_oracle_account: custody_account.oracle.oracle_account,
+        custody_twap_account: None, // TODO: add twap account
+        collateral_custody: position_account.collateral_custody,
+        collateral_custody_oracle_account: collateral_custody_account.oracle.oracle_account,
+        collateral_custody_twap_account: None, // TODO: add twap account
+        referral: referral_pda.copied(),
+        trader_stats: trader_stats_pda.copied(),
+    }
+    .to_account_metas(None);
+
+    let result: PositionInfo = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::GetPositionInfo {
+            params: GetPositionInfoParams {},
+        },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    assert_eq!(result.entry_price, position_account.price);
+    assert_eq!(result.size_usd, position_account.size_usd);
+
+    Ok(result)
+}
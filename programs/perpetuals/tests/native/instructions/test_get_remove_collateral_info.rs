@@ -0,0 +1,51 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetRemoveCollateralInfoParams,
+        state::{custody::Custody, perpetuals::RemoveCollateralInfo},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+pub async fn test_get_remove_collateral_info(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    params: GetRemoveCollateralInfoParams,
+) -> std::result::Result<RemoveCollateralInfo, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let custody_oracle_account_address = custody_account.oracle.oracle_account;
+
+    let accounts_meta = perpetuals::accounts::GetRemoveCollateralInfo {
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        position: *position_pda,
+        custody: custody_pda,
+        custody_oracle_account: custody_oracle_account_address,
+        custody_twap_account: None, // TODO: add twap account
+        collateral_custody: custody_pda,
+        collateral_custody_oracle_account: custody_oracle_account_address,
+        collateral_custody_twap_account: None, // TODO: add twap account
+    }
+    .to_account_metas(None);
+
+    let result: RemoveCollateralInfo = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::GetRemoveCollateralInfo { params },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
@@ -0,0 +1,60 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::InitMarginAccountParams, state::margin_account::MarginAccount},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_init_margin_account(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: InitMarginAccountParams,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let (margin_account_pda, margin_account_bump) =
+        pda::get_margin_account_pda(&owner.pubkey(), pool_pda);
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::InitMarginAccount {
+            owner: owner.pubkey(),
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            margin_account: margin_account_pda,
+            collateral_custody: collateral_custody_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::InitMarginAccount { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    {
+        let margin_account =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+        assert_eq!(margin_account.owner, owner.pubkey());
+        assert_eq!(margin_account.pool, *pool_pda);
+        assert_eq!(margin_account.collateral_custody, collateral_custody_pda);
+        assert_eq!(margin_account.collateral_amount, 0);
+        assert!(margin_account.positions.is_empty());
+        assert_eq!(margin_account.bump, margin_account_bump);
+    }
+
+    Ok((margin_account_pda, margin_account_bump))
+}
@@ -0,0 +1,46 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::InitReferralParams, state::referral::Referral},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_init_referral(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    params: InitReferralParams,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let (referral_pda, referral_bump) = pda::get_referral_pda(&owner.pubkey());
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::InitReferral {
+            owner: owner.pubkey(),
+            perpetuals: perpetuals_pda,
+            referral: referral_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::InitReferral { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let referral_account = utils::get_account::<Referral>(program_test_ctx, referral_pda).await;
+
+    assert_eq!(referral_account.owner, owner.pubkey());
+    assert_eq!(referral_account.volume_usd, 0);
+    assert!(referral_account.rebates.is_empty());
+    assert_eq!(referral_account.bump, referral_bump);
+
+    Ok((referral_pda, referral_bump))
+}
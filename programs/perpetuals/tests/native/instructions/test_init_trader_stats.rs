@@ -0,0 +1,46 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::InitTraderStatsParams, state::trader_stats::TraderStats},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_init_trader_stats(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    params: InitTraderStatsParams,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let (trader_stats_pda, trader_stats_bump) = pda::get_trader_stats_pda(&owner.pubkey());
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::InitTraderStats {
+            owner: owner.pubkey(),
+            perpetuals: perpetuals_pda,
+            trader_stats: trader_stats_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::InitTraderStats { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let trader_stats_account =
+        utils::get_account::<TraderStats>(program_test_ctx, trader_stats_pda).await;
+
+    assert_eq!(trader_stats_account.owner, owner.pubkey());
+    assert_eq!(trader_stats_account.daily_volume_usd, [0; 30]);
+    assert_eq!(trader_stats_account.bump, trader_stats_bump);
+
+    Ok((trader_stats_pda, trader_stats_bump))
+}
@@ -0,0 +1,47 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::InitUserPositionsParams, state::user_positions::UserPositions},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_init_user_positions(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let (user_positions_pda, user_positions_bump) = pda::get_user_positions_pda(&owner.pubkey());
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::InitUserPositions {
+            owner: owner.pubkey(),
+            perpetuals: perpetuals_pda,
+            user_positions: user_positions_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::InitUserPositions {
+            params: InitUserPositionsParams {},
+        },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let user_positions_account =
+        utils::get_account::<UserPositions>(program_test_ctx, user_positions_pda).await;
+
+    assert_eq!(user_positions_account.owner, owner.pubkey());
+    assert!(user_positions_account.positions.is_empty());
+    assert_eq!(user_positions_account.bump, user_positions_bump);
+
+    Ok((user_positions_pda, user_positions_bump))
+}
@@ -67,7 +67,11 @@ pub async fn test_liquidate(
             collateral_custody_token_account: custody_token_account_pda,
             token_program: anchor_spl::token::ID,
             custody_twap_account: None, // TODO: add twap account
+            custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &custody_account.oracle,
+            ),
             collateral_custody_twap_account: None, // TODO: add twap account
+            user_positions: utils::get_user_positions_account(program_test_ctx, &owner).await,
         }
         .to_account_metas(None),
         perpetuals::instruction::Liquidate {
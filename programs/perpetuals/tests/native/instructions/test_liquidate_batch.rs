@@ -0,0 +1,243 @@
+use {
+    super::get_update_pool_ix,
+    crate::utils::{self, pda},
+    anchor_lang::{
+        prelude::Pubkey, solana_program::instruction::AccountMeta, InstructionData, ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::LiquidateBatchParams,
+        state::{custody::Custody, position::Position},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::{
+        compute_budget::ComputeBudgetInstruction,
+        packet::PACKET_DATA_SIZE,
+        signer::{keypair::Keypair, Signer},
+        transaction::{Transaction, MAX_TX_ACCOUNT_LOCKS},
+    },
+    tokio::sync::RwLock,
+};
+
+// Maximum compute units a single transaction can request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+async fn get_liquidate_batch_tx(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    liquidator: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pdas: &[Pubkey],
+) -> std::result::Result<Transaction, BanksClientError> {
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+
+    let rewards_receiving_account_address =
+        utils::find_associated_token_account(&liquidator.pubkey(), custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let custody_oracle_account_address = custody_account.oracle.oracle_account;
+
+    let mut accounts_meta = perpetuals::accounts::LiquidateBatch {
+        signer: liquidator.pubkey(),
+        rewards_receiving_account: rewards_receiving_account_address,
+        transfer_authority: transfer_authority_pda,
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        custody: custody_pda,
+        custody_oracle_account: custody_oracle_account_address,
+        custody_twap_account: None, // TODO: add twap account
+        custody_reference_oracle_account: utils::get_reference_oracle_account(
+            &custody_account.oracle,
+        ),
+        collateral_custody: custody_pda,
+        collateral_custody_oracle_account: custody_oracle_account_address,
+        collateral_custody_twap_account: None, // TODO: add twap account
+        collateral_custody_token_account: custody_token_account_pda,
+        token_program: anchor_spl::token::ID,
+    }
+    .to_account_metas(None);
+
+    // For each position, add position and owner receiving account as remaining_account
+    let mut owners = Vec::new();
+    for position_pda in position_pdas {
+        let position_account =
+            utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+
+        if !owners.contains(&position_account.owner) {
+            owners.push(position_account.owner);
+        }
+
+        accounts_meta.push(AccountMeta {
+            pubkey: *position_pda,
+            is_signer: false,
+            is_writable: true,
+        });
+        accounts_meta.push(AccountMeta {
+            pubkey: utils::find_associated_token_account(
+                &position_account.owner,
+                custody_token_mint,
+            )
+            .0,
+            is_signer: false,
+            is_writable: true,
+        });
+    }
+
+    // Then the positions index of each owner that has one
+    for owner in owners.iter() {
+        if let Some(user_positions) =
+            utils::get_user_positions_account(program_test_ctx, owner).await
+        {
+            accounts_meta.push(AccountMeta {
+                pubkey: user_positions,
+                is_signer: false,
+                is_writable: true,
+            });
+        }
+    }
+
+    let ix = solana_sdk::instruction::Instruction {
+        program_id: perpetuals::id(),
+        accounts: accounts_meta,
+        data: perpetuals::instruction::LiquidateBatch {
+            params: LiquidateBatchParams {
+                // feed_id: [0; 32], // TODO: add feed id
+            },
+        }
+        .data(),
+    };
+
+    let update_pool_ix = get_update_pool_ix(program_test_ctx, payer, pool_pda).await?;
+
+    let last_blockhash = program_test_ctx.read().await.last_blockhash;
+
+    Ok(Transaction::new_signed_with_payer(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            update_pool_ix,
+            ix,
+        ],
+        Some(&payer.pubkey()),
+        &[liquidator, payer],
+        last_blockhash,
+    ))
+}
+
+// Simulate the batch liquidation and return consumed compute units, state is left untouched
+pub async fn simulate_liquidate_batch(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    liquidator: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pdas: &[Pubkey],
+) -> std::result::Result<u64, BanksClientError> {
+    let tx = get_liquidate_batch_tx(
+        program_test_ctx,
+        liquidator,
+        payer,
+        pool_pda,
+        custody_token_mint,
+        position_pdas,
+    )
+    .await?;
+
+    let mut ctx = program_test_ctx.write().await;
+    let result = ctx.banks_client.simulate_transaction(tx).await?;
+
+    if let Some(Err(err)) = result.result {
+        return Err(BanksClientError::TransactionError(err));
+    }
+
+    Ok(result.simulation_details.unwrap().units_consumed)
+}
+
+// Largest prefix of position_pdas a single liquidate_batch transaction can carry,
+// bounded by the serialized transaction size and the account lock limit
+pub async fn get_max_liquidate_batch_size(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    liquidator: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pdas: &[Pubkey],
+) -> std::result::Result<usize, BanksClientError> {
+    let mut max_batch_size = 0;
+
+    for batch_size in 1..=position_pdas.len() {
+        let tx = get_liquidate_batch_tx(
+            program_test_ctx,
+            liquidator,
+            payer,
+            pool_pda,
+            custody_token_mint,
+            &position_pdas[..batch_size],
+        )
+        .await?;
+
+        // compact-u16 signatures count, signatures, then the message
+        let tx_size = 1 + tx.signatures.len() * 64 + tx.message.serialize().len();
+
+        if tx_size > PACKET_DATA_SIZE || tx.message.account_keys.len() > MAX_TX_ACCOUNT_LOCKS {
+            break;
+        }
+        max_batch_size = batch_size;
+    }
+
+    Ok(max_batch_size)
+}
+
+pub async fn test_liquidate_batch(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    liquidator: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pdas: &[Pubkey],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+    let rewards_receiving_account_address =
+        utils::find_associated_token_account(&liquidator.pubkey(), custody_token_mint).0;
+
+    // Save account state before tx execution
+    let custody_token_account_before =
+        utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+    let rewards_receiving_account_before =
+        utils::get_token_account(program_test_ctx, rewards_receiving_account_address).await;
+
+    let tx = get_liquidate_batch_tx(
+        program_test_ctx,
+        liquidator,
+        payer,
+        pool_pda,
+        custody_token_mint,
+        position_pdas,
+    )
+    .await?;
+
+    {
+        let mut ctx = program_test_ctx.write().await;
+        ctx.banks_client.process_transaction(tx).await?;
+    }
+
+    // ==== THEN ==============================================================
+    // Check the balance change
+    {
+        let custody_token_account_after =
+            utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+        let rewards_receiving_account_after =
+            utils::get_token_account(program_test_ctx, rewards_receiving_account_address).await;
+
+        assert!(custody_token_account_after.amount <= custody_token_account_before.amount);
+        assert!(rewards_receiving_account_after.amount >= rewards_receiving_account_before.amount);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use {
+    super::{get_margin_account_remaining_accounts, get_update_pool_ix},
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::LiquidateMarginAccountParams, state::margin_account::MarginAccount,
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_liquidate_margin_account(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    liquidator: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    margin_account_pda: &Pubkey,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let collateral_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let rewards_receiving_account_address =
+        utils::find_associated_token_account(&liquidator.pubkey(), collateral_custody_token_mint).0;
+
+    // Save account state before tx execution
+    let margin_account_before =
+        utils::get_account::<MarginAccount>(program_test_ctx, *margin_account_pda).await;
+    let rewards_receiving_account_before =
+        utils::get_token_account(program_test_ctx, rewards_receiving_account_address).await;
+
+    let mut accounts_meta = perpetuals::accounts::LiquidateMarginAccount {
+        signer: liquidator.pubkey(),
+        rewards_receiving_account: rewards_receiving_account_address,
+        transfer_authority: transfer_authority_pda,
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        margin_account: *margin_account_pda,
+        user_positions: utils::get_user_positions_account(
+            program_test_ctx,
+            &margin_account_before.owner,
+        )
+        .await,
+        collateral_custody_token_account: collateral_custody_token_account_pda,
+        token_program: anchor_spl::token::ID,
+    }
+    .to_account_metas(None);
+
+    accounts_meta.extend(
+        get_margin_account_remaining_accounts(program_test_ctx, margin_account_pda, true).await,
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::LiquidateMarginAccount {
+            params: LiquidateMarginAccountParams {
+                // feed_id: [0; 32], // TODO: add feed id
+            },
+        },
+        Some(&payer.pubkey()),
+        &[liquidator, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    {
+        let margin_account_after =
+            utils::get_account::<MarginAccount>(program_test_ctx, *margin_account_pda).await;
+        let rewards_receiving_account_after =
+            utils::get_token_account(program_test_ctx, rewards_receiving_account_address).await;
+
+        assert!(margin_account_after.positions.is_empty());
+        assert!(rewards_receiving_account_after.amount >= rewards_receiving_account_before.amount);
+
+        // Closed positions accounts are gone
+        let mut ctx = program_test_ctx.write().await;
+        for position_pda in &margin_account_before.positions {
+            assert!(ctx
+                .banks_client
+                .get_account(*position_pda)
+                .await
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    Ok(())
+}
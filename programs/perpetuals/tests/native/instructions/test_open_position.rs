@@ -18,6 +18,29 @@ pub async fn test_open_position(
     pool_pda: &Pubkey,
     custody_token_mint: &Pubkey,
     params: OpenPositionParams,
+) -> std::result::Result<(solana_sdk::pubkey::Pubkey, u8), BanksClientError> {
+    test_open_position_with_collateral(
+        program_test_ctx,
+        owner,
+        payer,
+        pool_pda,
+        custody_token_mint,
+        custody_token_mint,
+        params,
+    )
+    .await
+}
+
+// Opens a position backed by another custody, shorts and virtual longs use a stable one
+#[allow(clippy::too_many_arguments)]
+pub async fn test_open_position_with_collateral(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: OpenPositionParams,
 ) -> std::result::Result<(solana_sdk::pubkey::Pubkey, u8), BanksClientError> {
     // ==== WHEN ==============================================================
 
@@ -25,23 +48,32 @@ pub async fn test_open_position(
     let transfer_authority_pda = pda::get_transfer_authority_pda().0;
     let perpetuals_pda = pda::get_perpetuals_pda().0;
     let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
-    let custody_token_account_pda =
-        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+    let collateral_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, collateral_custody_token_mint).0;
 
-    let (position_pda, position_bump) =
-        pda::get_position_pda(&owner.pubkey(), pool_pda, &custody_pda, params.side);
+    let (position_pda, position_bump) = pda::get_position_pda(
+        &owner.pubkey(),
+        pool_pda,
+        &custody_pda,
+        params.side,
+        params.position_id,
+    );
 
     let funding_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
+        utils::find_associated_token_account(&owner.pubkey(), collateral_custody_token_mint).0;
 
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
 
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+
     // Save account state before tx execution
     let owner_funding_account_before =
         utils::get_token_account(program_test_ctx, funding_account_address).await;
-    let custody_token_account_before =
-        utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+    let collateral_custody_token_account_before =
+        utils::get_token_account(program_test_ctx, collateral_custody_token_account_pda).await;
 
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
@@ -54,13 +86,24 @@ pub async fn test_open_position(
             position: position_pda,
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
-            collateral_custody: custody_pda,
-            collateral_custody_oracle_account: custody_oracle_account_address,
-            collateral_custody_token_account: custody_token_account_pda,
+            collateral_custody: collateral_custody_pda,
+            collateral_custody_oracle_account: collateral_custody_account.oracle.oracle_account,
+            collateral_custody_token_account: collateral_custody_token_account_pda,
             system_program: anchor_lang::system_program::ID,
             token_program: anchor_spl::token::ID,
             custody_twap_account: None, // TODO: add twap account
+            custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &custody_account.oracle,
+            ),
             collateral_custody_twap_account: None, // TODO: add twap account
+            funding_custody: None,
+            funding_custody_oracle_account: None,
+            funding_custody_twap_account: None,
+            funding_custody_token_account: None,
+            referral: None,
+            trader_stats: None,
+            user_positions: utils::get_user_positions_account(program_test_ctx, &owner.pubkey())
+                .await,
         }
         .to_account_metas(None),
         perpetuals::instruction::OpenPosition { params },
@@ -76,11 +119,14 @@ pub async fn test_open_position(
     {
         let owner_funding_account_after =
             utils::get_token_account(program_test_ctx, funding_account_address).await;
-        let custody_token_account_after =
-            utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+        let collateral_custody_token_account_after =
+            utils::get_token_account(program_test_ctx, collateral_custody_token_account_pda).await;
 
         assert!(owner_funding_account_after.amount < owner_funding_account_before.amount);
-        assert!(custody_token_account_after.amount > custody_token_account_before.amount);
+        assert!(
+            collateral_custody_token_account_after.amount
+                > collateral_custody_token_account_before.amount
+        );
     }
 
     // Check the position
@@ -90,6 +136,7 @@ pub async fn test_open_position(
         assert_eq!(position_account.owner, owner.pubkey());
         assert_eq!(position_account.pool, *pool_pda);
         assert_eq!(position_account.custody, custody_pda);
+        assert_eq!(position_account.collateral_custody, collateral_custody_pda);
         // Need to handle test/not test case
         // assert_eq!(
         //     position_account.open_time,
@@ -100,6 +147,134 @@ pub async fn test_open_position(
         assert_eq!(position_account.unrealized_profit_usd, 0);
         assert_eq!(position_account.unrealized_loss_usd, 0);
         assert_eq!(position_account.collateral_amount, params.collateral);
+        assert_eq!(position_account.position_id, params.position_id);
+        assert_eq!(position_account.bump, position_bump);
+    }
+
+    Ok((position_pda, position_bump))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn test_open_position_with_swap(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    funding_custody_token_mint: &Pubkey,
+    params: OpenPositionParams,
+) -> std::result::Result<(solana_sdk::pubkey::Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+    let funding_custody_pda = pda::get_custody_pda(pool_pda, funding_custody_token_mint).0;
+    let funding_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, funding_custody_token_mint).0;
+
+    let (position_pda, position_bump) = pda::get_position_pda(
+        &owner.pubkey(),
+        pool_pda,
+        &custody_pda,
+        params.side,
+        params.position_id,
+    );
+
+    let funding_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), funding_custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let custody_oracle_account_address = custody_account.oracle.oracle_account;
+
+    let funding_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, funding_custody_pda).await;
+    let funding_custody_oracle_account_address = funding_custody_account.oracle.oracle_account;
+
+    // Save account state before tx execution
+    let owner_funding_account_before =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+    let custody_token_account_before =
+        utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+    let funding_custody_token_account_before =
+        utils::get_token_account(program_test_ctx, funding_custody_token_account_pda).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::OpenPosition {
+            owner: owner.pubkey(),
+            funding_account: funding_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            position: position_pda,
+            custody: custody_pda,
+            custody_oracle_account: custody_oracle_account_address,
+            collateral_custody: custody_pda,
+            collateral_custody_oracle_account: custody_oracle_account_address,
+            collateral_custody_token_account: custody_token_account_pda,
+            system_program: anchor_lang::system_program::ID,
+            token_program: anchor_spl::token::ID,
+            custody_twap_account: None, // TODO: add twap account
+            custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &custody_account.oracle,
+            ),
+            collateral_custody_twap_account: None, // TODO: add twap account
+            funding_custody: Some(funding_custody_pda),
+            funding_custody_oracle_account: Some(funding_custody_oracle_account_address),
+            funding_custody_twap_account: None, // TODO: add twap account
+            funding_custody_token_account: Some(funding_custody_token_account_pda),
+            referral: None,
+            trader_stats: None,
+            user_positions: utils::get_user_positions_account(program_test_ctx, &owner.pubkey())
+                .await,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::OpenPosition { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the balance change, swapped collateral never leaves the collateral custody
+    {
+        let owner_funding_account_after =
+            utils::get_token_account(program_test_ctx, funding_account_address).await;
+        let custody_token_account_after =
+            utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+        let funding_custody_token_account_after =
+            utils::get_token_account(program_test_ctx, funding_custody_token_account_pda).await;
+
+        assert_eq!(
+            owner_funding_account_before.amount - owner_funding_account_after.amount,
+            params.collateral
+        );
+        assert_eq!(
+            custody_token_account_after.amount,
+            custody_token_account_before.amount
+        );
+        assert_eq!(
+            funding_custody_token_account_after.amount
+                - funding_custody_token_account_before.amount,
+            params.collateral
+        );
+    }
+
+    // Check the position
+    {
+        let position_account = utils::get_account::<Position>(program_test_ctx, position_pda).await;
+
+        assert_eq!(position_account.owner, owner.pubkey());
+        assert_eq!(position_account.custody, custody_pda);
+        assert_eq!(position_account.side, params.side);
+        assert!(position_account.collateral_amount > 0);
+        assert_eq!(position_account.position_id, params.position_id);
         assert_eq!(position_account.bump, position_bump);
     }
 
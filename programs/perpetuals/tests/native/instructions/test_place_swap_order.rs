@@ -0,0 +1,95 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::PlaceSwapOrderParams, state::swap_order::SwapOrder},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_place_swap_order(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    // Mint received by the User
+    dispensing_custody_token_mint: &Pubkey,
+    // Mint sent by the User
+    receiving_custody_token_mint: &Pubkey,
+    params: PlaceSwapOrderParams,
+) -> std::result::Result<(Pubkey, u8), BanksClientError> {
+    // ==== WHEN ==============================================================
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let dispensing_custody_pda = pda::get_custody_pda(pool_pda, dispensing_custody_token_mint).0;
+    let receiving_custody_pda = pda::get_custody_pda(pool_pda, receiving_custody_token_mint).0;
+    let (swap_order_pda, swap_order_bump) =
+        pda::get_swap_order_pda(&owner.pubkey(), pool_pda, params.order_id);
+    let (swap_order_token_account_pda, swap_order_token_account_bump) =
+        pda::get_swap_order_token_account_pda(&swap_order_pda);
+
+    let funding_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), receiving_custody_token_mint).0;
+
+    // Save account state before tx execution
+    let owner_funding_account_before =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::PlaceSwapOrder {
+            owner: owner.pubkey(),
+            funding_account: funding_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            swap_order: swap_order_pda,
+            swap_order_token_account: swap_order_token_account_pda,
+            receiving_custody: receiving_custody_pda,
+            receiving_custody_token_mint: *receiving_custody_token_mint,
+            dispensing_custody: dispensing_custody_pda,
+            system_program: anchor_lang::system_program::ID,
+            token_program: anchor_spl::token::ID,
+            rent: anchor_lang::solana_program::sysvar::rent::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::PlaceSwapOrder { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the tokens are escrowed
+    let owner_funding_account_after =
+        utils::get_token_account(program_test_ctx, funding_account_address).await;
+    let swap_order_token_account =
+        utils::get_token_account(program_test_ctx, swap_order_token_account_pda).await;
+
+    assert_eq!(
+        owner_funding_account_before.amount - owner_funding_account_after.amount,
+        params.amount_in
+    );
+    assert_eq!(swap_order_token_account.amount, params.amount_in);
+
+    // Check the order
+    {
+        let swap_order = utils::get_account::<SwapOrder>(program_test_ctx, swap_order_pda).await;
+
+        assert_eq!(swap_order.owner, owner.pubkey());
+        assert_eq!(swap_order.pool, *pool_pda);
+        assert_eq!(swap_order.receiving_custody, receiving_custody_pda);
+        assert_eq!(swap_order.dispensing_custody, dispensing_custody_pda);
+        assert_eq!(swap_order.order_id, params.order_id);
+        assert_eq!(swap_order.amount_in, params.amount_in);
+        assert_eq!(swap_order.limit_price, params.limit_price);
+        assert_eq!(swap_order.expiry_time, params.expiry_time);
+        assert_eq!(swap_order.bump, swap_order_bump);
+        assert_eq!(swap_order.token_account_bump, swap_order_token_account_bump);
+    }
+
+    Ok((swap_order_pda, swap_order_bump))
+}
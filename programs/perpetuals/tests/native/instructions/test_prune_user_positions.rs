@@ -0,0 +1,55 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, solana_program::instruction::AccountMeta, ToAccountMetas},
+    perpetuals::{instructions::PruneUserPositionsParams, state::user_positions::UserPositions},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_prune_user_positions(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    position_pdas: &[Pubkey],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let user_positions_pda = pda::get_user_positions_pda(&owner.pubkey()).0;
+
+    let mut accounts_meta = perpetuals::accounts::PruneUserPositions {
+        owner: owner.pubkey(),
+        user_positions: user_positions_pda,
+    }
+    .to_account_metas(None);
+
+    for position_pda in position_pdas {
+        accounts_meta.push(AccountMeta {
+            pubkey: *position_pda,
+            is_signer: false,
+            is_writable: false,
+        });
+    }
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::PruneUserPositions {
+            params: PruneUserPositionsParams {},
+        },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let user_positions_account =
+        utils::get_account::<UserPositions>(program_test_ctx, user_positions_pda).await;
+
+    for position_pda in position_pdas {
+        assert!(!user_positions_account.positions.contains(position_pda));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::RemoveCustodyParams,
+        state::{multisig::Multisig, pool::Pool},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_remove_custody(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    admin: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: RemoveCustodyParams,
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::RemoveCustody {
+                admin: admin.pubkey(),
+                multisig: multisig_pda,
+                transfer_authority: transfer_authority_pda,
+                perpetuals: perpetuals_pda,
+                pool: *pool_pda,
+                custody: custody_pda,
+                custody_token_account: custody_token_account_pda,
+                system_program: anchor_lang::system_program::ID,
+                token_program: anchor_spl::token::ID,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            accounts_meta.push(AccountMeta {
+                pubkey: signer.pubkey(),
+                is_signer: true,
+                is_writable: false,
+            });
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::RemoveCustody {
+                params: params.clone(),
+            },
+            Some(&payer.pubkey()),
+            &[admin, payer, signer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+
+    assert!(!pool_account.custodies.contains(&custody_pda));
+    assert_eq!(pool_account.ratios, params.ratios);
+    assert!(pool_account.aum_cache.len() <= pool_account.custodies.len());
+
+    {
+        let mut ctx = program_test_ctx.write().await;
+        let banks_client = &mut ctx.banks_client;
+
+        assert!(banks_client
+            .get_account(custody_pda)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(banks_client
+            .get_account(custody_token_account_pda)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use {
+    super::get_margin_account_remaining_accounts,
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::RemoveMarginParams, state::margin_account::MarginAccount},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_remove_margin(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: RemoveMarginParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let margin_account_pda = pda::get_margin_account_pda(&owner.pubkey(), pool_pda).0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+    let collateral_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(&owner.pubkey(), collateral_custody_token_mint).0;
+
+    let amount = params.amount;
+
+    // Save account state before tx execution
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+    let margin_account_before =
+        utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+    let mut accounts_meta = perpetuals::accounts::RemoveMargin {
+        owner: owner.pubkey(),
+        receiving_account: receiving_account_address,
+        transfer_authority: transfer_authority_pda,
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        margin_account: margin_account_pda,
+        collateral_custody: collateral_custody_pda,
+        collateral_custody_token_account: collateral_custody_token_account_pda,
+        token_program: anchor_spl::token::ID,
+    }
+    .to_account_metas(None);
+
+    accounts_meta.extend(
+        get_margin_account_remaining_accounts(program_test_ctx, &margin_account_pda, false).await,
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::RemoveMargin { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the balance change
+    {
+        let owner_receiving_account_after =
+            utils::get_token_account(program_test_ctx, receiving_account_address).await;
+        let margin_account_after =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+        assert_eq!(
+            owner_receiving_account_after.amount,
+            owner_receiving_account_before.amount + amount
+        );
+        assert_eq!(
+            margin_account_after.collateral_amount,
+            margin_account_before.collateral_amount - amount
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use {
+    crate::utils::pda,
+    anchor_lang::{prelude::Pubkey, AnchorSerialize, ToAccountMetas},
+    perpetuals::instructions::{OraclePriceMessage, SetCustomOraclePricePermissionlessParams},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::{
+        ed25519_program,
+        instruction::Instruction,
+        signer::{keypair::Keypair, Signer},
+        sysvar,
+    },
+    tokio::sync::RwLock,
+};
+
+// Signed message envelope for the custody, as an oracle authority would produce it
+pub fn get_oracle_price_message(
+    pool_pda: &Pubkey,
+    domain: u64,
+    params: SetCustomOraclePricePermissionlessParams,
+) -> OraclePriceMessage {
+    OraclePriceMessage {
+        prefix: OraclePriceMessage::PREFIX,
+        version: OraclePriceMessage::VERSION,
+        program_id: perpetuals::id(),
+        domain,
+        pool: *pool_pda,
+        custody: params.custody_account,
+        params,
+    }
+}
+
+pub async fn test_set_custom_oracle_price_permissionless(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: SetCustomOraclePricePermissionlessParams,
+    signed_messages: &[(&Keypair, OraclePriceMessage)],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let oracle_pda = pda::get_custom_oracle_account(pool_pda, custody_token_mint).0;
+
+    let accounts_meta = perpetuals::accounts::SetCustomOraclePricePermissionless {
+        perpetuals: perpetuals_pda,
+        pool: *pool_pda,
+        custody: custody_pda,
+        oracle_account: oracle_pda,
+        ix_sysvar: sysvar::instructions::ID,
+    }
+    .to_account_metas(None);
+
+    let messages: Vec<(&Keypair, Vec<u8>)> = signed_messages
+        .iter()
+        .map(|(signer, message)| (*signer, message.try_to_vec().unwrap()))
+        .collect();
+
+    crate::utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::SetCustomOraclePricePermissionless { params },
+        Some(&payer.pubkey()),
+        &[payer],
+        Some(get_ed25519_ix(&messages)),
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(())
+}
+
+// Ed25519Program verification instruction with every signature, public key and message
+// stored in the instruction itself
+fn get_ed25519_ix(messages: &[(&Keypair, Vec<u8>)]) -> Instruction {
+    const OFFSETS_START: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+    let mut offsets: Vec<u8> = Vec::new();
+    let mut payload: Vec<u8> = Vec::new();
+    let payload_start = OFFSETS_START + messages.len() * OFFSETS_LEN;
+
+    for (signer, message) in messages {
+        let pubkey_offset = payload_start + payload.len();
+        payload.extend_from_slice(signer.pubkey().as_ref());
+        let signature_offset = payload_start + payload.len();
+        payload.extend_from_slice(signer.sign_message(message).as_ref());
+        let message_offset = payload_start + payload.len();
+        payload.extend_from_slice(message);
+
+        for value in [
+            signature_offset as u16,
+            CURRENT_INSTRUCTION,
+            pubkey_offset as u16,
+            CURRENT_INSTRUCTION,
+            message_offset as u16,
+            message.len() as u16,
+            CURRENT_INSTRUCTION,
+        ] {
+            offsets.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    let mut data = vec![messages.len() as u8, 0];
+    data.extend(offsets);
+    data.extend(payload);
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
@@ -0,0 +1,47 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::SetDelegateParams, state::delegate::Delegate},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_set_delegate(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    delegate_authority: &Pubkey,
+    payer: &Keypair,
+    params: SetDelegateParams,
+) -> std::result::Result<Pubkey, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let (delegate_pda, delegate_bump) = pda::get_delegate_pda(&owner.pubkey(), delegate_authority);
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::SetDelegate {
+            owner: owner.pubkey(),
+            delegate_authority: *delegate_authority,
+            perpetuals: perpetuals_pda,
+            delegate: delegate_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::SetDelegate { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let delegate_account = utils::get_account::<Delegate>(program_test_ctx, delegate_pda).await;
+
+    assert_eq!(delegate_account.owner, owner.pubkey());
+    assert_eq!(delegate_account.delegate, *delegate_authority);
+    assert_eq!(delegate_account.bump, delegate_bump);
+
+    Ok(delegate_pda)
+}
@@ -0,0 +1,85 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::SetPoolConfigParams,
+        state::{multisig::Multisig, pool::Pool},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_set_pool_config(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    admin: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    params: SetPoolConfigParams,
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+    let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::SetPoolConfig {
+                admin: admin.pubkey(),
+                multisig: multisig_pda,
+                perpetuals: perpetuals_pda,
+                pool: *pool_pda,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            // Pool custodies, checked against the new cache age
+            for custody in &pool_account.custodies {
+                accounts_meta.push(AccountMeta {
+                    pubkey: *custody,
+                    is_signer: false,
+                    is_writable: false,
+                });
+            }
+
+            accounts_meta.push(AccountMeta {
+                pubkey: signer.pubkey(),
+                is_signer: true,
+                is_writable: false,
+            });
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::SetPoolConfig {
+                params: params.clone(),
+            },
+            Some(&payer.pubkey()),
+            &[admin, payer, signer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+
+    assert_eq!(
+        pool_account.aum_cache_max_age_sec,
+        params.aum_cache_max_age_sec
+    );
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use {
+    super::get_update_pool_ix,
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::SettlePositionParams,
+        state::{custody::Custody, margin_account::MarginAccount, position::Position},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_settle_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    signer: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    params: SettlePositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+
+    let position_account = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let owner = position_account.owner;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(&owner, custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let custody_oracle_account_address = custody_account.oracle.oracle_account;
+
+    // Margin positions also need the margin account and its deposit custody
+    let mut margin_account_pda = None;
+    let mut deposit_custody = None;
+    let mut deposit_custody_oracle_account = None;
+    let mut deposit_custody_token_account = None;
+    let mut deposit_receiving_account = None;
+
+    if let Some(margin_account_key) = position_account.margin_account {
+        let margin_account =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_key).await;
+        let deposit_custody_account =
+            utils::get_account::<Custody>(program_test_ctx, margin_account.collateral_custody)
+                .await;
+
+        // the deposit custody is only passed on its own when it isn't the position custody
+        if margin_account.collateral_custody != custody_pda {
+            deposit_custody = Some(margin_account.collateral_custody);
+            deposit_custody_oracle_account = Some(deposit_custody_account.oracle.oracle_account);
+        }
+        deposit_custody_token_account = Some(deposit_custody_account.token_account);
+        deposit_receiving_account =
+            Some(utils::find_associated_token_account(&owner, &deposit_custody_account.mint).0);
+        margin_account_pda = Some(margin_account_key);
+    }
+
+    // Save account state before tx execution
+    let owner_receiving_account_before =
+        utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::SettlePosition {
+            signer: signer.pubkey(),
+            owner,
+            receiving_account: receiving_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            position: *position_pda,
+            custody: custody_pda,
+            custody_oracle_account: custody_oracle_account_address,
+            custody_twap_account: None, // TODO: add twap account
+            collateral_custody: custody_pda,
+            collateral_custody_oracle_account: custody_oracle_account_address,
+            collateral_custody_twap_account: None, // TODO: add twap account
+            user_positions: utils::get_user_positions_account(program_test_ctx, &owner).await,
+            collateral_custody_token_account: custody_token_account_pda,
+            margin_account: margin_account_pda,
+            deposit_custody,
+            deposit_custody_oracle_account,
+            deposit_custody_token_account,
+            deposit_receiving_account,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::SettlePosition { params },
+        Some(&payer.pubkey()),
+        &[signer, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Check the owner got paid out and the position is closed
+    {
+        let owner_receiving_account_after =
+            utils::get_token_account(program_test_ctx, receiving_account_address).await;
+
+        assert!(owner_receiving_account_after.amount > owner_receiving_account_before.amount);
+
+        let position_account = program_test_ctx
+            .write()
+            .await
+            .banks_client
+            .get_account(*position_pda)
+            .await
+            .unwrap();
+
+        assert!(position_account.is_none());
+    }
+
+    // Check the settled position left its margin account
+    if let Some(margin_account_pda) = margin_account_pda {
+        let margin_account =
+            utils::get_account::<MarginAccount>(program_test_ctx, margin_account_pda).await;
+
+        assert!(!margin_account.positions.contains(position_pda));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::SettleReferralRebateParams,
+        state::{custody::Custody, referral::Referral},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_settle_referral_rebate(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    signer: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    referral_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: SettleReferralRebateParams,
+) -> std::result::Result<u64, BanksClientError> {
+    // ==== WHEN ==============================================================
+    // Prepare PDA and addresses
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+
+    // Save account state before tx execution
+    let referral_account_before =
+        utils::get_account::<Referral>(program_test_ctx, *referral_pda).await;
+    let custody_account_before = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+
+    let receiving_account_address =
+        utils::find_associated_token_account(&referral_account_before.owner, custody_token_mint).0;
+    let receiving_account_before =
+        utils::get_token_account_balance(program_test_ctx, receiving_account_address).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::SettleReferralRebate {
+            signer: signer.pubkey(),
+            receiving_account: receiving_account_address,
+            transfer_authority: transfer_authority_pda,
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            referral: *referral_pda,
+            custody: custody_pda,
+            custody_token_account: custody_token_account_pda,
+            token_program: anchor_spl::token::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::SettleReferralRebate { params },
+        Some(&payer.pubkey()),
+        &[signer, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let rebate = referral_account_before
+        .rebates
+        .iter()
+        .find(|rebate| rebate.custody == custody_pda)
+        .unwrap()
+        .amount;
+
+    let referral_account_after =
+        utils::get_account::<Referral>(program_test_ctx, *referral_pda).await;
+    let custody_account_after = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let receiving_account_after =
+        utils::get_token_account_balance(program_test_ctx, receiving_account_address).await;
+
+    assert_eq!(receiving_account_after - receiving_account_before, rebate);
+    assert_eq!(
+        custody_account_before.assets.referral_rebates
+            - custody_account_after.assets.referral_rebates,
+        rebate
+    );
+    assert!(!referral_account_after
+        .rebates
+        .iter()
+        .any(|rebate| rebate.custody == custody_pda));
+
+    Ok(rebate)
+}
@@ -20,6 +20,34 @@ pub async fn test_swap(
     // Mint sent by the User
     receiving_custody_token_mint: &Pubkey,
     params: SwapParams,
+) -> std::result::Result<(), BanksClientError> {
+    test_swap_with_referral(
+        program_test_ctx,
+        owner,
+        payer,
+        pool_pda,
+        dispensing_custody_token_mint,
+        receiving_custody_token_mint,
+        None,
+        None,
+        params,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn test_swap_with_referral(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    // Mint received by the User
+    dispensing_custody_token_mint: &Pubkey,
+    // Mint sent by the User
+    receiving_custody_token_mint: &Pubkey,
+    referral_pda: Option<&Pubkey>,
+    trader_stats_pda: Option<&Pubkey>,
+    params: SwapParams,
 ) -> std::result::Result<(), BanksClientError> {
     // ==== WHEN ==============================================================
     // Prepare PDA and addresses
@@ -69,7 +97,15 @@ pub async fn test_swap(
             dispensing_custody_token_account: dispensing_custody_token_account_pda,
             token_program: anchor_spl::token::ID,
             receiving_custody_twap_account: None, // TODO: add twap account
+            receiving_custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &receiving_custody_account.oracle,
+            ),
             dispensing_custody_twap_account: None, // TODO: add twap account
+            dispensing_custody_reference_oracle_account: utils::get_reference_oracle_account(
+                &dispensing_custody_account.oracle,
+            ),
+            referral: referral_pda.copied(),
+            trader_stats: trader_stats_pda.copied(),
         }
         .to_account_metas(None),
         perpetuals::instruction::Swap { params },
@@ -0,0 +1,106 @@
+use {
+    super::get_update_pool_ix,
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::SwapPositionCollateralParams,
+        state::{custody::Custody, position::Position},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_swap_position_collateral(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+    new_collateral_custody_token_mint: &Pubkey,
+    params: SwapPositionCollateralParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let new_collateral_custody_pda =
+        pda::get_custody_pda(pool_pda, new_collateral_custody_token_mint).0;
+
+    let min_amount_out = params.min_amount_out;
+
+    let position_before = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+
+    let custody_account =
+        utils::get_account::<Custody>(program_test_ctx, position_before.custody).await;
+    let collateral_custody_before =
+        utils::get_account::<Custody>(program_test_ctx, position_before.collateral_custody).await;
+    let new_collateral_custody_before =
+        utils::get_account::<Custody>(program_test_ctx, new_collateral_custody_pda).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::SwapPositionCollateral {
+            owner: owner.pubkey(),
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            position: *position_pda,
+            custody: position_before.custody,
+            custody_oracle_account: custody_account.oracle.oracle_account,
+            custody_twap_account: None, // TODO: add twap account
+            collateral_custody: position_before.collateral_custody,
+            collateral_custody_oracle_account: collateral_custody_before.oracle.oracle_account,
+            collateral_custody_twap_account: None, // TODO: add twap account
+            new_collateral_custody: new_collateral_custody_pda,
+            new_collateral_custody_oracle_account: new_collateral_custody_before
+                .oracle
+                .oracle_account,
+            new_collateral_custody_twap_account: None, // TODO: add twap account
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::SwapPositionCollateral { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let position_after = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let collateral_custody_after =
+        utils::get_account::<Custody>(program_test_ctx, position_before.collateral_custody).await;
+    let new_collateral_custody_after =
+        utils::get_account::<Custody>(program_test_ctx, new_collateral_custody_pda).await;
+
+    // Check the position, only its collateral changes
+    assert_eq!(
+        position_after.collateral_custody,
+        new_collateral_custody_pda
+    );
+    assert_eq!(position_after.price, position_before.price);
+    assert_eq!(position_after.size_usd, position_before.size_usd);
+    assert!(position_after.collateral_amount >= min_amount_out);
+    assert!(position_after.locked_amount > 0);
+
+    // Check collateral and locked funds moved between the custodies
+    assert_eq!(
+        collateral_custody_before.assets.collateral - collateral_custody_after.assets.collateral,
+        position_before.collateral_amount
+    );
+    assert_eq!(
+        new_collateral_custody_after.assets.collateral
+            - new_collateral_custody_before.assets.collateral,
+        position_after.collateral_amount
+    );
+    assert_eq!(
+        collateral_custody_before.assets.locked - collateral_custody_after.assets.locked,
+        position_before.locked_amount
+    );
+    assert_eq!(
+        new_collateral_custody_after.assets.locked - new_collateral_custody_before.assets.locked,
+        position_after.locked_amount
+    );
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::TransferPositionParams, state::position::Position},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_transfer_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    new_owner: &Pubkey,
+    payer: &Keypair,
+    position_pda: &Pubkey,
+    params: TransferPositionParams,
+) -> std::result::Result<Pubkey, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    let position_before = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let (new_position_pda, new_position_bump) = pda::get_position_pda(
+        new_owner,
+        &position_before.pool,
+        &position_before.custody,
+        position_before.side,
+        position_before.position_id,
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::TransferPosition {
+            owner: owner.pubkey(),
+            new_owner: *new_owner,
+            perpetuals: perpetuals_pda,
+            pool: position_before.pool,
+            position: *position_pda,
+            new_position: new_position_pda,
+            custody: position_before.custody,
+            user_positions: utils::get_user_positions_account(program_test_ctx, &owner.pubkey())
+                .await,
+            new_owner_user_positions: utils::get_user_positions_account(
+                program_test_ctx,
+                new_owner,
+            )
+            .await,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::TransferPosition { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let new_position = utils::get_account::<Position>(program_test_ctx, new_position_pda).await;
+
+    assert_eq!(new_position.owner, *new_owner);
+    assert_eq!(new_position.side, position_before.side);
+    assert_eq!(new_position.price, position_before.price);
+    assert_eq!(new_position.size_usd, position_before.size_usd);
+    assert_eq!(new_position.collateral_usd, position_before.collateral_usd);
+    assert_eq!(new_position.locked_amount, position_before.locked_amount);
+    assert_eq!(
+        new_position.collateral_amount,
+        position_before.collateral_amount
+    );
+    assert_eq!(new_position.position_id, position_before.position_id);
+    assert_eq!(new_position.bump, new_position_bump);
+
+    // Check the previous position account is closed
+    let position_account = program_test_ctx
+        .write()
+        .await
+        .banks_client
+        .get_account(*position_pda)
+        .await
+        .unwrap();
+
+    assert!(position_account.is_none());
+
+    Ok(new_position_pda)
+}
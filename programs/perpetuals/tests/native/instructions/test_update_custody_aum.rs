@@ -0,0 +1,61 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::state::{custody::Custody, pool::Pool},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_update_custody_aum(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+
+    // Prepare PDA and addresses
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, *custody_pda).await;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::UpdateCustodyAum {
+            payer: payer.pubkey(),
+            perpetuals: perpetuals_pda,
+            pool: *pool_pda,
+            custody: *custody_pda,
+            custody_oracle_account: custody_account.oracle.oracle_account,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::UpdateCustodyAum {},
+        Some(&payer.pubkey()),
+        &[payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+    let token_id = pool_account.get_token_id(custody_pda).unwrap();
+
+    // Check the custody valuation has been refreshed and the pool total matches the cache
+    {
+        let current_time = utils::get_current_unix_timestamp(program_test_ctx).await;
+
+        assert_eq!(pool_account.aum_cache[token_id].update_time, current_time);
+        assert_eq!(
+            pool_account.aum_usd,
+            pool_account
+                .aum_cache
+                .iter()
+                .map(|cache| cache.get_aum_usd().unwrap())
+                .sum::<u128>()
+        );
+    }
+
+    Ok(())
+}
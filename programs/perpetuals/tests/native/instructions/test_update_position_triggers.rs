@@ -0,0 +1,51 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{instructions::UpdatePositionTriggersParams, state::position::Position},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_update_position_triggers(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    signer: &Keypair,
+    payer: &Keypair,
+    position_pda: &Pubkey,
+    delegate_pda: Option<Pubkey>,
+    params: UpdatePositionTriggersParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let position_before = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let take_profit_price = params.take_profit_price;
+    let stop_loss_price = params.stop_loss_price;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::UpdatePositionTriggers {
+            signer: signer.pubkey(),
+            owner: position_before.owner,
+            perpetuals: perpetuals_pda,
+            pool: position_before.pool,
+            position: *position_pda,
+            delegate: delegate_pda,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::UpdatePositionTriggers { params },
+        Some(&payer.pubkey()),
+        &[signer, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let position = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+
+    assert_eq!(position.take_profit_price, take_profit_price);
+    assert_eq!(position.stop_loss_price, stop_loss_price);
+    assert_eq!(position.size_usd, position_before.size_usd);
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use {
+    crate::utils::{self, pda},
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::WithdrawFeesParams,
+        state::{custody::Custody, multisig::Multisig},
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+#[allow(clippy::too_many_arguments)]
+pub async fn test_withdraw_fees(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    admin: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    receiving_token_account: &Pubkey,
+    params: WithdrawFeesParams,
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let transfer_authority_pda = pda::get_transfer_authority_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // Save account state before tx execution
+    let custody_account_before = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let receiving_account_before =
+        utils::get_token_account_balance(program_test_ctx, *receiving_token_account).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::WithdrawFees {
+                admin: admin.pubkey(),
+                multisig: multisig_pda,
+                transfer_authority: transfer_authority_pda,
+                perpetuals: perpetuals_pda,
+                pool: *pool_pda,
+                custody: custody_pda,
+                custody_token_account: custody_token_account_pda,
+                receiving_token_account: *receiving_token_account,
+                token_program: anchor_spl::token::ID,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            accounts_meta.push(AccountMeta {
+                pubkey: signer.pubkey(),
+                is_signer: true,
+                is_writable: false,
+            });
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::WithdrawFees {
+                params: params.clone(),
+            },
+            Some(&payer.pubkey()),
+            &[admin, payer, signer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    let custody_account_after = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let receiving_account_after =
+        utils::get_token_account_balance(program_test_ctx, *receiving_token_account).await;
+
+    assert_eq!(
+        custody_account_before.assets.protocol_fees - custody_account_after.assets.protocol_fees,
+        params.amount
+    );
+    assert_eq!(
+        receiving_account_after - receiving_account_before,
+        params.amount
+    );
+
+    Ok(())
+}
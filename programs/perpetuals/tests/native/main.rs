@@ -4,10 +4,19 @@ pub mod utils;
 
 use tests_suite::{
     basic_interactions::basic_interactions,
-    liquidity::{fixed_fees, insuffisient_fund as liquidity_insuffisient_fund, min_max_ratio},
+    liquidity::{
+        aum_cache, fixed_fees, insuffisient_fund as liquidity_insuffisient_fund, min_max_ratio,
+    },
     lp_token::lp_token_price,
-    position::{liquidate_position, max_user_profit, min_max_leverage},
-    swap::insuffisient_fund as swap_insuffisient_fund,
+    oracle::{permissionless_quorum, pyth_price_update, reference_band},
+    position::{
+        close_margin_position, cross_margin, delegate, dynamic_spread, liquidate_batch,
+        liquidate_position, max_user_profit, min_max_leverage, multiple_positions,
+        open_close_with_swap, open_interest_limits, position_info, remove_collateral_amount,
+        stablecoin_depeg, swap_position_collateral, transfer_position, user_positions, wind_down,
+        withdraw_profit,
+    },
+    swap::{fee_tiers, insuffisient_fund as swap_insuffisient_fund, referral, swap_order},
 };
 
 #[tokio::test]
@@ -15,14 +24,38 @@ pub async fn test_integration() {
     basic_interactions().await;
 
     swap_insuffisient_fund().await;
+    swap_order().await;
+    referral().await;
+    fee_tiers().await;
 
     fixed_fees().await;
+    aum_cache().await;
     liquidity_insuffisient_fund().await;
     min_max_ratio().await;
 
     min_max_leverage().await;
     liquidate_position().await;
+    liquidate_batch().await;
     max_user_profit().await;
+    open_close_with_swap().await;
+    cross_margin().await;
+    close_margin_position().await;
+    open_interest_limits().await;
+    dynamic_spread().await;
+    withdraw_profit().await;
+    position_info().await;
+    remove_collateral_amount().await;
+    transfer_position().await;
+    delegate().await;
+    multiple_positions().await;
+    user_positions().await;
+    wind_down().await;
+    stablecoin_depeg().await;
+    swap_position_collateral().await;
 
     lp_token_price().await;
+
+    pyth_price_update().await;
+    permissionless_quorum().await;
+    reference_band().await;
 }
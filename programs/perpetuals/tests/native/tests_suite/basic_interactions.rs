@@ -110,10 +110,12 @@ pub async fn basic_interactions() {
                 // max price paid (slippage implied)
                 price: utils::scale(1_550, USDC_DECIMALS),
                 collateral: utils::scale_f64(0.1, ETH_DECIMALS),
+                min_amount_out: 0,
                 size: utils::scale_f64(0.1, ETH_DECIMALS),
                 side: Side::Long,
                 take_profit_price: None,
                 stop_loss_price: None,
+                position_id: 0,
                 // feed_id: [0; 32], // TODO: add feed id
             },
         )
@@ -132,6 +134,7 @@ pub async fn basic_interactions() {
             ClosePositionParams {
                 // lowest exit price paid (slippage implied)
                 price: utils::scale(1_450, USDC_DECIMALS),
+                min_amount_out: 0,
                 // feed_id: [0; 32], // TODO: add feed id
             },
         )
@@ -0,0 +1,330 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{AddLiquidityParams, SetPoolConfigParams, SwapParams},
+        state::{custody::Custody, pool::Pool},
+    },
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn aum_cache() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(2_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(0, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Anyone: Crank every custody valuation
+    for custody_info in &test_setup.custodies_info {
+        instructions::test_update_custody_aum(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &custody_info.custody_pda,
+        )
+        .await
+        .unwrap();
+    }
+
+    let pool_before =
+        utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+
+    // Check the cache covers the whole pool: USDC at 1 and 10,000 ETH at 1,500
+    {
+        let usdc_custody = utils::get_account::<Custody>(
+            &test_setup.program_test_ctx,
+            test_setup.custodies_info[0].custody_pda,
+        )
+        .await;
+
+        assert_eq!(pool_before.aum_cache.len(), 2);
+        assert_eq!(
+            pool_before.aum_cache[0].owned_usd,
+            usdc_custody.assets.owned
+        );
+        assert_eq!(
+            pool_before.aum_cache[1].owned_usd,
+            utils::scale(15_000_000, USDC_DECIMALS)
+        );
+        assert_eq!(
+            pool_before.aum_cache[1].price,
+            utils::scale(1_500, USDC_DECIMALS)
+        );
+        assert_eq!(
+            pool_before.aum_usd,
+            (pool_before.aum_cache[0].owned_usd + pool_before.aum_cache[1].owned_usd) as u128
+        );
+
+        // The breakdown is served from the cache without remaining accounts
+        let aum_breakdown = instructions::test_get_aum_breakdown(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(aum_breakdown.aum_usd, pool_before.aum_usd);
+        assert_eq!(aum_breakdown.custodies, pool_before.aum_cache);
+        assert!(aum_breakdown.lp_token_price > 0);
+    }
+
+    // Martin: Swap between the crank and the next deposit
+    instructions::test_swap(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        SwapParams {
+            amount_in: utils::scale(300, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check the swap re-priced both custodies so that LP tokens are priced against
+    // the balances after the swap
+    let pool_before = {
+        let pool_account =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+        let usdc_custody = utils::get_account::<Custody>(
+            &test_setup.program_test_ctx,
+            test_setup.custodies_info[0].custody_pda,
+        )
+        .await;
+        let eth_custody = utils::get_account::<Custody>(
+            &test_setup.program_test_ctx,
+            test_setup.custodies_info[1].custody_pda,
+        )
+        .await;
+
+        assert_eq!(
+            pool_account.aum_cache[0].owned_usd,
+            usdc_custody.assets.owned
+        );
+        assert_eq!(
+            pool_account.aum_cache[1].owned_usd,
+            (eth_custody.assets.owned as u128 * 1_500 / 1_000) as u64
+        );
+        assert!(eth_custody.assets.owned < utils::scale(10_000, ETH_DECIMALS));
+
+        // The cached value matches a full re-pricing of the pool
+        instructions::test_update_pool_aum(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+        )
+        .await
+        .unwrap();
+
+        let repriced_pool =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+
+        assert_eq!(pool_account.aum_usd, repriced_pool.aum_usd);
+
+        repriced_pool
+    };
+
+    // Alice: Add liquidity while the cache is fresh
+    instructions::test_add_liquidity(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        AddLiquidityParams {
+            amount_in: utils::scale(500, USDC_DECIMALS),
+            min_lp_amount_out: 1,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check the deposit has been added to the cached valuation without re-pricing other custodies
+    {
+        let pool_after =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+        let usdc_custody = utils::get_account::<Custody>(
+            &test_setup.program_test_ctx,
+            test_setup.custodies_info[0].custody_pda,
+        )
+        .await;
+
+        assert!(pool_after.aum_cache[0].owned_usd > pool_before.aum_cache[0].owned_usd);
+        assert!(
+            pool_after.aum_cache[0].owned_usd
+                <= pool_before.aum_cache[0].owned_usd + utils::scale(500, USDC_DECIMALS)
+        );
+        assert_eq!(pool_after.aum_cache[0].owned_usd, usdc_custody.assets.owned);
+        assert_eq!(pool_after.aum_cache[1], pool_before.aum_cache[1]);
+        assert_eq!(
+            pool_after.aum_usd,
+            (pool_after.aum_cache[0].owned_usd + pool_after.aum_cache[1].owned_usd) as u128
+        );
+    }
+
+    // Check the cache can't outlive the custody prices, 30 seconds in the fixtures
+    {
+        let pool_account =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+
+        assert_eq!(pool_account.aum_cache_max_age_sec, 30);
+    }
+
+    // Admin: A cache age above the custody oracles' max price age should fail
+    assert!(instructions::test_set_pool_config(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        SetPoolConfigParams {
+            aum_cache_max_age_sec: 31,
+        },
+        &multisig_signers,
+    )
+    .await
+    .is_err());
+
+    // Admin: Shorten the cache age to 10 seconds
+    instructions::test_set_pool_config(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        SetPoolConfigParams {
+            aum_cache_max_age_sec: 10,
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    utils::warp_forward(&test_setup.program_test_ctx, 11).await;
+
+    // A stale cache is rejected when custodies are not provided for re-pricing
+    assert!(instructions::test_get_aum_breakdown(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        true,
+    )
+    .await
+    .is_err());
+
+    // Alice: Once the cache is stale the pool is re-priced from remaining accounts
+    instructions::test_add_liquidity(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        AddLiquidityParams {
+            amount_in: utils::scale(500, USDC_DECIMALS),
+            min_lp_amount_out: 1,
+        },
+    )
+    .await
+    .unwrap();
+
+    {
+        let pool_account =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+        let usdc_custody = utils::get_account::<Custody>(
+            &test_setup.program_test_ctx,
+            test_setup.custodies_info[0].custody_pda,
+        )
+        .await;
+
+        assert_eq!(
+            pool_account.aum_usd,
+            utils::scale(15_000_000, USDC_DECIMALS) as u128 + usdc_custody.assets.owned as u128
+        );
+
+        // Re-pricing refreshes the cache
+        let current_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        assert_eq!(pool_account.aum_cache[0].update_time, current_time);
+        assert_eq!(pool_account.aum_cache[1].update_time, current_time);
+    }
+}
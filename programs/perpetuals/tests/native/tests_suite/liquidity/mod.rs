@@ -1,5 +1,6 @@
+pub mod aum_cache;
 pub mod fixed_fees;
 pub mod insuffisient_fund;
 pub mod min_max_ratio;
 
-pub use {fixed_fees::*, insuffisient_fund::*, min_max_ratio::*};
+pub use {aum_cache::*, fixed_fees::*, insuffisient_fund::*, min_max_ratio::*};
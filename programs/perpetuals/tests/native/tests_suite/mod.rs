@@ -1,5 +1,6 @@
 pub mod basic_interactions;
 pub mod liquidity;
 pub mod lp_token;
+pub mod oracle;
 pub mod position;
 pub mod swap;
@@ -0,0 +1,5 @@
+pub mod permissionless_quorum;
+pub mod pyth_price_update;
+pub mod reference_band;
+
+pub use {permissionless_quorum::*, pyth_price_update::*, reference_band::*};
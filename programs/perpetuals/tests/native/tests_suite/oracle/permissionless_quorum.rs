@@ -0,0 +1,244 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::SetCustomOraclePricePermissionlessParams,
+        state::{
+            custody::Custody,
+            oracle::{CustomOracle, OracleParams},
+        },
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signer::{keypair::Keypair, Signer},
+    },
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+const MESSAGE_DOMAIN: u64 = 7;
+
+pub async fn permissionless_quorum() {
+    let test_setup = utils::TestSetup::new(
+        vec![utils::UserParam {
+            name: "alice",
+            token_balances: hashmap! {
+                "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                "eth" => utils::scale(5, ETH_DECIMALS),
+            },
+        }],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+    let eth_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+
+    let authority_a = Keypair::new();
+    let authority_b = Keypair::new();
+    let forger = Keypair::new();
+
+    // Any 2 of the 3 oracle authorities, quotes at most 1% apart
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        utils::set_custody_oracle(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &eth_custody_pda,
+            OracleParams {
+                oracle_authority: authority_a.pubkey(),
+                extra_oracle_authorities: [
+                    authority_b.pubkey(),
+                    Pubkey::new_unique(),
+                    Pubkey::default(),
+                    Pubkey::default(),
+                ],
+                min_oracle_signatures: 2,
+                max_quote_spread: 100,
+                message_domain: MESSAGE_DOMAIN,
+                ..eth_custody.oracle
+            },
+            &multisig_signers,
+        )
+        .await;
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+    let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+    let quote = |price: u64| SetCustomOraclePricePermissionlessParams {
+        custody_account: eth_custody_pda,
+        price: utils::scale(price, ETH_DECIMALS),
+        expo: -(ETH_DECIMALS as i32),
+        conf: utils::scale(10, ETH_DECIMALS),
+        ema: utils::scale(price, ETH_DECIMALS),
+        publish_time,
+    };
+    let message = |price: u64| {
+        instructions::get_oracle_price_message(&test_setup.pool_pda, MESSAGE_DOMAIN, quote(price))
+    };
+
+    // A single authority doesn't reach the quorum
+    assert!(instructions::test_set_custom_oracle_price_permissionless(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        quote(1_600),
+        &[(&authority_a, message(1_600))],
+    )
+    .await
+    .is_err());
+
+    // Nor does the same authority signing twice
+    assert!(instructions::test_set_custom_oracle_price_permissionless(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        quote(1_600),
+        &[
+            (&authority_a, message(1_600)),
+            (&authority_a, message(1_601))
+        ],
+    )
+    .await
+    .is_err());
+
+    // A quote signed by an unknown key is rejected
+    assert!(instructions::test_set_custom_oracle_price_permissionless(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        quote(1_600),
+        &[(&authority_a, message(1_600)), (&forger, message(1_600))],
+    )
+    .await
+    .is_err());
+
+    // Quotes signed for another deployment don't replay here
+    assert!(instructions::test_set_custom_oracle_price_permissionless(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        quote(1_500),
+        &[
+            (
+                &authority_a,
+                instructions::get_oracle_price_message(
+                    &test_setup.pool_pda,
+                    MESSAGE_DOMAIN + 1,
+                    quote(1_500)
+                )
+            ),
+            (
+                &authority_b,
+                instructions::get_oracle_price_message(
+                    &test_setup.pool_pda,
+                    MESSAGE_DOMAIN + 1,
+                    quote(1_500)
+                )
+            ),
+        ],
+    )
+    .await
+    .is_err());
+
+    // A leaked key can't pull the median away from an honest quote
+    assert!(instructions::test_set_custom_oracle_price_permissionless(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        quote(1_500),
+        &[
+            (&authority_a, message(1_500)),
+            (&authority_b, message(1_600))
+        ],
+    )
+    .await
+    .is_err());
+
+    // Two authorities agreeing within the spread set the median
+    instructions::test_set_custom_oracle_price_permissionless(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        quote(1_500),
+        &[
+            (&authority_a, message(1_500)),
+            (&authority_b, message(1_510)),
+        ],
+    )
+    .await
+    .unwrap();
+
+    {
+        let eth_oracle =
+            utils::get_account::<CustomOracle>(&test_setup.program_test_ctx, eth_oracle_pda).await;
+
+        assert_eq!(eth_oracle.price, utils::scale(1_505, ETH_DECIMALS));
+        assert_eq!(eth_oracle.publish_time, publish_time);
+    }
+}
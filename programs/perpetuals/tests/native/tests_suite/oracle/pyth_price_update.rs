@@ -0,0 +1,281 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::SwapParams,
+        state::{
+            custody::{Custody, PricingParams},
+            oracle::{OracleParams, OracleType},
+            pool::Pool,
+        },
+    },
+    solana_sdk::pubkey::Pubkey,
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+const ETH_FEED_ID: [u8; 32] = [1; 32];
+
+pub async fn pyth_price_update() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(7_500, USDC_DECIMALS),
+                    "eth" => utils::scale(5, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(7_500, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(5, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+    // Switch ETH custody to a Pyth price update account built locally
+    let price_update_address = Pubkey::new_unique();
+    {
+        utils::set_pyth_price_update(
+            &test_setup.program_test_ctx,
+            &price_update_address,
+            ETH_FEED_ID,
+            150_000_000_000,
+            150_000_000_000,
+            100_000_000,
+            -8,
+        )
+        .await;
+
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        utils::set_custody_oracle(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &eth_custody_pda,
+            OracleParams {
+                oracle_account: price_update_address,
+                oracle_type: OracleType::Pyth,
+                feed_id: ETH_FEED_ID,
+                ..eth_custody.oracle
+            },
+            &multisig_signers,
+        )
+        .await;
+    }
+
+    // Swap priced with a fresh Pyth update should succeed
+    {
+        // Martin: Swap 1 ETH for (1.5k) USDC
+        instructions::test_swap(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            usdc_mint,
+            // The program receives ETH
+            eth_mint,
+            SwapParams {
+                amount_in: utils::scale(1, ETH_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Swap priced with an update older than the custody max_price_age_sec should fail
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        utils::warp_forward(
+            &test_setup.program_test_ctx,
+            eth_custody.oracle.max_price_age_sec as i64 + 1,
+        )
+        .await;
+
+        assert!(instructions::test_swap(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            usdc_mint,
+            // The program receives ETH
+            eth_mint,
+            SwapParams {
+                amount_in: utils::scale(1, ETH_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .is_err());
+    }
+
+    // Posting a new update makes the custody usable again
+    {
+        utils::set_pyth_price_update(
+            &test_setup.program_test_ctx,
+            &price_update_address,
+            ETH_FEED_ID,
+            150_000_000_000,
+            150_000_000_000,
+            100_000_000,
+            -8,
+        )
+        .await;
+
+        instructions::test_swap(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            usdc_mint,
+            // The program receives ETH
+            eth_mint,
+            SwapParams {
+                amount_in: utils::scale(1, ETH_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Custodies using the EMA read the one published with the price update when no TWAP
+    // account is passed, the pool can be re-priced and swapped against without one
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        utils::set_custody_pricing(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &eth_custody_pda,
+            PricingParams {
+                use_ema: true,
+                ..eth_custody.pricing
+            },
+            &multisig_signers,
+        )
+        .await;
+
+        utils::set_pyth_price_update(
+            &test_setup.program_test_ctx,
+            &price_update_address,
+            ETH_FEED_ID,
+            150_000_000_000,
+            140_000_000_000,
+            100_000_000,
+            -8,
+        )
+        .await;
+
+        instructions::test_update_pool_aum(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+        )
+        .await
+        .unwrap();
+
+        let pool_account =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        assert_eq!(
+            pool_account.aum_cache[1].price,
+            utils::scale(1_400, USDC_DECIMALS)
+        );
+        assert_eq!(
+            pool_account.aum_cache[1].owned_usd,
+            (eth_custody.assets.owned as u128 * 1_400 / 1_000) as u64
+        );
+
+        // Martin: Swap 100 USDC for ETH
+        instructions::test_swap(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            // The program receives USDC
+            usdc_mint,
+            SwapParams {
+                amount_in: utils::scale(100, USDC_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            AddLiquidityParams, ClosePositionParams, OpenPositionParams,
+            SetCustomOraclePriceParams, SwapParams,
+        },
+        state::{
+            custody::{Custody, PricingParams},
+            oracle::{OracleParams, OracleType},
+            position::Side,
+        },
+    },
+    solana_sdk::pubkey::Pubkey,
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn reference_band() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(5, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "executioner",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        max_leverage: 100_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let executioner = test_setup.get_user_keypair_by_name("executioner");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let eth_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+    // Check the ETH custom oracle against a Pyth reference at 1_500, within 5%
+    let reference_address = Pubkey::new_unique();
+    let program_test_ctx = &test_setup.program_test_ctx;
+    let payer = &test_setup.payer_keypair;
+    let multisig_signers = &multisig_signers;
+    let set_reference_oracle = |allow_exit_outside_reference: bool| async move {
+        let eth_custody = utils::get_account::<Custody>(program_test_ctx, eth_custody_pda).await;
+
+        utils::set_custody_oracle(
+            program_test_ctx,
+            admin_a,
+            payer,
+            &eth_custody_pda,
+            OracleParams {
+                reference_oracle_account: reference_address,
+                reference_oracle_type: OracleType::Pyth,
+                max_reference_deviation: 500,
+                allow_exit_outside_reference,
+                ..eth_custody.oracle
+            },
+            multisig_signers,
+        )
+        .await;
+    };
+    let post_reference_price = || async move {
+        let eth_custody = utils::get_account::<Custody>(program_test_ctx, eth_custody_pda).await;
+
+        utils::set_pyth_price_update(
+            program_test_ctx,
+            &reference_address,
+            eth_custody.oracle.feed_id,
+            150_000_000_000,
+            150_000_000_000,
+            100_000_000,
+            -8,
+        )
+        .await;
+    };
+
+    post_reference_price().await;
+    set_reference_oracle(false).await;
+
+    // Martin: Open a 5x and a 2x ETH long while the price sits inside the band
+    let open_position = |collateral: u64, size: u64, position_id: u64| {
+        instructions::test_open_position(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            eth_mint,
+            OpenPositionParams {
+                price: utils::scale(1_550, ETH_DECIMALS),
+                collateral: utils::scale(collateral, ETH_DECIMALS),
+                min_amount_out: 0,
+                size: utils::scale(size, ETH_DECIMALS),
+                side: Side::Long,
+                take_profit_price: None,
+                stop_loss_price: None,
+                position_id,
+            },
+        )
+    };
+
+    let liquidated_position_pda = open_position(1, 5, 0).await.unwrap().0;
+    let closed_position_pda = open_position(1, 2, 1).await.unwrap().0;
+
+    // ETH custom oracle drops 10%, away from the reference
+    {
+        let publish_time = utils::get_current_unix_timestamp(program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            program_test_ctx,
+            admin_a,
+            payer,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_350, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_350, ETH_DECIMALS),
+                publish_time,
+            },
+            multisig_signers,
+        )
+        .await
+        .unwrap();
+
+        post_reference_price().await;
+    }
+
+    let swap_eth_for_usdc = || {
+        instructions::test_swap(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            usdc_mint,
+            // The program receives ETH
+            eth_mint,
+            SwapParams {
+                amount_in: utils::scale(1, ETH_DECIMALS) / 10,
+                min_amount_out: 0,
+            },
+        )
+    };
+    let close_position = || {
+        instructions::test_close_position(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            eth_mint,
+            &closed_position_pda,
+            ClosePositionParams {
+                // lowest exit price paid (slippage implied)
+                price: utils::scale(1_000, USDC_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+    };
+    let liquidate = || {
+        instructions::test_liquidate(
+            program_test_ctx,
+            executioner,
+            payer,
+            &test_setup.pool_pda,
+            eth_mint,
+            &liquidated_position_pda,
+        )
+    };
+
+    // Opens, swaps and LP actions are refused outside the band
+    assert!(open_position(1, 2, 2).await.is_err());
+    assert!(swap_eth_for_usdc().await.is_err());
+    assert!(instructions::test_add_liquidity(
+        program_test_ctx,
+        alice,
+        payer,
+        &test_setup.pool_pda,
+        eth_mint,
+        AddLiquidityParams {
+            amount_in: utils::scale(1, ETH_DECIMALS),
+            min_lp_amount_out: 1,
+        },
+    )
+    .await
+    .is_err());
+
+    // So are closes and liquidations by default
+    assert!(close_position().await.is_err());
+    assert!(liquidate().await.is_err());
+
+    // Exits go through once allowed outside the band, entries are still refused
+    set_reference_oracle(true).await;
+
+    close_position().await.unwrap();
+    liquidate().await.unwrap();
+
+    assert!(open_position(1, 2, 2).await.is_err());
+    assert!(swap_eth_for_usdc().await.is_err());
+
+    // Back within the band the swap goes through
+    {
+        let publish_time = utils::get_current_unix_timestamp(program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            program_test_ctx,
+            admin_a,
+            payer,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_450, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_450, ETH_DECIMALS),
+                publish_time,
+            },
+            multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    swap_eth_for_usdc().await.unwrap();
+}
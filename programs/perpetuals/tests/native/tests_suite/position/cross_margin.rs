@@ -0,0 +1,354 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            AddCollateralParams, AddMarginParams, AddMarginPositionParams, InitMarginAccountParams,
+            OpenPositionParams, RemoveMarginParams, SetCustomOraclePriceParams,
+        },
+        state::{custody::PricingParams, margin_account::MarginAccount, position::Side},
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn cross_margin() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "executioner",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        // Expressed in BPS, with BPS = 10_000
+                        // 50_000 = x5, 100_000 = x10
+                        max_leverage: 100_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let executioner = test_setup.get_user_keypair_by_name("executioner");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open 1 ETH long position x5
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Martin: Back the position with a 1_000 USDC cross-margin deposit
+    let margin_account_pda = instructions::test_init_margin_account(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        InitMarginAccountParams {},
+    )
+    .await
+    .unwrap()
+    .0;
+
+    instructions::test_add_margin(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        AddMarginParams {
+            amount: utils::scale(1_000, USDC_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    instructions::test_add_margin_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+        AddMarginPositionParams {},
+    )
+    .await
+    .unwrap();
+
+    // Only the deposit and position custodies are loaded with the margin account
+    {
+        let margin_account =
+            utils::get_account::<MarginAccount>(&test_setup.program_test_ctx, margin_account_pda)
+                .await;
+
+        assert_eq!(
+            margin_account.custodies,
+            vec![
+                test_setup.custodies_info[0].custody_pda,
+                test_setup.custodies_info[1].custody_pda
+            ]
+        );
+    }
+
+    // Martin: Try and fail to add collateral to the position, it is backed by the deposit
+    assert!(instructions::test_add_collateral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        AddCollateralParams {
+            collateral: utils::scale_f64(0.1, ETH_DECIMALS),
+        },
+    )
+    .await
+    .is_err());
+
+    // Makes ETH price to drop 10%, the position alone would be liquidated
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_350, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_350, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Executioner: Try and fail to liquidate the position on its own
+    assert!(instructions::test_liquidate(
+        &test_setup.program_test_ctx,
+        executioner,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+    )
+    .await
+    .is_err());
+
+    // Executioner: Try and fail to liquidate the margin account, the deposit keeps it healthy
+    assert!(instructions::test_liquidate_margin_account(
+        &test_setup.program_test_ctx,
+        executioner,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        &margin_account_pda,
+    )
+    .await
+    .is_err());
+
+    // Martin: Try and fail to withdraw the whole deposit
+    assert!(instructions::test_remove_margin(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        RemoveMarginParams {
+            amount: utils::scale(1_000, USDC_DECIMALS),
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin: Withdraw the part of the deposit that is not needed
+    instructions::test_remove_margin(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        RemoveMarginParams {
+            amount: utils::scale(100, USDC_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Makes ETH price to drop 26%, total equity goes under maintenance margin
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_100, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_100, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Executioner: Liquidate the whole margin account
+    instructions::test_liquidate_margin_account(
+        &test_setup.program_test_ctx,
+        executioner,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        &margin_account_pda,
+    )
+    .await
+    .unwrap();
+
+    // Martin: Withdraw what is left of the deposit
+    {
+        let margin_account =
+            utils::get_account::<MarginAccount>(&test_setup.program_test_ctx, margin_account_pda)
+                .await;
+
+        // Position loss went over its own collateral and was covered by the deposit
+        assert!(margin_account.collateral_amount > 0);
+        assert!(margin_account.collateral_amount < utils::scale(900, USDC_DECIMALS));
+
+        instructions::test_remove_margin(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            usdc_mint,
+            RemoveMarginParams {
+                amount: margin_account.collateral_amount,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Check user final balances
+    {
+        let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+        let martin_usdc_pda = utils::find_associated_token_account(&martin.pubkey(), usdc_mint).0;
+
+        let martin_eth_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+        let martin_usdc_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_usdc_pda).await;
+
+        assert_eq!(martin_eth_balance, utils::scale(1, ETH_DECIMALS));
+        assert!(martin_usdc_balance > utils::scale(100, USDC_DECIMALS));
+        assert!(martin_usdc_balance < utils::scale(1_000, USDC_DECIMALS));
+    }
+}
@@ -0,0 +1,240 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            ClosePositionParams, OpenPositionParams, SetDelegateParams,
+            UpdatePositionTriggersParams,
+        },
+        state::{delegate::DelegatePermissions, position::Side},
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn delegate() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open 1 ETH long position x2
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(2, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Alice: Cannot update Martin's position without a delegation
+    assert!(instructions::test_update_position_triggers(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &position_pda,
+        None,
+        UpdatePositionTriggersParams {
+            take_profit_price: Some(utils::scale(2_000, USDC_DECIMALS)),
+            stop_loss_price: None,
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin: Delegate position management to Alice, positions up to 1,000 USD only
+    let delegate_pda = instructions::test_set_delegate(
+        &test_setup.program_test_ctx,
+        martin,
+        &alice.pubkey(),
+        &test_setup.payer_keypair,
+        SetDelegateParams {
+            permissions: DelegatePermissions {
+                allow_close_position: true,
+                allow_add_collateral: false,
+                allow_update_triggers: true,
+            },
+            expiry: None,
+            max_size_usd: Some(utils::scale(1_000, USDC_DECIMALS)),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Alice: The position is larger than the delegated size
+    assert!(instructions::test_update_position_triggers(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &position_pda,
+        Some(delegate_pda),
+        UpdatePositionTriggersParams {
+            take_profit_price: Some(utils::scale(2_000, USDC_DECIMALS)),
+            stop_loss_price: None,
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin: Raise the delegated size
+    instructions::test_set_delegate(
+        &test_setup.program_test_ctx,
+        martin,
+        &alice.pubkey(),
+        &test_setup.payer_keypair,
+        SetDelegateParams {
+            permissions: DelegatePermissions {
+                allow_close_position: true,
+                allow_add_collateral: false,
+                allow_update_triggers: true,
+            },
+            expiry: None,
+            max_size_usd: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Alice: Cannot place the stop loss above the entry price of a long
+    assert!(instructions::test_update_position_triggers(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &position_pda,
+        Some(delegate_pda),
+        UpdatePositionTriggersParams {
+            take_profit_price: Some(utils::scale(2_000, USDC_DECIMALS)),
+            stop_loss_price: Some(utils::scale(1_600, USDC_DECIMALS)),
+        },
+    )
+    .await
+    .is_err());
+
+    // Alice: Update the position take profit and stop loss
+    instructions::test_update_position_triggers(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &position_pda,
+        Some(delegate_pda),
+        UpdatePositionTriggersParams {
+            take_profit_price: Some(utils::scale(2_000, USDC_DECIMALS)),
+            stop_loss_price: Some(utils::scale(1_200, USDC_DECIMALS)),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Alice: Close the position, the proceeds go to Martin
+    let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+    let martin_eth_balance_before =
+        utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+
+    instructions::test_delegate_close_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &delegate_pda,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        ClosePositionParams {
+            // lowest price received (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await
+            > martin_eth_balance_before
+    );
+}
@@ -0,0 +1,172 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{GetEntryPriceAndFeeParams, SetCustomOraclePriceParams},
+        state::{custody::PricingParams, position::Side},
+    },
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn dynamic_spread() {
+    let test_setup = utils::TestSetup::new(
+        vec![utils::UserParam {
+            name: "alice",
+            token_balances: hashmap! {
+                "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                "eth" => utils::scale(10_000, ETH_DECIMALS),
+            },
+        }],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        // Expressed in BPS, with BPS = 10_000
+                        // 1% static spread widened by the full spot/EMA divergence
+                        use_dynamic_spread: true,
+                        ema_spread_mult: 10_000,
+                        min_dynamic_spread: 0,
+                        max_dynamic_spread: 1_000,
+                        ..utils::fixtures::pricing_params_regular(true)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let entry_params = |side: Side| GetEntryPriceAndFeeParams {
+        collateral: utils::scale(1, USDC_DECIMALS),
+        size: utils::scale_f64(0.01, ETH_DECIMALS),
+        side,
+    };
+
+    // Spot and EMA agree, only the static spread applies
+    {
+        let quote = instructions::test_get_entry_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            usdc_mint,
+            entry_params(Side::Long),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote.spread, 100);
+        assert_eq!(quote.entry_price, 1_515_000_000);
+    }
+
+    // EMA lags 2% behind the spot price
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_500, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_470, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // The divergence widens the spread from 100 to 300 BPS
+    {
+        // long pays max(spot, ema) + 3%
+        let quote = instructions::test_get_entry_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            usdc_mint,
+            entry_params(Side::Long),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote.spread, 300);
+        assert_eq!(quote.entry_price, 1_545_000_000);
+
+        // short gets min(spot, ema) - 3%
+        let quote = instructions::test_get_entry_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            usdc_mint,
+            entry_params(Side::Short),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote.spread, 300);
+        assert_eq!(quote.entry_price, 1_425_900_000);
+    }
+}
@@ -0,0 +1,364 @@
+use {
+    crate::{
+        instructions,
+        utils::{self, pda},
+    },
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{OpenPositionParams, SetCustomOraclePriceParams},
+        state::{
+            custody::{Custody, PricingParams},
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{Position, Side},
+        },
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+// Owners of the positions the price drop makes liquidatable, more than a single
+// transaction can carry
+const UNHEALTHY_OWNERS: [&str; 10] = [
+    "martin", "paul", "kevin", "anna", "bruno", "clara", "david", "emma", "felix", "gina",
+];
+
+pub async fn liquidate_batch() {
+    let test_setup = utils::TestSetup::new(
+        UNHEALTHY_OWNERS
+            .iter()
+            .chain(["john"].iter())
+            .map(|name| utils::UserParam {
+                name,
+                token_balances: hashmap! {
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            })
+            .chain([
+                utils::UserParam {
+                    name: "alice",
+                    token_balances: hashmap! {
+                        "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                        "eth" => utils::scale(100, ETH_DECIMALS),
+                    },
+                },
+                utils::UserParam {
+                    name: "executioner",
+                    token_balances: hashmap! {},
+                },
+            ])
+            .collect(),
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        // Expressed in BPS, with BPS = 10_000
+                        // 50_000 = x5, 100_000 = x10
+                        max_leverage: 100_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let executioner = test_setup.get_user_keypair_by_name("executioner");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Unhealthy owners: Open 1 ETH long position x5
+    // John: Open 1 ETH long position x2, stays healthy after the price drop
+    let mut positions = Vec::new();
+    for (name, size) in UNHEALTHY_OWNERS
+        .iter()
+        .map(|name| (*name, 5))
+        .chain([("john", 2)])
+    {
+        let position_pda = instructions::test_open_position(
+            &test_setup.program_test_ctx,
+            test_setup.get_user_keypair_by_name(name),
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            OpenPositionParams {
+                // max price paid (slippage implied)
+                price: utils::scale(1_550, ETH_DECIMALS),
+                collateral: utils::scale(1, ETH_DECIMALS),
+                min_amount_out: 0,
+                size: utils::scale(size, ETH_DECIMALS),
+                side: Side::Long,
+                take_profit_price: None,
+                stop_loss_price: None,
+                position_id: 0,
+                // feed_id: [0; 32], // TODO: add feed id
+            },
+        )
+        .await
+        .unwrap()
+        .0;
+
+        positions.push(position_pda);
+    }
+    let unhealthy_positions = &positions[..UNHEALTHY_OWNERS.len()];
+    let healthy_position = positions[UNHEALTHY_OWNERS.len()];
+
+    // Makes ETH price to drop 10%
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_350, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_350, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Every position has its own owner, so each adds a position and a receiving account
+    let max_batch_size = instructions::get_max_liquidate_batch_size(
+        &test_setup.program_test_ctx,
+        executioner,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        unhealthy_positions,
+    )
+    .await
+    .unwrap();
+
+    assert!(max_batch_size > 1);
+    assert!(max_batch_size < unhealthy_positions.len());
+
+    // Check the largest batch fits the compute budget
+    {
+        let mut previous_units = 0;
+        for batch_size in 1..=max_batch_size {
+            let units = instructions::simulate_liquidate_batch(
+                &test_setup.program_test_ctx,
+                executioner,
+                &test_setup.payer_keypair,
+                &test_setup.pool_pda,
+                eth_mint,
+                &unhealthy_positions[..batch_size],
+            )
+            .await
+            .unwrap();
+
+            assert!(units <= instructions::MAX_COMPUTE_UNIT_LIMIT as u64);
+
+            // Pricing once must make every additional position cheaper than the first one
+            if batch_size > 1 {
+                assert!(units - previous_units < previous_units);
+            }
+            previous_units = units;
+        }
+    }
+
+    // Executioner: Liquidate the largest batch, then the rest with the healthy position
+    let (first_batch, second_batch) = unhealthy_positions.split_at(max_batch_size);
+
+    for (batch, owners) in [
+        (first_batch.to_vec(), &UNHEALTHY_OWNERS[..max_batch_size]),
+        (
+            [second_batch, &[healthy_position]].concat(),
+            &UNHEALTHY_OWNERS[max_batch_size..],
+        ),
+    ] {
+        let liquidated_positions = &batch[..owners.len()];
+
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+        let eth_custody_token_account_pda =
+            pda::get_custody_token_account_pda(&test_setup.pool_pda, eth_mint).0;
+        let executioner_eth_pda =
+            utils::find_associated_token_account(&executioner.pubkey(), eth_mint).0;
+        let owners_eth_pda = owners
+            .iter()
+            .map(|name| {
+                let owner = test_setup.get_user_keypair_by_name(name).pubkey();
+                utils::find_associated_token_account(&owner, eth_mint).0
+            })
+            .collect::<Vec<_>>();
+
+        let mut collateral_amount = 0;
+        let mut locked_amount = 0;
+        for position_pda in liquidated_positions {
+            let position =
+                utils::get_account::<Position>(&test_setup.program_test_ctx, *position_pda).await;
+            collateral_amount += position.collateral_amount;
+            locked_amount += position.locked_amount;
+        }
+
+        let custody_before =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+        let custody_balance_before = utils::get_token_account_balance(
+            &test_setup.program_test_ctx,
+            eth_custody_token_account_pda,
+        )
+        .await;
+        let executioner_balance_before =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, executioner_eth_pda)
+                .await;
+        let mut owners_balance_before = Vec::new();
+        for owner_eth_pda in owners_eth_pda.iter() {
+            owners_balance_before.push(
+                utils::get_token_account_balance(&test_setup.program_test_ctx, *owner_eth_pda)
+                    .await,
+            );
+        }
+
+        instructions::test_liquidate_batch(
+            &test_setup.program_test_ctx,
+            executioner,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            &batch,
+        )
+        .await
+        .unwrap();
+
+        // Check balances
+        {
+            let mut owners_payout = Vec::new();
+            for (owner_eth_pda, balance_before) in
+                owners_eth_pda.iter().zip(owners_balance_before.iter())
+            {
+                owners_payout.push(
+                    utils::get_token_account_balance(&test_setup.program_test_ctx, *owner_eth_pda)
+                        .await
+                        - balance_before,
+                );
+            }
+
+            // Identical positions, identical payouts
+            assert!(owners_payout[0] > 0);
+            assert!(owners_payout
+                .iter()
+                .all(|payout| *payout == owners_payout[0]));
+
+            // The liquidation fee is taken from each position's amount out
+            let liquidation_fee = custody_before.fees.liquidation;
+            let amount_out = owners_payout[0] as u128 * Perpetuals::BPS_POWER
+                / (Perpetuals::BPS_POWER - liquidation_fee as u128);
+            let expected_reward = Pool::get_fee_amount(liquidation_fee, amount_out as u64).unwrap()
+                * owners.len() as u64;
+
+            let reward =
+                utils::get_token_account_balance(&test_setup.program_test_ctx, executioner_eth_pda)
+                    .await
+                    - executioner_balance_before;
+
+            assert!(reward.abs_diff(expected_reward) <= owners.len() as u64);
+
+            // Custody pays out exactly what owners and liquidator receive
+            let custody_balance_after = utils::get_token_account_balance(
+                &test_setup.program_test_ctx,
+                eth_custody_token_account_pda,
+            )
+            .await;
+
+            assert_eq!(
+                custody_balance_before - custody_balance_after,
+                owners_payout.iter().sum::<u64>() + reward
+            );
+
+            let custody_after =
+                utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+            assert_eq!(
+                custody_before.assets.collateral - custody_after.assets.collateral,
+                collateral_amount
+            );
+            assert_eq!(
+                custody_before.assets.locked - custody_after.assets.locked,
+                locked_amount
+            );
+        }
+    }
+
+    // Check positions state
+    {
+        let mut ctx = test_setup.program_test_ctx.write().await;
+
+        for position_pda in unhealthy_positions {
+            assert!(ctx
+                .banks_client
+                .get_account(*position_pda)
+                .await
+                .unwrap()
+                .is_none());
+        }
+
+        assert!(ctx
+            .banks_client
+            .get_account(healthy_position)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -106,10 +106,12 @@ pub async fn max_user_profit() {
             // max price paid (slippage implied)
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
             size: utils::scale(5, ETH_DECIMALS),
             side: Side::Long,
             take_profit_price: None,
             stop_loss_price: None,
+            position_id: 0,
             // feed_id: [0; 32], // TODO: add feed id
         },
     )
@@ -156,6 +158,7 @@ pub async fn max_user_profit() {
         ClosePositionParams {
             // lowest exit price paid (slippage implied)
             price: utils::scale(2_970, USDC_DECIMALS),
+            min_amount_out: 0,
             // feed_id: [0; 32], // TODO: add feed id
         },
     )
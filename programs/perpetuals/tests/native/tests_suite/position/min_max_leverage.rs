@@ -104,10 +104,12 @@ pub async fn min_max_leverage() {
             // max price paid (slippage implied)
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
             size: utils::scale(10, ETH_DECIMALS),
             side: Side::Long,
             take_profit_price: None,
             stop_loss_price: None,
+            position_id: 0,
             // feed_id: [0; 32], // TODO: add feed id
         },
     )
@@ -125,10 +127,12 @@ pub async fn min_max_leverage() {
             // max price paid (slippage implied)
             price: utils::scale(1_550, ETH_DECIMALS),
             collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
             size: utils::scale_f64(0.5, ETH_DECIMALS),
             side: Side::Long,
             take_profit_price: None,
             stop_loss_price: None,
+            position_id: 0,
             // feed_id: [0; 32], // TODO: add feed id
         },
     )
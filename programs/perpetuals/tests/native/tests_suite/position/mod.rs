@@ -1,5 +1,27 @@
+pub mod close_margin_position;
+pub mod cross_margin;
+pub mod delegate;
+pub mod dynamic_spread;
+pub mod liquidate_batch;
 pub mod liquidate_position;
 pub mod max_user_profit;
 pub mod min_max_leverage;
+pub mod multiple_positions;
+pub mod open_close_with_swap;
+pub mod open_interest_limits;
+pub mod position_info;
+pub mod remove_collateral_amount;
+pub mod stablecoin_depeg;
+pub mod swap_position_collateral;
+pub mod transfer_position;
+pub mod user_positions;
+pub mod wind_down;
+pub mod withdraw_profit;
 
-pub use {liquidate_position::*, max_user_profit::*, min_max_leverage::*};
+pub use {
+    close_margin_position::*, cross_margin::*, delegate::*, dynamic_spread::*, liquidate_batch::*,
+    liquidate_position::*, max_user_profit::*, min_max_leverage::*, multiple_positions::*,
+    open_close_with_swap::*, open_interest_limits::*, position_info::*,
+    remove_collateral_amount::*, stablecoin_depeg::*, swap_position_collateral::*,
+    transfer_position::*, user_positions::*, wind_down::*, withdraw_profit::*,
+};
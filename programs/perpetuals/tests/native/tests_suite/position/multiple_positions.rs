@@ -0,0 +1,149 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        state::position::{Position, Side},
+    },
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn multiple_positions() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open two isolated 0.5 ETH long positions x2 in the same market
+    let mut position_pdas = vec![];
+    for position_id in [0, 1] {
+        let position_pda = instructions::test_open_position(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            OpenPositionParams {
+                // max price paid (slippage implied)
+                price: utils::scale(1_550, ETH_DECIMALS),
+                collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+                min_amount_out: 0,
+                size: utils::scale(1, ETH_DECIMALS),
+                side: Side::Long,
+                take_profit_price: None,
+                stop_loss_price: None,
+                position_id,
+            },
+        )
+        .await
+        .unwrap()
+        .0;
+
+        position_pdas.push(position_pda);
+    }
+
+    assert_ne!(position_pdas[0], position_pdas[1]);
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Martin: Close the second position, the first one is left untouched
+    let first_position_before =
+        utils::get_account::<Position>(&test_setup.program_test_ctx, position_pdas[0]).await;
+
+    instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pdas[1],
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    let first_position =
+        utils::get_account::<Position>(&test_setup.program_test_ctx, position_pdas[0]).await;
+
+    assert_eq!(first_position.position_id, 0);
+    assert_eq!(first_position.size_usd, first_position_before.size_usd);
+    assert_eq!(
+        first_position.collateral_amount,
+        first_position_before.collateral_amount
+    );
+}
@@ -0,0 +1,166 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        state::position::Side,
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn open_close_with_swap() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_500, USDC_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open 2 ETH long position funded with 1_500 USDC
+    let position_pda = instructions::test_open_position_with_swap(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, USDC_DECIMALS),
+            collateral: utils::scale(1_500, USDC_DECIMALS),
+            min_amount_out: utils::scale_f64(0.9, ETH_DECIMALS),
+            size: utils::scale(2, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Martin: Try and fail to close with an unreachable USDC payout
+    assert!(instructions::test_close_position_with_swap(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        &position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+            min_amount_out: utils::scale(1_500, USDC_DECIMALS),
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin: Close the position and get USDC back
+    instructions::test_close_position_with_swap(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        &position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+            min_amount_out: utils::scale(1_000, USDC_DECIMALS),
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check user final balances, no ETH ever reached the user
+    {
+        let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+        let martin_usdc_pda = utils::find_associated_token_account(&martin.pubkey(), usdc_mint).0;
+
+        let martin_eth_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+        let martin_usdc_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_usdc_pda).await;
+
+        assert_eq!(martin_eth_balance, 0);
+        assert!(martin_usdc_balance >= utils::scale(1_000, USDC_DECIMALS));
+        assert!(martin_usdc_balance < utils::scale(1_500, USDC_DECIMALS));
+    }
+}
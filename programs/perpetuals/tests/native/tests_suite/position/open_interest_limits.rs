@@ -0,0 +1,210 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::OpenPositionParams,
+        state::{
+            custody::{Custody, PricingParams},
+            position::Side,
+        },
+    },
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn open_interest_limits() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        max_oi_long_usd: utils::scale(3_000, USDC_DECIMALS),
+                        max_oi_short_usd: utils::scale(3_000, USDC_DECIMALS),
+                        // Expressed in BPS, with BPS = 10_000
+                        // long and short may differ by up to 50% of the side limit
+                        max_oi_imbalance: 5_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let eth_custody_pda = &test_setup.custodies_info[1].custody_pda;
+
+    let open_long_params = |size: u64| OpenPositionParams {
+        // max price paid (slippage implied)
+        price: utils::scale(1_550, ETH_DECIMALS),
+        // x2 leverage
+        collateral: size / 2,
+        min_amount_out: 0,
+        size,
+        side: Side::Long,
+        take_profit_price: None,
+        stop_loss_price: None,
+        position_id: 0,
+        // feed_id: [0; 32], // TODO: add feed id
+    };
+
+    // Martin: Open 1 ETH long position should fail
+    // Fails because ~1,515 USD of open interest exceeds the 1,500 USD allowed imbalance
+    assert!(instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_long_params(utils::scale(1, ETH_DECIMALS)),
+    )
+    .await
+    .is_err());
+
+    // Martin: Open 0.5 ETH long position
+    instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_long_params(utils::scale_f64(0.5, ETH_DECIMALS)),
+    )
+    .await
+    .unwrap();
+
+    // Check headroom
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, *eth_custody_pda).await;
+        let oi_long_usd = eth_custody.trade_stats.oi_long_usd;
+
+        let headroom = instructions::test_get_open_interest_headroom(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_custody_pda,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(headroom.oi_long_usd, oi_long_usd);
+        assert_eq!(headroom.oi_short_usd, 0);
+        assert_eq!(
+            headroom.long_headroom_usd,
+            utils::scale(1_500, USDC_DECIMALS) - oi_long_usd
+        );
+        assert_eq!(
+            headroom.short_headroom_usd,
+            utils::scale(1_500, USDC_DECIMALS) + oi_long_usd
+        );
+    }
+
+    // Alice: Open 0.6 ETH long position should fail
+    assert!(instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_long_params(utils::scale_f64(0.6, ETH_DECIMALS)),
+    )
+    .await
+    .is_err());
+
+    // Alice: Open 0.4 ETH long position
+    instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_long_params(utils::scale_f64(0.4, ETH_DECIMALS)),
+    )
+    .await
+    .unwrap();
+
+    // Check headroom
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, *eth_custody_pda).await;
+
+        let headroom = instructions::test_get_open_interest_headroom(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_custody_pda,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(headroom.oi_long_usd, eth_custody.trade_stats.oi_long_usd);
+        assert_eq!(
+            headroom.long_headroom_usd,
+            utils::scale(1_500, USDC_DECIMALS) - eth_custody.trade_stats.oi_long_usd
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            ClosePositionParams, InitReferralParams, InitTraderStatsParams, OpenPositionParams,
+            SetCustomOraclePriceParams, SetFeeTiersParams,
+        },
+        math,
+        state::{
+            custody::{Fees, PricingParams},
+            perpetuals::{FeeTier, Perpetuals},
+            position::Side,
+        },
+    },
+    solana_sdk::signer::Signer,
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn position_info() {
+    let fees = Fees {
+        referral_discount: 2_000,
+        referral_rebate: 3_000,
+        ..utils::fixtures::fees_linear_regular()
+    };
+
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: Some(fees),
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        max_leverage: 100_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: Some(fees),
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // 20% fee discount for every trader with stats
+    instructions::test_set_fee_tiers(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        SetFeeTiersParams {
+            fee_tiers: [
+                FeeTier {
+                    min_volume_usd: 0,
+                    fee_discount: 2_000,
+                },
+                FeeTier::default(),
+                FeeTier::default(),
+                FeeTier::default(),
+            ],
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    // Paul: Register as referrer, Martin: Track his volume
+    let (referral_pda, _) = instructions::test_init_referral(
+        &test_setup.program_test_ctx,
+        paul,
+        &test_setup.payer_keypair,
+        InitReferralParams {},
+    )
+    .await
+    .unwrap();
+
+    let (trader_stats_pda, _) = instructions::test_init_trader_stats(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        InitTraderStatsParams {},
+    )
+    .await
+    .unwrap();
+
+    // Martin: Open 1 ETH long position x5
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // ETH price rises to 1_600
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_600, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_600, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Discounts lower the exit fee the view reports
+    let full_fee_info = instructions::test_get_position_info(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let info = instructions::test_get_position_info(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+        Some(&referral_pda),
+        Some(&trader_stats_pda),
+    )
+    .await
+    .unwrap();
+
+    assert!(info.exit_fee < full_fee_info.exit_fee);
+    assert!(info.profit_usd > full_fee_info.profit_usd);
+
+    // Martin: Close with the same discounts, the payout matches the view
+    let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+    let martin_eth_balance_before =
+        utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+
+    instructions::test_close_position_with_referral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        Some(&referral_pda),
+        Some(&trader_stats_pda),
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_500, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    let payout = utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda)
+        .await
+        - martin_eth_balance_before;
+
+    // collateral + pnl, converted at the 1_600 ETH price
+    let expected_payout = math::checked_as_u64(
+        (info.collateral_usd + info.profit_usd - info.loss_usd) as u128
+            * 10u128.pow(ETH_DECIMALS as u32)
+            / (1_600 * 10u128.pow(Perpetuals::USD_DECIMALS as u32)),
+    )
+    .unwrap();
+
+    assert!(payout.abs_diff(expected_payout) <= expected_payout / 10_000);
+}
@@ -0,0 +1,201 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            GetRemoveCollateralInfoParams, OpenPositionParams, RemoveCollateralAmountParams,
+        },
+        state::position::{Position, Side},
+    },
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn remove_collateral_amount() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open 1 ETH long position x2
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(2, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Preview removing 0.5 ETH of collateral
+    let info = instructions::test_get_remove_collateral_info(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        GetRemoveCollateralInfoParams {
+            collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    // ~0.5 ETH at 1,500 USD, x2 becomes ~x4
+    assert!(info.collateral_usd > utils::scale(740, USDC_DECIMALS));
+    assert!(info.collateral_usd < utils::scale(760, USDC_DECIMALS));
+    assert!(info.leverage > 35_000 && info.leverage < 45_000);
+    assert!(info.liquidation_price > 0);
+
+    // Martin: Remove 0.5 ETH with a too tight usd bound should fail
+    assert!(instructions::test_remove_collateral_amount(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        RemoveCollateralAmountParams {
+            collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+            max_collateral_usd_delta: info.collateral_usd - 1,
+        },
+    )
+    .await
+    .is_err());
+
+    let position_before =
+        utils::get_account::<Position>(&test_setup.program_test_ctx, position_pda).await;
+
+    // Martin: Remove 0.5 ETH
+    instructions::test_remove_collateral_amount(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        RemoveCollateralAmountParams {
+            collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+            max_collateral_usd_delta: info.collateral_usd,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check the preview matches the resulting position
+    {
+        let position =
+            utils::get_account::<Position>(&test_setup.program_test_ctx, position_pda).await;
+
+        assert_eq!(
+            position.collateral_amount,
+            position_before.collateral_amount - utils::scale_f64(0.5, ETH_DECIMALS)
+        );
+        assert_eq!(
+            position.collateral_usd,
+            position_before.collateral_usd - info.collateral_usd
+        );
+    }
+
+    // Martin: Removing the whole collateral should fail
+    assert!(instructions::test_remove_collateral_amount(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        RemoveCollateralAmountParams {
+            collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+            max_collateral_usd_delta: u64::MAX,
+        },
+    )
+    .await
+    .is_err());
+}
@@ -0,0 +1,270 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            ClosePositionParams, OpenPositionParams, SetCustomOraclePriceParams,
+            SwapPositionCollateralParams,
+        },
+        state::{
+            custody::{Custody, PricingParams},
+            position::Side,
+        },
+    },
+    solana_sdk::{pubkey::Pubkey, signer::Signer},
+};
+
+const USDC_DECIMALS: u8 = 6;
+const USDT_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn stablecoin_depeg() {
+    // Stablecoins depeg beyond 2%, new positions backed by them are then refused.
+    // EMA pricing so payouts convert at max(spot, ema) until the spot policy is on
+    let stable_pricing_params = PricingParams {
+        depeg_threshold: 200,
+        block_positions_on_depeg: true,
+        pay_out_at_spot_on_depeg: false,
+        ..utils::fixtures::pricing_params_regular(true)
+    };
+
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(10_000, USDC_DECIMALS),
+                    "usdt" => utils::scale(10_000, USDT_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "usdt" => utils::scale(1_000, USDT_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "usdt",
+                decimals: USDT_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(33.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: Some(stable_pricing_params),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdt",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(33.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDT_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDT_DECIMALS),
+                    pricing_params: Some(stable_pricing_params),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, USDT_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(34.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let usdt_mint = &test_setup.get_mint_by_name("usdt");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let usdc_oracle_pda = test_setup.custodies_info[0].custom_oracle_pda;
+    let usdc_custody_pda = test_setup.custodies_info[0].custody_pda;
+
+    let program_test_ctx = &test_setup.program_test_ctx;
+    let payer = &test_setup.payer_keypair;
+    let multisig_signers = &multisig_signers;
+
+    // Martin: Open 0.2 ETH shorts x3 backed by stablecoins
+    let open_short = |collateral_mint: Pubkey, position_id: u64| async move {
+        instructions::test_open_position_with_collateral(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            eth_mint,
+            &collateral_mint,
+            OpenPositionParams {
+                // min price paid (slippage implied)
+                price: utils::scale(1_400, USDC_DECIMALS),
+                collateral: utils::scale(100, USDC_DECIMALS),
+                min_amount_out: 0,
+                size: utils::scale(1, ETH_DECIMALS) / 5,
+                side: Side::Short,
+                take_profit_price: None,
+                stop_loss_price: None,
+                position_id,
+            },
+        )
+        .await
+    };
+    let set_usdc_price = |price: u64| async move {
+        let publish_time = utils::get_current_unix_timestamp(program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            program_test_ctx,
+            admin_a,
+            payer,
+            &test_setup.pool_pda,
+            &usdc_custody_pda,
+            &usdc_oracle_pda,
+            SetCustomOraclePriceParams {
+                price,
+                expo: -(USDC_DECIMALS as i32),
+                conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                ema: utils::scale(1, USDC_DECIMALS),
+                publish_time,
+            },
+            multisig_signers,
+        )
+        .await
+        .unwrap();
+    };
+    let swap_collateral = |position_pda, new_collateral_mint| {
+        instructions::test_swap_position_collateral(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            position_pda,
+            new_collateral_mint,
+            SwapPositionCollateralParams { min_amount_out: 0 },
+        )
+    };
+
+    let spot_closed_position_pda = open_short(*usdc_mint, 0).await.unwrap().0;
+    let ema_closed_position_pda = open_short(*usdc_mint, 1).await.unwrap().0;
+    let usdc_position_pda = open_short(*usdc_mint, 2).await.unwrap().0;
+
+    // USDC spot drops to 0.95 while its EMA stays at 1
+    set_usdc_price(utils::scale_f64(0.95, USDC_DECIMALS)).await;
+
+    // No new position backed by USDC, USDT still works
+    assert!(open_short(*usdc_mint, 3).await.is_err());
+    let usdt_position_pda = open_short(*usdt_mint, 4).await.unwrap().0;
+
+    // Martin: Can't move collateral into USDC, can move out of it
+    assert!(swap_collateral(&usdt_position_pda, usdc_mint)
+        .await
+        .is_err());
+    swap_collateral(&usdc_position_pda, usdt_mint)
+        .await
+        .unwrap();
+
+    // Close payouts convert at max(spot, ema) by default, at spot once the policy says so
+    let martin_usdc_pda = utils::find_associated_token_account(&martin.pubkey(), usdc_mint).0;
+    let close_short = |position_pda| async move {
+        let balance_before =
+            utils::get_token_account_balance(program_test_ctx, martin_usdc_pda).await;
+
+        instructions::test_close_position(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            eth_mint,
+            position_pda,
+            ClosePositionParams {
+                // max price paid (slippage implied)
+                price: utils::scale(1_600, USDC_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+
+        utils::get_token_account_balance(program_test_ctx, martin_usdc_pda).await - balance_before
+    };
+
+    let ema_payout = close_short(&ema_closed_position_pda).await;
+
+    let usdc_custody = utils::get_account::<Custody>(program_test_ctx, usdc_custody_pda).await;
+    utils::set_custody_pricing(
+        program_test_ctx,
+        admin_a,
+        payer,
+        &usdc_custody_pda,
+        PricingParams {
+            pay_out_at_spot_on_depeg: true,
+            ..usdc_custody.pricing
+        },
+        multisig_signers,
+    )
+    .await;
+
+    let spot_payout = close_short(&spot_closed_position_pda).await;
+
+    // Same position, 1 / 0.95 more tokens paid at spot
+    assert!(spot_payout > ema_payout);
+    assert!(spot_payout <= ema_payout * 106 / 100);
+
+    // USDC back to peg, positions can be backed by it again
+    set_usdc_price(utils::scale(1, USDC_DECIMALS)).await;
+
+    swap_collateral(&usdt_position_pda, usdc_mint)
+        .await
+        .unwrap();
+}
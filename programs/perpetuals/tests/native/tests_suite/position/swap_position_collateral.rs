@@ -0,0 +1,202 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams, SwapPositionCollateralParams},
+        state::position::{Position, Side},
+    },
+};
+
+const USDC_DECIMALS: u8 = 6;
+const USDT_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn swap_position_collateral() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(10_000, USDC_DECIMALS),
+                    "usdt" => utils::scale(10_000, USDT_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "usdt",
+                decimals: USDT_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(33.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdt",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(33.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDT_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDT_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, USDT_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(34.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let usdt_mint = &test_setup.get_mint_by_name("usdt");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open a 0.2 ETH short x3 backed by 100 USDC
+    let position_pda = instructions::test_open_position_with_collateral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        OpenPositionParams {
+            // min price paid (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+            collateral: utils::scale(100, USDC_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(1, ETH_DECIMALS) / 5,
+            side: Side::Short,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    let position_before =
+        utils::get_account::<Position>(&test_setup.program_test_ctx, position_pda).await;
+
+    // Martin: Swap fees make 100 USDT out of 100 USDC fail the slippage check
+    assert!(instructions::test_swap_position_collateral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+        usdt_mint,
+        SwapPositionCollateralParams {
+            min_amount_out: position_before.collateral_amount,
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin: Move the position collateral to USDT
+    instructions::test_swap_position_collateral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+        usdt_mint,
+        SwapPositionCollateralParams {
+            min_amount_out: utils::scale(90, USDT_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check the collateral is swapped at the 1:1 oracle prices minus swap fees,
+    // and the position keeps what it owed so far
+    {
+        let position_after =
+            utils::get_account::<Position>(&test_setup.program_test_ctx, position_pda).await;
+
+        assert!(position_after.collateral_amount < position_before.collateral_amount);
+        assert!(position_after.collateral_amount >= utils::scale(90, USDT_DECIMALS));
+        assert_eq!(position_after.locked_amount, position_before.locked_amount);
+        assert_eq!(position_after.price, position_before.price);
+        assert_eq!(
+            position_after.unrealized_loss_usd,
+            position_before.unrealized_loss_usd
+        );
+    }
+
+    // Martin: Close the position, paid out in USDT
+    instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        ClosePositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_600, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+}
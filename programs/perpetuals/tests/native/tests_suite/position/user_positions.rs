@@ -0,0 +1,268 @@
+use {
+    crate::{instructions, utils},
+    anchor_lang::{AccountDeserialize, AccountSerialize},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams, SetCustomOraclePriceParams},
+        state::{custody::PricingParams, position::Side, user_positions::UserPositions},
+    },
+    solana_sdk::{account, pubkey::Pubkey},
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn user_positions() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "eth" => utils::scale(5, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "executioner",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(PricingParams {
+                        max_leverage: 100_000,
+                        ..utils::fixtures::pricing_params_regular(false)
+                    }),
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let executioner = test_setup.get_user_keypair_by_name("executioner");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let program_test_ctx = &test_setup.program_test_ctx;
+    let payer = &test_setup.payer_keypair;
+
+    // Martin: Index his positions
+    let user_positions_pda =
+        instructions::test_init_user_positions(program_test_ctx, martin, payer)
+            .await
+            .unwrap()
+            .0;
+
+    let get_indexed_positions = || async move {
+        utils::get_account::<UserPositions>(program_test_ctx, user_positions_pda)
+            .await
+            .positions
+    };
+
+    // Martin: Open a 5x and a 2x ETH long
+    let open_position = |size: u64, position_id: u64| {
+        instructions::test_open_position(
+            program_test_ctx,
+            martin,
+            payer,
+            &test_setup.pool_pda,
+            eth_mint,
+            OpenPositionParams {
+                // max price paid (slippage implied)
+                price: utils::scale(1_550, ETH_DECIMALS),
+                collateral: utils::scale(1, ETH_DECIMALS),
+                min_amount_out: 0,
+                size: utils::scale(size, ETH_DECIMALS),
+                side: Side::Long,
+                take_profit_price: None,
+                stop_loss_price: None,
+                position_id,
+            },
+        )
+    };
+
+    let liquidated_position_pda = open_position(5, 0).await.unwrap().0;
+    let closed_position_pda = open_position(2, 1).await.unwrap().0;
+
+    assert_eq!(
+        get_indexed_positions().await,
+        vec![liquidated_position_pda, closed_position_pda]
+    );
+
+    // Martin: Close the 2x, it leaves the index
+    instructions::test_close_position(
+        program_test_ctx,
+        martin,
+        payer,
+        &test_setup.pool_pda,
+        eth_mint,
+        &closed_position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_000, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_indexed_positions().await, vec![liquidated_position_pda]);
+
+    // Makes ETH price to drop 10%
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            program_test_ctx,
+            admin_a,
+            payer,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_350, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_350, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Executioner: Batch liquidate the 5x, it leaves the index as well
+    instructions::test_liquidate_batch(
+        program_test_ctx,
+        executioner,
+        payer,
+        &test_setup.pool_pda,
+        eth_mint,
+        &[liquidated_position_pda],
+    )
+    .await
+    .unwrap();
+
+    assert!(get_indexed_positions().await.is_empty());
+
+    // Fill the index with keys of accounts that no longer exist, leaving one slot
+    let stale_position_pdas: Vec<Pubkey> = (1..UserPositions::MAX_POSITIONS)
+        .map(|_| Pubkey::new_unique())
+        .collect();
+    {
+        let mut ctx = program_test_ctx.write().await;
+        let user_positions_account = ctx
+            .banks_client
+            .get_account(user_positions_pda)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut user_positions =
+            UserPositions::try_deserialize(&mut user_positions_account.data.as_slice()).unwrap();
+        user_positions.positions = stale_position_pdas.clone();
+
+        let mut data = Vec::with_capacity(UserPositions::LEN);
+        user_positions.try_serialize(&mut data).unwrap();
+        data.resize(UserPositions::LEN, 0);
+
+        ctx.set_account(
+            &user_positions_pda,
+            &account::Account {
+                data,
+                ..user_positions_account
+            }
+            .into(),
+        );
+    }
+
+    // The last slot takes one more position, the next open is refused
+    let open_position_pda = open_position(2, 2).await.unwrap().0;
+    assert!(open_position(2, 3).await.is_err());
+
+    // Martin: Can't prune a position that is still open
+    assert!(instructions::test_prune_user_positions(
+        program_test_ctx,
+        martin,
+        payer,
+        &[open_position_pda],
+    )
+    .await
+    .is_err());
+
+    // Martin: Prune the stale keys
+    for stale_position_pdas in stale_position_pdas.chunks(20) {
+        instructions::test_prune_user_positions(
+            program_test_ctx,
+            martin,
+            payer,
+            stale_position_pdas,
+        )
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(get_indexed_positions().await, vec![open_position_pda]);
+
+    // Opens go through again
+    open_position(2, 3).await.unwrap();
+}
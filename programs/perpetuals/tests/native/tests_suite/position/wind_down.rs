@@ -0,0 +1,603 @@
+use {
+    crate::{
+        instructions,
+        utils::{self, pda},
+    },
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            AddLiquidityParams, AddMarginParams, AddMarginPositionParams, InitMarginAccountParams,
+            InitReferralParams, OpenPositionParams, RemoveCustodyParams,
+            RemoveLiquidityInKindParams, RemovePoolParams, SetCustodyConfigParams,
+            SetCustomOraclePriceParams, SettlePositionParams, SettleReferralRebateParams,
+            SwapParams, WindDownPoolParams, WithdrawFeesParams,
+        },
+        state::{
+            custody::{BorrowRateParams, Custody, Fees, PricingParams},
+            margin_account::MarginAccount,
+            pool::{Pool, TokenRatios},
+            position::Side,
+            user_positions::UserPositions,
+        },
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn wind_down() {
+    let eth_fees = Fees {
+        referral_rebate: 3_000,
+        ..utils::fixtures::fees_linear_regular()
+    };
+    // no spread and no interest, the position opens and settles at the oracle price
+    let eth_pricing = PricingParams {
+        trade_spread_long: 0,
+        trade_spread_short: 0,
+        ..utils::fixtures::pricing_params_regular(false)
+    };
+    let eth_borrow_rate = BorrowRateParams {
+        slope1: 0,
+        slope2: 0,
+        ..utils::fixtures::borrow_rate_regular()
+    };
+
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(0, USDC_DECIMALS),
+                    "eth" => utils::scale(0, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: Some(eth_pricing),
+                    permissions: None,
+                    fees: Some(eth_fees),
+                    borrow_rate: Some(eth_borrow_rate),
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+    let martin_usdc_pda = utils::find_associated_token_account(&martin.pubkey(), usdc_mint).0;
+
+    // Paul: Register as referrer
+    let (referral_pda, _) = instructions::test_init_referral(
+        &test_setup.program_test_ctx,
+        paul,
+        &test_setup.payer_keypair,
+        InitReferralParams {},
+    )
+    .await
+    .unwrap();
+
+    // Martin: Swap 150 USDC for ETH through Paul's referral, Paul leaves the rebate unclaimed
+    instructions::test_swap_with_referral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        Some(&referral_pda),
+        None,
+        SwapParams {
+            amount_in: utils::scale(150, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Martin: Index his positions
+    let user_positions_pda = instructions::test_init_user_positions(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Martin: Open 1 ETH long position x5
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Martin: Open a 0.5 ETH long position x5 backed by a 500 USDC cross-margin deposit
+    let margin_account_pda = instructions::test_init_margin_account(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        InitMarginAccountParams {},
+    )
+    .await
+    .unwrap()
+    .0;
+
+    instructions::test_add_margin(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        AddMarginParams {
+            amount: utils::scale(500, USDC_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    let margin_position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale_f64(2.5, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 1,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    instructions::test_add_margin_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &margin_position_pda,
+        AddMarginPositionParams {},
+    )
+    .await
+    .unwrap();
+
+    let martin_eth_balance_before_settlement =
+        utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+
+    // Admin: Enable a dynamic spread floor and price impact, settlement must ignore both
+    {
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+        let pool =
+            utils::get_account::<Pool>(&test_setup.program_test_ctx, test_setup.pool_pda).await;
+
+        instructions::test_set_custody_config(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            SetCustodyConfigParams {
+                is_stable: eth_custody.is_stable,
+                is_virtual: eth_custody.is_virtual,
+                oracle: eth_custody.oracle,
+                pricing: PricingParams {
+                    use_dynamic_spread: true,
+                    min_dynamic_spread: 200,
+                    max_dynamic_spread: 1_000,
+                    price_impact_mult: 10_000,
+                    max_price_impact: 500,
+                    ..eth_custody.pricing
+                },
+                permissions: eth_custody.permissions,
+                fees: eth_custody.fees,
+                borrow_rate: eth_custody.borrow_rate,
+                ratios: pool.ratios,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Admin: Wind down the pool, positions can be settled in one hour
+    let deadline = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await + 3_600;
+
+    instructions::test_wind_down_pool(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        WindDownPoolParams { deadline },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    // New positions, deposits and swaps are rejected
+    {
+        assert!(instructions::test_open_position(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            OpenPositionParams {
+                price: utils::scale(1_450, ETH_DECIMALS),
+                collateral: utils::scale_f64(0.5, ETH_DECIMALS),
+                min_amount_out: 0,
+                size: utils::scale(1, ETH_DECIMALS),
+                side: Side::Short,
+                take_profit_price: None,
+                stop_loss_price: None,
+                position_id: 0,
+            },
+        )
+        .await
+        .is_err());
+
+        assert!(instructions::test_add_liquidity(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            usdc_mint,
+            AddLiquidityParams {
+                amount_in: utils::scale(100, USDC_DECIMALS),
+                min_lp_amount_out: 1,
+            },
+        )
+        .await
+        .is_err());
+
+        assert!(instructions::test_swap(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            usdc_mint,
+            SwapParams {
+                amount_in: utils::scale(100, USDC_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .is_err());
+    }
+
+    // Anyone: Settling before the deadline should fail
+    assert!(instructions::test_settle_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        SettlePositionParams {},
+    )
+    .await
+    .is_err());
+
+    utils::warp_forward(&test_setup.program_test_ctx, 3_601).await;
+
+    // Refresh oracle prices after the warp
+    for (custody_info, price, conf, decimals) in [
+        (
+            &test_setup.custodies_info[0],
+            utils::scale(1, USDC_DECIMALS),
+            utils::scale_f64(0.01, USDC_DECIMALS),
+            USDC_DECIMALS,
+        ),
+        (
+            &test_setup.custodies_info[1],
+            utils::scale(1_500, ETH_DECIMALS),
+            utils::scale(10, ETH_DECIMALS),
+            ETH_DECIMALS,
+        ),
+    ] {
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &custody_info.custody_pda,
+            &custody_info.custom_oracle_pda,
+            SetCustomOraclePriceParams {
+                price,
+                expo: -(decimals as i32),
+                conf,
+                ema: price,
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Anyone: Settle Martin's position at the oracle price
+    instructions::test_settle_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        SettlePositionParams {},
+    )
+    .await
+    .unwrap();
+
+    let martin_usdc_balance_before_settlement =
+        utils::get_token_account_balance(&test_setup.program_test_ctx, martin_usdc_pda).await;
+
+    // Anyone: Settle Martin's margin position, its deposit is refunded with it
+    instructions::test_settle_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &margin_position_pda,
+        SettlePositionParams {},
+    )
+    .await
+    .unwrap();
+
+    // Check Martin got exactly his collateral and deposit back, the price did not move
+    // and the settled positions left his index and margin account
+    {
+        let user_positions =
+            utils::get_account::<UserPositions>(&test_setup.program_test_ctx, user_positions_pda)
+                .await;
+        assert!(user_positions.positions.is_empty());
+
+        let martin_eth_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+
+        assert_eq!(
+            martin_eth_balance - martin_eth_balance_before_settlement,
+            utils::scale_f64(1.5, ETH_DECIMALS)
+        );
+
+        let martin_usdc_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_usdc_pda).await;
+
+        assert_eq!(
+            martin_usdc_balance - martin_usdc_balance_before_settlement,
+            utils::scale(500, USDC_DECIMALS)
+        );
+
+        let margin_account =
+            utils::get_account::<MarginAccount>(&test_setup.program_test_ctx, margin_account_pda)
+                .await;
+        assert!(margin_account.positions.is_empty());
+        assert_eq!(margin_account.collateral_amount, 0);
+        assert_eq!(
+            margin_account.custodies,
+            vec![margin_account.collateral_custody]
+        );
+    }
+
+    // Alice: Redeem all LP tokens in kind
+    {
+        let lp_token_mint_pda = pda::get_lp_token_mint_pda(&test_setup.pool_pda).0;
+        let alice_lp_token_pda =
+            utils::find_associated_token_account(&alice.pubkey(), &lp_token_mint_pda).0;
+        let lp_amount_in =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, alice_lp_token_pda)
+                .await;
+
+        instructions::test_remove_liquidity_in_kind(
+            &test_setup.program_test_ctx,
+            alice,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            RemoveLiquidityInKindParams { lp_amount_in },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Check the pool has been drained of LP owned assets
+    for custody_info in &test_setup.custodies_info {
+        let custody_account =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, custody_info.custody_pda)
+                .await;
+
+        assert_eq!(custody_account.assets.owned, 0);
+    }
+
+    // Anyone: Pay Paul's unclaimed rebate out to him
+    let rebate = instructions::test_settle_referral_rebate(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &referral_pda,
+        eth_mint,
+        SettleReferralRebateParams {},
+    )
+    .await
+    .unwrap();
+    assert!(rebate > 0);
+
+    // Admin: Withdraw protocol fees, nothing else is left in the custodies
+    for mint in [usdc_mint, eth_mint] {
+        let custody_pda = pda::get_custody_pda(&test_setup.pool_pda, mint).0;
+        let custody_account =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, custody_pda).await;
+
+        assert_eq!(custody_account.assets.collateral, 0);
+        assert_eq!(custody_account.assets.locked, 0);
+        assert_eq!(custody_account.assets.referral_rebates, 0);
+
+        if custody_account.assets.protocol_fees > 0 {
+            instructions::test_withdraw_fees(
+                &test_setup.program_test_ctx,
+                admin_a,
+                &test_setup.payer_keypair,
+                &test_setup.pool_pda,
+                mint,
+                &utils::find_associated_token_account(&alice.pubkey(), mint).0,
+                WithdrawFeesParams {
+                    amount: custody_account.assets.protocol_fees,
+                },
+                &multisig_signers,
+            )
+            .await
+            .unwrap();
+        }
+
+        let custody_token_account_pda =
+            pda::get_custody_token_account_pda(&test_setup.pool_pda, mint).0;
+
+        assert_eq!(
+            utils::get_token_account_balance(
+                &test_setup.program_test_ctx,
+                custody_token_account_pda
+            )
+            .await,
+            0
+        );
+    }
+
+    // Admin: Remove the custodies, then the pool
+    instructions::test_remove_custody(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        RemoveCustodyParams {
+            ratios: vec![TokenRatios {
+                target: utils::ratio_from_percentage(100.0),
+                min: utils::ratio_from_percentage(0.0),
+                max: utils::ratio_from_percentage(100.0),
+            }],
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    instructions::test_remove_custody(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        RemoveCustodyParams { ratios: vec![] },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    instructions::test_remove_pool(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        RemovePoolParams {},
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+}
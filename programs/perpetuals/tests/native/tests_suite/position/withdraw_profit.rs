@@ -0,0 +1,291 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            ClosePositionParams, OpenPositionParams, SetCustomOraclePriceParams,
+            WithdrawProfitParams,
+        },
+        state::position::{Position, Side},
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn withdraw_profit() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10_000, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Martin: Open 1 ETH long position x5
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            min_amount_out: 0,
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+            take_profit_price: None,
+            stop_loss_price: None,
+            position_id: 0,
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Makes ETH price to raise 20%
+    {
+        let eth_test_oracle_pda = test_setup.custodies_info[1].custom_oracle_pda;
+        let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_custody_pda,
+            &eth_test_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_800, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_800, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Martin: Withdraw more than the unrealized profit should fail
+    assert!(instructions::test_withdraw_profit(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        WithdrawProfitParams {
+            profit_usd: utils::scale(10_000, USDC_DECIMALS),
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin: Withdraw 500 USD of profit
+    instructions::test_withdraw_profit(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        WithdrawProfitParams {
+            profit_usd: utils::scale(500, USDC_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check entry basis has been moved to the current price
+    {
+        let position =
+            utils::get_account::<Position>(&test_setup.program_test_ctx, position_pda).await;
+
+        assert!(position.price > utils::scale(1_700, USDC_DECIMALS));
+        assert!(position.unrealized_profit_usd > 0);
+    }
+
+    // Martin: Withdraw the rest of the profit
+    {
+        let info = instructions::test_get_position_info(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &position_pda,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        instructions::test_withdraw_profit(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            &position_pda,
+            WithdrawProfitParams {
+                profit_usd: info.profit_usd,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Martin: An immediate second withdrawal pays nothing
+    assert!(instructions::test_withdraw_profit(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        WithdrawProfitParams { profit_usd: 1 },
+    )
+    .await
+    .is_err());
+
+    {
+        let info = instructions::test_get_position_info(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &position_pda,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        // rounding dust at most
+        assert!(info.profit_usd < utils::scale_f64(0.01, USDC_DECIMALS));
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Martin: Withdrawn profit cannot be withdrawn again
+    // ~1,250 USD of profit before the first withdrawal
+    assert!(instructions::test_withdraw_profit(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        WithdrawProfitParams {
+            profit_usd: utils::scale(1_000, USDC_DECIMALS),
+        },
+    )
+    .await
+    .is_err());
+
+    instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_750, USDC_DECIMALS),
+            min_amount_out: 0,
+            // feed_id: [0; 32], // TODO: add feed id
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check user gains
+    {
+        let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+
+        let martin_eth_balance =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, martin_eth_pda).await;
+
+        // Started with 2 ETH, ~1,250 USD of profit is ~0.69 ETH at 1,800
+        assert!(martin_eth_balance > utils::scale_f64(2.6, ETH_DECIMALS));
+        assert!(martin_eth_balance < utils::scale_f64(2.75, ETH_DECIMALS));
+    }
+}
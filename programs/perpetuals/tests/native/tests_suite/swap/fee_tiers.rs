@@ -0,0 +1,176 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{InitTraderStatsParams, SetFeeTiersParams, SwapParams},
+        state::{perpetuals::FeeTier, trader_stats::TraderStats},
+    },
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn fee_tiers() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(7_500, USDC_DECIMALS),
+                    "eth" => utils::scale(5, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(1, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(7_500, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(5, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    // Thresholds must be ascending
+    assert!(instructions::test_set_fee_tiers(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        SetFeeTiersParams {
+            fee_tiers: [
+                FeeTier {
+                    min_volume_usd: utils::scale(1_000, USDC_DECIMALS),
+                    fee_discount: 1_000,
+                },
+                FeeTier {
+                    min_volume_usd: utils::scale(100, USDC_DECIMALS),
+                    fee_discount: 2_000,
+                },
+                FeeTier::default(),
+                FeeTier::default(),
+            ],
+        },
+        &multisig_signers,
+    )
+    .await
+    .is_err());
+
+    // 20% discount from 100 USD of 30-day volume
+    instructions::test_set_fee_tiers(
+        &test_setup.program_test_ctx,
+        admin_a,
+        &test_setup.payer_keypair,
+        SetFeeTiersParams {
+            fee_tiers: [
+                FeeTier {
+                    min_volume_usd: utils::scale(100, USDC_DECIMALS),
+                    fee_discount: 2_000,
+                },
+                FeeTier::default(),
+                FeeTier::default(),
+                FeeTier::default(),
+            ],
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    let (trader_stats_pda, _) = instructions::test_init_trader_stats(
+        &test_setup.program_test_ctx,
+        paul,
+        &test_setup.payer_keypair,
+        InitTraderStatsParams {},
+    )
+    .await
+    .unwrap();
+
+    // Paul: Swap 150 USDC for ETH twice, the second swap gets the discount
+    for _ in 0..2 {
+        instructions::test_swap_with_referral(
+            &test_setup.program_test_ctx,
+            paul,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            usdc_mint,
+            None,
+            Some(&trader_stats_pda),
+            SwapParams {
+                amount_in: utils::scale(150, USDC_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Check the volume is recorded
+    {
+        let trader_stats_account =
+            utils::get_account::<TraderStats>(&test_setup.program_test_ctx, trader_stats_pda).await;
+
+        assert_eq!(
+            trader_stats_account.daily_volume_usd.iter().sum::<u64>(),
+            utils::scale(300, USDC_DECIMALS)
+        );
+    }
+}
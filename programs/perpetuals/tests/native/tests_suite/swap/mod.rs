@@ -1,3 +1,6 @@
+pub mod fee_tiers;
 pub mod insuffisient_fund;
+pub mod referral;
+pub mod swap_order;
 
-pub use insuffisient_fund::*;
+pub use {fee_tiers::*, insuffisient_fund::*, referral::*, swap_order::*};
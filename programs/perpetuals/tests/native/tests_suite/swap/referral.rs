@@ -0,0 +1,192 @@
+use {
+    crate::{
+        instructions,
+        utils::{self, pda},
+    },
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClaimReferralRebateParams, InitReferralParams, SwapParams},
+        state::{custody::Fees, referral::Referral},
+    },
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn referral() {
+    let fees = Fees {
+        referral_discount: 2_000,
+        referral_rebate: 3_000,
+        ..utils::fixtures::fees_linear_regular()
+    };
+
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(7_500, USDC_DECIMALS),
+                    "eth" => utils::scale(5, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(1, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(1, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: Some(fees),
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(7_500, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: Some(fees),
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(5, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let eth_custody_pda = pda::get_custody_pda(&test_setup.pool_pda, eth_mint).0;
+
+    // Martin: Register as referrer
+    let (referral_pda, _) = instructions::test_init_referral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        InitReferralParams {},
+    )
+    .await
+    .unwrap();
+
+    // Martin: Referring his own trades should fail
+    assert!(instructions::test_swap_with_referral(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        Some(&referral_pda),
+        None,
+        SwapParams {
+            amount_in: utils::scale(150, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .is_err());
+
+    // Paul: Swap 150 USDC for ETH through Martin's referral
+    instructions::test_swap_with_referral(
+        &test_setup.program_test_ctx,
+        paul,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        usdc_mint,
+        Some(&referral_pda),
+        None,
+        SwapParams {
+            amount_in: utils::scale(150, USDC_DECIMALS),
+            min_amount_out: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Check the volume and rebate are attributed to Martin
+    {
+        let referral_account =
+            utils::get_account::<Referral>(&test_setup.program_test_ctx, referral_pda).await;
+
+        assert_eq!(
+            referral_account.volume_usd,
+            utils::scale(150, USDC_DECIMALS)
+        );
+        assert_eq!(referral_account.rebates.len(), 1);
+        assert_eq!(referral_account.rebates[0].custody, eth_custody_pda);
+        assert!(referral_account.rebates[0].amount > 0);
+    }
+
+    // Martin: Claim the ETH rebate
+    let rebate = instructions::test_claim_referral_rebate(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        ClaimReferralRebateParams {},
+    )
+    .await
+    .unwrap();
+    assert!(rebate > 0);
+
+    // Martin: Nothing left to claim
+    assert!(instructions::test_claim_referral_rebate(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        ClaimReferralRebateParams {},
+    )
+    .await
+    .is_err());
+}
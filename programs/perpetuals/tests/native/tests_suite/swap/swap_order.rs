@@ -0,0 +1,197 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            CancelSwapOrderParams, ExecuteSwapOrderParams, PlaceSwapOrderParams,
+            SetCustomOraclePriceParams,
+        },
+        state::perpetuals::Perpetuals,
+    },
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+
+pub async fn swap_order() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(7_500, USDC_DECIMALS),
+                    "eth" => utils::scale(5, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(7_500, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(5, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+
+    let expiry_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await + 3_600;
+
+    // Martin: Sell 1 ETH for USDC once ETH reaches 1_600 USDC
+    let (swap_order_pda, _) = instructions::test_place_swap_order(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        // The program receives ETH
+        eth_mint,
+        PlaceSwapOrderParams {
+            order_id: 0,
+            amount_in: utils::scale(1, ETH_DECIMALS),
+            limit_price: utils::scale(1_600, Perpetuals::PRICE_DECIMALS),
+            expiry_time,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Anyone: Executing below the limit price should fail
+    assert!(instructions::test_execute_swap_order(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &swap_order_pda,
+        ExecuteSwapOrderParams {},
+    )
+    .await
+    .is_err());
+
+    // ETH price goes up to 1_700 USDC
+    {
+        let eth_test_setup_custody = &test_setup.custodies_info[1];
+        let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+        instructions::test_set_custom_oracle_price(
+            &test_setup.program_test_ctx,
+            admin_a,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &eth_test_setup_custody.custody_pda,
+            &eth_test_setup_custody.custom_oracle_pda,
+            SetCustomOraclePriceParams {
+                price: utils::scale(1_700, ETH_DECIMALS),
+                expo: -(ETH_DECIMALS as i32),
+                conf: utils::scale(10, ETH_DECIMALS),
+                ema: utils::scale(1_700, ETH_DECIMALS),
+                publish_time,
+            },
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Anyone: Execute Martin's order
+    instructions::test_execute_swap_order(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &swap_order_pda,
+        ExecuteSwapOrderParams {},
+    )
+    .await
+    .unwrap();
+
+    // Martin: Place another order and cancel it
+    {
+        let (swap_order_pda, _) = instructions::test_place_swap_order(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            // The program receives USDC
+            usdc_mint,
+            PlaceSwapOrderParams {
+                order_id: 1,
+                amount_in: utils::scale(500, USDC_DECIMALS),
+                limit_price: utils::scale_f64(0.0005, Perpetuals::PRICE_DECIMALS),
+                expiry_time,
+            },
+        )
+        .await
+        .unwrap();
+
+        instructions::test_cancel_swap_order(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            usdc_mint,
+            &swap_order_pda,
+            CancelSwapOrderParams {},
+        )
+        .await
+        .unwrap();
+    }
+}
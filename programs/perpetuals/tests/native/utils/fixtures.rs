@@ -51,6 +51,8 @@ pub fn fees_linear_regular() -> Fees {
         protocol_share: 25,
         fee_max: 0,
         fee_optimal: 0,
+        referral_discount: 0,
+        referral_rebate: 0,
     }
 }
 
@@ -68,6 +70,19 @@ pub fn pricing_params_regular(use_ema: bool) -> PricingParams {
         max_utilization: 0,
         max_position_locked_usd: 0,
         max_total_locked_usd: 0,
+        max_oi_long_usd: 0,
+        max_oi_short_usd: 0,
+        max_oi_imbalance: 0,
+        use_dynamic_spread: false,
+        conf_spread_mult: 0,
+        ema_spread_mult: 0,
+        min_dynamic_spread: 0,
+        max_dynamic_spread: 0,
+        price_impact_mult: 0,
+        max_price_impact: 0,
+        depeg_threshold: 0,
+        block_positions_on_depeg: false,
+        pay_out_at_spot_on_depeg: false,
     }
 }
 
@@ -79,6 +94,18 @@ pub fn oracle_params_regular(oracle_account: Pubkey) -> OracleParams {
         max_price_error: 1_000_000,
         max_price_age_sec: 30,
         feed_id: [0; 32],
+        ema_half_life_sec: 0,
+        max_price_deviation: 0,
+        extra_oracle_authorities: [Pubkey::default(); 4],
+        min_oracle_signatures: 0,
+        max_quote_spread: 0,
+        message_domain: 0,
+        allow_legacy_message: false,
+        reject_stale_updates: false,
+        reference_oracle_account: Pubkey::default(),
+        reference_oracle_type: OracleType::None,
+        max_reference_deviation: 0,
+        allow_exit_outside_reference: false,
     }
 }
 
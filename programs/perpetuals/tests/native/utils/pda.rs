@@ -1,6 +1,6 @@
 use {
     anchor_lang::{prelude::Pubkey, solana_program},
-    perpetuals::state::position::Side,
+    perpetuals::state::position::{Position, Side},
 };
 
 pub fn get_multisig_pda() -> (Pubkey, u8) {
@@ -49,6 +49,7 @@ pub fn get_position_pda(
     pool_pda: &Pubkey,
     custody_pda: &Pubkey,
     side: Side,
+    position_id: u64,
 ) -> (Pubkey, u8) {
     Pubkey::find_program_address(
         &[
@@ -57,6 +58,7 @@ pub fn get_position_pda(
             pool_pda.as_ref(),
             custody_pda.as_ref(),
             &[side as u8],
+            Position::get_id_seed(&position_id),
         ],
         &perpetuals::id(),
     )
@@ -86,3 +88,58 @@ pub fn get_custom_oracle_account(pool_pda: &Pubkey, custody_mint: &Pubkey) -> (P
         &perpetuals::id(),
     )
 }
+
+pub fn get_margin_account_pda(owner: &Pubkey, pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["margin_account".as_ref(), owner.as_ref(), pool_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_swap_order_pda(owner: &Pubkey, pool_pda: &Pubkey, order_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "swap_order".as_ref(),
+            owner.as_ref(),
+            pool_pda.as_ref(),
+            &order_id.to_le_bytes(),
+        ],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_swap_order_token_account_pda(swap_order_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["swap_order_token_account".as_ref(), swap_order_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_referral_pda(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&["referral".as_ref(), owner.as_ref()], &perpetuals::id())
+}
+
+pub fn get_trader_stats_pda(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["trader_stats".as_ref(), owner.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_delegate_pda(owner: &Pubkey, delegate_authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "delegate".as_ref(),
+            owner.as_ref(),
+            delegate_authority.as_ref(),
+        ],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_user_positions_pda(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &["user_positions".as_ref(), owner.as_ref()],
+        &perpetuals::id(),
+    )
+}
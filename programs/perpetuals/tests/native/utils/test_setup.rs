@@ -81,11 +81,11 @@ pub struct TestSetup {
 
 impl TestSetup {
     pub fn get_user_keypair_by_name(&self, name: &str) -> &Keypair {
-        self.users.get(&name.to_string()).unwrap()
+        self.users.get(name).unwrap()
     }
 
     pub fn get_multisig_member_keypair_by_name(&self, name: &str) -> &Keypair {
-        self.multisig_members.get(&name.to_string()).unwrap()
+        self.multisig_members.get(name).unwrap()
     }
 
     pub fn get_multisig_signers(&self) -> Vec<&Keypair> {
@@ -93,7 +93,7 @@ impl TestSetup {
     }
 
     pub fn get_mint_by_name(&self, name: &str) -> Pubkey {
-        self.mints.get(&name.to_string()).unwrap().pubkey
+        self.mints.get(name).unwrap().pubkey
     }
 
     // Initialize everything required to test the program
@@ -218,8 +218,8 @@ impl TestSetup {
         {
             for user_param in users_param.as_slice() {
                 for (mint_name, amount) in &user_param.token_balances {
-                    let mint = mints.get(&mint_name.to_string()).unwrap().pubkey;
-                    let user = users.get(&user_param.name.to_string()).unwrap().pubkey();
+                    let mint = mints.get(*mint_name).unwrap().pubkey;
+                    let user = users.get(user_param.name).unwrap().pubkey();
 
                     let (ata, _) = utils::find_associated_token_account(&user, &mint);
 
@@ -256,7 +256,7 @@ impl TestSetup {
 
             for (idx, custody_param) in custodies_params.iter().enumerate() {
                 let mint_info = mints
-                    .get(&custody_param.setup_custody_params.mint_name.to_string())
+                    .get(custody_param.setup_custody_params.mint_name)
                     .unwrap();
 
                 let custom_oracle_pda =
@@ -365,12 +365,10 @@ impl TestSetup {
         // Add liquidity
         for custody_param in custodies_params.as_slice() {
             let mint_info = mints
-                .get(&custody_param.setup_custody_params.mint_name.to_string())
+                .get(custody_param.setup_custody_params.mint_name)
                 .unwrap();
 
-            let liquidity_provider = users
-                .get(&custody_param.payer_user_name.to_string())
-                .unwrap();
+            let liquidity_provider = users.get(custody_param.payer_user_name).unwrap();
 
             println!(
                 "adding liquidity for mint {}",
@@ -1,5 +1,5 @@
 use {
-    crate::instructions,
+    crate::{instructions, utils::pda},
     anchor_lang::{
         prelude::*,
         solana_program::{
@@ -13,8 +13,14 @@ use {
     perpetuals::{
         instructions::SetCustodyConfigParams,
         math,
-        state::{custody::Custody, perpetuals::Perpetuals, pool::TokenRatios},
+        state::{
+            custody::{Custody, PricingParams},
+            oracle::OracleParams,
+            perpetuals::Perpetuals,
+            pool::{Pool, TokenRatios},
+        },
     },
+    pyth_solana_receiver_sdk::price_update::{PriceFeedMessage, PriceUpdateV2, VerificationLevel},
     solana_program_test::{BanksClientError, ProgramTest, ProgramTestContext},
     solana_sdk::{account, signature::Keypair, signer::Signer, signers::Signers},
     std::ops::{Div, Mul},
@@ -201,7 +207,8 @@ pub async fn create_and_simulate_perpetuals_view_ix<T: InstructionData, U: Borsh
         return_data.push(0u8);
     }
 
-    Ok(U::try_from_slice(return_data.as_slice()).unwrap())
+    // Padding may exceed the serialized size (e.g. None options), read only what's needed
+    Ok(U::deserialize(&mut return_data.as_slice()).unwrap())
 }
 
 pub async fn create_and_execute_perpetuals_ix<T: InstructionData, U: Signers>(
@@ -225,14 +232,14 @@ pub async fn create_and_execute_perpetuals_ix<T: InstructionData, U: Signers>(
 
     let mut instructions: Vec<solana_sdk::instruction::Instruction> = Vec::new();
 
-    if pre_ix.is_some() {
-        instructions.push(pre_ix.unwrap());
+    if let Some(pre_ix) = pre_ix {
+        instructions.push(pre_ix);
     }
 
     instructions.push(ix);
 
-    if post_ix.is_some() {
-        instructions.push(post_ix.unwrap());
+    if let Some(post_ix) = post_ix {
+        instructions.push(post_ix);
     }
 
     let tx = solana_sdk::transaction::Transaction::new_signed_with_payer(
@@ -284,6 +291,141 @@ pub async fn set_custody_ratios(
     .unwrap();
 }
 
+pub async fn set_custody_oracle(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    custody_admin: &Keypair,
+    payer: &Keypair,
+    custody_pda: &Pubkey,
+    oracle: OracleParams,
+    multisig_signers: &[&Keypair],
+) {
+    let custody_account = get_account::<Custody>(program_test_ctx, *custody_pda).await;
+    let pool_account = get_account::<Pool>(program_test_ctx, custody_account.pool).await;
+
+    instructions::test_set_custody_config(
+        program_test_ctx,
+        custody_admin,
+        payer,
+        &custody_account.pool,
+        custody_pda,
+        SetCustodyConfigParams {
+            is_stable: custody_account.is_stable,
+            is_virtual: custody_account.is_virtual,
+            oracle,
+            pricing: custody_account.pricing,
+            permissions: custody_account.permissions,
+            fees: custody_account.fees,
+            borrow_rate: custody_account.borrow_rate,
+            ratios: pool_account.ratios,
+        },
+        multisig_signers,
+    )
+    .await
+    .unwrap();
+}
+
+pub async fn set_custody_pricing(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    custody_admin: &Keypair,
+    payer: &Keypair,
+    custody_pda: &Pubkey,
+    pricing: PricingParams,
+    multisig_signers: &[&Keypair],
+) {
+    let custody_account = get_account::<Custody>(program_test_ctx, *custody_pda).await;
+    let pool_account = get_account::<Pool>(program_test_ctx, custody_account.pool).await;
+
+    instructions::test_set_custody_config(
+        program_test_ctx,
+        custody_admin,
+        payer,
+        &custody_account.pool,
+        custody_pda,
+        SetCustodyConfigParams {
+            is_stable: custody_account.is_stable,
+            is_virtual: custody_account.is_virtual,
+            oracle: custody_account.oracle,
+            pricing,
+            permissions: custody_account.permissions,
+            fees: custody_account.fees,
+            borrow_rate: custody_account.borrow_rate,
+            ratios: pool_account.ratios,
+        },
+        multisig_signers,
+    )
+    .await
+    .unwrap();
+}
+
+// Writes a fully verified PriceUpdateV2 account published at the current clock time,
+// the same account the Pyth receiver would post, so Pyth custodies can be tested offline
+pub async fn set_pyth_price_update(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    price_update_address: &Pubkey,
+    feed_id: [u8; 32],
+    price: i64,
+    ema_price: i64,
+    conf: u64,
+    exponent: i32,
+) {
+    let publish_time = get_current_unix_timestamp(program_test_ctx).await;
+
+    let mut ctx = program_test_ctx.write().await;
+    let posted_slot = ctx.banks_client.get_root_slot().await.unwrap();
+
+    let price_update = PriceUpdateV2 {
+        write_authority: Pubkey::default(),
+        verification_level: VerificationLevel::Full,
+        price_message: PriceFeedMessage {
+            feed_id,
+            price,
+            conf,
+            exponent,
+            publish_time,
+            prev_publish_time: publish_time,
+            ema_price,
+            ema_conf: conf,
+        },
+        posted_slot,
+    };
+
+    let mut data = Vec::new();
+    price_update.try_serialize(&mut data).unwrap();
+
+    ctx.set_account(
+        price_update_address,
+        &account::Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: pyth_solana_receiver_sdk::ID,
+            ..account::Account::default()
+        }
+        .into(),
+    );
+}
+
+// Reference oracle account to pass along with the custody oracle, None if no band is configured
+pub fn get_reference_oracle_account(oracle: &OracleParams) -> Option<Pubkey> {
+    (oracle.max_reference_deviation > 0).then_some(oracle.reference_oracle_account)
+}
+
+// Owner's positions index to pass along, None if the owner never initialized one
+pub async fn get_user_positions_account(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Pubkey,
+) -> Option<Pubkey> {
+    let user_positions_pda = pda::get_user_positions_pda(owner).0;
+
+    let mut ctx = program_test_ctx.write().await;
+    let account = ctx
+        .banks_client
+        .get_account(user_positions_pda)
+        .await
+        .unwrap();
+
+    account.map(|_| user_positions_pda)
+}
+
 #[derive(Clone, Copy)]
 pub struct SetupCustodyInfo {
     pub custom_oracle_pda: Pubkey,
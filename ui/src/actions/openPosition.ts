@@ -171,6 +171,7 @@ export async function openPositionBuilder(
     collateral: new BN(finalPayAmount * 10 ** positionCustody.decimals),
     size: new BN(positionAmount * 10 ** positionCustody.decimals),
     side: side.toString() == "Long" ? TradeSide.Long : TradeSide.Short,
+    positionId: new BN(0),
   };
 
   let methodBuilder = perpetual_program.methods.openPosition(params).accounts({